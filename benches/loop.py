@@ -0,0 +1,16 @@
+# Dispatch loop benchmark: `rustpy bench --no-cache -n 10 benches/loop.py`
+# with a release build. Loops over globals and over locals.
+def count(n):
+    total = 0
+    i = 0
+    while i < n:
+        total = total + i % 7
+        i = i + 1
+    return total
+
+total = 0
+i = 0
+while i < 300000:
+    total = total + i % 7
+    i = i + 1
+print(count(300000) + total)
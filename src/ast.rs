@@ -1,3 +1,7 @@
+use std::ops::{Deref, DerefMut};
+
+use crate::diagnostic::Span;
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Operator {
     Not,
@@ -6,6 +10,8 @@ pub enum Operator {
     Sub,
     Mul,
     Div,
+    FloorDiv,
+    Mod,
     And,
     Or,
     Equal,
@@ -21,9 +27,11 @@ pub enum Literal {
     None,
     True,
     False,
+    Ellipsis,
     Integer(i64),
     Float(f64),
     String(String),
+    Bytes(Vec<u8>),
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -34,19 +42,158 @@ pub enum Expression {
     Call(CallExpression),
     If(IfExpression),
     While(WhileExpression),
+    Try(TryExpression),
+    Raise(RaiseExpression),
+    Assert(AssertExpression),
     Continue,
     Break,
     Return(ReturnExpression),
+    Import(ImportExpression),
+    Attribute(AttributeExpression),
     Assignment(AssignmentExpression),
+    Annotation(AnnotationExpression),
     Unary(UnaryExpression),
     Binary(BinaryExpression),
+    Index(IndexExpression),
     Variable(String),
     Literal(Literal),
 }
 
+// Long operator chains and deep nesting would overflow the Rust stack with
+// the default recursive drop, so children are detached and dropped from a
+// worklist instead.
+impl Drop for Expression {
+    fn drop(&mut self) {
+        let mut pending = Vec::new();
+        self.take_children(&mut pending);
+        while let Some(mut expr) = pending.pop() {
+            expr.take_children(&mut pending);
+        }
+    }
+}
+
+/// A child expression along with the source it was parsed from.
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub expr: Box<Expression>,
+    pub span: Span,
+}
+
+impl Node {
+    pub fn new(expr: Expression, span: Span) -> Node {
+        Node {
+            expr: Box::new(expr),
+            span,
+        }
+    }
+}
+
+// a node built outside of the parser, e.g. by tests, spans nothing
+impl From<Expression> for Node {
+    fn from(expr: Expression) -> Node {
+        Node::new(expr, Span::default())
+    }
+}
+
+// spans are ignored, so a tree equals the same tree parsed elsewhere
+impl PartialEq for Node {
+    fn eq(&self, other: &Self) -> bool {
+        self.expr == other.expr
+    }
+}
+
+impl Deref for Node {
+    type Target = Expression;
+
+    fn deref(&self) -> &Expression {
+        &self.expr
+    }
+}
+
+impl DerefMut for Node {
+    fn deref_mut(&mut self) -> &mut Expression {
+        &mut self.expr
+    }
+}
+
+impl Expression {
+    fn take_children(&mut self, pending: &mut Vec<Expression>) {
+        let mut take = |child: &mut Node| {
+            if !matches!(**child, Expression::Empty) {
+                pending.push(std::mem::replace(&mut **child, Expression::Empty));
+            }
+        };
+        match self {
+            Expression::Block(block) => block.exprs.iter_mut().for_each(take),
+            Expression::Function(function) => function.body.exprs.iter_mut().for_each(take),
+            Expression::Call(call) => {
+                take(&mut call.callable);
+                call.args.iter_mut().for_each(&mut take);
+                call.kwargs.iter_mut().for_each(|(_, arg)| take(arg));
+            }
+            Expression::If(if_expr) => {
+                take(&mut if_expr.condition);
+                take(&mut if_expr.then_branch);
+                for elif in if_expr.elif_branches.iter_mut() {
+                    take(&mut elif.condition);
+                    take(&mut elif.then_branch);
+                }
+                take(&mut if_expr.else_branch);
+            }
+            Expression::While(while_expr) => {
+                take(&mut while_expr.condition);
+                take(&mut while_expr.body);
+                take(&mut while_expr.else_branch);
+            }
+            Expression::Try(try_expr) => {
+                take(&mut try_expr.body);
+                for handler in try_expr.handlers.iter_mut() {
+                    take(&mut handler.exception_type);
+                    take(&mut handler.body);
+                }
+                take(&mut try_expr.else_branch);
+            }
+            Expression::Raise(raise_expr) => {
+                take(&mut raise_expr.exception);
+                take(&mut raise_expr.cause);
+            }
+            Expression::Assert(assert_expr) => {
+                take(&mut assert_expr.test);
+                take(&mut assert_expr.message);
+            }
+            Expression::Return(return_expr) => take(&mut return_expr.expr),
+            Expression::Attribute(attribute) => take(&mut attribute.object),
+            Expression::Assignment(assignment) => {
+                take(&mut assignment.lhs);
+                take(&mut assignment.rhs);
+            }
+            Expression::Annotation(annotation) => {
+                if let Some(value) = annotation.value.as_mut() {
+                    take(value);
+                }
+            }
+            Expression::Unary(unary) => take(&mut unary.expr),
+            Expression::Binary(binary) => {
+                take(&mut binary.lhs);
+                take(&mut binary.rhs);
+            }
+            Expression::Index(index) => {
+                take(&mut index.object);
+                take(&mut index.index);
+            }
+            Expression::Empty
+            | Expression::Continue
+            | Expression::Break
+            | Expression::Import(_)
+            | Expression::Variable(_)
+            | Expression::Literal(_) => {}
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Program {
-    pub stmts: Vec<Box<Expression>>,
+    pub stmts: Vec<Node>,
 }
 
 impl Program {
@@ -57,62 +204,149 @@ impl Program {
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct BlockExpression {
-    pub exprs: Vec<Box<Expression>>,
+    pub exprs: Vec<Node>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct FunctionExpression {
     pub name: String,
     pub args: Vec<String>,
+    // one entry per argument
+    pub arg_types: Vec<Option<TypeAnnotation>>,
+    pub return_type: Option<TypeAnnotation>,
     pub body: BlockExpression,
 }
 
+// `name` or `name[args, ...]`, e.g. `int` or `dict[str, list[int]]`
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypeAnnotation {
+    pub name: String,
+    pub args: Vec<TypeAnnotation>,
+}
+
+impl std::fmt::Display for TypeAnnotation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        if !self.args.is_empty() {
+            let args = self
+                .args
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect::<Vec<String>>()
+                .join(", ");
+            write!(f, "[{}]", args)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct CallExpression {
-    pub callable: Box<Expression>,
-    pub args: Vec<Box<Expression>>,
+    pub callable: Node,
+    pub args: Vec<Node>,
+    // `name=value` arguments, always after the positional ones
+    pub kwargs: Vec<(String, Node)>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct IfExpression {
-    pub condition: Box<Expression>,
-    pub then_branch: Box<Expression>,
+    pub condition: Node,
+    pub then_branch: Node,
     pub elif_branches: Vec<ElifExpression>,
-    pub else_branch: Box<Expression>,
+    pub else_branch: Node,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct ElifExpression {
-    pub condition: Box<Expression>,
-    pub then_branch: Box<Expression>,
+    pub condition: Node,
+    pub then_branch: Node,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct WhileExpression {
-    pub condition: Box<Expression>,
-    pub body: Box<Expression>,
+    pub condition: Node,
+    pub body: Node,
+    // runs when the condition turns false, but not after a break
+    pub else_branch: Node,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TryExpression {
+    pub body: Node,
+    pub handlers: Vec<ExceptExpression>,
+    // runs when the body raised nothing
+    pub else_branch: Node,
+}
+
+// `except type as name:`, with Empty as the type of a bare `except:`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExceptExpression {
+    pub exception_type: Node,
+    pub name: Option<String>,
+    pub body: Node,
+}
+
+// `raise exception from cause`, Empty for the parts left out so that a bare
+// `raise` re-raises the exception being handled
+#[derive(Clone, Debug, PartialEq)]
+pub struct RaiseExpression {
+    pub exception: Node,
+    pub cause: Node,
+}
+
+// `assert test, message`, Empty without a message
+#[derive(Clone, Debug, PartialEq)]
+pub struct AssertExpression {
+    pub test: Node,
+    pub message: Node,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct ReturnExpression {
-    pub expr: Box<Expression>,
+    pub expr: Node,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImportExpression {
+    pub name: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttributeExpression {
+    pub object: Node,
+    pub name: String,
+}
+
+// `object[index]`
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexExpression {
+    pub object: Node,
+    pub index: Node,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct AssignmentExpression {
-    pub lhs: Box<Expression>,
-    pub rhs: Box<Expression>,
+    pub lhs: Node,
+    pub rhs: Node,
+}
+
+// `name: annotation` with an optional `= value`
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnnotationExpression {
+    pub name: String,
+    pub annotation: TypeAnnotation,
+    pub value: Option<Node>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct BinaryExpression {
-    pub lhs: Box<Expression>,
+    pub lhs: Node,
     pub op: Operator,
-    pub rhs: Box<Expression>,
+    pub rhs: Node,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct UnaryExpression {
     pub op: Operator,
-    pub expr: Box<Expression>,
+    pub expr: Node,
 }
@@ -0,0 +1,69 @@
+use ::base64::engine::general_purpose::STANDARD;
+use ::base64::{DecodeError, Engine};
+
+use crate::native::{NativeContext, NativeFunction, NativeResult};
+use crate::object::Value;
+use crate::vm::VmError;
+
+pub const MODULE_NAME: &str = "base64";
+
+/// Attributes of the built-in `base64` module.
+pub fn attributes() -> Vec<(String, Value)> {
+    vec![
+        NativeFunction::new("b64encode", 1, b64encode),
+        NativeFunction::new("b64decode", 1, b64decode),
+    ]
+    .into_iter()
+    .map(|function| {
+        (
+            function.name.to_string(),
+            Value::NativeFunction(function.into()),
+        )
+    })
+    .collect()
+}
+
+fn b64encode(_context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    match &args[0] {
+        Value::Bytes(bytes) => Ok(Value::Bytes(
+            STANDARD.encode(bytes.as_slice()).into_bytes().into(),
+        )),
+        other => Err(VmError::InvalidOperand(format!(
+            "TypeError: a bytes-like object is required, not '{}'",
+            other.type_name()
+        ))),
+    }
+}
+
+// like CPython without `validate`, characters outside of the alphabet are
+// discarded before decoding
+fn b64decode(_context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    let encoded = match &args[0] {
+        Value::Bytes(bytes) => bytes.to_vec(),
+        Value::String(text) if text.is_ascii() => text.as_bytes().to_vec(),
+        Value::String(_) => {
+            return Err(VmError::ValueError(String::from(
+                "ValueError: string argument should contain only ASCII characters",
+            )))
+        }
+        other => {
+            return Err(VmError::InvalidOperand(format!(
+                "TypeError: argument should be a bytes-like object or ASCII string, not '{}'",
+                other.type_name()
+            )))
+        }
+    };
+    let encoded: Vec<u8> = encoded
+        .into_iter()
+        .filter(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'+' | b'/' | b'='))
+        .collect();
+    match STANDARD.decode(&encoded) {
+        Ok(bytes) => Ok(Value::Bytes(bytes.into())),
+        Err(DecodeError::InvalidPadding | DecodeError::InvalidLength(_)) => Err(
+            VmError::ValueError(String::from("binascii.Error: Incorrect padding")),
+        ),
+        Err(_) => Err(VmError::ValueError(String::from(
+            "binascii.Error: Invalid base64-encoded string",
+        ))),
+    }
+}
@@ -1,21 +1,30 @@
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 
 pub const SIZE_INSTRUCTION: usize = 1;
+// index operands are a byte, two after a `Wide` prefix and eight after an
+// `ExtraWide` one
+pub const SIZE_NARROW_INDEX: usize = 1;
+pub const SIZE_WIDE_INDEX: usize = 2;
 pub const SIZE_INDEX: usize = 8;
+// signed offset of short jumps, relative to the jump opcode
+pub const SIZE_JUMP: usize = 2;
 
-#[derive(Clone, Debug, IntoPrimitive, TryFromPrimitive)]
+#[derive(Clone, Debug, PartialEq, IntoPrimitive, TryFromPrimitive)]
 #[repr(u8)]
 pub enum Bytecode {
     Nop = 0x00,
 
     // Literals
     None = 0x01,
-    True = 0x02,
-    False = 0x03,
+    PushTrue = 0x02,
+    PushFalse = 0x03,
     Const = 0x04,
 
     // Stack Manipulation
     Pop = 0x10,
+    // pops the value of a top-level expression statement into the result
+    // `Interpreter::run` returns
+    PopResult = 0x1F,
 
     // Unary Ops
     Not = 0x11,
@@ -26,15 +35,26 @@ pub enum Bytecode {
     GetGlobal = 0x21,
     SetLocal = 0x22,
     GetLocal = 0x23,
+    GetBuiltin = 0x24,
+    // `name = name + value`, appending to strings in place
+    InplaceAddGlobal = 0x25,
+    InplaceAddLocal = 0x26,
 
     // Functions and Objects
     Call = 0x30,
     Return = 0x31,
+    GetAttr = 0x32,
+    CallKw = 0x33,
+    GetItem = 0x34,
+    // pushes the function in a constant, for the definition to bind it
+    MakeFunction = 0x35,
 
     // Control Flow
     Jump = 0x40,
     JumpIfFalse = 0x41,
     Loop = 0x42,
+    // jump with a `SIZE_INDEX` offset, for targets short jumps can't reach
+    JumpLong = 0x43,
 
     // Binary Ops
     And = 0x50,
@@ -50,7 +70,71 @@ pub enum Bytecode {
     Sub = 0x60,
     Mul = 0x61,
     Div = 0x62,
+    Mod = 0x63,
+    FloorDiv = 0x64,
+
+    // Modules
+    Import = 0x70,
+
+    // Exceptions
+    SetupTry = 0x80,
+    PopTry = 0x81,
+    PopExcept = 0x82,
+    ExceptMatch = 0x83,
+    Raise = 0x84,
+
+    // Superinstructions the peephole pass fuses from hot sequences, their
+    // operands being single bytes. `Const` + `InplaceAdd*`, with the
+    // variable and the constant:
+    IncrementGlobal = 0x90,
+    IncrementLocal = 0x91,
+    // `Const` + `Less` + `JumpIfFalse`, with the offset and the constant
+    ConstLessJumpIfFalse = 0x92,
+
+    // Operand prefixes
+    Wide = 0xF0,
+    ExtraWide = 0xF1,
 
     // For disassembler usage
     Unknown = 0xFF,
 }
+
+impl Bytecode {
+    /// Size of the operand following the opcode without prefix: `SIZE_JUMP`
+    /// for short jumps, `SIZE_INDEX` for long ones, `SIZE_NARROW_INDEX` for
+    /// indexes, the sum of both parts for superinstructions and 0 without
+    /// operand.
+    pub fn operand_size(&self) -> usize {
+        match self {
+            Bytecode::Jump | Bytecode::JumpIfFalse | Bytecode::Loop | Bytecode::SetupTry => {
+                SIZE_JUMP
+            }
+            Bytecode::JumpLong => SIZE_INDEX,
+            Bytecode::IncrementGlobal | Bytecode::IncrementLocal => 2 * SIZE_NARROW_INDEX,
+            Bytecode::ConstLessJumpIfFalse => SIZE_JUMP + SIZE_NARROW_INDEX,
+            _ if self.has_index() => SIZE_NARROW_INDEX,
+            _ => 0,
+        }
+    }
+
+    /// Whether the operand is an index, which a prefix may widen.
+    pub fn has_index(&self) -> bool {
+        matches!(
+            self,
+            Bytecode::Const
+                | Bytecode::SetGlobal
+                | Bytecode::GetGlobal
+                | Bytecode::SetLocal
+                | Bytecode::GetLocal
+                | Bytecode::GetBuiltin
+                | Bytecode::InplaceAddGlobal
+                | Bytecode::InplaceAddLocal
+                | Bytecode::Call
+                | Bytecode::CallKw
+                | Bytecode::GetAttr
+                | Bytecode::MakeFunction
+                | Bytecode::Import
+                | Bytecode::Raise
+        )
+    }
+}
@@ -0,0 +1,107 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::config::Config;
+use crate::rpyc::EXTENSION;
+use crate::sys::VERSION;
+
+/// Directory next to a script its compiled bytecode is cached in.
+pub const CACHE_DIR: &str = "__rustpycache__";
+
+// bytes of the digest kept in file names, as hex
+const KEY_SIZE: usize = 8;
+
+/// Where the bytecode of `script` is cached, `dir/__rustpycache__/name.key.rpyc`
+/// with a key hashing its source and the settings that change what it
+/// compiles to, so an edit or another setting misses the cache.
+pub(crate) fn cache_path(script: &Path, source: &str, config: &Config) -> Option<PathBuf> {
+    let stem = script.file_stem()?.to_str()?;
+    let mut hasher = Sha256::new();
+    hasher.update(VERSION);
+    hasher.update(format!(
+        "{:?} {:?} {:?} {:?}\n",
+        config.peephole, config.opt_level, config.max_parse_depth, config.max_tokens
+    ));
+    hasher.update(source);
+    let key: String = hasher.finalize()[..KEY_SIZE]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    let name = format!("{}.{}.{}", stem, key, EXTENSION);
+    Some(script.parent()?.join(CACHE_DIR).join(name))
+}
+
+/// Caches `bytes` at `path`, dropping what was cached for earlier versions
+/// of the script. Written to a temporary file first, so other runs never
+/// read half a file.
+pub(crate) fn write(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let (Some(dir), Some(script)) = (path.parent(), script_name(path)) else {
+        return Ok(());
+    };
+    fs::create_dir_all(dir)?;
+    for entry in fs::read_dir(dir)?.flatten() {
+        let stale = entry.path();
+        if stale != path && script_name(&stale) == Some(script) {
+            let _ = fs::remove_file(stale);
+        }
+    }
+    let temporary = path.with_extension("tmp");
+    fs::write(&temporary, bytes)?;
+    fs::rename(temporary, path)
+}
+
+// the script name of a cache entry
+fn script_name(path: &Path) -> Option<&str> {
+    let name = path.file_name()?.to_str()?;
+    let mut parts = name.rsplitn(3, '.');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(EXTENSION), Some(key), Some(stem)) if key.len() == 2 * KEY_SIZE => Some(stem),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_path() {
+        let config = Config::new();
+        let path = cache_path(Path::new("dir/script.py"), "x = 1\n", &config).unwrap();
+        assert_eq!(path.parent(), Some(Path::new("dir/__rustpycache__")));
+        assert_eq!(script_name(&path), Some("script"));
+
+        vec![
+            (Path::new("dir/script.py"), "x = 2\n", Config::new()),
+            (Path::new("dir/other.py"), "x = 1\n", Config::new()),
+            (
+                Path::new("dir/script.py"),
+                "x = 1\n",
+                Config {
+                    peephole: true,
+                    ..Config::new()
+                },
+            ),
+            (
+                Path::new("dir/script.py"),
+                "x = 1\n",
+                Config {
+                    opt_level: 2,
+                    ..Config::new()
+                },
+            ),
+        ]
+        .into_iter()
+        .for_each(|(script, source, config)| {
+            assert_ne!(cache_path(script, source, &config), Some(path.clone()));
+        });
+        assert_eq!(script_name(Path::new("script.rpyc")), None);
+        assert_eq!(
+            script_name(Path::new("a.b.0123456789abcdef.rpyc")),
+            Some("a.b")
+        );
+    }
+}
@@ -1,11 +1,73 @@
-use crate::ast::Literal;
-use crate::bytecode::Bytecode;
+use crate::bytecode::{
+    Bytecode, SIZE_INDEX, SIZE_INSTRUCTION, SIZE_JUMP, SIZE_NARROW_INDEX, SIZE_WIDE_INDEX,
+};
+use crate::object::Value;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+
+/// A jump offset too large for the operand of a short jump.
+#[derive(Debug)]
+pub struct JumpTooLong;
+
+/// Opcode and operand placement of an instruction, prefix included.
+pub struct Decoded {
+    pub op: Bytecode,
+    pub operand_addr: usize,
+    pub operand_size: usize,
+}
+
+impl Decoded {
+    pub fn next_addr(&self) -> usize {
+        self.operand_addr + self.operand_size
+    }
+}
+
+/// Global slots the chunk's `GetAttr`s last found module attributes in, by
+/// instruction address. A slot is only used after checking the global in
+/// it has the attribute's name, so the cache never changes what a chunk
+/// means and chunks compare equal whatever it holds.
+#[derive(Clone, Debug, Default)]
+pub struct InlineCache(RefCell<Vec<Option<u64>>>);
+
+impl InlineCache {
+    pub fn get(&self, addr: usize) -> Option<u64> {
+        self.0.borrow().get(addr).copied().flatten()
+    }
+
+    pub fn set(&self, addr: usize, slot: u64) {
+        let mut slots = self.0.borrow_mut();
+        if slots.len() <= addr {
+            slots.resize(addr + 1, None);
+        }
+        slots[addr] = Some(slot);
+    }
+}
+
+impl PartialEq for InlineCache {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl PartialOrd for InlineCache {
+    fn partial_cmp(&self, _other: &Self) -> Option<Ordering> {
+        Some(Ordering::Equal)
+    }
+}
 
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
 pub struct Chunk {
     pub name: String,
     pub data: Vec<u8>,
-    pub constants: Vec<Literal>,
+    // runtime values, so Const only has to clone them
+    pub constants: Vec<Value>,
+    // (offset, line) pairs in offset order: the instructions from each
+    // offset up to the next one come from that source line
+    pub lines: Vec<(u64, usize)>,
+    // (name, start, end) of each local variable: its slot holds it while
+    // the instructions from offset `start` up to `end` run
+    pub locals: Vec<(String, u64, u64)>,
+    pub cache: InlineCache,
 }
 
 impl Chunk {
@@ -14,6 +76,9 @@ impl Chunk {
             name: String::from("__main__"),
             data: Vec::new(),
             constants: Vec::new(),
+            lines: Vec::new(),
+            locals: Vec::new(),
+            cache: InlineCache::default(),
         }
     }
 
@@ -21,47 +86,315 @@ impl Chunk {
         self.data.len() as u64
     }
 
-    pub fn get_data_u64_safe(&self, index: usize) -> Option<u64> {
-        match self.data.get(index..index + 8) {
-            Some(bytes) => {
-                if let Ok(array) = bytes.try_into() {
-                    return Some(u64::from_ne_bytes(array));
-                }
-                return None;
+    /// The `size`-byte operand at `index`, None when the chunk is truncated.
+    pub fn read_operand(&self, index: usize, size: usize) -> Option<u64> {
+        let bytes = self.data.get(index..index.checked_add(size)?)?;
+        match size {
+            SIZE_NARROW_INDEX => Some(bytes[0] as u64),
+            SIZE_WIDE_INDEX => bytes.try_into().ok().map(u16::from_ne_bytes).map(u64::from),
+            SIZE_INDEX => bytes.try_into().ok().map(u64::from_ne_bytes),
+            _ => None,
+        }
+    }
+
+    /// The instruction at `addr`, None for unknown opcodes and prefixes not
+    /// followed by an instruction with an index. The operand may be cut
+    /// short.
+    pub fn decode(&self, addr: usize) -> Option<Decoded> {
+        let op = Bytecode::try_from(*self.data.get(addr)?).ok()?;
+        let operand_size = match op {
+            Bytecode::Wide => SIZE_WIDE_INDEX,
+            Bytecode::ExtraWide => SIZE_INDEX,
+            op => {
+                return Some(Decoded {
+                    operand_size: op.operand_size(),
+                    op,
+                    operand_addr: addr + SIZE_INSTRUCTION,
+                })
             }
-            None => None,
+        };
+        let addr = addr + SIZE_INSTRUCTION;
+        let op = Bytecode::try_from(*self.data.get(addr)?).ok()?;
+        op.has_index().then_some(Decoded {
+            op,
+            operand_addr: addr + SIZE_INSTRUCTION,
+            operand_size,
+        })
+    }
+
+    /// The short jump offset at `index`, None when the chunk is truncated.
+    pub fn get_jump_offset_safe(&self, index: usize) -> Option<i64> {
+        let bytes = self.data.get(index..index.checked_add(SIZE_JUMP)?)?;
+        bytes
+            .try_into()
+            .ok()
+            .map(|bytes| i16::from_ne_bytes(bytes) as i64)
+    }
+
+    /// Index of `value` in the constant pool, shared with an identical
+    /// literal already there.
+    pub fn add_constant(&mut self, value: Value) -> u64 {
+        if let Some(index) = self
+            .constants
+            .iter()
+            .position(|constant| same_literal(constant, &value))
+        {
+            return index as u64;
         }
+        self.constants.push(value);
+        (self.constants.len() - 1) as u64
     }
 
-    pub fn get_data_u64(&self, index: usize) -> u64 {
-        assert!(index + 8 < self.data.len());
-        let bytes = &self.data[index..index + 8];
-        if let Ok(array) = bytes.try_into() {
-            return u64::from_ne_bytes(array);
+    /// Marks the instructions emitted from now on as coming from `line`.
+    pub fn set_line(&mut self, line: usize) {
+        let offset = self.size();
+        match self.lines.last_mut() {
+            Some((_, last)) if *last == line => {}
+            // nothing was emitted for the previous line
+            Some((start, last)) if *start == offset => *last = line,
+            _ => self.lines.push((offset, line)),
         }
-        unreachable!();
     }
 
-    pub fn add_constant(&mut self, literal: &Literal) -> u64 {
-        self.constants.push(literal.clone());
-        (self.constants.len() - 1) as u64
+    /// Sorted names of the locals holding a value at `offset`.
+    pub fn locals_at(&self, offset: u64) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .locals
+            .iter()
+            .filter(|(_, start, end)| (*start..*end).contains(&offset))
+            .map(|(name, _, _)| name.to_string())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Source line of the instruction at `offset`, None without line info.
+    pub fn line_at(&self, offset: u64) -> Option<usize> {
+        let index = self.lines.partition_point(|(start, _)| *start <= offset);
+        index.checked_sub(1).map(|index| self.lines[index].1)
     }
 
     pub fn emit(&mut self, op: Bytecode) {
         self.data.push(op as u8);
     }
 
-    pub fn emit_index(&mut self, index: u64) -> u64 {
-        let index_addr = self.size();
-        self.data.extend_from_slice(&index.to_ne_bytes());
-        index_addr
+    /// Emits `op` with its index operand, prefixed when a byte can't hold
+    /// it.
+    pub fn emit_with_index(&mut self, op: Bytecode, index: u64) {
+        if let Ok(index) = u8::try_from(index) {
+            self.emit(op);
+            self.data.push(index);
+        } else if let Ok(index) = u16::try_from(index) {
+            self.emit(Bytecode::Wide);
+            self.emit(op);
+            self.data.extend_from_slice(&index.to_ne_bytes());
+        } else {
+            self.emit(Bytecode::ExtraWide);
+            self.emit(op);
+            self.data.extend_from_slice(&index.to_ne_bytes());
+        }
     }
 
-    pub fn patch_jump_addr(&mut self, jump_offset_addr: u64, target_addr: u64) {
-        let offset = target_addr - jump_offset_addr + 1;
-        let index_bytes = offset.to_ne_bytes();
-        for i in 0..index_bytes.len() {
-            self.data[jump_offset_addr as usize + i] = index_bytes[i];
+    /// Replaces the `size` bytes index operand at `addr`, returning whether
+    /// the new index fits it.
+    pub fn patch_index(&mut self, addr: usize, size: usize, index: u64) -> bool {
+        let bytes = match size {
+            SIZE_NARROW_INDEX => u8::try_from(index).map(|index| vec![index]),
+            SIZE_WIDE_INDEX => u16::try_from(index).map(|index| index.to_ne_bytes().to_vec()),
+            _ => Ok(index.to_ne_bytes().to_vec()),
+        };
+        let end = addr.saturating_add(size);
+        match (bytes, self.data.get_mut(addr..end)) {
+            (Ok(bytes), Some(operand)) => {
+                operand.copy_from_slice(&bytes);
+                true
+            }
+            _ => false,
         }
     }
+
+    /// Emits the forward jump `op`, returning the address of its operand
+    /// for `patch_jump`.
+    pub fn emit_jump(&mut self, op: Bytecode) -> u64 {
+        let operand_size = op.operand_size();
+        self.emit(op);
+        let operand_addr = self.size();
+        self.data.resize(self.data.len() + operand_size, 0);
+        operand_addr
+    }
+
+    /// Emits a jump back to `target_addr`, long if a short one can't reach.
+    pub fn emit_loop(&mut self, target_addr: u64) {
+        let offset = target_addr as i64 - self.size() as i64;
+        match i16::try_from(offset) {
+            Ok(offset) => {
+                self.emit(Bytecode::Loop);
+                self.data.extend_from_slice(&offset.to_ne_bytes());
+            }
+            Err(_) => {
+                self.emit(Bytecode::JumpLong);
+                self.data.extend_from_slice(&offset.to_ne_bytes());
+            }
+        }
+    }
+
+    /// Points the jump whose operand is at `operand_addr` to `target_addr`.
+    pub fn patch_jump(&mut self, operand_addr: u64, target_addr: u64) -> Result<(), JumpTooLong> {
+        let start = operand_addr as usize;
+        let jump_addr = start - SIZE_INSTRUCTION;
+        let offset = target_addr as i64 - jump_addr as i64;
+        if self.data[jump_addr] == Bytecode::JumpLong as u8 {
+            self.data[start..start + SIZE_INDEX].copy_from_slice(&offset.to_ne_bytes());
+            return Ok(());
+        }
+        let offset = i16::try_from(offset).map_err(|_| JumpTooLong)?;
+        self.data[start..start + SIZE_JUMP].copy_from_slice(&offset.to_ne_bytes());
+        Ok(())
+    }
+}
+
+// unlike `==`, tells 1, 1.0 and True as well as 0.0 and -0.0 apart, and
+// matches NaNs with the same bits
+pub(crate) fn same_literal(lhs: &Value, rhs: &Value) -> bool {
+    match (lhs, rhs) {
+        (Value::None, Value::None) | (Value::Ellipsis, Value::Ellipsis) => true,
+        (Value::Bool(lhs), Value::Bool(rhs)) => lhs == rhs,
+        (Value::Integer(lhs), Value::Integer(rhs)) => lhs == rhs,
+        (Value::Float(lhs), Value::Float(rhs)) => lhs.to_bits() == rhs.to_bits(),
+        (Value::String(lhs), Value::String(rhs)) => lhs == rhs,
+        (Value::Bytes(lhs), Value::Bytes(rhs)) => lhs == rhs,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lines() {
+        let mut chunk = Chunk::new();
+        chunk.set_line(1);
+        chunk.emit(Bytecode::None);
+        chunk.set_line(1);
+        chunk.emit(Bytecode::Pop);
+        chunk.set_line(2);
+        chunk.set_line(3);
+        chunk.emit_with_index(Bytecode::Const, 0);
+        chunk.emit(Bytecode::Pop);
+        assert_eq!(chunk.lines, vec![(0, 1), (2, 3)]);
+        vec![
+            (0, Some(1)),
+            (1, Some(1)),
+            (2, Some(3)),
+            (4, Some(3)),
+            (50, Some(3)),
+        ]
+        .into_iter()
+        .for_each(|(offset, line)| assert_eq!(chunk.line_at(offset), line));
+        assert_eq!(Chunk::new().line_at(0), None);
+    }
+
+    #[test]
+    fn test_add_constant() {
+        let mut chunk = Chunk::new();
+        vec![
+            (Value::Integer(1), 0),
+            (Value::Float(1.0), 1),
+            (Value::Bool(true), 2),
+            (Value::Integer(1), 0),
+            (Value::Float(0.0), 3),
+            (Value::Float(-0.0), 4),
+            (Value::Float(f64::NAN), 5),
+            (Value::Float(f64::NAN), 5),
+            (Value::String(String::from("1").into()), 6),
+            (Value::Bytes(b"1".to_vec().into()), 7),
+            (Value::String(String::from("1").into()), 6),
+            (Value::Float(1.0), 1),
+            (Value::List(Vec::new().into()), 8),
+            (Value::List(Vec::new().into()), 9),
+        ]
+        .into_iter()
+        .for_each(|(value, index)| assert_eq!(chunk.add_constant(value), index));
+    }
+
+    #[test]
+    fn test_index_operands() {
+        let mut chunk = Chunk::new();
+        vec![
+            (0, 0, SIZE_NARROW_INDEX),
+            (255, 2, SIZE_NARROW_INDEX),
+            (256, 4, SIZE_WIDE_INDEX),
+            (65535, 8, SIZE_WIDE_INDEX),
+            (65536, 12, SIZE_INDEX),
+        ]
+        .into_iter()
+        .for_each(|(index, addr, operand_size)| {
+            chunk.emit_with_index(Bytecode::GetGlobal, index);
+            let decoded = chunk.decode(addr).unwrap();
+            assert!(matches!(decoded.op, Bytecode::GetGlobal));
+            assert_eq!(decoded.operand_size, operand_size);
+            assert_eq!(decoded.next_addr(), chunk.data.len());
+            assert_eq!(
+                chunk.read_operand(decoded.operand_addr, operand_size),
+                Some(index)
+            );
+        });
+        assert_eq!(chunk.data.len(), 22);
+
+        let decoded = chunk.decode(4).unwrap();
+        assert!(chunk.patch_index(decoded.operand_addr, decoded.operand_size, 300));
+        assert!(!chunk.patch_index(decoded.operand_addr, decoded.operand_size, 65536));
+        assert_eq!(
+            chunk.read_operand(decoded.operand_addr, SIZE_WIDE_INDEX),
+            Some(300)
+        );
+
+        // prefixes only widen indexes
+        let mut chunk = Chunk::new();
+        chunk.emit(Bytecode::Wide);
+        chunk.emit(Bytecode::Jump);
+        assert!(chunk.decode(0).is_none());
+    }
+
+    #[test]
+    fn test_jumps() {
+        let mut chunk = Chunk::new();
+        chunk.emit(Bytecode::Nop);
+        let forward_addr = chunk.emit_jump(Bytecode::JumpIfFalse);
+        chunk.emit_loop(0);
+        assert!(chunk.patch_jump(forward_addr, chunk.size()).is_ok());
+        assert_eq!(chunk.get_jump_offset_safe(forward_addr as usize), Some(6));
+        assert_eq!(chunk.get_jump_offset_safe(5), Some(-4));
+
+        // too far for a short jump, either way
+        chunk.data.resize(40000, Bytecode::Nop as u8);
+        assert!(chunk.patch_jump(forward_addr, chunk.size()).is_err());
+        chunk.emit_loop(0);
+        assert!(matches!(
+            Bytecode::try_from(chunk.data[40000]),
+            Ok(Bytecode::JumpLong)
+        ));
+        assert_eq!(
+            chunk.read_operand(40001, SIZE_INDEX),
+            Some(-40000i64 as u64)
+        );
+        let long_addr = chunk.emit_jump(Bytecode::JumpLong);
+        assert!(chunk.patch_jump(long_addr, 0).is_ok());
+        assert_eq!(
+            chunk.read_operand(40010, SIZE_INDEX),
+            Some(-40009i64 as u64)
+        );
+    }
+
+    #[test]
+    fn test_inline_cache() {
+        let cached = Chunk::new();
+        cached.cache.set(3, 7);
+        assert_eq!(cached.cache.get(3), Some(7));
+        assert_eq!(cached.cache.get(2), None);
+        assert_eq!(cached.cache.get(40), None);
+        assert_eq!(cached, Chunk::new());
+    }
 }
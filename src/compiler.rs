@@ -1,43 +1,154 @@
 use crate::ast::{
-    AssignmentExpression, BinaryExpression, BlockExpression, CallExpression, Expression,
-    FunctionExpression, IfExpression, Literal, Operator, Program, ReturnExpression,
-    UnaryExpression, WhileExpression,
+    AnnotationExpression, AssertExpression, AssignmentExpression, AttributeExpression,
+    BinaryExpression, BlockExpression, CallExpression, Expression, FunctionExpression,
+    IfExpression, ImportExpression, IndexExpression, Literal, Node, Operator, Program,
+    RaiseExpression, ReturnExpression, TryExpression, UnaryExpression, WhileExpression,
 };
 use crate::bytecode::Bytecode;
 use crate::chunk::Chunk;
+use crate::config::DEFAULT_OPT_LEVEL;
+use crate::diagnostic::Span;
 use crate::function::Function;
+use crate::module::{DOC_GLOBAL, MAIN_MODULE_NAME, MODULE_NAME_GLOBAL};
 use crate::object::{Object, Value};
+use crate::optimizer::{optimize, strip_unreachable};
+use crate::register;
 use crate::symbol_table::SymbolTable;
+use crate::vm::{binary_op, logic_op, unary_op};
+use std::rc::Rc;
 
 #[derive(Clone, Debug)]
 pub enum CompilerError {
-    NameNotFound(String),
-    InvalidExpression(String),
+    NameNotFound(String, Span),
+    InvalidExpression(String, Span),
+    LimitExceeded(String, Span),
 }
 
+/// Nesting the compiler accepts, above what the default parser limit lets
+/// through but low enough for a 2MB thread stack in debug builds.
+/// Left-associative operator chains don't count towards it.
+const MAX_COMPILE_DEPTH: usize = 500;
+
+/// Class failed asserts raise.
+const ASSERTION_ERROR: &str = "AssertionError";
+
 struct Local {
     name: String,
     depth: usize,
+    // offset of the instruction storing it first
+    start: u64,
+}
+
+// try bodies and except clauses being emitted, which a break or continue
+// leaving them has to pop at runtime
+enum ExceptionBlock {
+    Try,
+    Except,
 }
 
 pub struct Compiler<'a> {
     program: Program,
     globals: &'a mut SymbolTable,
+    builtins: &'a SymbolTable,
+    // module globals are stored in the shared table as `module.name`
+    module: Option<String>,
     locals: Vec<Local>,
     scope_depth: usize,
+    // nesting of the expression being emitted
+    depth: usize,
+    // source of the expression being emitted, for errors
+    span: Span,
+    // byte offset of each source line, empty when compiling without source
+    line_starts: Vec<usize>,
     continue_addr_stack: Vec<u64>,
     break_addr_stack: Vec<u64>,
+    exception_blocks: Vec<ExceptionBlock>,
+    // size of `exception_blocks` when each enclosing loop started
+    loop_exception_depths: Vec<usize>,
+    // forward jumps are emitted long, after a short one couldn't reach
+    long_jumps: bool,
+    jump_overflow: bool,
+    peephole: bool,
+    opt_level: u8,
+    register_backend: bool,
 }
 
 impl Compiler<'_> {
-    pub fn new(program: Program, globals: &mut SymbolTable) -> Compiler {
+    pub fn new<'a>(
+        program: Program,
+        globals: &'a mut SymbolTable,
+        builtins: &'a SymbolTable,
+    ) -> Compiler<'a> {
         Compiler {
             program,
             globals,
+            builtins,
+            module: None,
             locals: Vec::new(),
             scope_depth: 0,
+            depth: 0,
+            span: Span::default(),
+            line_starts: Vec::new(),
             continue_addr_stack: Vec::new(),
             break_addr_stack: Vec::new(),
+            exception_blocks: Vec::new(),
+            loop_exception_depths: Vec::new(),
+            long_jumps: false,
+            jump_overflow: false,
+            peephole: false,
+            opt_level: DEFAULT_OPT_LEVEL,
+            register_backend: false,
+        }
+    }
+
+    pub fn new_module<'a>(
+        program: Program,
+        globals: &'a mut SymbolTable,
+        builtins: &'a SymbolTable,
+        module: &str,
+    ) -> Compiler<'a> {
+        let mut compiler = Compiler::new(program, globals, builtins);
+        compiler.module = Some(module.to_string());
+        compiler
+    }
+
+    /// Records which line of `source` each instruction comes from, for
+    /// runtime errors and disassembly.
+    pub fn with_source(mut self, source: &str) -> Self {
+        self.line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        self
+    }
+
+    /// Runs the peephole optimizer over every compiled chunk.
+    pub fn with_peephole(mut self, peephole: bool) -> Self {
+        self.peephole = peephole;
+        self
+    }
+
+    /// Picks the passes to run, see `Config::opt_level`.
+    pub fn with_opt_level(mut self, opt_level: u8) -> Self {
+        self.opt_level = opt_level;
+        self
+    }
+
+    /// Also lowers every function the register VM supports for it.
+    pub fn with_register_backend(mut self, register_backend: bool) -> Self {
+        self.register_backend = register_backend;
+        self
+    }
+
+    // post-passes over a compiled function
+    fn finish_function(&self, function: &mut Function) {
+        if self.peephole || self.opt_level >= 2 {
+            optimize(&mut function.chunk);
+        }
+        if self.opt_level >= 1 {
+            strip_unreachable(&mut function.chunk);
+        }
+        if self.register_backend {
+            function.registers = register::lower(&function.chunk, function.arity);
         }
     }
 
@@ -47,21 +158,106 @@ impl Compiler<'_> {
     }
 
     pub fn compile(&mut self) -> Result<Function, CompilerError> {
-        self.emit_program()
+        let mut function = self.emit_program()?;
+        if self.jump_overflow {
+            // some function is too large for short jumps
+            self.long_jumps = true;
+            self.jump_overflow = false;
+            function = self.emit_program()?;
+        }
+        self.finish_function(&mut function);
+        Ok(function)
     }
 
     fn emit_program(&mut self) -> Result<Function, CompilerError> {
-        let mut function = Function::new_global_scope();
+        let mut function = match &self.module {
+            Some(module) => Function::new_module_scope(module),
+            None => Function::new_global_scope(),
+        };
         self.init_compiler();
-        for expr in self.program.stmts.clone() {
-            self.emit_expression(&mut function, expr.as_ref())?;
-        }
+        self.declare_module_name();
+        function.doc = docstring(&self.program.stmts);
+        self.declare_module_doc(&function.doc);
+        // emitting needs `self` mutably, so the statements are moved out
+        // for the duration rather than deep-copied
+        let stmts = std::mem::take(&mut self.program.stmts);
+        let result = check_placement(&stmts).and_then(|_| {
+            stmts
+                .iter()
+                .try_for_each(|expr| self.emit_statement(&mut function, expr))
+        });
+        self.program.stmts = stmts;
+        result?;
         // Always finish with a Nop opcode
         function.chunk.emit(Bytecode::Nop);
         Ok(function)
     }
 
+    // Expression statements leave the stack as they found it, top-level ones
+    // of the main script storing their value as the result of the run.
+    fn emit_statement(
+        &mut self,
+        function: &mut Function,
+        expr: &Node,
+    ) -> Result<(), CompilerError> {
+        self.emit_expression(function, expr)?;
+        if matches!(
+            **expr,
+            Expression::Call(_)
+                | Expression::Attribute(_)
+                | Expression::Index(_)
+                | Expression::Unary(_)
+                | Expression::Binary(_)
+                | Expression::Variable(_)
+                | Expression::Literal(_)
+        ) {
+            match self.module {
+                None if function.is_global_scope() => function.chunk.emit(Bytecode::PopResult),
+                _ => function.chunk.emit(Bytecode::Pop),
+            }
+        }
+        Ok(())
+    }
+
+    // Emitting recurses into subexpressions, so nesting is bounded to keep
+    // pathological programs from overflowing the Rust stack.
     fn emit_expression(
+        &mut self,
+        function: &mut Function,
+        expr: &Node,
+    ) -> Result<(), CompilerError> {
+        if self.depth >= MAX_COMPILE_DEPTH {
+            return Err(CompilerError::LimitExceeded(
+                format!("Expression nested deeper than {} levels", MAX_COMPILE_DEPTH),
+                expr.span,
+            ));
+        }
+        self.depth += 1;
+        let enclosing_span = std::mem::replace(&mut self.span, expr.span);
+        self.mark_line(function, expr.span);
+        let result = self.emit_nested_expression(function, expr);
+        self.span = enclosing_span;
+        self.depth -= 1;
+        // what the enclosing expression emits after its operands is on its
+        // line, statements having none keep the last one
+        if self.depth > 0 {
+            self.mark_line(function, enclosing_span);
+        }
+        result
+    }
+
+    // Marks the code emitted next as coming from the line `span` starts on.
+    // Nodes the parser made up, like an empty else branch, have no span.
+    fn mark_line(&self, function: &mut Function, span: Span) {
+        if span.start < span.end && !self.line_starts.is_empty() {
+            let line = self
+                .line_starts
+                .partition_point(|start| *start <= span.start);
+            function.chunk.set_line(line);
+        }
+    }
+
+    fn emit_nested_expression(
         &mut self,
         function: &mut Function,
         expr: &Expression,
@@ -70,34 +266,69 @@ impl Compiler<'_> {
         match expr {
             Expression::Function(function_expression) => {
                 let name = function_expression.name.to_string();
-                let function_id = self.globals.insert(&name, None);
-                dbg!(&self.globals);
+                // script level definitions are globals, declared first so
+                // the body can call itself
+                if function.is_global_scope() {
+                    let global_name = self.global_name(&name);
+                    self.get_or_declare_global(&global_name);
+                }
                 let mut child_function = Function::new(name.to_string());
-                self.emit_function_expression(&mut child_function, &function_expression)?;
-                let function_object = Object::new_with_name(name, Value::Function(child_function));
-                self.globals.set(function_id, function_object);
+                child_function.module = self.module.clone();
+                // function bodies only see their own locals
+                let enclosing_locals = std::mem::take(&mut self.locals);
+                let result =
+                    self.emit_function_expression(&mut child_function, function_expression);
+                self.locals = enclosing_locals;
+                result?;
+                self.finish_function(&mut child_function);
+                let index = function
+                    .chunk
+                    .add_constant(Value::Function(Rc::new(child_function)));
+                function
+                    .chunk
+                    .emit_with_index(Bytecode::MakeFunction, index);
+                self.emit_store_variable(function, &name);
                 Ok(())
             }
             Expression::Call(call_expression) => {
-                self.emit_call_expression(function, &call_expression)
+                self.emit_call_expression(function, call_expression)
             }
             Expression::Block(block_expression) => {
-                self.emit_block_expression(function, &block_expression)
+                self.emit_block_expression(function, block_expression)
             }
-            Expression::If(if_expression) => self.emit_if_expression(function, &if_expression),
+            Expression::If(if_expression) => self.emit_if_expression(function, if_expression),
             Expression::While(while_expression) => {
-                self.emit_while_expression(function, &while_expression)
+                self.emit_while_expression(function, while_expression)
+            }
+            Expression::Try(try_expression) => self.emit_try_expression(function, try_expression),
+            Expression::Raise(raise_expression) => {
+                self.emit_raise_expression(function, raise_expression)
+            }
+            Expression::Assert(assert_expression) => {
+                self.emit_assert_expression(function, assert_expression)
             }
             Expression::Continue => self.emit_continue_statement(function),
             Expression::Break => self.emit_break_statement(function),
             Expression::Return(return_expression) => {
-                self.emit_return_expression(function, &return_expression)
+                self.emit_return_expression(function, return_expression)
+            }
+            Expression::Import(import_expression) => {
+                self.emit_import_expression(function, import_expression)
+            }
+            Expression::Attribute(attribute_expression) => {
+                self.emit_attribute_expression(function, attribute_expression)
+            }
+            Expression::Index(index_expression) => {
+                self.emit_index_expression(function, index_expression)
             }
-            Expression::Assignment(assignment) => self.emit_assignment_op(function, &assignment),
-            Expression::Unary(unary) => self.emit_unary_op(function, &unary),
-            Expression::Binary(binary) => self.emit_binary_op(function, &binary),
-            Expression::Variable(value) => self.emit_variable_op(function, &value),
-            Expression::Literal(identifier) => self.emit_literal(chunk, &identifier),
+            Expression::Assignment(assignment) => self.emit_assignment_op(function, assignment),
+            Expression::Annotation(annotation) => {
+                self.emit_annotation_expression(function, annotation)
+            }
+            Expression::Unary(unary) => self.emit_unary_op(function, unary),
+            Expression::Binary(binary) => self.emit_binary_op(function, binary),
+            Expression::Variable(value) => self.emit_variable_op(function, value),
+            Expression::Literal(identifier) => self.emit_literal(chunk, identifier),
             Expression::Empty => {
                 chunk.emit(Bytecode::Nop);
                 Ok(())
@@ -111,10 +342,25 @@ impl Compiler<'_> {
         function_expression: &FunctionExpression,
     ) -> Result<(), CompilerError> {
         function.arity = function_expression.args.len();
+        function.doc = docstring(&function_expression.body.exprs);
+        function.params = function_expression.args.clone();
+        function.arg_types = function_expression.arg_types.clone();
+        function.return_type = function_expression.return_type.clone();
         function_expression.args.iter().for_each(|arg_name| {
-            self.declare_local(arg_name);
+            self.declare_local(arg_name, 0);
         });
-        self.emit_block_expression(function, &function_expression.body)
+        self.emit_block_expression(function, &function_expression.body)?;
+        // falling off the end returns None
+        function.chunk.emit(Bytecode::None);
+        function.chunk.emit(Bytecode::Return);
+        let end = function.chunk.size();
+        for local in &self.locals {
+            function
+                .chunk
+                .locals
+                .push((local.name.to_string(), local.start, end));
+        }
+        Ok(())
     }
 
     fn emit_call_expression(
@@ -122,15 +368,38 @@ impl Compiler<'_> {
         function: &mut Function,
         call_expression: &CallExpression,
     ) -> Result<(), CompilerError> {
-        // emit in reverse order to save processing in interpreting loop
+        // in order, so the first argument lands in the callee's first slot
         call_expression
             .args
             .iter()
+            .try_for_each(|expr| self.emit_expression(function, expr))?;
+        if call_expression.kwargs.is_empty() {
+            self.emit_expression(function, &call_expression.callable)?;
+            function
+                .chunk
+                .emit_with_index(Bytecode::Call, call_expression.args.len() as u64);
+            return Ok(());
+        }
+
+        // keyword values go above the positional arguments, topped by their
+        // comma separated names
+        call_expression
+            .kwargs
+            .iter()
             .rev()
-            .try_for_each(|expr| self.emit_expression(function, expr.as_ref()))?;
-        self.emit_expression(function, call_expression.callable.as_ref())?;
-        function.chunk.emit(Bytecode::Call);
-        function.chunk.emit_index(call_expression.args.len() as u64);
+            .try_for_each(|(_, expr)| self.emit_expression(function, expr))?;
+        let names = call_expression
+            .kwargs
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .collect::<Vec<String>>()
+            .join(",");
+        let index = function.chunk.add_constant(Value::String(names.into()));
+        function.chunk.emit_with_index(Bytecode::Const, index);
+        self.emit_expression(function, &call_expression.callable)?;
+        function
+            .chunk
+            .emit_with_index(Bytecode::CallKw, call_expression.args.len() as u64);
         Ok(())
     }
 
@@ -140,10 +409,24 @@ impl Compiler<'_> {
         block_expr: &BlockExpression,
     ) -> Result<(), CompilerError> {
         self.begin_scope();
-        block_expr
+        // nothing after a statement leaving the block runs
+        let end = block_expr
             .exprs
             .iter()
-            .try_for_each(|expr| self.emit_expression(function, expr.as_ref()))?;
+            .position(|expr| {
+                self.opt_level >= 1
+                    && matches!(
+                        **expr,
+                        Expression::Return(_)
+                            | Expression::Break
+                            | Expression::Continue
+                            | Expression::Raise(_)
+                    )
+            })
+            .map_or(block_expr.exprs.len(), |index| index + 1);
+        block_expr.exprs[..end]
+            .iter()
+            .try_for_each(|expr| self.emit_statement(function, expr))?;
         self.end_scope(function);
         Ok(())
     }
@@ -156,31 +439,25 @@ impl Compiler<'_> {
         // Emit If branch
         let mut exit_jump_addrs: Vec<u64> = Vec::new();
 
-        let exit_jump_addr = self.emit_if_branch(
-            function,
-            if_expr.condition.as_ref(),
-            if_expr.then_branch.as_ref(),
-        )?;
+        let exit_jump_addr =
+            self.emit_if_branch(function, &if_expr.condition, &if_expr.then_branch)?;
         exit_jump_addrs.push(exit_jump_addr);
 
         // Emit Elif branches
         if_expr.elif_branches.iter().try_for_each(|elif_expr| {
-            let exit_jump_addr = self.emit_if_branch(
-                function,
-                elif_expr.condition.as_ref(),
-                elif_expr.then_branch.as_ref(),
-            )?;
+            let exit_jump_addr =
+                self.emit_if_branch(function, &elif_expr.condition, &elif_expr.then_branch)?;
             exit_jump_addrs.push(exit_jump_addr);
             Ok(())
         })?;
 
         // Emit Else branch
-        self.emit_expression(function, if_expr.else_branch.as_ref())?;
+        self.emit_statement(function, &if_expr.else_branch)?;
 
         // Patch exit addresses to prevent fallthrough
         let next_addr = function.chunk.size();
         exit_jump_addrs.iter().for_each(|addr| {
-            function.chunk.patch_jump_addr(*addr, next_addr);
+            self.patch_jump(function, *addr, next_addr);
         });
         Ok(())
     }
@@ -188,18 +465,14 @@ impl Compiler<'_> {
     fn emit_if_branch(
         &mut self,
         function: &mut Function,
-        condition: &Expression,
-        then_branch: &Expression,
+        condition: &Node,
+        then_branch: &Node,
     ) -> Result<u64, CompilerError> {
         self.emit_expression(function, condition)?;
-        function.chunk.emit(Bytecode::JumpIfFalse);
-        let jump_offset_addr = function.chunk.emit_index(0);
-        self.emit_expression(function, then_branch)?;
-        function.chunk.emit(Bytecode::Jump);
-        let exit_offset_addr = function.chunk.emit_index(0);
-        function
-            .chunk
-            .patch_jump_addr(jump_offset_addr, function.chunk.size());
+        let jump_offset_addr = self.emit_jump(function, Bytecode::JumpIfFalse);
+        self.emit_statement(function, then_branch)?;
+        let exit_offset_addr = self.emit_jump(function, Bytecode::Jump);
+        self.patch_jump(function, jump_offset_addr, function.chunk.size());
         Ok(exit_offset_addr)
     }
 
@@ -212,105 +485,353 @@ impl Compiler<'_> {
         let start_addr = function.chunk.size();
 
         self.continue_addr_stack.push(start_addr);
+        self.loop_exception_depths.push(self.exception_blocks.len());
         let start_break_addr_stack_size = self.break_addr_stack.len();
 
-        self.emit_expression(function, while_expr.condition.as_ref())?;
-        function.chunk.emit(Bytecode::JumpIfFalse);
-        let jump_offset_addr = function.chunk.emit_index(0);
+        self.emit_expression(function, &while_expr.condition)?;
+        let jump_offset_addr = self.emit_jump(function, Bytecode::JumpIfFalse);
 
         // emit body
-        self.emit_expression(function, while_expr.body.as_ref())?;
+        self.emit_statement(function, &while_expr.body)?;
 
         // next continue should not refer to this loop
         self.continue_addr_stack.pop();
+        self.loop_exception_depths.pop();
 
         // loop to the beginning
-        let chunk = &mut function.chunk;
-        chunk.emit(Bytecode::Loop);
-        chunk.emit_index(start_addr);
+        function.chunk.emit_loop(start_addr);
+
+        // a false condition runs the else branch, which breaks jump over
+        let else_addr = function.chunk.size();
+        self.patch_jump(function, jump_offset_addr, else_addr);
+        let break_addrs = self.break_addr_stack.split_off(start_break_addr_stack_size);
+        self.emit_statement(function, &while_expr.else_branch)?;
 
         // exit address
-        let exit_addr = chunk.size();
-        chunk.patch_jump_addr(jump_offset_addr, exit_addr);
+        let exit_addr = function.chunk.size();
 
         // patch break jumps
-        while self.break_addr_stack.len() > start_break_addr_stack_size {
-            let jump_offset_addr = self.break_addr_stack.pop().unwrap();
-            chunk.patch_jump_addr(jump_offset_addr, exit_addr);
+        for jump_offset_addr in break_addrs {
+            self.patch_jump(function, jump_offset_addr, exit_addr);
         }
 
         Ok(())
     }
 
     fn emit_continue_statement(&mut self, function: &mut Function) -> Result<(), CompilerError> {
-        if self.continue_addr_stack.is_empty() {
-            return Err(CompilerError::InvalidExpression(String::from(
-                "continue without loop",
-            )));
-        }
+        self.emit_exception_exits(function);
+        // `check_placement` ensured there is an enclosing loop
         let loop_start_addr = self.continue_addr_stack.last().unwrap();
-        function.chunk.emit(Bytecode::Loop);
-        function.chunk.emit_index(*loop_start_addr);
+        function.chunk.emit_loop(*loop_start_addr);
         Ok(())
     }
 
     fn emit_break_statement(&mut self, function: &mut Function) -> Result<(), CompilerError> {
-        function.chunk.emit(Bytecode::Jump);
-        let break_offset_addr = function.chunk.emit_index(0);
+        self.emit_exception_exits(function);
+        let break_offset_addr = self.emit_jump(function, Bytecode::Jump);
         self.break_addr_stack.push(break_offset_addr);
         Ok(())
     }
 
+    // forward jump `op`, returning the operand address to patch
+    fn emit_jump(&mut self, function: &mut Function, op: Bytecode) -> u64 {
+        if !self.long_jumps {
+            return function.chunk.emit_jump(op);
+        }
+        if let Bytecode::Jump = op {
+            return function.chunk.emit_jump(Bytecode::JumpLong);
+        }
+        // conditional jumps and handlers land on a long jump the other path
+        // skips
+        let short_addr = function.chunk.emit_jump(op);
+        let skip_addr = function.chunk.emit_jump(Bytecode::Jump);
+        self.patch_jump(function, short_addr, function.chunk.size());
+        let long_addr = function.chunk.emit_jump(Bytecode::JumpLong);
+        self.patch_jump(function, skip_addr, function.chunk.size());
+        long_addr
+    }
+
+    fn patch_jump(&mut self, function: &mut Function, operand_addr: u64, target_addr: u64) {
+        if function
+            .chunk
+            .patch_jump(operand_addr, target_addr)
+            .is_err()
+        {
+            self.jump_overflow = true;
+        }
+    }
+
+    // leaves the try bodies and except clauses entered since the innermost
+    // loop started
+    fn emit_exception_exits(&mut self, function: &mut Function) {
+        let loop_depth = self.loop_exception_depths.last().copied().unwrap_or(0);
+        for block in self.exception_blocks[loop_depth..].iter().rev() {
+            match block {
+                ExceptionBlock::Try => function.chunk.emit(Bytecode::PopTry),
+                ExceptionBlock::Except => function.chunk.emit(Bytecode::PopExcept),
+            }
+        }
+    }
+
+    fn emit_try_expression(
+        &mut self,
+        function: &mut Function,
+        try_expr: &TryExpression,
+    ) -> Result<(), CompilerError> {
+        let handler_offset_addr = self.emit_jump(function, Bytecode::SetupTry);
+        self.exception_blocks.push(ExceptionBlock::Try);
+        self.emit_statement(function, &try_expr.body)?;
+        self.exception_blocks.pop();
+        function.chunk.emit(Bytecode::PopTry);
+
+        // exceptions raised by the else branch aren't handled here
+        self.emit_statement(function, &try_expr.else_branch)?;
+        let mut exit_jump_addrs = vec![self.emit_jump(function, Bytecode::Jump)];
+
+        // the clauses start with the raised exception on the stack
+        self.patch_jump(function, handler_offset_addr, function.chunk.size());
+        self.exception_blocks.push(ExceptionBlock::Except);
+        for handler in try_expr.handlers.iter() {
+            let next_jump_addr = match *handler.exception_type {
+                Expression::Empty => None,
+                _ => {
+                    self.emit_expression(function, &handler.exception_type)?;
+                    function.chunk.emit(Bytecode::ExceptMatch);
+                    Some(self.emit_jump(function, Bytecode::JumpIfFalse))
+                }
+            };
+            match &handler.name {
+                Some(name) => self.emit_store_variable(function, name),
+                None => function.chunk.emit(Bytecode::Pop),
+            }
+            self.emit_statement(function, &handler.body)?;
+            function.chunk.emit(Bytecode::PopExcept);
+            exit_jump_addrs.push(self.emit_jump(function, Bytecode::Jump));
+            if let Some(next_jump_addr) = next_jump_addr {
+                self.patch_jump(function, next_jump_addr, function.chunk.size());
+            }
+        }
+        self.exception_blocks.pop();
+
+        // no clause matched, so the exception propagates
+        function.chunk.emit(Bytecode::PopExcept);
+        function.chunk.emit_with_index(Bytecode::Raise, 1);
+
+        let exit_addr = function.chunk.size();
+        exit_jump_addrs.iter().for_each(|addr| {
+            self.patch_jump(function, *addr, exit_addr);
+        });
+        Ok(())
+    }
+
+    // Raise takes the exception and the cause present, if any, so that a
+    // bare `raise` has none
+    fn emit_raise_expression(
+        &mut self,
+        function: &mut Function,
+        raise_expr: &RaiseExpression,
+    ) -> Result<(), CompilerError> {
+        let mut count = 0;
+        for expr in [&raise_expr.exception, &raise_expr.cause] {
+            if **expr != Expression::Empty {
+                self.emit_expression(function, expr)?;
+                count += 1;
+            }
+        }
+        function.chunk.emit_with_index(Bytecode::Raise, count);
+        Ok(())
+    }
+
+    // `if not test: raise AssertionError(message)`, with the builtin class
+    // whatever the script calls its own. Stripped from `-O2` builds, the
+    // test and message not running at all.
+    fn emit_assert_expression(
+        &mut self,
+        function: &mut Function,
+        assert_expr: &AssertExpression,
+    ) -> Result<(), CompilerError> {
+        if self.opt_level >= 2 {
+            return Ok(());
+        }
+        self.emit_expression(function, &assert_expr.test)?;
+        function.chunk.emit(Bytecode::Not);
+        let skip_addr = self.emit_jump(function, Bytecode::JumpIfFalse);
+        let mut args = 0;
+        if *assert_expr.message != Expression::Empty {
+            self.emit_expression(function, &assert_expr.message)?;
+            args = 1;
+        }
+        if !self.builtins.contains_name(ASSERTION_ERROR) {
+            return Err(CompilerError::NameNotFound(
+                format!("Name {} not found", ASSERTION_ERROR),
+                self.span,
+            ));
+        }
+        function.chunk.emit_with_index(
+            Bytecode::GetBuiltin,
+            self.builtins.get_index(ASSERTION_ERROR),
+        );
+        function.chunk.emit_with_index(Bytecode::Call, args);
+        function.chunk.emit_with_index(Bytecode::Raise, 1);
+        self.patch_jump(function, skip_addr, function.chunk.size());
+        Ok(())
+    }
+
     fn emit_return_expression(
         &mut self,
         function: &mut Function,
         return_expression: &ReturnExpression,
     ) -> Result<(), CompilerError> {
-        match return_expression.expr.as_ref() {
+        match *return_expression.expr {
             Expression::Empty => function.chunk.emit(Bytecode::None),
-            _ => self.emit_expression(function, return_expression.expr.as_ref())?,
+            _ => self.emit_expression(function, &return_expression.expr)?,
         };
         function.chunk.emit(Bytecode::Return);
         Ok(())
     }
 
+    fn emit_import_expression(
+        &mut self,
+        function: &mut Function,
+        import_expr: &ImportExpression,
+    ) -> Result<(), CompilerError> {
+        let index = function
+            .chunk
+            .add_constant(Value::String(import_expr.name.to_string().into()));
+        function.chunk.emit_with_index(Bytecode::Import, index);
+        self.emit_store_variable(function, &import_expr.name);
+        Ok(())
+    }
+
+    fn emit_attribute_expression(
+        &mut self,
+        function: &mut Function,
+        attribute_expr: &AttributeExpression,
+    ) -> Result<(), CompilerError> {
+        self.emit_expression(function, &attribute_expr.object)?;
+        let index = function
+            .chunk
+            .add_constant(Value::String(attribute_expr.name.to_string().into()));
+        function.chunk.emit_with_index(Bytecode::GetAttr, index);
+        Ok(())
+    }
+
+    fn emit_index_expression(
+        &mut self,
+        function: &mut Function,
+        index_expr: &IndexExpression,
+    ) -> Result<(), CompilerError> {
+        self.emit_expression(function, &index_expr.object)?;
+        self.emit_expression(function, &index_expr.index)?;
+        function.chunk.emit(Bytecode::GetItem);
+        Ok(())
+    }
+
     fn emit_assignment_op(
         &mut self,
         function: &mut Function,
         assignment_expr: &AssignmentExpression,
     ) -> Result<(), CompilerError> {
-        self.emit_expression(function, assignment_expr.rhs.as_ref())?;
-        match assignment_expr.lhs.as_ref() {
+        if self.emit_inplace_add(function, assignment_expr)? {
+            return Ok(());
+        }
+        self.emit_expression(function, &assignment_expr.rhs)?;
+        match &*assignment_expr.lhs {
             Expression::Variable(variable_expr) => {
-                if self.is_global_scope() {
-                    let index = self.get_or_declare_global(variable_expr);
-                    function.chunk.emit(Bytecode::SetGlobal);
-                    function.chunk.emit_index(index);
-                } else if function.is_global_scope() && self.globals.contains_name(variable_expr) {
-                    let index = self.globals.get_index(variable_expr);
-                    function.chunk.emit(Bytecode::SetGlobal);
-                    function.chunk.emit_index(index);
-                } else {
-                    let index = self.get_or_declare_local(variable_expr);
-                    function.chunk.emit(Bytecode::SetLocal);
-                    function.chunk.emit_index(index);
-                }
+                self.emit_store_variable(function, variable_expr)
             }
             _ => {
-                return Err(CompilerError::NameNotFound(String::from(
-                    "Assignment must set a variable",
-                )));
+                return Err(CompilerError::NameNotFound(
+                    String::from("Assignment must set a variable"),
+                    self.span,
+                ));
             }
         };
         Ok(())
     }
 
+    // `s = s + piece` updates `s` in place so that building a string in a
+    // loop doesn't copy it on every iteration. Only applies when the read
+    // and the store resolve to the same variable.
+    fn emit_inplace_add(
+        &mut self,
+        function: &mut Function,
+        assignment_expr: &AssignmentExpression,
+    ) -> Result<bool, CompilerError> {
+        let (identifier, binary_expr) = match (&*assignment_expr.lhs, &*assignment_expr.rhs) {
+            (Expression::Variable(identifier), Expression::Binary(binary_expr))
+                if binary_expr.op == Operator::Add
+                    && matches!(&*binary_expr.lhs, Expression::Variable(lhs) if lhs == identifier) =>
+            {
+                (identifier, binary_expr)
+            }
+            _ => return Ok(false),
+        };
+        let local_index = self
+            .locals
+            .iter()
+            .rposition(|local| &local.name == identifier);
+        let global_name = self.global_name(identifier);
+        let stores_global = self.is_global_scope()
+            || (function.is_global_scope() && self.globals.contains_name(&global_name));
+        let (op, index) = match (stores_global, local_index) {
+            (true, None) => match self.resolve_global(identifier) {
+                Some(index) => (Bytecode::InplaceAddGlobal, index),
+                None => return Ok(false),
+            },
+            (false, Some(index)) => (Bytecode::InplaceAddLocal, index as u64),
+            _ => return Ok(false),
+        };
+        self.emit_expression(function, &binary_expr.rhs)?;
+        function.chunk.emit_with_index(op, index);
+        Ok(true)
+    }
+
+    // Annotations are not evaluated; a bare `name: type` binds nothing
+    fn emit_annotation_expression(
+        &mut self,
+        function: &mut Function,
+        annotation_expr: &AnnotationExpression,
+    ) -> Result<(), CompilerError> {
+        match &annotation_expr.value {
+            Some(value) => {
+                self.emit_expression(function, value)?;
+                self.emit_store_variable(function, &annotation_expr.name);
+            }
+            None => function.chunk.emit(Bytecode::Nop),
+        }
+        Ok(())
+    }
+
+    fn emit_store_variable(&mut self, function: &mut Function, identifier: &String) {
+        let global_name = self.global_name(identifier);
+        if self.is_global_scope() {
+            let index = self.get_or_declare_global(&global_name);
+            function.chunk.emit_with_index(Bytecode::SetGlobal, index);
+        } else if function.is_global_scope() && self.globals.contains_name(&global_name) {
+            let index = self.globals.get_index(&global_name);
+            function.chunk.emit_with_index(Bytecode::SetGlobal, index);
+        } else {
+            // a new local's slot is the value itself, left on the stack
+            let declared = self.locals.iter().any(|local| &local.name == identifier);
+            let index = self.get_or_declare_local(identifier, function.chunk.size());
+            function.chunk.emit_with_index(Bytecode::SetLocal, index);
+            if declared {
+                function.chunk.emit(Bytecode::Pop);
+            }
+        }
+    }
+
     fn emit_unary_op(
         &mut self,
         function: &mut Function,
         unary_expr: &UnaryExpression,
     ) -> Result<(), CompilerError> {
-        self.emit_expression(function, unary_expr.expr.as_ref())?;
+        if let Some(value) = fold_unary(unary_expr, 0).filter(|_| self.opt_level >= 1) {
+            self.emit_constant(&mut function.chunk, value);
+            return Ok(());
+        }
+        self.emit_expression(function, &unary_expr.expr)?;
         self.emit_op(&mut function.chunk, &unary_expr.op)?;
         Ok(())
     }
@@ -320,88 +841,76 @@ impl Compiler<'_> {
         function: &mut Function,
         binary_expr: &BinaryExpression,
     ) -> Result<(), CompilerError> {
-        self.emit_expression(function, binary_expr.lhs.as_ref())?;
-        self.emit_expression(function, binary_expr.rhs.as_ref())?;
-        self.emit_op(&mut function.chunk, &binary_expr.op)?;
+        if let Some(value) = fold_binary(binary_expr, 0).filter(|_| self.opt_level >= 1) {
+            self.emit_constant(&mut function.chunk, value);
+            return Ok(());
+        }
+        // `a + b + ... + z` nests on the left as deep as the chain is long,
+        // so walk down the left operands instead of recursing into them
+        let mut chain = vec![binary_expr];
+        let mut lhs = &binary_expr.lhs;
+        while let Expression::Binary(binary_expr) = &**lhs {
+            chain.push(binary_expr);
+            lhs = &binary_expr.lhs;
+        }
+        self.emit_expression(function, lhs)?;
+        for binary_expr in chain.iter().rev() {
+            self.emit_expression(function, &binary_expr.rhs)?;
+            self.emit_op(&mut function.chunk, &binary_expr.op)?;
+        }
         Ok(())
     }
 
+    // Names resolve as locals, then (module) globals, then builtins, so a
+    // script may shadow any builtin with its own definition.
     fn emit_variable_op(
         &mut self,
         function: &mut Function,
         identifier: &String,
     ) -> Result<(), CompilerError> {
-        if self.is_global_scope() {
-            let index: u64;
-            if self.globals.contains_name(identifier) {
-                index = self.globals.get_index(identifier);
-            } else {
-                return Err(CompilerError::NameNotFound(format!(
-                    "Name {} not found",
-                    identifier
-                )));
-            }
-            function.chunk.emit(Bytecode::GetGlobal);
-            function.chunk.emit_index(index);
+        let local_index = self
+            .locals
+            .iter()
+            .rposition(|local| &local.name == identifier);
+        if let Some(index) = local_index {
+            function
+                .chunk
+                .emit_with_index(Bytecode::GetLocal, index as u64);
+        } else if let Some(index) = self.resolve_global(identifier) {
+            function.chunk.emit_with_index(Bytecode::GetGlobal, index);
+        } else if self.builtins.contains_name(identifier) {
+            function
+                .chunk
+                .emit_with_index(Bytecode::GetBuiltin, self.builtins.get_index(identifier));
         } else {
-            if function.is_global_scope() && self.globals.contains_name(identifier) {
-                let index = self.globals.get_index(identifier);
-                function.chunk.emit(Bytecode::GetGlobal);
-                function.chunk.emit_index(index);
-            } else if let Some(index) = self
-                .locals
-                .iter()
-                .rposition(|local| &local.name == identifier)
-            {
-                function.chunk.emit(Bytecode::GetLocal);
-                function.chunk.emit_index(index as u64);
-            } else if &function.name == identifier {
-                // recursive call
-                let index = self.globals.get_index(identifier);
-                function.chunk.emit(Bytecode::GetGlobal);
-                function.chunk.emit_index(index);
-            } else {
-                return Err(CompilerError::NameNotFound(format!(
-                    "Name {} not found",
-                    identifier
-                )));
-            }
+            return Err(CompilerError::NameNotFound(
+                format!("Name {} not found", identifier),
+                self.span,
+            ));
         }
         Ok(())
     }
 
     fn emit_op(&self, chunk: &mut Chunk, op: &Operator) -> Result<(), CompilerError> {
-        match op {
-            Operator::Not => chunk.emit(Bytecode::Not),
-            Operator::Neg => chunk.emit(Bytecode::Neg),
-            Operator::And => chunk.emit(Bytecode::And),
-            Operator::Or => chunk.emit(Bytecode::Or),
-            Operator::Equal => chunk.emit(Bytecode::Equal),
-            Operator::NotEqual => chunk.emit(Bytecode::NotEqual),
-            Operator::Less => chunk.emit(Bytecode::Less),
-            Operator::LessEqual => chunk.emit(Bytecode::LessEqual),
-            Operator::Greater => chunk.emit(Bytecode::Greater),
-            Operator::GreaterEqual => chunk.emit(Bytecode::GreaterEqual),
-            Operator::Add => chunk.emit(Bytecode::Add),
-            Operator::Sub => chunk.emit(Bytecode::Sub),
-            Operator::Mul => chunk.emit(Bytecode::Mul),
-            Operator::Div => chunk.emit(Bytecode::Div),
-        }
+        chunk.emit(operator_bytecode(op));
         Ok(())
     }
 
     fn emit_literal(&self, chunk: &mut Chunk, literal: &Literal) -> Result<(), CompilerError> {
-        match literal {
-            Literal::None => chunk.emit(Bytecode::None),
-            Literal::True => chunk.emit(Bytecode::True),
-            Literal::False => chunk.emit(Bytecode::False),
-            _ => {
-                let index = chunk.add_constant(literal);
-                chunk.emit(Bytecode::Const);
-                chunk.emit_index(index as u64);
+        self.emit_constant(chunk, Value::from_literal(literal));
+        Ok(())
+    }
+
+    fn emit_constant(&self, chunk: &mut Chunk, value: Value) {
+        match value {
+            Value::None => chunk.emit(Bytecode::None),
+            Value::Bool(true) => chunk.emit(Bytecode::PushTrue),
+            Value::Bool(false) => chunk.emit(Bytecode::PushFalse),
+            value => {
+                let index = chunk.add_constant(value);
+                chunk.emit_with_index(Bytecode::Const, index);
             }
         }
-        Ok(())
     }
 
     fn is_global_scope(&self) -> bool {
@@ -412,7 +921,48 @@ impl Compiler<'_> {
         self.scope_depth += 1;
     }
 
-    fn get_or_declare_global(&mut self, identifier: &String) -> u64 {
+    fn global_name(&self, identifier: &str) -> String {
+        match &self.module {
+            Some(module) => format!("{}.{}", module, identifier),
+            None => identifier.to_string(),
+        }
+    }
+
+    fn resolve_global(&self, identifier: &str) -> Option<u64> {
+        let global_name = self.global_name(identifier);
+        if self.globals.contains_name(&global_name) {
+            Some(self.globals.get_index(&global_name))
+        } else {
+            None
+        }
+    }
+
+    // `__name__` is "__main__" for the entry script, the module name otherwise
+    fn declare_module_name(&mut self) {
+        let global_name = self.global_name(MODULE_NAME_GLOBAL);
+        let module_name = match &self.module {
+            Some(module) => module.to_string(),
+            None => String::from(MAIN_MODULE_NAME),
+        };
+        let index = self.get_or_declare_global(&global_name);
+        self.globals.set(
+            index,
+            Object::new_with_id(index, global_name, Value::String(module_name.into())),
+        );
+    }
+
+    fn declare_module_doc(&mut self, doc: &Option<String>) {
+        let global_name = self.global_name(DOC_GLOBAL);
+        let value = match doc {
+            Some(doc) => Value::String(doc.to_string().into()),
+            None => Value::None,
+        };
+        let index = self.get_or_declare_global(&global_name);
+        self.globals
+            .set(index, Object::new_with_id(index, global_name, value));
+    }
+
+    fn get_or_declare_global(&mut self, identifier: &str) -> u64 {
         if self.globals.contains_name(identifier) {
             self.globals.get_index(identifier)
         } else {
@@ -420,21 +970,22 @@ impl Compiler<'_> {
         }
     }
 
-    fn get_or_declare_local(&mut self, identifier: &String) -> u64 {
+    fn get_or_declare_local(&mut self, identifier: &String, start: u64) -> u64 {
         match self
             .locals
             .iter()
             .rposition(|local| &local.name == identifier)
         {
             Some(index) => index as u64,
-            _ => self.declare_local(identifier),
+            _ => self.declare_local(identifier, start),
         }
     }
 
-    fn declare_local(&mut self, identifier: &String) -> u64 {
+    fn declare_local(&mut self, identifier: &String, start: u64) -> u64 {
         self.locals.push(Local {
             name: identifier.to_string(),
             depth: self.scope_depth,
+            start,
         });
         (self.locals.len() - 1) as u64
     }
@@ -442,10 +993,167 @@ impl Compiler<'_> {
     fn end_scope(&mut self, function: &mut Function) {
         self.scope_depth -= 1;
 
+        let end = function.chunk.size();
         while !self.locals.is_empty() && self.locals[self.locals.len() - 1].depth > self.scope_depth
         {
             function.chunk.emit(Bytecode::Pop);
-            self.locals.pop();
+            if let Some(local) = self.locals.pop() {
+                function.chunk.locals.push((local.name, local.start, end));
+            }
+        }
+    }
+}
+
+// A body starting with a string literal documents its function or module
+fn operator_bytecode(op: &Operator) -> Bytecode {
+    match op {
+        Operator::Not => Bytecode::Not,
+        Operator::Neg => Bytecode::Neg,
+        Operator::And => Bytecode::And,
+        Operator::Or => Bytecode::Or,
+        Operator::Equal => Bytecode::Equal,
+        Operator::NotEqual => Bytecode::NotEqual,
+        Operator::Less => Bytecode::Less,
+        Operator::LessEqual => Bytecode::LessEqual,
+        Operator::Greater => Bytecode::Greater,
+        Operator::GreaterEqual => Bytecode::GreaterEqual,
+        Operator::Add => Bytecode::Add,
+        Operator::Sub => Bytecode::Sub,
+        Operator::Mul => Bytecode::Mul,
+        Operator::Div => Bytecode::Div,
+        Operator::FloorDiv => Bytecode::FloorDiv,
+        Operator::Mod => Bytecode::Mod,
+    }
+}
+
+// Value of an expression made of literals only. Expressions that fail, or
+// would make huge constants, aren't folded so they fail or run at runtime.
+fn constant_value(expr: &Expression, depth: usize) -> Option<Value> {
+    match expr {
+        _ if depth > MAX_COMPILE_DEPTH => None,
+        Expression::Literal(literal) => Some(Value::from_literal(literal)),
+        Expression::Unary(unary_expr) => fold_unary(unary_expr, depth + 1),
+        Expression::Binary(binary_expr) => fold_binary(binary_expr, depth + 1),
+        _ => None,
+    }
+}
+
+fn fold_unary(unary_expr: &UnaryExpression, depth: usize) -> Option<Value> {
+    let rhs = constant_value(&unary_expr.expr, depth)?;
+    unary_op(&operator_bytecode(&unary_expr.op), &rhs).ok()
+}
+
+/// Longest string or bytes constant folding produces.
+const MAX_FOLDED_LENGTH: usize = 4096;
+
+fn fold_binary(binary_expr: &BinaryExpression, depth: usize) -> Option<Value> {
+    // left operand chains are walked like `emit_binary_op` does
+    let mut chain = vec![binary_expr];
+    let mut lhs = &binary_expr.lhs;
+    while let Expression::Binary(binary_expr) = &**lhs {
+        chain.push(binary_expr);
+        lhs = &binary_expr.lhs;
+    }
+    let mut value = constant_value(lhs, depth)?;
+    for binary_expr in chain.iter().rev() {
+        let rhs = constant_value(&binary_expr.rhs, depth)?;
+        value = fold_binary_op(&binary_expr.op, value, rhs)?;
+    }
+    Some(value)
+}
+
+fn fold_binary_op(op: &Operator, lhs: Value, rhs: Value) -> Option<Value> {
+    let op = operator_bytecode(op);
+    let folded_length = |value: &Value| match value {
+        Value::String(value) => value.len(),
+        Value::Bytes(value) => value.len(),
+        _ => 0,
+    };
+    match (&op, &lhs, &rhs) {
+        // formatting may pad to any width
+        (Bytecode::Mod, Value::String(_), _) => return None,
+        (Bytecode::Mul, sequence, Value::Integer(count))
+        | (Bytecode::Mul, Value::Integer(count), sequence) => {
+            let length = folded_length(sequence).checked_mul((*count).max(0) as usize)?;
+            if length > MAX_FOLDED_LENGTH {
+                return None;
+            }
+        }
+        _ => {}
+    }
+    let result = match op {
+        Bytecode::Add
+        | Bytecode::Sub
+        | Bytecode::Mul
+        | Bytecode::Div
+        | Bytecode::FloorDiv
+        | Bytecode::Mod => binary_op(&op, &lhs, &rhs),
+        _ => logic_op(&op, &lhs, &rhs),
+    }
+    .ok()?;
+    if folded_length(&result) > MAX_FOLDED_LENGTH {
+        return None;
+    }
+    Some(result)
+}
+
+fn docstring(exprs: &[Node]) -> Option<String> {
+    match exprs.first().map(|expr| &**expr) {
+        Some(Expression::Literal(Literal::String(doc))) => Some(doc.to_string()),
+        _ => None,
+    }
+}
+
+/// Rejects `return` outside of a function and `break` or `continue` outside
+/// of a loop before anything is emitted. A function body starts outside of
+/// any loop, even when it is defined in one.
+fn check_placement(exprs: &[Node]) -> Result<(), CompilerError> {
+    exprs
+        .iter()
+        .try_for_each(|expr| check_statement_placement(expr, false, false))
+}
+
+fn check_statement_placement(
+    expr: &Node,
+    in_function: bool,
+    in_loop: bool,
+) -> Result<(), CompilerError> {
+    let check = |expr: &Node| check_statement_placement(expr, in_function, in_loop);
+    let misplaced = |message: &str| {
+        Err(CompilerError::InvalidExpression(
+            message.to_string(),
+            expr.span,
+        ))
+    };
+    match &**expr {
+        Expression::Return(_) if !in_function => misplaced("'return' outside function"),
+        Expression::Break if !in_loop => misplaced("'break' outside loop"),
+        Expression::Continue if !in_loop => misplaced("'continue' not properly in loop"),
+        Expression::Block(block) => block.exprs.iter().try_for_each(check),
+        Expression::Function(function) => function
+            .body
+            .exprs
+            .iter()
+            .try_for_each(|expr| check_statement_placement(expr, true, false)),
+        Expression::If(if_expr) => {
+            check(&if_expr.then_branch)?;
+            for elif in if_expr.elif_branches.iter() {
+                check(&elif.then_branch)?;
+            }
+            check(&if_expr.else_branch)
+        }
+        Expression::While(while_expr) => {
+            check_statement_placement(&while_expr.body, in_function, true)?;
+            // the else branch runs once the loop is over
+            check(&while_expr.else_branch)
+        }
+        Expression::Try(try_expr) => {
+            check(&try_expr.body)?;
+            for handler in try_expr.handlers.iter() {
+                check(&handler.body)?;
+            }
+            check(&try_expr.else_branch)
         }
+        _ => Ok(()),
     }
 }
@@ -1,10 +1,82 @@
+use std::path::PathBuf;
+
 #[derive(Clone, Debug)]
 pub struct Config {
     pub trace: bool,
+    // Directories searched by `import` before RUSTPYPATH
+    pub search_path: Vec<PathBuf>,
+    // Sandbox limits, None means unlimited
+    pub max_output_bytes: Option<usize>,
+    pub max_print_calls: Option<usize>,
+    // Parser limits guarding against deeply nested or huge sources
+    pub max_parse_depth: Option<usize>,
+    pub max_tokens: Option<usize>,
+    // Values the VM operand stack may hold, separate from the call depth
+    pub max_stack_size: Option<usize>,
+    // raise TypeError when arguments or return values don't match the
+    // function annotations
+    pub check_annotations: bool,
+    // record the results of nondeterministic natives for replaying the run
+    pub deterministic: bool,
+    // count executed opcodes and calls per call site
+    pub profile_opcodes: bool,
+    // rewrite compiled chunks with the peephole optimizer
+    pub peephole: bool,
+    // compiler passes to run: none at 0, constant folding and dead code
+    // elimination at 1, the peephole optimizer and stripping asserts too
+    // at 2
+    pub opt_level: u8,
+    // run the functions the register VM supports on it, experimental
+    pub register_backend: bool,
+    // with the jit feature, compile functions to native code once called
+    // this many times
+    pub jit_threshold: Option<usize>,
+    // sandbox: let scripts open files with `open()`
+    pub allow_file_io: bool,
+    // reuse the bytecode `Interpreter::run_script` caches next to scripts
+    pub cache_bytecode: bool,
+    // script path and arguments, `sys.argv`
+    pub argv: Vec<String>,
+}
+
+/// Nesting the parser accepts by default, well within the main thread stack.
+pub const DEFAULT_MAX_PARSE_DEPTH: usize = 200;
+
+/// Optimization level scripts compile at by default, `-O1`.
+pub const DEFAULT_OPT_LEVEL: u8 = 1;
+
+/// Highest optimization level, `-O2`.
+pub const MAX_OPT_LEVEL: u8 = 2;
+
+/// Operand stack size the VM allows by default, a few hundred MB of values.
+pub const DEFAULT_MAX_STACK_SIZE: usize = 1 << 22;
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Config {
     pub fn new() -> Config {
-        Config { trace: false }
+        Config {
+            trace: false,
+            search_path: Vec::new(),
+            max_output_bytes: None,
+            max_print_calls: None,
+            max_parse_depth: Some(DEFAULT_MAX_PARSE_DEPTH),
+            max_tokens: None,
+            max_stack_size: Some(DEFAULT_MAX_STACK_SIZE),
+            check_annotations: false,
+            deterministic: false,
+            profile_opcodes: false,
+            peephole: false,
+            opt_level: DEFAULT_OPT_LEVEL,
+            register_backend: false,
+            jit_threshold: None,
+            allow_file_io: false,
+            cache_bytecode: false,
+            argv: Vec::new(),
+        }
     }
 }
@@ -0,0 +1,359 @@
+use std::fmt::Display;
+
+use crate::compiler::CompilerError;
+use crate::exception::Exception;
+use crate::parser::ParserError;
+use crate::vm::VmError;
+use crate::InterpreterError;
+
+/// Byte range `start..end` into the source the diagnostic refers to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// 1-based position, columns count characters rather than bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LineCol {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    // None when the location is unknown, e.g. for errors raised at runtime
+    pub span: Option<Span>,
+    pub severity: Severity,
+    pub message: String,
+    // Python exception name, e.g. "SyntaxError" or "NameError"
+    pub code: &'static str,
+    // hint on how to fix the error, shown below the source
+    pub help: Option<String>,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    /// Text of the 1-based `line` of `source` without its indentation, None
+    /// past the last line.
+    pub fn line(source: &str, line: usize) -> Option<Span> {
+        let start = match line {
+            0 => return None,
+            1 => 0,
+            _ => source.match_indices('\n').nth(line - 2)?.0 + 1,
+        };
+        let text = source[start..].split('\n').next().unwrap_or_default();
+        let indent = text.len() - text.trim_start().len();
+        Some(Span::new(start + indent, start + text.trim_end().len()))
+    }
+
+    /// Start and end positions of the span within `source`.
+    pub fn line_col(&self, source: &str) -> (LineCol, LineCol) {
+        (line_col(source, self.start), line_col(source, self.end))
+    }
+}
+
+/// Translates a byte offset into `source` to a line and column. Offsets past
+/// the end map to the end of the source, offsets inside a multi-byte
+/// character map to that character.
+pub fn line_col(source: &str, offset: usize) -> LineCol {
+    let mut position = LineCol { line: 1, column: 1 };
+    for (index, chr) in source.char_indices() {
+        if index + chr.len_utf8() > offset {
+            break;
+        }
+        if chr == '\n' {
+            position.line += 1;
+            position.column = 1;
+        } else {
+            position.column += 1;
+        }
+    }
+    position
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: &str) -> Diagnostic {
+        Diagnostic {
+            span: None,
+            severity: Severity::Error,
+            message: message.to_string(),
+            code,
+            help: None,
+        }
+    }
+
+    pub fn with_span(mut self, span: Span) -> Diagnostic {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_help(mut self, help: &str) -> Diagnostic {
+        self.help = Some(help.to_string());
+        self
+    }
+
+    /// Renders the diagnostic for a terminal, quoting the line of `source`
+    /// the span starts on with carets under the span:
+    ///
+    /// ```text
+    /// error[SyntaxError]: If expression missing colon ':'
+    ///  --> script.py:1:9
+    ///   |
+    /// 1 | if x > 0
+    ///   |         ^
+    /// ```
+    /// ```
+    pub fn render(&self, path: &str, source: &str) -> String {
+        let mut lines = vec![self.to_string()];
+        let Some(span) = self.span else {
+            lines.push(format!(" --> {}", path));
+            if let Some(help) = &self.help {
+                lines.push(format!(" = help: {}", help));
+            }
+            return lines.join("\n") + "\n";
+        };
+        let (start, end) = span.line_col(source);
+        let text = source.lines().nth(start.line - 1).unwrap_or_default();
+        // spans running past the line are underlined up to its end, empty
+        // ones get a single caret
+        let width = match end.line == start.line {
+            true => end.column.saturating_sub(start.column),
+            false => (text.chars().count() + 1).saturating_sub(start.column),
+        };
+        let gutter = " ".repeat(start.line.to_string().len());
+        lines.push(format!(
+            "{}--> {}:{}:{}",
+            gutter, path, start.line, start.column
+        ));
+        lines.push(format!("{} |", gutter));
+        lines.push(format!("{} | {}", start.line, text));
+        lines.push(format!(
+            "{} | {}{}",
+            gutter,
+            " ".repeat(start.column - 1),
+            "^".repeat(width.max(1))
+        ));
+        if let Some(help) = &self.help {
+            lines.push(format!("{} = help: {}", gutter, help));
+        }
+        lines.join("\n") + "\n"
+    }
+}
+
+const NAME_HELP: &str = "assign, import or define the name before using it";
+const SPLIT_HELP: &str = "split the expression into smaller ones";
+
+impl InterpreterError {
+    pub fn diagnostic(&self) -> Diagnostic {
+        match self {
+            InterpreterError::LexerError(message, span) => {
+                let (code, message) = match message.strip_prefix("IndentationError: ") {
+                    Some(message) => ("IndentationError", message),
+                    None => (
+                        "SyntaxError",
+                        message.strip_prefix("SyntaxError: ").unwrap_or(message),
+                    ),
+                };
+                Diagnostic::error(code, message).with_span(*span)
+            }
+            InterpreterError::ParserError(err) => match err {
+                ParserError::InvalidOperator(message, span)
+                | ParserError::InvalidPrimary(message, span)
+                | ParserError::InvalidExpression(message, span)
+                | ParserError::DuplicateArgument(message, span)
+                | ParserError::DuplicateKeyword(message, span) => {
+                    Diagnostic::error("SyntaxError", message).with_span(*span)
+                }
+                ParserError::LimitExceeded(message, span) => {
+                    Diagnostic::error("RecursionError", message)
+                        .with_span(*span)
+                        .with_help(SPLIT_HELP)
+                }
+            },
+            InterpreterError::CompilerError(err) => match err {
+                CompilerError::NameNotFound(message, span) => {
+                    Diagnostic::error("NameError", message)
+                        .with_span(*span)
+                        .with_help(NAME_HELP)
+                }
+                CompilerError::InvalidExpression(message, span) => {
+                    Diagnostic::error("SyntaxError", message).with_span(*span)
+                }
+                CompilerError::LimitExceeded(message, span) => {
+                    Diagnostic::error("RecursionError", message)
+                        .with_span(*span)
+                        .with_help(SPLIT_HELP)
+                }
+            },
+            // the exception an uncaught error stands for has the precise class,
+            // e.g. OverflowError for some InvalidOperand errors
+            InterpreterError::VmError(err) => match Exception::from_error(err) {
+                Some(exception) => {
+                    let diagnostic = Diagnostic::error(exception.class, &exception.message());
+                    match exception.class {
+                        "NameError" => diagnostic.with_help(NAME_HELP),
+                        _ => diagnostic,
+                    }
+                }
+                None => match err {
+                    VmError::ResourceLimitExceeded(message) => {
+                        Diagnostic::error("ResourceLimitExceeded", message)
+                            .with_help("the limits are set in the interpreter configuration")
+                    }
+                    VmError::ReplayDiverged(message) => {
+                        Diagnostic::error("ReplayDiverged", message)
+                    }
+                    VmError::PermissionError(message) => Diagnostic::error(
+                        "PermissionError",
+                        message.strip_prefix("PermissionError: ").unwrap_or(message),
+                    ),
+                    VmError::SystemExit(status) => {
+                        Diagnostic::error("SystemExit", &status.to_string())
+                    }
+                    VmError::InvalidBytecode(message) => Diagnostic::error("SystemError", message),
+                    // the rest stand for exceptions
+                    other => Diagnostic::error("SystemError", &format!("{:?}", other)),
+                },
+            },
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        };
+        match self.message.as_str() {
+            "" => write!(f, "{}[{}]", severity, self.code),
+            message => write!(f, "{}[{}]: {}", severity, self.code, message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_col() {
+        vec![
+            ("", 0, (1, 1)),
+            ("abc", 0, (1, 1)),
+            ("abc", 2, (1, 3)),
+            ("abc", 3, (1, 4)),
+            ("abc", 10, (1, 4)),
+            ("a\nbc", 2, (2, 1)),
+            ("a\nbc", 3, (2, 2)),
+            ("a\n\nb", 3, (3, 1)),
+            // 'é' is two bytes wide but a single column
+            ("é = 1", 2, (1, 2)),
+            ("é = 1", 1, (1, 1)),
+        ]
+        .into_iter()
+        .for_each(|(source, offset, (line, column))| {
+            assert_eq!(line_col(source, offset), LineCol { line, column });
+        });
+    }
+
+    #[test]
+    fn test_diagnostic_codes() {
+        vec![
+            (
+                InterpreterError::LexerError(
+                    String::from("Error: invalid character: $"),
+                    Span::new(4, 5),
+                ),
+                "SyntaxError",
+            ),
+            (
+                InterpreterError::LexerError(
+                    String::from("IndentationError: unindent does not match any outer indentation level at line 3"),
+                    Span::new(10, 12),
+                ),
+                "IndentationError",
+            ),
+            (
+                InterpreterError::CompilerError(CompilerError::NameNotFound(
+                    String::from("Name x not found"),
+                    Span::new(0, 1),
+                )),
+                "NameError",
+            ),
+            (
+                InterpreterError::VmError(VmError::WrongArgumentCount(String::from(
+                    "Function: f expect 1 arguments, 2 given.",
+                ))),
+                "TypeError",
+            ),
+        ]
+        .into_iter()
+        .for_each(|(err, code)| {
+            let diagnostic = err.diagnostic();
+            assert_eq!(diagnostic.code, code);
+            assert_eq!(diagnostic.severity, Severity::Error);
+        });
+    }
+
+    #[test]
+    fn test_span_line() {
+        let source = "x = 1\nif x:\n    y = 2  \n";
+        vec![
+            (0, None),
+            (1, Some(Span::new(0, 5))),
+            (2, Some(Span::new(6, 11))),
+            (3, Some(Span::new(16, 21))),
+            (4, Some(Span::new(24, 24))),
+            (5, None),
+        ]
+        .into_iter()
+        .for_each(|(line, span)| assert_eq!(Span::line(source, line), span, "{}", line));
+    }
+
+    #[test]
+    fn test_render() {
+        let source = "x = 1\nif x > 0\n    print(y)\n";
+        vec![
+            (
+                Diagnostic::error("SyntaxError", "If expression missing colon ':'")
+                    .with_span(Span::new(14, 14)),
+                "error[SyntaxError]: If expression missing colon ':'\n \
+                 --> main.py:2:9\n  |\n2 | if x > 0\n  |         ^\n",
+            ),
+            (
+                Diagnostic::error("NameError", "Name y not found")
+                    .with_span(Span::new(25, 26))
+                    .with_help(NAME_HELP),
+                "error[NameError]: Name y not found\n \
+                 --> main.py:3:11\n  |\n3 |     print(y)\n  |           ^\n  \
+                 = help: assign, import or define the name before using it\n",
+            ),
+            // spans over several lines are underlined to the end of the first
+            (
+                Diagnostic::error("SyntaxError", "message").with_span(Span::new(6, 20)),
+                "error[SyntaxError]: message\n --> main.py:2:1\n  |\n2 | if x > 0\n  | ^^^^^^^^\n",
+            ),
+            (
+                Diagnostic::error("ValueError", ""),
+                "error[ValueError]\n --> main.py\n",
+            ),
+        ]
+        .into_iter()
+        .for_each(|(diagnostic, expected)| {
+            assert_eq!(diagnostic.render("main.py", source), expected);
+        });
+    }
+}
@@ -0,0 +1,197 @@
+use std::fmt::Display;
+
+use crate::object::Value;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValueDiff {
+    Equal,
+    TypeMismatch {
+        path: String,
+        expected: Value,
+        actual: Value,
+    },
+    ValueMismatch {
+        path: String,
+        expected: Value,
+        actual: Value,
+    },
+    StringMismatch {
+        path: String,
+        index: usize,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl ValueDiff {
+    pub fn is_equal(&self) -> bool {
+        matches!(self, ValueDiff::Equal)
+    }
+}
+
+impl Value {
+    /// Structurally compares `self` (the expected value) against `actual`.
+    pub fn diff(&self, actual: &Value) -> ValueDiff {
+        diff_at(String::from("$"), self, actual)
+    }
+}
+
+fn diff_at(path: String, expected: &Value, actual: &Value) -> ValueDiff {
+    if expected.type_name() != actual.type_name() {
+        return ValueDiff::TypeMismatch {
+            path,
+            expected: expected.clone(),
+            actual: actual.clone(),
+        };
+    }
+    match (expected, actual) {
+        (Value::String(expected), Value::String(actual)) => {
+            match first_mismatch(expected, actual) {
+                Some(index) => ValueDiff::StringMismatch {
+                    path,
+                    index,
+                    expected: expected.to_string(),
+                    actual: actual.to_string(),
+                },
+                None => ValueDiff::Equal,
+            }
+        }
+        (Value::List(expected_items), Value::List(actual_items))
+        | (Value::Tuple(expected_items), Value::Tuple(actual_items))
+            if expected_items.len() == actual_items.len() =>
+        {
+            expected_items
+                .iter()
+                .zip(actual_items.iter())
+                .enumerate()
+                .map(|(index, (expected, actual))| {
+                    diff_at(format!("{}[{}]", path, index), expected, actual)
+                })
+                .find(|diff| !diff.is_equal())
+                .unwrap_or(ValueDiff::Equal)
+        }
+        _ if expected == actual => ValueDiff::Equal,
+        _ => ValueDiff::ValueMismatch {
+            path,
+            expected: expected.clone(),
+            actual: actual.clone(),
+        },
+    }
+}
+
+fn first_mismatch(expected: &str, actual: &str) -> Option<usize> {
+    let mut expected_chars = expected.chars();
+    let mut actual_chars = actual.chars();
+    let mut index = 0;
+    loop {
+        match (expected_chars.next(), actual_chars.next()) {
+            (None, None) => return None,
+            (Some(lhs), Some(rhs)) if lhs == rhs => index += 1,
+            _ => return Some(index),
+        }
+    }
+}
+
+impl Display for ValueDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Equal => write!(f, "values are equal"),
+            Self::TypeMismatch {
+                path,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "at {}: expected {} {}, got {} {}",
+                path,
+                expected.type_name(),
+                expected,
+                actual.type_name(),
+                actual
+            ),
+            Self::ValueMismatch {
+                path,
+                expected,
+                actual,
+            } => write!(f, "at {}: expected {}, got {}", path, expected, actual),
+            Self::StringMismatch {
+                path,
+                index,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "at {}: strings differ at index {}\n  expected: {:?}\n  actual:   {:?}",
+                path, index, expected, actual
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        vec![
+            (Value::Integer(1), Value::Integer(1), true),
+            (Value::Bool(true), Value::Bool(true), true),
+            (Value::Integer(1), Value::Integer(2), false),
+            (Value::Integer(1), Value::Float(1.0), false),
+            (
+                Value::String(String::from("abc").into()),
+                Value::String(String::from("abc").into()),
+                true,
+            ),
+            (
+                Value::String(String::from("abc").into()),
+                Value::String(String::from("abd").into()),
+                false,
+            ),
+        ]
+        .into_iter()
+        .for_each(|(expected, actual, is_equal)| {
+            assert_eq!(expected.diff(&actual).is_equal(), is_equal);
+        });
+    }
+
+    #[test]
+    fn test_diff_rendering() {
+        vec![
+            (
+                Value::Integer(1),
+                Value::Integer(2),
+                "at $: expected 1, got 2",
+            ),
+            (
+                Value::Integer(1),
+                Value::String(String::from("1").into()),
+                "at $: expected int 1, got str 1",
+            ),
+            (
+                Value::String(String::from("hello").into()),
+                Value::String(String::from("help").into()),
+                "at $: strings differ at index 3\n  expected: \"hello\"\n  actual:   \"help\"",
+            ),
+            (
+                Value::List(
+                    vec![Value::Integer(1), Value::String(String::from("ab").into())].into(),
+                ),
+                Value::List(
+                    vec![Value::Integer(1), Value::String(String::from("ac").into())].into(),
+                ),
+                "at $[1]: strings differ at index 1\n  expected: \"ab\"\n  actual:   \"ac\"",
+            ),
+            (
+                Value::List(vec![Value::Integer(1)].into()),
+                Value::List(vec![].into()),
+                "at $: expected [1], got []",
+            ),
+        ]
+        .into_iter()
+        .for_each(|(expected, actual, rendered)| {
+            assert_eq!(expected.diff(&actual).to_string(), rendered);
+        });
+    }
+}
@@ -1,22 +1,34 @@
 use std::fmt::Debug;
 
-use crate::bytecode::{Bytecode, SIZE_INDEX, SIZE_INSTRUCTION};
+use crate::bytecode::{Bytecode, SIZE_INDEX, SIZE_INSTRUCTION, SIZE_JUMP, SIZE_NARROW_INDEX};
 use crate::chunk::Chunk;
 
 pub struct Instruction {
     ip: usize,
     op: Bytecode,
     index: Option<u64>,
+    // constant index of superinstructions
+    constant: Option<u64>,
+    // source line, on the first instruction of each line
+    line: Option<usize>,
 }
 
 impl Debug for Instruction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{:>4} ", line)?,
+            None => write!(f, "     ")?,
+        }
         match self.index {
             Some(index) => f.write_fmt(format_args!(
                 "{:08X}: {:?}, dec: {} hex: Ox{:X}",
                 self.ip, self.op, index, index
-            )),
-            None => f.write_fmt(format_args!("{:08X}: {:?}", self.ip, self.op)),
+            ))?,
+            None => f.write_fmt(format_args!("{:08X}: {:?}", self.ip, self.op))?,
+        }
+        match self.constant {
+            Some(constant) => write!(f, ", const: {}", constant),
+            None => Ok(()),
         }
     }
 }
@@ -34,95 +46,69 @@ impl Disassembler {
         let mut result: Vec<Instruction> = Vec::new();
         let mut ip = 0;
         while ip < self.chunk.data.len() {
-            let op = self.chunk.data[ip];
-            let op = match Bytecode::try_from(op) {
-                Ok(op) => op,
-                Err(_) => {
-                    result.push(Instruction {
-                        ip,
-                        op: Bytecode::Unknown,
-                        index: None,
-                    });
-                    continue;
-                }
+            let Some(decoded) = self.chunk.decode(ip) else {
+                result.push(Instruction {
+                    ip,
+                    op: Bytecode::Unknown,
+                    index: None,
+                    constant: None,
+                    line: None,
+                });
+                ip += SIZE_INSTRUCTION;
+                continue;
             };
-            match op {
-                Bytecode::Nop | Bytecode::None | Bytecode::True | Bytecode::False => {
-                    result.push(Instruction {
-                        ip,
-                        op,
-                        index: None,
-                    });
-                    ip += SIZE_INSTRUCTION;
-                }
-
-                Bytecode::Const => {
-                    result.push(Instruction {
-                        ip,
-                        op,
-                        index: self.chunk.get_data_u64_safe(ip + SIZE_INSTRUCTION),
-                    });
-                    ip += SIZE_INSTRUCTION + SIZE_INDEX;
-                }
-                Bytecode::SetGlobal => {
-                    result.push(Instruction {
-                        ip,
-                        op,
-                        index: self.chunk.get_data_u64_safe(ip + SIZE_INSTRUCTION),
-                    });
-                    ip += SIZE_INSTRUCTION + SIZE_INDEX;
-                }
-                Bytecode::GetGlobal => {
-                    result.push(Instruction {
-                        ip,
-                        op,
-                        index: self.chunk.get_data_u64_safe(ip + SIZE_INSTRUCTION),
-                    });
-                    ip += SIZE_INSTRUCTION + SIZE_INDEX;
-                }
-
-                Bytecode::Jump | Bytecode::JumpIfFalse | Bytecode::Loop => {
-                    result.push(Instruction {
-                        ip,
-                        op,
-                        index: self.chunk.get_data_u64_safe(ip + SIZE_INSTRUCTION),
-                    });
-                    ip += SIZE_INSTRUCTION + SIZE_INDEX;
-                }
-
-                Bytecode::Call
-                | Bytecode::Return
-                | Bytecode::Not
-                | Bytecode::Neg
-                | Bytecode::And
-                | Bytecode::Or
-                | Bytecode::Equal
-                | Bytecode::NotEqual
-                | Bytecode::Less
-                | Bytecode::LessEqual
-                | Bytecode::Greater
-                | Bytecode::GreaterEqual
-                | Bytecode::Add
-                | Bytecode::Sub
-                | Bytecode::Mul
-                | Bytecode::Div => {
-                    result.push(Instruction {
-                        ip,
-                        op,
-                        index: None,
-                    });
-                    ip += SIZE_INSTRUCTION;
-                }
-                _ => {
-                    result.push(Instruction {
-                        ip,
-                        op: Bytecode::Unknown,
-                        index: None,
-                    });
-                    ip += SIZE_INSTRUCTION;
-                }
+            let byte = |offset| {
+                self.chunk
+                    .read_operand(decoded.operand_addr + offset, SIZE_NARROW_INDEX)
             };
+            let index = match decoded.op {
+                // jumps show their target address
+                Bytecode::Jump
+                | Bytecode::JumpIfFalse
+                | Bytecode::Loop
+                | Bytecode::SetupTry
+                | Bytecode::ConstLessJumpIfFalse => self
+                    .chunk
+                    .get_jump_offset_safe(decoded.operand_addr)
+                    .and_then(|offset| jump_target(ip, offset)),
+                Bytecode::JumpLong => self
+                    .chunk
+                    .read_operand(decoded.operand_addr, SIZE_INDEX)
+                    .and_then(|offset| jump_target(ip, offset as i64)),
+                Bytecode::IncrementGlobal | Bytecode::IncrementLocal => byte(0),
+                _ if decoded.op.has_index() => self
+                    .chunk
+                    .read_operand(decoded.operand_addr, decoded.operand_size),
+                _ => None,
+            };
+            let constant = match decoded.op {
+                Bytecode::IncrementGlobal | Bytecode::IncrementLocal => byte(SIZE_NARROW_INDEX),
+                Bytecode::ConstLessJumpIfFalse => byte(SIZE_JUMP),
+                _ => None,
+            };
+            result.push(Instruction {
+                ip,
+                op: decoded.op.clone(),
+                index,
+                constant,
+                line: None,
+            });
+            ip = decoded.next_addr();
+        }
+        for instruction in result.iter_mut() {
+            let ip = instruction.ip as u64;
+            instruction.line = self
+                .chunk
+                .lines
+                .iter()
+                .find(|(offset, _)| *offset == ip)
+                .map(|(_, line)| *line);
         }
         result
     }
 }
+
+fn jump_target(ip: usize, offset: i64) -> Option<u64> {
+    ip.checked_add_signed(offset as isize)
+        .map(|addr| addr as u64)
+}
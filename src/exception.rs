@@ -0,0 +1,258 @@
+use crate::format::repr;
+use crate::native::{NativeFunction, VARIADIC};
+use crate::object::Value;
+use crate::vm::VmError;
+
+/// Built-in exception classes with their base class, the root having none.
+/// There are no user-defined classes, so this is the whole hierarchy.
+const EXCEPTION_CLASSES: &[(&str, &str)] = &[
+    ("BaseException", ""),
+    ("Exception", "BaseException"),
+    ("ArithmeticError", "Exception"),
+    ("OverflowError", "ArithmeticError"),
+    ("ZeroDivisionError", "ArithmeticError"),
+    ("AssertionError", "Exception"),
+    ("AttributeError", "Exception"),
+    ("ImportError", "Exception"),
+    ("ModuleNotFoundError", "ImportError"),
+    ("LookupError", "Exception"),
+    ("MemoryError", "Exception"),
+    ("IndexError", "LookupError"),
+    ("KeyError", "LookupError"),
+    ("NameError", "Exception"),
+    ("OSError", "Exception"),
+    ("FileNotFoundError", "OSError"),
+    ("PermissionError", "OSError"),
+    ("RuntimeError", "Exception"),
+    ("NotImplementedError", "RuntimeError"),
+    ("RecursionError", "RuntimeError"),
+    ("StopIteration", "Exception"),
+    ("TypeError", "Exception"),
+    ("ValueError", "Exception"),
+    ("UnicodeError", "ValueError"),
+    ("UnicodeDecodeError", "UnicodeError"),
+    ("UnicodeEncodeError", "UnicodeError"),
+];
+
+/// Exception raised by a script or converted from a VM error so that an
+/// `except` clause can handle it.
+#[derive(Clone, Debug, PartialEq, PartialOrd)]
+pub struct Exception {
+    // one of `EXCEPTION_CLASSES`
+    pub class: &'static str,
+    pub args: Vec<Value>,
+    // `__cause__`, set by `raise ... from ...`
+    pub cause: Option<Box<Exception>>,
+}
+
+impl Exception {
+    pub fn new(class: &'static str, args: Vec<Value>) -> Exception {
+        Exception {
+            class,
+            args,
+            cause: None,
+        }
+    }
+
+    /// The exception a caught error stands for. Errors enforcing sandbox
+    /// limits or ending the run can't be caught, so they have none.
+    pub fn from_error(error: &VmError) -> Option<Exception> {
+        let (default_class, message) = match error {
+            VmError::InvalidOperand(message) | VmError::WrongArgumentCount(message) => {
+                ("TypeError", message)
+            }
+            VmError::UndefinedName(message) => ("NameError", message),
+            VmError::AttributeError(message) => ("AttributeError", message),
+            VmError::ImportError(message) => ("ImportError", message),
+            VmError::ValueError(message) => ("ValueError", message),
+            VmError::IndexError(message) => ("IndexError", message),
+            VmError::ZeroDivisionError(message) => ("ZeroDivisionError", message),
+            VmError::OsError(message) => ("OSError", message),
+            VmError::Exception(exception) => return Some(exception.clone()),
+            VmError::InvalidBytecode(_)
+            | VmError::ResourceLimitExceeded(_)
+            | VmError::ReplayDiverged(_)
+            | VmError::PermissionError(_)
+            | VmError::SystemExit(_) => return None,
+        };
+        // messages mostly start with a more precise class, e.g. the
+        // "OverflowError: ..." of an InvalidOperand
+        let (class, message) = match message.split_once(": ") {
+            Some((prefix, rest)) => match class_named(prefix) {
+                Some(class) => (class, rest),
+                None => (default_class, message.as_str()),
+            },
+            None => (default_class, message.as_str()),
+        };
+        Some(Exception::new(
+            class,
+            vec![Value::String(message.to_string().into())],
+        ))
+    }
+
+    /// The VM error reporting this exception when nothing handles it, the
+    /// dedicated variant where there is one.
+    pub fn into_error(self) -> VmError {
+        let message = self.to_string();
+        match self.class {
+            "TypeError" => VmError::InvalidOperand(message),
+            "NameError" => VmError::UndefinedName(message),
+            "AttributeError" => VmError::AttributeError(message),
+            "ImportError" => VmError::ImportError(message),
+            "ValueError" => VmError::ValueError(message),
+            "IndexError" => VmError::IndexError(message),
+            "ZeroDivisionError" => VmError::ZeroDivisionError(message),
+            "OSError" => VmError::OsError(message),
+            _ => VmError::Exception(self),
+        }
+    }
+
+    /// Text of `str(exception)`.
+    pub fn message(&self) -> String {
+        match self.args.as_slice() {
+            [] => String::new(),
+            // the missing key may well be an empty string
+            [arg] if self.class == "KeyError" => repr(arg),
+            [arg] => arg.to_string(),
+            args => Value::Tuple(args.to_vec().into()).to_string(),
+        }
+    }
+
+    pub fn repr(&self) -> String {
+        let args: Vec<String> = self.args.iter().map(repr).collect();
+        format!("{}({})", self.class, args.join(", "))
+    }
+
+    pub fn is_instance(&self, class: &str) -> bool {
+        let mut current = self.class;
+        loop {
+            if current == class {
+                return true;
+            }
+            match EXCEPTION_CLASSES.iter().find(|(name, _)| *name == current) {
+                Some((_, base)) if !base.is_empty() => current = base,
+                _ => return false,
+            }
+        }
+    }
+}
+
+// "ValueError: message", or just the class without a message
+impl std::fmt::Display for Exception {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.message() {
+            message if message.is_empty() => write!(f, "{}", self.class),
+            message => write!(f, "{}: {}", self.class, message),
+        }
+    }
+}
+
+fn class_named(name: &str) -> Option<&'static str> {
+    EXCEPTION_CLASSES
+        .iter()
+        .find(|(class, _)| *class == name)
+        .map(|(class, _)| *class)
+}
+
+/// The exception classes as builtins, calling one creates an instance.
+pub fn exception_classes() -> Vec<NativeFunction> {
+    EXCEPTION_CLASSES
+        .iter()
+        .map(|(class, _)| {
+            NativeFunction::new(class, VARIADIC, |_, args| {
+                Ok(Value::Exception(Exception::new(class, args).into()))
+            })
+        })
+        .collect()
+}
+
+/// Exception raised by `raise value`, which may be an instance or a class
+/// to instantiate without arguments.
+pub fn exception_of(value: &Value) -> Result<Exception, VmError> {
+    match value {
+        Value::Exception(exception) => Ok(exception.as_ref().clone()),
+        Value::NativeFunction(function) => match class_named(&function.name) {
+            Some(class) => Ok(Exception::new(class, Vec::new())),
+            None => Err(not_an_exception()),
+        },
+        _ => Err(not_an_exception()),
+    }
+}
+
+fn not_an_exception() -> VmError {
+    VmError::InvalidOperand(String::from(
+        "TypeError: exceptions must derive from BaseException",
+    ))
+}
+
+/// Whether the clause `except class:` handles `exception`, `class` being an
+/// exception class or a tuple of them.
+pub fn exception_matches(exception: &Exception, class: &Value) -> Result<bool, VmError> {
+    match class {
+        Value::NativeFunction(function) if class_named(&function.name).is_some() => {
+            Ok(exception.is_instance(&function.name))
+        }
+        Value::Tuple(classes) => {
+            for class in classes.iter() {
+                if exception_matches(exception, class)? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        _ => Err(VmError::InvalidOperand(String::from(
+            "TypeError: catching classes that do not inherit from BaseException is not allowed",
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_error() {
+        vec![
+            (
+                VmError::ZeroDivisionError(String::from("ZeroDivisionError: division by zero")),
+                Some(("ZeroDivisionError", "division by zero")),
+            ),
+            (
+                VmError::InvalidOperand(String::from("OverflowError: integer overflow")),
+                Some(("OverflowError", "integer overflow")),
+            ),
+            (
+                VmError::WrongArgumentCount(String::from("Function: f expect 1 arguments")),
+                Some(("TypeError", "Function: f expect 1 arguments")),
+            ),
+            (
+                VmError::ValueError(String::from("binascii.Error: Incorrect padding")),
+                Some(("ValueError", "binascii.Error: Incorrect padding")),
+            ),
+            (
+                VmError::ResourceLimitExceeded(String::from("value stack limit exceeded")),
+                None,
+            ),
+            (VmError::SystemExit(1), None),
+        ]
+        .into_iter()
+        .for_each(|(error, expected)| {
+            let exception = Exception::from_error(&error);
+            assert_eq!(
+                exception
+                    .as_ref()
+                    .map(|exception| (exception.class, exception.message())),
+                expected.map(|(class, message)| (class, message.to_string())),
+            );
+        });
+    }
+
+    #[test]
+    fn test_is_instance() {
+        let exception = Exception::new("ZeroDivisionError", Vec::new());
+        assert!(exception.is_instance("ZeroDivisionError"));
+        assert!(exception.is_instance("ArithmeticError"));
+        assert!(exception.is_instance("BaseException"));
+        assert!(!exception.is_instance("ValueError"));
+    }
+}
@@ -0,0 +1,191 @@
+use std::fmt::Debug;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::vm::VmError;
+
+/// Text file returned by `open()`. Copies share the handle, so closing one
+/// closes all of them. The handle is behind a mutex as bound methods are
+/// natives, which must be thread safe.
+#[derive(Clone)]
+pub struct File {
+    pub name: String,
+    pub mode: String,
+    // None once closed
+    handle: Arc<Mutex<Option<Handle>>>,
+}
+
+enum Handle {
+    Read(BufReader<fs::File>),
+    Write(fs::File),
+}
+
+impl File {
+    /// Opens `name` in mode "r", "w" or "a", optionally followed by "t".
+    pub fn open(name: &str, mode: &str) -> Result<File, VmError> {
+        let mut options = fs::OpenOptions::new();
+        match mode.strip_suffix('t').unwrap_or(mode) {
+            "r" => options.read(true),
+            "w" => options.write(true).create(true).truncate(true),
+            "a" => options.append(true).create(true),
+            _ => {
+                return Err(VmError::ValueError(format!(
+                    "ValueError: invalid mode: '{}'",
+                    mode
+                )))
+            }
+        };
+        let file = options.open(name).map_err(|err| os_error(&err, name))?;
+        let handle = if mode.starts_with('r') {
+            Handle::Read(BufReader::new(file))
+        } else {
+            Handle::Write(file)
+        };
+        Ok(File {
+            name: name.to_string(),
+            mode: mode.to_string(),
+            handle: Arc::new(Mutex::new(Some(handle))),
+        })
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.lock().is_none()
+    }
+
+    pub fn close(&self) {
+        self.lock().take();
+    }
+
+    /// The rest of the file, or its next `size` characters.
+    pub fn read(&self, size: Option<usize>) -> Result<String, VmError> {
+        self.with_reader(|reader| match size {
+            None => {
+                let mut text = String::new();
+                reader.read_to_string(&mut text)?;
+                Ok(text)
+            }
+            Some(size) => {
+                let mut text = String::new();
+                while text.chars().count() < size {
+                    match read_char(reader)? {
+                        Some(chr) => text.push(chr),
+                        None => break,
+                    }
+                }
+                Ok(text)
+            }
+        })
+    }
+
+    /// The next line with its newline, empty at the end of the file.
+    pub fn readline(&self) -> Result<String, VmError> {
+        self.with_reader(|reader| {
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+            Ok(line)
+        })
+    }
+
+    /// Writes `text`, returning the number of characters written.
+    pub fn write(&self, text: &str) -> Result<usize, VmError> {
+        match &mut *self.lock() {
+            Some(Handle::Write(file)) => {
+                file.write_all(text.as_bytes())
+                    .map_err(|err| os_error(&err, &self.name))?;
+                Ok(text.chars().count())
+            }
+            Some(Handle::Read(_)) => Err(unsupported("not writable")),
+            None => Err(closed_error()),
+        }
+    }
+
+    fn with_reader<T>(
+        &self,
+        read: impl FnOnce(&mut BufReader<fs::File>) -> io::Result<T>,
+    ) -> Result<T, VmError> {
+        match &mut *self.lock() {
+            Some(Handle::Read(reader)) => read(reader).map_err(|err| match err.kind() {
+                io::ErrorKind::InvalidData => VmError::ValueError(format!(
+                    "UnicodeDecodeError: '{}' is not valid utf-8",
+                    self.name
+                )),
+                _ => os_error(&err, &self.name),
+            }),
+            Some(Handle::Write(_)) => Err(unsupported("not readable")),
+            None => Err(closed_error()),
+        }
+    }
+
+    // a panic while holding the handle leaves it usable, the file state
+    // being whatever the OS has
+    fn lock(&self) -> MutexGuard<'_, Option<Handle>> {
+        self.handle
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // identity of the shared handle, for equality and hashing
+    pub fn id(&self) -> usize {
+        Arc::as_ptr(&self.handle) as usize
+    }
+}
+
+// next UTF-8 encoded character of `reader`, None at the end of the file
+fn read_char(reader: &mut impl Read) -> io::Result<Option<char>> {
+    let mut bytes = [0u8; 4];
+    if reader.read(&mut bytes[..1])? == 0 {
+        return Ok(None);
+    }
+    let width = match bytes[0] {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        _ => 4,
+    };
+    reader.read_exact(&mut bytes[1..width])?;
+    std::str::from_utf8(&bytes[..width])
+        .ok()
+        .and_then(|text| text.chars().next())
+        .map(Some)
+        .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidData))
+}
+
+fn os_error(err: &io::Error, name: &str) -> VmError {
+    let kind = match err.kind() {
+        io::ErrorKind::NotFound => "FileNotFoundError",
+        io::ErrorKind::PermissionDenied => "PermissionError",
+        _ => "OSError",
+    };
+    VmError::OsError(format!("{}: {}: '{}'", kind, err, name))
+}
+
+fn unsupported(reason: &str) -> VmError {
+    VmError::OsError(format!("io.UnsupportedOperation: {}", reason))
+}
+
+fn closed_error() -> VmError {
+    VmError::ValueError(String::from("ValueError: I/O operation on closed file."))
+}
+
+impl PartialEq for File {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.handle, &other.handle)
+    }
+}
+
+impl PartialOrd for File {
+    fn partial_cmp(&self, _other: &Self) -> Option<std::cmp::Ordering> {
+        None
+    }
+}
+
+impl Debug for File {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "<_io.TextIOWrapper name='{}' mode='{}'>",
+            self.name, self.mode
+        )
+    }
+}
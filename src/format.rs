@@ -0,0 +1,759 @@
+use crate::object::Value;
+use crate::vm::VmError;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Align {
+    Left,
+    Right,
+    Center,
+    // padding goes between the sign and the digits
+    AfterSign,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Sign {
+    Minus,
+    Plus,
+    Space,
+}
+
+/// Format specification shared by `str.format` and `%` formatting:
+/// `[[fill]align][sign][#][0][width][,|_][.precision][type]`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FormatSpec {
+    fill: char,
+    align: Option<Align>,
+    sign: Sign,
+    alternate: bool,
+    width: Option<usize>,
+    grouping: Option<char>,
+    precision: Option<usize>,
+    kind: Option<char>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FieldNumbering {
+    Unset,
+    Automatic(usize),
+    Manual,
+}
+
+impl Default for FormatSpec {
+    fn default() -> Self {
+        FormatSpec {
+            fill: ' ',
+            align: None,
+            sign: Sign::Minus,
+            alternate: false,
+            width: None,
+            grouping: None,
+            precision: None,
+            kind: None,
+        }
+    }
+}
+
+impl FormatSpec {
+    pub fn parse(spec: &str) -> Result<FormatSpec, VmError> {
+        let chars: Vec<char> = spec.chars().collect();
+        let mut format_spec = FormatSpec::default();
+        let mut index = 0;
+
+        if let Some(align) = chars.get(1).and_then(|chr| parse_align(*chr)) {
+            format_spec.fill = chars[0];
+            format_spec.align = Some(align);
+            index = 2;
+        } else if let Some(align) = chars.first().and_then(|chr| parse_align(*chr)) {
+            format_spec.align = Some(align);
+            index = 1;
+        }
+
+        let sign = match chars.get(index) {
+            Some('+') => Some(Sign::Plus),
+            Some(' ') => Some(Sign::Space),
+            Some('-') => Some(Sign::Minus),
+            _ => None,
+        };
+        if let Some(sign) = sign {
+            format_spec.sign = sign;
+            index += 1;
+        }
+
+        if chars.get(index) == Some(&'#') {
+            format_spec.alternate = true;
+            index += 1;
+        }
+        if chars.get(index) == Some(&'0') {
+            format_spec.zero_pad();
+            index += 1;
+        }
+
+        format_spec.width = parse_number(&chars, &mut index);
+        if let Some(separator @ (',' | '_')) = chars.get(index) {
+            format_spec.grouping = Some(*separator);
+            index += 1;
+        }
+        if chars.get(index) == Some(&'.') {
+            index += 1;
+            format_spec.precision = parse_number(&chars, &mut index);
+            if format_spec.precision.is_none() {
+                return Err(VmError::ValueError(String::from(
+                    "ValueError: Format specifier missing precision",
+                )));
+            }
+        }
+        if let Some(kind) = chars.get(index) {
+            format_spec.kind = Some(*kind);
+            index += 1;
+        }
+        if index < chars.len() {
+            return Err(VmError::ValueError(String::from(
+                "ValueError: Invalid format specifier",
+            )));
+        }
+        Ok(format_spec)
+    }
+
+    // a leading `0` pads numbers with zeros after the sign
+    fn zero_pad(&mut self) {
+        if self.align.is_none() {
+            self.fill = '0';
+            self.align = Some(Align::AfterSign);
+        }
+    }
+}
+
+fn parse_align(chr: char) -> Option<Align> {
+    match chr {
+        '<' => Some(Align::Left),
+        '>' => Some(Align::Right),
+        '^' => Some(Align::Center),
+        '=' => Some(Align::AfterSign),
+        _ => None,
+    }
+}
+
+fn parse_number(chars: &[char], index: &mut usize) -> Option<usize> {
+    let start = *index;
+    while chars.get(*index).is_some_and(|chr| chr.is_ascii_digit()) {
+        *index += 1;
+    }
+    chars[start..*index].iter().collect::<String>().parse().ok()
+}
+
+/// Formats `value` according to `spec`, like Python's `format(value, spec)`.
+pub fn format_value(value: &Value, spec: &FormatSpec) -> Result<String, VmError> {
+    match (value, spec.kind) {
+        (Value::String(text), None | Some('s')) => Ok(format_string(text, spec)),
+        (Value::Integer(value), _) => format_integer(*value, spec),
+        (Value::Bool(value), Some(_)) => format_integer(i64::from(*value), spec),
+        (Value::Float(value), _) => format_float(*value, spec),
+        (other, None) => Ok(format_string(&other.to_string(), spec)),
+        (other, Some(kind)) => Err(unknown_format_code(kind, other)),
+    }
+}
+
+fn unknown_format_code(kind: char, value: &Value) -> VmError {
+    VmError::ValueError(format!(
+        "ValueError: Unknown format code '{}' for object of type '{}'",
+        kind,
+        value.type_name()
+    ))
+}
+
+fn format_string(text: &str, spec: &FormatSpec) -> String {
+    let text: String = match spec.precision {
+        Some(precision) => text.chars().take(precision).collect(),
+        None => text.to_string(),
+    };
+    let align = match spec.align {
+        Some(Align::AfterSign) | None => Align::Left,
+        Some(align) => align,
+    };
+    pad("", &text, spec, align)
+}
+
+fn format_integer(value: i64, spec: &FormatSpec) -> Result<String, VmError> {
+    let magnitude = value.unsigned_abs();
+    let (digits, prefix, group_size) = match spec.kind {
+        None | Some('d') | Some('n') => (magnitude.to_string(), "", 3),
+        Some('x') => (format!("{:x}", magnitude), "0x", 4),
+        Some('X') => (format!("{:X}", magnitude), "0X", 4),
+        Some('o') => (format!("{:o}", magnitude), "0o", 4),
+        Some('b') => (format!("{:b}", magnitude), "0b", 4),
+        Some('e' | 'E' | 'f' | 'F' | 'g' | 'G' | '%') => return format_float(value as f64, spec),
+        Some(kind) => return Err(unknown_format_code(kind, &Value::Integer(value))),
+    };
+    if spec.precision.is_some() {
+        return Err(VmError::ValueError(String::from(
+            "ValueError: Precision not allowed in integer format specifier",
+        )));
+    }
+    let digits = match spec.grouping {
+        Some(separator) => group_digits(&digits, separator, group_size),
+        None => digits,
+    };
+    let prefix = if spec.alternate { prefix } else { "" };
+    Ok(pad_number(value < 0, prefix, &digits, spec))
+}
+
+fn format_float(value: f64, spec: &FormatSpec) -> Result<String, VmError> {
+    let negative = value.is_sign_negative() && !value.is_nan();
+    let magnitude = value.abs();
+    let upper = matches!(spec.kind, Some('F' | 'E' | 'G'));
+    let body = if magnitude.is_nan() {
+        String::from("nan")
+    } else if magnitude.is_infinite() {
+        String::from("inf")
+    } else {
+        match spec.kind {
+            None => match spec.precision {
+                Some(precision) => {
+                    let body = format_general(magnitude, precision, spec.alternate);
+                    if body.contains(['.', 'e']) {
+                        body
+                    } else {
+                        body + ".0"
+                    }
+                }
+                None => float_repr(magnitude),
+            },
+            Some('f' | 'F') => format!("{:.*}", spec.precision.unwrap_or(6), magnitude),
+            Some('e' | 'E') => format_scientific(magnitude, spec.precision.unwrap_or(6)),
+            Some('g' | 'G') => {
+                format_general(magnitude, spec.precision.unwrap_or(6), spec.alternate)
+            }
+            Some('%') => format!("{:.*}%", spec.precision.unwrap_or(6), magnitude * 100.0),
+            Some(kind) => return Err(unknown_format_code(kind, &Value::Float(value))),
+        }
+    };
+    let body = if upper { body.to_uppercase() } else { body };
+    let body = match spec.grouping {
+        Some(separator) if magnitude.is_finite() => {
+            let split = body.find(['.', 'e', 'E', '%']).unwrap_or(body.len());
+            group_digits(&body[..split], separator, 3) + &body[split..]
+        }
+        _ => body,
+    };
+    Ok(pad_number(negative, "", &body, spec))
+}
+
+// Rust writes `1.5e-5`, Python writes `1.5e-05`
+fn python_exponent(formatted: &str) -> String {
+    match formatted.split_once('e') {
+        Some((mantissa, exponent)) => {
+            let exponent: i32 = exponent.parse().unwrap_or(0);
+            let sign = if exponent < 0 { '-' } else { '+' };
+            format!("{}e{}{:02}", mantissa, sign, exponent.abs())
+        }
+        None => formatted.to_string(),
+    }
+}
+
+fn format_scientific(value: f64, precision: usize) -> String {
+    python_exponent(&format!("{:.*e}", precision, value))
+}
+
+// `g`: scientific for very small or large exponents, fixed otherwise, with
+// insignificant trailing zeros removed unless `alternate` is set
+fn format_general(value: f64, precision: usize, alternate: bool) -> String {
+    let precision = precision.max(1);
+    let scientific = format!("{:.*e}", precision - 1, value);
+    let exponent: i32 = match scientific.split_once('e') {
+        Some((_, exponent)) => exponent.parse().unwrap_or(0),
+        None => 0,
+    };
+    let strip = |digits: String| -> String {
+        if alternate || !digits.contains('.') {
+            digits
+        } else {
+            digits
+                .trim_end_matches('0')
+                .trim_end_matches('.')
+                .to_string()
+        }
+    };
+    if -4 <= exponent && exponent < precision as i32 {
+        let decimals = (precision as i32 - 1 - exponent) as usize;
+        strip(format!("{:.*}", decimals, value))
+    } else {
+        let (mantissa, _) = scientific.split_once('e').unwrap_or((&scientific, ""));
+        python_exponent(&format!("{}e{}", strip(mantissa.to_string()), exponent))
+    }
+}
+
+/// Shortest representation that round-trips, spelled the way Python does.
+pub fn float_repr(value: f64) -> String {
+    if value.is_nan() {
+        return String::from("nan");
+    }
+    if value.is_infinite() {
+        return String::from(if value < 0.0 { "-inf" } else { "inf" });
+    }
+    let exponent = if value == 0.0 {
+        0
+    } else {
+        value.abs().log10().floor() as i32
+    };
+    if (-4..16).contains(&exponent) {
+        let formatted = value.to_string();
+        if formatted.contains('.') {
+            formatted
+        } else {
+            formatted + ".0"
+        }
+    } else {
+        python_exponent(&format!("{:e}", value))
+    }
+}
+
+fn group_digits(digits: &str, separator: char, group_size: usize) -> String {
+    let mut grouped = String::new();
+    let count = digits.chars().count();
+    for (index, chr) in digits.chars().enumerate() {
+        if index > 0 && (count - index).is_multiple_of(group_size) {
+            grouped.push(separator);
+        }
+        grouped.push(chr);
+    }
+    grouped
+}
+
+fn pad_number(negative: bool, prefix: &str, body: &str, spec: &FormatSpec) -> String {
+    let sign = match (negative, spec.sign) {
+        (true, _) => "-",
+        (false, Sign::Plus) => "+",
+        (false, Sign::Space) => " ",
+        (false, Sign::Minus) => "",
+    };
+    pad(&format!("{}{}", sign, prefix), body, spec, Align::Right)
+}
+
+fn pad(sign: &str, body: &str, spec: &FormatSpec, default_align: Align) -> String {
+    let len = sign.chars().count() + body.chars().count();
+    let width = spec.width.unwrap_or(0);
+    if len >= width {
+        return format!("{}{}", sign, body);
+    }
+    let fill = |count: usize| spec.fill.to_string().repeat(count);
+    let padding = width - len;
+    match spec.align.unwrap_or(default_align) {
+        Align::Left => format!("{}{}{}", sign, body, fill(padding)),
+        Align::Right => format!("{}{}{}", fill(padding), sign, body),
+        Align::AfterSign => format!("{}{}{}", sign, fill(padding), body),
+        Align::Center => format!(
+            "{}{}{}{}",
+            fill(padding / 2),
+            sign,
+            body,
+            fill(padding - padding / 2)
+        ),
+    }
+}
+
+/// Python `repr` of a value: strings are quoted, everything else prints as is.
+/// `b'...'` form of a bytes value, non-printable bytes as `\xNN`.
+pub fn bytes_repr(value: &[u8]) -> String {
+    let quote = if value.contains(&b'\'') && !value.contains(&b'"') {
+        '"'
+    } else {
+        '\''
+    };
+    let mut repr = format!("b{}", quote);
+    for byte in value {
+        match byte {
+            b'\\' => repr.push_str("\\\\"),
+            b'\n' => repr.push_str("\\n"),
+            b'\r' => repr.push_str("\\r"),
+            b'\t' => repr.push_str("\\t"),
+            byte if char::from(*byte) == quote => {
+                repr.push('\\');
+                repr.push(quote);
+            }
+            0x20..=0x7e => repr.push(char::from(*byte)),
+            byte => repr.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    repr.push(quote);
+    repr
+}
+
+/// Python `repr()`: strings quoted and escaped, other values as displayed,
+/// which already gives containers their literal syntax.
+pub fn repr(value: &Value) -> String {
+    match value {
+        Value::String(text) => {
+            let quote = if text.contains('\'') && !text.contains('"') {
+                '"'
+            } else {
+                '\''
+            };
+            let mut repr = String::from(quote);
+            for chr in text.chars() {
+                match chr {
+                    '\\' => repr.push_str("\\\\"),
+                    '\n' => repr.push_str("\\n"),
+                    '\r' => repr.push_str("\\r"),
+                    '\t' => repr.push_str("\\t"),
+                    chr if chr == quote => {
+                        repr.push('\\');
+                        repr.push(chr);
+                    }
+                    chr if chr.is_control() && (chr as u32) < 0x100 => {
+                        repr.push_str(&format!("\\x{:02x}", chr as u32))
+                    }
+                    chr if chr.is_control() => repr.push_str(&format!("\\u{:04x}", chr as u32)),
+                    chr => repr.push(chr),
+                }
+            }
+            repr.push(quote);
+            repr
+        }
+        Value::Exception(exception) => exception.repr(),
+        other => other.to_string(),
+    }
+}
+
+/// `template.format(*args)`
+pub fn str_format(template: &str, args: &[Value]) -> Result<String, VmError> {
+    let mut result = String::new();
+    let mut numbering = FieldNumbering::Unset;
+    let mut chars = template.chars().peekable();
+    while let Some(chr) = chars.next() {
+        match chr {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                result.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                result.push('}');
+            }
+            '}' => {
+                return Err(VmError::ValueError(String::from(
+                    "ValueError: Single '}' encountered in format string",
+                )))
+            }
+            '{' => {
+                let mut field = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some('{') => {
+                            return Err(VmError::ValueError(String::from(
+                                "ValueError: Nested replacement fields are not supported",
+                            )))
+                        }
+                        Some(chr) => field.push(chr),
+                        None => {
+                            return Err(VmError::ValueError(String::from(
+                                "ValueError: Single '{' encountered in format string",
+                            )))
+                        }
+                    }
+                }
+                result.push_str(&format_field(&field, args, &mut numbering)?);
+            }
+            chr => result.push(chr),
+        }
+    }
+    Ok(result)
+}
+
+fn format_field(
+    field: &str,
+    args: &[Value],
+    numbering: &mut FieldNumbering,
+) -> Result<String, VmError> {
+    let (field, spec) = field.split_once(':').unwrap_or((field, ""));
+    let (name, conversion) = match field.split_once('!') {
+        Some((name, conversion)) => (name, Some(conversion)),
+        None => (field, None),
+    };
+
+    let index = if name.is_empty() {
+        let index = match numbering {
+            FieldNumbering::Manual => {
+                return Err(VmError::ValueError(String::from(
+                    "ValueError: cannot switch from manual field specification to automatic field numbering",
+                )))
+            }
+            FieldNumbering::Unset => 0,
+            FieldNumbering::Automatic(index) => *index,
+        };
+        *numbering = FieldNumbering::Automatic(index + 1);
+        index
+    } else if let Ok(index) = name.parse::<usize>() {
+        if let FieldNumbering::Automatic(_) = numbering {
+            return Err(VmError::ValueError(String::from(
+                "ValueError: cannot switch from automatic field numbering to manual field specification",
+            )));
+        }
+        *numbering = FieldNumbering::Manual;
+        index
+    } else {
+        return Err(VmError::ValueError(format!(
+            "ValueError: Unsupported replacement field '{}'",
+            name
+        )));
+    };
+
+    let value = match args.get(index) {
+        Some(value) => value,
+        None => {
+            return Err(VmError::IndexError(format!(
+                "IndexError: Replacement index {} out of range for positional args tuple",
+                index
+            )))
+        }
+    };
+    let value = match conversion {
+        None => value.clone(),
+        Some("s") => Value::String(value.to_string().into()),
+        Some("r") => Value::String(repr(value).into()),
+        Some(conversion) => {
+            return Err(VmError::ValueError(format!(
+                "ValueError: Unknown conversion specifier {}",
+                conversion
+            )))
+        }
+    };
+    format_value(&value, &FormatSpec::parse(spec)?)
+}
+
+/// `template % args`, printf-style formatting on top of the same engine.
+pub fn percent_format(template: &str, args: &[Value]) -> Result<String, VmError> {
+    let mut result = String::new();
+    let mut args = args.iter();
+    let chars: Vec<char> = template.chars().collect();
+    let mut index = 0;
+    while index < chars.len() {
+        let chr = chars[index];
+        index += 1;
+        if chr != '%' {
+            result.push(chr);
+            continue;
+        }
+
+        let mut spec = FormatSpec::default();
+        let mut zero = false;
+        while let Some(flag) = chars.get(index) {
+            match flag {
+                '-' => spec.align = Some(Align::Left),
+                '+' => spec.sign = Sign::Plus,
+                ' ' if spec.sign != Sign::Plus => spec.sign = Sign::Space,
+                ' ' => (),
+                '0' => zero = true,
+                '#' => spec.alternate = true,
+                _ => break,
+            }
+            index += 1;
+        }
+        spec.width = parse_number(&chars, &mut index);
+        if chars.get(index) == Some(&'.') {
+            index += 1;
+            spec.precision = Some(parse_number(&chars, &mut index).unwrap_or(0));
+        }
+        let kind = match chars.get(index) {
+            Some(kind) => *kind,
+            None => {
+                return Err(VmError::ValueError(String::from(
+                    "ValueError: incomplete format",
+                )))
+            }
+        };
+        index += 1;
+        if kind == '%' {
+            result.push('%');
+            continue;
+        }
+        if zero {
+            spec.zero_pad();
+        }
+
+        let value = match args.next() {
+            Some(value) => value,
+            None => {
+                return Err(VmError::InvalidOperand(String::from(
+                    "TypeError: not enough arguments for format string",
+                )))
+            }
+        };
+        let formatted = match kind {
+            's' => format_string(&value.to_string(), &spec),
+            'r' => format_string(&repr(value), &spec),
+            'd' | 'i' | 'u' | 'x' | 'X' | 'o' => {
+                let integer = match value {
+                    Value::Integer(value) => *value,
+                    Value::Float(value) => value.trunc() as i64,
+                    Value::Bool(value) => *value as i64,
+                    other => {
+                        return Err(VmError::InvalidOperand(format!(
+                            "TypeError: %{} format: a real number is required, not {}",
+                            kind,
+                            other.type_name()
+                        )))
+                    }
+                };
+                spec.kind = Some(if matches!(kind, 'i' | 'u') { 'd' } else { kind });
+                format_integer(integer, &spec)?
+            }
+            'f' | 'F' | 'e' | 'E' | 'g' | 'G' => {
+                let float = match value {
+                    Value::Integer(value) => *value as f64,
+                    Value::Float(value) => *value,
+                    Value::Bool(value) => *value as i64 as f64,
+                    other => {
+                        return Err(VmError::InvalidOperand(format!(
+                            "TypeError: must be real number, not {}",
+                            other.type_name()
+                        )))
+                    }
+                };
+                spec.kind = Some(kind);
+                format_float(float, &spec)?
+            }
+            other => {
+                return Err(VmError::ValueError(format!(
+                    "ValueError: unsupported format character '{}'",
+                    other
+                )))
+            }
+        };
+        result.push_str(&formatted);
+    }
+    if args.next().is_some() {
+        return Err(VmError::InvalidOperand(String::from(
+            "TypeError: not all arguments converted during string formatting",
+        )));
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_value() {
+        vec![
+            (Value::Integer(42), "", "42"),
+            (Value::Integer(42), ">5", "   42"),
+            (Value::Integer(42), "*<5", "42***"),
+            (Value::Integer(42), "^6", "  42  "),
+            (Value::Integer(-42), "05", "-0042"),
+            (Value::Integer(42), "+d", "+42"),
+            (Value::Integer(255), "#x", "0xff"),
+            (Value::Integer(255), "#010x", "0x000000ff"),
+            (Value::Integer(5), "b", "101"),
+            (Value::Integer(1234567), ",", "1,234,567"),
+            (Value::Integer(3), ".2f", "3.00"),
+            (Value::Float(1.23456), ".2f", "1.23"),
+            (Value::Float(-1.23456), "8.3f", "  -1.235"),
+            (Value::Float(1234.5), ",.1f", "1,234.5"),
+            (Value::Float(0.25), ".0%", "25%"),
+            (Value::Float(12345.678), "e", "1.234568e+04"),
+            (Value::Float(0.00001234), "g", "1.234e-05"),
+            (Value::Float(123.456), "g", "123.456"),
+            (Value::Float(2.0), "", "2.0"),
+            (Value::Float(2.0), ".3", "2.0"),
+            (Value::String(String::from("abc").into()), "", "abc"),
+            (Value::String(String::from("abc").into()), ">5", "  abc"),
+            (Value::String(String::from("abcdef").into()), ".3", "abc"),
+            (Value::None, "", "None"),
+            (Value::Bool(true), "", "True"),
+            (Value::Bool(true), "d", "1"),
+        ]
+        .into_iter()
+        .for_each(|(value, spec, expected)| {
+            let spec = FormatSpec::parse(spec).unwrap();
+            assert_eq!(format_value(&value, &spec).unwrap(), expected);
+        });
+    }
+
+    #[test]
+    fn test_str_format() {
+        vec![
+            ("{} {}", vec![Value::Integer(1), Value::Integer(2)], "1 2"),
+            ("{1} {0}", vec![Value::Integer(1), Value::Integer(2)], "2 1"),
+            ("{{{}}}", vec![Value::Integer(1)], "{1}"),
+            (
+                "{:>4}|",
+                vec![Value::String(String::from("a").into())],
+                "   a|",
+            ),
+            ("{!r}", vec![Value::String(String::from("a").into())], "'a'"),
+            ("{0:.1f}", vec![Value::Float(2.25)], "2.2"),
+        ]
+        .into_iter()
+        .for_each(|(template, args, expected)| {
+            assert_eq!(str_format(template, &args).unwrap(), expected);
+        });
+
+        vec![
+            ("{} {}", vec![Value::Integer(1)]),
+            ("{} {0}", vec![Value::Integer(1)]),
+            ("{", vec![]),
+            ("}", vec![]),
+            ("{:d}", vec![Value::String(String::from("a").into())]),
+        ]
+        .into_iter()
+        .for_each(|(template, args)| {
+            assert!(str_format(template, &args).is_err());
+        });
+    }
+
+    #[test]
+    fn test_percent_format() {
+        vec![
+            ("%d items", Value::Integer(3), "3 items"),
+            ("%5d|", Value::Integer(3), "    3|"),
+            ("%-5d|", Value::Integer(3), "3    |"),
+            ("%05.1f", Value::Float(1.23456), "001.2"),
+            ("%s!", Value::String(String::from("hi").into()), "hi!"),
+            ("%r", Value::String(String::from("hi").into()), "'hi'"),
+            ("%x", Value::Integer(255), "ff"),
+            ("%d%%", Value::Float(99.9), "99%"),
+        ]
+        .into_iter()
+        .for_each(|(template, arg, expected)| {
+            assert_eq!(percent_format(template, &[arg]).unwrap(), expected);
+        });
+
+        vec![
+            ("%d %d", Value::Integer(1)),
+            ("no directives", Value::Integer(1)),
+            ("%d", Value::String(String::from("a").into())),
+        ]
+        .into_iter()
+        .for_each(|(template, arg)| {
+            assert!(percent_format(template, &[arg]).is_err());
+        });
+    }
+
+    #[test]
+    fn test_float_repr() {
+        vec![
+            (1.0, "1.0"),
+            (0.1, "0.1"),
+            (-2.5, "-2.5"),
+            (1e16, "1e+16"),
+            (1.5e-5, "1.5e-05"),
+            (123456789.0, "123456789.0"),
+        ]
+        .into_iter()
+        .for_each(|(value, expected)| assert_eq!(float_repr(value), expected));
+    }
+
+    #[test]
+    fn test_bytes_repr() {
+        vec![
+            (&b"abc"[..], "b'abc'"),
+            (&b"it's"[..], "b\"it's\""),
+            (&b"\x00\n\\"[..], "b'\\x00\\n\\\\'"),
+            (&[0xc3, 0xa9][..], "b'\\xc3\\xa9'"),
+        ]
+        .into_iter()
+        .for_each(|(value, expected)| assert_eq!(bytes_repr(value), expected));
+    }
+}
@@ -1,12 +1,27 @@
+use crate::ast::TypeAnnotation;
 use crate::chunk::Chunk;
+use crate::object::Value;
+use crate::register::RegisterCode;
+use crate::rpyc;
 
 const GLOBAL_SCOPE: &str = "<main>";
+const MODULE_SCOPE_PREFIX: &str = "<module ";
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Function {
     pub name: String,
     pub arity: usize,
     pub chunk: Chunk,
+    // leading string literal of the body, exposed as `__doc__`
+    pub doc: Option<String>,
+    pub params: Vec<String>,
+    // annotations, only enforced with `Config::check_annotations`
+    pub arg_types: Vec<Option<TypeAnnotation>>,
+    pub return_type: Option<TypeAnnotation>,
+    // module whose globals the code uses, None in the main script
+    pub module: Option<String>,
+    // the chunk lowered for the register VM, with `Config::register_backend`
+    pub registers: Option<RegisterCode>,
 }
 
 impl PartialOrd for Function {
@@ -21,6 +36,25 @@ impl Function {
             name: GLOBAL_SCOPE.to_string(),
             arity: 0,
             chunk: Chunk::new(),
+            doc: None,
+            params: Vec::new(),
+            arg_types: Vec::new(),
+            return_type: None,
+            module: None,
+            registers: None,
+        }
+    }
+    pub fn new_module_scope(module: &str) -> Function {
+        Function {
+            name: format!("{}{}>", MODULE_SCOPE_PREFIX, module),
+            arity: 0,
+            chunk: Chunk::new(),
+            doc: None,
+            params: Vec::new(),
+            arg_types: Vec::new(),
+            return_type: None,
+            module: Some(module.to_string()),
+            registers: None,
         }
     }
     pub fn new(name: String) -> Function {
@@ -28,10 +62,88 @@ impl Function {
             name,
             arity: 0,
             chunk: Chunk::new(),
+            doc: None,
+            params: Vec::new(),
+            arg_types: Vec::new(),
+            return_type: None,
+            module: None,
+            registers: None,
         }
     }
 
+    /// `name(a: int, b) -> str`, the parameters as they were declared.
+    pub fn signature(&self) -> String {
+        let params = self
+            .params
+            .iter()
+            .enumerate()
+            .map(|(index, param)| match self.arg_types.get(index) {
+                Some(Some(annotation)) => format!("{}: {}", param, annotation),
+                _ => param.to_string(),
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+        match &self.return_type {
+            Some(annotation) => format!("{}({}) -> {}", self.name, params, annotation),
+            None => format!("{}({})", self.name, params),
+        }
+    }
+
+    /// The function and the ones it defines in the `.rpyc` format, failing
+    /// for constants that can't be serialized.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        rpyc::write_function(self)
+    }
+
+    /// Reads back a function written by `to_bytes`. Register code isn't
+    /// serialized, the function runs on the stack VM.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Function, String> {
+        rpyc::read_function(bytes)
+    }
+
     pub fn is_global_scope(&self) -> bool {
-        self.name == GLOBAL_SCOPE
+        self.name == GLOBAL_SCOPE || self.name.starts_with(MODULE_SCOPE_PREFIX)
+    }
+
+    /// Functions defined in the body, the ones they define included.
+    pub fn nested_functions(&self) -> Vec<&Function> {
+        let mut functions = Vec::new();
+        for constant in &self.chunk.constants {
+            if let Value::Function(function) = constant {
+                functions.push(function.as_ref());
+                functions.extend(function.nested_functions());
+            }
+        }
+        functions
     }
 }
+
+/// Whether `value` satisfies `annotation`, None for annotations that can't
+/// be checked at runtime such as user classes. Like in type checkers an int
+/// is accepted where a float is expected.
+pub fn annotation_accepts(annotation: &TypeAnnotation, value: &Value) -> Option<bool> {
+    let accepted = match annotation.name.as_str() {
+        "Any" | "object" => true,
+        "None" => matches!(value, Value::None),
+        "bool" => matches!(value, Value::Bool(_)),
+        // bool is a subclass of int
+        "int" => matches!(value, Value::Integer(_) | Value::Bool(_)),
+        "float" => matches!(value, Value::Float(_) | Value::Integer(_) | Value::Bool(_)),
+        "str" => matches!(value, Value::String(_)),
+        "bytes" => matches!(value, Value::Bytes(_)),
+        "list" => matches!(value, Value::List(_)),
+        "tuple" => matches!(value, Value::Tuple(_)),
+        "Optional" => {
+            matches!(value, Value::None) || annotation_accepts(annotation.args.first()?, value)?
+        }
+        "Union" => {
+            let mut accepted = false;
+            for arg in &annotation.args {
+                accepted |= annotation_accepts(arg, value)?;
+            }
+            accepted
+        }
+        _ => return None,
+    };
+    Some(accepted)
+}
@@ -0,0 +1,361 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+use crate::gc::Container;
+use crate::native::{
+    check_arity, iterator_of, NativeContext, NativeFunction, NativeResult, VARIADIC,
+};
+use crate::object::Value;
+use crate::vm::VmError;
+
+pub const MODULE_NAME: &str = "functools";
+
+/// Entries `lru_cache` keeps unless told otherwise.
+const DEFAULT_MAXSIZE: usize = 128;
+
+/// `functools.partial` object, calling `function` with the bound arguments
+/// before the ones it is called with.
+#[derive(Clone, PartialEq)]
+pub struct Partial {
+    pub function: Box<Value>,
+    pub args: Vec<Value>,
+    pub kwargs: Vec<(String, Value)>,
+}
+
+/// Function wrapped by `lru_cache`, remembering its results by arguments.
+/// Copies share the cache.
+#[derive(Clone)]
+pub struct CachedFunction {
+    pub function: Box<Value>,
+    cache: Rc<RefCell<Cache>>,
+}
+
+struct Cache {
+    // None for an unbounded cache
+    maxsize: Option<usize>,
+    // entries by the hash of their arguments, colliding ones sharing a bucket
+    entries: HashMap<u64, Vec<Entry>>,
+    len: usize,
+    // bumped on every lookup, entries remembering when they were last used
+    clock: u64,
+}
+
+struct Entry {
+    key: Key,
+    result: Value,
+    used: u64,
+}
+
+impl Partial {
+    /// Arguments of a call through the partial, keyword arguments of the
+    /// call overriding the bound ones.
+    pub fn bind(
+        &self,
+        args: Vec<Value>,
+        kwargs: Vec<(String, Value)>,
+    ) -> (Vec<Value>, Vec<(String, Value)>) {
+        let mut bound_kwargs: Vec<(String, Value)> = self
+            .kwargs
+            .iter()
+            .filter(|(name, _)| !kwargs.iter().any(|(other, _)| other == name))
+            .cloned()
+            .collect();
+        bound_kwargs.extend(kwargs);
+        (
+            self.args.iter().cloned().chain(args).collect(),
+            bound_kwargs,
+        )
+    }
+}
+
+impl CachedFunction {
+    pub fn new(function: Value, maxsize: Option<usize>) -> CachedFunction {
+        let cache = Cache {
+            maxsize,
+            entries: HashMap::new(),
+            len: 0,
+            clock: 0,
+        };
+        CachedFunction {
+            function: Box::new(function),
+            cache: Rc::new(RefCell::new(cache)),
+        }
+    }
+
+    /// Result cached for a call with `args` and `kwargs`, if any.
+    pub fn lookup(
+        &self,
+        args: &[Value],
+        kwargs: &[(String, Value)],
+    ) -> Result<Option<Value>, VmError> {
+        let key = Key::new(args, kwargs)?;
+        let mut cache = self.cache.borrow_mut();
+        cache.clock += 1;
+        let clock = cache.clock;
+        let entry = cache
+            .entries
+            .get_mut(&key.hash)
+            .and_then(|bucket| bucket.iter_mut().find(|entry| entry.key == key));
+        Ok(entry.map(|entry| {
+            entry.used = clock;
+            entry.result.clone()
+        }))
+    }
+
+    /// Remembers `result` for a call with `args` and `kwargs`, evicting the
+    /// least recently used entry when the cache is full.
+    pub fn store(
+        &self,
+        args: &[Value],
+        kwargs: &[(String, Value)],
+        result: &Value,
+    ) -> Result<(), VmError> {
+        let key = Key::new(args, kwargs)?;
+        let mut cache = self.cache.borrow_mut();
+        if cache.maxsize == Some(0) {
+            return Ok(());
+        }
+        if cache.maxsize.is_some_and(|maxsize| cache.len >= maxsize) {
+            cache.evict_oldest();
+        }
+        let used = cache.clock;
+        cache.entries.entry(key.hash).or_default().push(Entry {
+            key,
+            result: result.clone(),
+            used,
+        });
+        cache.len += 1;
+        Ok(())
+    }
+
+    /// The cache, for the collector to look into.
+    pub(crate) fn container(&self) -> Rc<dyn Container> {
+        self.cache.clone()
+    }
+
+    // identity of the shared cache, for equality and hashing
+    pub fn id(&self) -> usize {
+        Rc::as_ptr(&self.cache) as usize
+    }
+}
+
+// arguments of a call, which like in Python must all be hashable
+#[derive(PartialEq)]
+struct Key {
+    hash: u64,
+    args: Vec<Value>,
+    kwargs: Vec<(String, Value)>,
+}
+
+impl Key {
+    fn new(args: &[Value], kwargs: &[(String, Value)]) -> Result<Key, VmError> {
+        let (args, kwargs) = (args.to_vec(), kwargs.to_vec());
+        let mut hasher = DefaultHasher::new();
+        for value in args.iter().chain(kwargs.iter().map(|(_, value)| value)) {
+            if let Value::List(_) = value {
+                return Err(VmError::InvalidOperand(String::from(
+                    "TypeError: unhashable type: 'list'",
+                )));
+            }
+            value.hash(&mut hasher);
+        }
+        kwargs.hash(&mut hasher);
+        Ok(Key {
+            hash: hasher.finish(),
+            args,
+            kwargs,
+        })
+    }
+}
+
+impl Cache {
+    fn evict_oldest(&mut self) {
+        let oldest = self
+            .entries
+            .iter()
+            .flat_map(|(key, bucket)| {
+                bucket
+                    .iter()
+                    .enumerate()
+                    .map(move |(index, entry)| (entry.used, *key, index))
+            })
+            .min();
+        if let Some((_, key, index)) = oldest {
+            if let Some(bucket) = self.entries.get_mut(&key) {
+                bucket.remove(index);
+                if bucket.is_empty() {
+                    self.entries.remove(&key);
+                }
+            }
+            self.len -= 1;
+        }
+    }
+}
+
+impl Container for RefCell<Cache> {
+    fn visit(&self, visit: &mut dyn FnMut(&Value)) -> bool {
+        let Ok(cache) = self.try_borrow() else {
+            return false;
+        };
+        cache
+            .entries
+            .values()
+            .flatten()
+            .flat_map(|entry| {
+                entry
+                    .key
+                    .args
+                    .iter()
+                    .chain(entry.key.kwargs.iter().map(|(_, value)| value))
+                    .chain([&entry.result])
+            })
+            .for_each(visit);
+        true
+    }
+
+    fn clear(&self) {
+        // dropped once no longer borrowed, as dropping them may reach back
+        let entries = {
+            let mut cache = self.borrow_mut();
+            cache.len = 0;
+            std::mem::take(&mut cache.entries)
+        };
+        drop(entries);
+    }
+}
+
+impl PartialOrd for Partial {
+    fn partial_cmp(&self, _other: &Self) -> Option<Ordering> {
+        None
+    }
+}
+
+impl Debug for Partial {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut args = vec![self.function.repr()];
+        args.extend(self.args.iter().map(|arg| arg.repr()));
+        args.extend(
+            self.kwargs
+                .iter()
+                .map(|(name, arg)| format!("{}={}", name, arg.repr())),
+        );
+        write!(f, "functools.partial({})", args.join(", "))
+    }
+}
+
+impl Debug for CachedFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "<functools._lru_cache_wrapper object at {:#x}>",
+            self.id()
+        )
+    }
+}
+
+impl PartialEq for CachedFunction {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.cache, &other.cache)
+    }
+}
+
+impl PartialOrd for CachedFunction {
+    fn partial_cmp(&self, _other: &Self) -> Option<Ordering> {
+        None
+    }
+}
+
+/// Attributes of the built-in `functools` module.
+pub fn attributes() -> Vec<(String, Value)> {
+    vec![
+        NativeFunction::new("reduce", VARIADIC, reduce),
+        NativeFunction::new("partial", VARIADIC, partial).with_any_kwargs(),
+        NativeFunction::new("lru_cache", VARIADIC, lru_cache).with_kwargs(&["maxsize"]),
+    ]
+    .into_iter()
+    .map(|function| {
+        (
+            function.name.to_string(),
+            Value::NativeFunction(function.into()),
+        )
+    })
+    .collect()
+}
+
+// `partial(function, *args, **kwargs)`
+fn partial(context: &mut NativeContext, mut args: Vec<Value>) -> NativeResult {
+    check_arity("partial", &args, 1, VARIADIC)?;
+    let function = args.remove(0);
+    if !function.is_callable() {
+        return Err(VmError::InvalidOperand(String::from(
+            "TypeError: the first argument must be callable",
+        )));
+    }
+    Ok(Value::Partial(
+        (Partial {
+            function: Box::new(function),
+            args,
+            kwargs: context.kwargs.clone(),
+        })
+        .into(),
+    ))
+}
+
+// `lru_cache(function)`, or `lru_cache(maxsize=128)` returning the decorator
+fn lru_cache(context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    check_arity("lru_cache", &args, 0, 1)?;
+    let maxsize = match args.first().or(context.kwarg("maxsize")) {
+        Some(function) if function.is_callable() => {
+            return Ok(Value::CachedFunction(
+                CachedFunction::new(args[0].clone(), Some(DEFAULT_MAXSIZE)).into(),
+            ))
+        }
+        None => Some(DEFAULT_MAXSIZE),
+        Some(Value::None) => None,
+        Some(Value::Integer(maxsize)) => Some((*maxsize).max(0) as usize),
+        Some(_) => {
+            return Err(VmError::InvalidOperand(String::from(
+                "TypeError: Expected first argument to be an integer, a callable, or None",
+            )))
+        }
+    };
+    Ok(Value::NativeFunction(
+        NativeFunction::new("decorating_function", 1, move |_, args| {
+            if !args[0].is_callable() {
+                return Err(VmError::InvalidOperand(String::from(
+                    "TypeError: the first argument must be callable",
+                )));
+            }
+            Ok(Value::CachedFunction(
+                CachedFunction::new(args[0].clone(), maxsize).into(),
+            ))
+        })
+        .into(),
+    ))
+}
+
+// `reduce(function, iterable[, initial])`, folding the items from the left
+fn reduce(context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    check_arity("reduce", &args, 2, 3)?;
+    let items = iterator_of(context, &args[1])?;
+    let mut accumulator = match args.get(2) {
+        Some(initial) => initial.clone(),
+        None => match items.next_item(context)? {
+            Some(first) => first,
+            None => {
+                return Err(VmError::InvalidOperand(String::from(
+                    "TypeError: reduce() of empty iterable with no initial value",
+                )))
+            }
+        },
+    };
+    while let Some(item) = items.next_item(context)? {
+        accumulator = context.call(&args[0], vec![accumulator, item])?;
+    }
+    Ok(accumulator)
+}
@@ -0,0 +1,306 @@
+use std::collections::{HashMap, HashSet};
+use std::rc::{Rc, Weak};
+
+use crate::exception::Exception;
+use crate::object::Value;
+
+/// Stores into containers between collections, however small the heap.
+const MIN_THRESHOLD: usize = 1000;
+
+/// Shared state holding values the collector looks into. Those given
+/// values after their creation are tracked and cleared, as scripts can make
+/// them refer to themselves; the others, like iterators, only refer to
+/// values older than themselves, so clearing the tracked ones breaks every
+/// cycle running through them.
+pub(crate) trait Container {
+    /// Calls `visit` with every value held, false when the container is in
+    /// use and can't be looked into.
+    fn visit(&self, visit: &mut dyn FnMut(&Value)) -> bool;
+    /// Calls `visit` with a clone of every container held other than
+    /// through a value.
+    fn visit_containers(&self, _visit: &mut dyn FnMut(Rc<dyn Container>)) {}
+    /// Drops every value held.
+    fn clear(&self);
+}
+
+/// Collector of the reference cycles running through containers.
+///
+/// Roots aren't listed but found like CPython's collector finds them: an
+/// allocation with more references than the values reachable from the
+/// containers hold is referenced from the stack, frames, globals or the
+/// host, so it and what it reaches are alive. Containers left over only
+/// reference each other and get cleared, which breaks their cycles.
+pub(crate) struct Heap {
+    containers: HashMap<usize, Weak<dyn Container>>,
+    // stores since the last collection, and how many make for the next
+    stores: usize,
+    threshold: usize,
+}
+
+struct Node {
+    // references to the allocation, and how many of them the graph holds
+    refs: usize,
+    internal: usize,
+    edges: Vec<usize>,
+}
+
+enum Pending {
+    Value(Value),
+    Container(Rc<dyn Container>),
+}
+
+// allocations reachable from the containers and their references to each
+// other, clones of them waiting to be followed
+#[derive(Default)]
+struct Graph {
+    nodes: HashMap<usize, Node>,
+    pending: Vec<(usize, Pending)>,
+    // containers in use, alive whatever references them
+    busy: Vec<usize>,
+    // values looked at, what the walk costs
+    visited: usize,
+}
+
+impl Heap {
+    pub(crate) fn new() -> Heap {
+        Heap {
+            containers: HashMap::new(),
+            stores: 0,
+            threshold: MIN_THRESHOLD,
+        }
+    }
+
+    /// Notes a store into `container`, true once enough were made since the
+    /// last collection for another to be worth its walk over the heap.
+    pub(crate) fn track(&mut self, container: Rc<dyn Container>) -> bool {
+        self.containers
+            .entry(address(&container))
+            .or_insert_with(|| Rc::downgrade(&container));
+        self.stores += 1;
+        self.stores >= self.threshold
+    }
+
+    /// Clears the containers only reachable through reference cycles,
+    /// returning how many.
+    pub(crate) fn collect(&mut self) -> usize {
+        self.containers
+            .retain(|_, container| container.strong_count() > 0);
+        let containers: Vec<(usize, Rc<dyn Container>)> = self
+            .containers
+            .iter()
+            .filter_map(|(addr, container)| Some((*addr, container.upgrade()?)))
+            .collect();
+
+        let mut graph = Graph::default();
+        for (addr, container) in &containers {
+            // not counting the reference just upgraded
+            graph
+                .nodes
+                .insert(*addr, Node::new(Rc::strong_count(container) - 1));
+            graph
+                .pending
+                .push((*addr, Pending::Container(container.clone())));
+        }
+        while let Some((addr, pending)) = graph.pending.pop() {
+            graph.follow(addr, pending);
+        }
+
+        let reached = graph.reachable();
+        let garbage: Vec<&Rc<dyn Container>> = containers
+            .iter()
+            .filter(|(addr, _)| !reached.contains(addr))
+            .map(|(_, container)| container)
+            .collect();
+        garbage.iter().for_each(|container| container.clear());
+
+        self.stores = 0;
+        self.threshold = MIN_THRESHOLD.max(graph.visited);
+        garbage.len()
+    }
+}
+
+impl Node {
+    fn new(refs: usize) -> Node {
+        Node {
+            refs,
+            internal: 0,
+            edges: Vec::new(),
+        }
+    }
+}
+
+impl Graph {
+    // notes that `from` references `value`, following it the first time
+    fn reference(&mut self, from: usize, value: &Value) {
+        self.visited += 1;
+        let (addr, refs) = match value {
+            Value::List(items) | Value::Tuple(items) => (address(items), Rc::strong_count(items)),
+            Value::Partial(partial) => (address(partial), Rc::strong_count(partial)),
+            Value::Exception(exception) => (address(exception), Rc::strong_count(exception)),
+            Value::CachedFunction(cached) => (address(cached), Rc::strong_count(cached)),
+            Value::Iterator(iterator) => (address(iterator), Rc::strong_count(iterator)),
+            // hold no values, or only constants and host data no cycle runs
+            // through
+            _ => return,
+        };
+        if self.edge(from, addr, refs) {
+            self.pending.push((addr, Pending::Value(value.clone())));
+        }
+    }
+
+    // true when `to` wasn't seen before
+    fn edge(&mut self, from: usize, to: usize, refs: usize) -> bool {
+        if let Some(node) = self.nodes.get_mut(&from) {
+            node.edges.push(to);
+        }
+        let seen = self.nodes.contains_key(&to);
+        self.nodes
+            .entry(to)
+            .or_insert_with(|| Node::new(refs))
+            .internal += 1;
+        !seen
+    }
+
+    fn follow(&mut self, addr: usize, pending: Pending) {
+        match pending {
+            Pending::Container(container) => {
+                if !container.visit(&mut |value| self.reference(addr, value)) {
+                    self.busy.push(addr);
+                }
+                container.visit_containers(&mut |held| self.contain(addr, held));
+            }
+            Pending::Value(value) => match &value {
+                Value::List(items) | Value::Tuple(items) => {
+                    items.iter().for_each(|item| self.reference(addr, item))
+                }
+                Value::Partial(partial) => {
+                    self.reference(addr, &partial.function);
+                    partial
+                        .args
+                        .iter()
+                        .chain(partial.kwargs.iter().map(|(_, value)| value))
+                        .for_each(|value| self.reference(addr, value));
+                }
+                Value::Exception(exception) => self.exception(addr, exception),
+                Value::CachedFunction(cached) => {
+                    self.reference(addr, &cached.function);
+                    self.contain(addr, cached.container());
+                }
+                Value::Iterator(iterator) => self.contain(addr, iterator.container()),
+                _ => (),
+            },
+        }
+    }
+
+    // notes that `from` holds `container`, given a clone of it
+    fn contain(&mut self, from: usize, container: Rc<dyn Container>) {
+        // not counting the clone
+        let refs = Rc::strong_count(&container) - 1;
+        if self.edge(from, address(&container), refs) {
+            self.pending
+                .push((address(&container), Pending::Container(container)));
+        }
+    }
+
+    fn exception(&mut self, addr: usize, exception: &Exception) {
+        exception
+            .args
+            .iter()
+            .for_each(|value| self.reference(addr, value));
+        if let Some(cause) = &exception.cause {
+            self.exception(addr, cause);
+        }
+    }
+
+    // allocations referenced from outside the graph, what they reach and
+    // the containers in use
+    fn reachable(&self) -> HashSet<usize> {
+        let mut alive: Vec<usize> = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| node.refs > node.internal)
+            .map(|(addr, _)| *addr)
+            .chain(self.busy.iter().copied())
+            .collect();
+        let mut reached: HashSet<usize> = alive.iter().copied().collect();
+        while let Some(addr) = alive.pop() {
+            for edge in &self.nodes[&addr].edges {
+                if reached.insert(*edge) {
+                    alive.push(*edge);
+                }
+            }
+        }
+        reached
+    }
+}
+
+fn address<T: ?Sized>(rc: &Rc<T>) -> usize {
+    Rc::as_ptr(rc) as *const () as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::functools::{CachedFunction, Partial};
+    use crate::iterator::Iterator;
+
+    type Wrapper = fn(Value) -> Value;
+
+    #[test]
+    fn test_collect() {
+        vec![
+            (|value| value) as Wrapper,
+            |value| Value::Tuple(Rc::new(vec![Value::Integer(1), value])),
+            |value| {
+                Value::Partial(Rc::new(Partial {
+                    function: Box::new(Value::None),
+                    args: vec![value],
+                    kwargs: Vec::new(),
+                }))
+            },
+            |value| Value::Iterator(Rc::new(Iterator::map(value, Vec::new()))),
+            |value| {
+                let source = Iterator::repeat(value, None);
+                Value::Iterator(Rc::new(Iterator::filter(None, source)))
+            },
+        ]
+        .into_iter()
+        .for_each(|wrap| {
+            let mut heap = Heap::new();
+            let cached = Rc::new(CachedFunction::new(Value::None, None));
+            let value = Value::CachedFunction(cached.clone());
+            // the cache remembers a result referring back to it
+            cached
+                .store(&[Value::Integer(1)], &[], &wrap(value.clone()))
+                .unwrap();
+            heap.track(cached.container());
+            let weak = Rc::downgrade(&cached);
+            drop(cached);
+
+            assert_eq!(heap.collect(), 0);
+            assert!(weak.upgrade().is_some());
+            drop(value);
+            assert!(weak.upgrade().is_some());
+            assert_eq!(heap.collect(), 1);
+            assert!(weak.upgrade().is_none());
+            assert_eq!(heap.collect(), 0);
+        });
+    }
+
+    #[test]
+    fn test_collect_reachable() {
+        // a cycle kept alive by an outside reference into it survives
+        let mut heap = Heap::new();
+        let cached = Rc::new(CachedFunction::new(Value::None, None));
+        let list = Value::List(Rc::new(vec![Value::CachedFunction(cached.clone())]));
+        cached.store(&[], &[], &list).unwrap();
+        heap.track(cached.container());
+        let weak = Rc::downgrade(&cached);
+        drop(cached);
+        assert_eq!(heap.collect(), 0);
+        assert!(weak.upgrade().is_some());
+        drop(list);
+        assert_eq!(heap.collect(), 1);
+        assert!(weak.upgrade().is_none());
+    }
+}
@@ -0,0 +1,222 @@
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+use crate::native::{check_arity, NativeContext, NativeFunction, NativeResult, VARIADIC};
+use crate::object::Value;
+use crate::vm::VmError;
+
+pub const MODULE_NAME: &str = "hashlib";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Algorithm {
+    Md5,
+    Sha256,
+}
+
+impl Algorithm {
+    fn from_name(name: &str) -> Option<Algorithm> {
+        match name.to_lowercase().as_str() {
+            "md5" => Some(Algorithm::Md5),
+            "sha256" => Some(Algorithm::Sha256),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Algorithm::Md5 => "md5",
+            Algorithm::Sha256 => "sha256",
+        }
+    }
+
+    fn digest_size(&self) -> usize {
+        match self {
+            Algorithm::Md5 => 16,
+            Algorithm::Sha256 => 32,
+        }
+    }
+
+    fn block_size(&self) -> usize {
+        64
+    }
+}
+
+/// Hash object returned by `hashlib.sha256()` and friends. Copies share the
+/// data fed so far, which is behind a mutex as bound methods are natives.
+#[derive(Clone)]
+pub struct HashObject {
+    pub algorithm: Algorithm,
+    data: Arc<Mutex<Vec<u8>>>,
+}
+
+impl HashObject {
+    pub fn new(algorithm: Algorithm, data: &[u8]) -> HashObject {
+        HashObject {
+            algorithm,
+            data: Arc::new(Mutex::new(data.to_vec())),
+        }
+    }
+
+    pub fn update(&self, data: &[u8]) {
+        self.lock().extend_from_slice(data);
+    }
+
+    /// Digest of the data fed so far, which may still be added to.
+    pub fn digest(&self) -> Vec<u8> {
+        let data = self.lock();
+        match self.algorithm {
+            Algorithm::Md5 => Md5::digest(data.as_slice()).to_vec(),
+            Algorithm::Sha256 => Sha256::digest(data.as_slice()).to_vec(),
+        }
+    }
+
+    pub fn hexdigest(&self) -> String {
+        self.digest()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Independent hash object fed with the same data.
+    pub fn copy(&self) -> HashObject {
+        HashObject::new(self.algorithm, &self.lock())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<u8>> {
+        self.data
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // identity of the shared data, for equality and hashing
+    pub fn id(&self) -> usize {
+        Arc::as_ptr(&self.data) as usize
+    }
+}
+
+impl PartialEq for HashObject {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.data, &other.data)
+    }
+}
+
+impl PartialOrd for HashObject {
+    fn partial_cmp(&self, _other: &Self) -> Option<std::cmp::Ordering> {
+        None
+    }
+}
+
+impl Debug for HashObject {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "<{} _hashlib.HASH object @ {:#x}>",
+            self.algorithm.name(),
+            self.id()
+        )
+    }
+}
+
+/// Attributes of the built-in `hashlib` module.
+pub fn attributes() -> Vec<(String, Value)> {
+    vec![
+        NativeFunction::new("md5", VARIADIC, |context, args| {
+            hash(context, args, "md5", Algorithm::Md5)
+        })
+        .with_kwargs(&["data"]),
+        NativeFunction::new("sha256", VARIADIC, |context, args| {
+            hash(context, args, "sha256", Algorithm::Sha256)
+        })
+        .with_kwargs(&["data"]),
+        NativeFunction::new("new", VARIADIC, new).with_kwargs(&["data"]),
+    ]
+    .into_iter()
+    .map(|function| {
+        (
+            function.name.to_string(),
+            Value::NativeFunction(function.into()),
+        )
+    })
+    .collect()
+}
+
+// bytes to hash, refusing strings like CPython as their encoding is ambiguous
+fn data_arg(value: &Value) -> Result<&[u8], VmError> {
+    match value {
+        Value::Bytes(bytes) => Ok(bytes),
+        Value::String(_) => Err(VmError::InvalidOperand(String::from(
+            "TypeError: Strings must be encoded before hashing",
+        ))),
+        other => Err(VmError::InvalidOperand(format!(
+            "TypeError: object supporting the buffer API required, not '{}'",
+            other.type_name()
+        ))),
+    }
+}
+
+// `md5(data=b"")` and `sha256(data=b"")`
+fn hash(
+    context: &mut NativeContext,
+    args: Vec<Value>,
+    name: &str,
+    algorithm: Algorithm,
+) -> NativeResult {
+    check_arity(name, &args, 0, 1)?;
+    let data = match args.first().or(context.kwarg("data")) {
+        Some(data) => data_arg(data)?,
+        None => &[],
+    };
+    Ok(Value::HashObject(HashObject::new(algorithm, data).into()))
+}
+
+// `new(name, data=b"")`
+fn new(context: &mut NativeContext, mut args: Vec<Value>) -> NativeResult {
+    check_arity("new", &args, 1, 2)?;
+    let algorithm = match &args.remove(0) {
+        Value::String(name) => Algorithm::from_name(name).ok_or_else(|| {
+            VmError::ValueError(format!("ValueError: unsupported hash type {}", name))
+        })?,
+        other => {
+            return Err(VmError::InvalidOperand(format!(
+                "TypeError: new() argument 'name' must be str, not {}",
+                other.type_name()
+            )))
+        }
+    };
+    hash(context, args, "new", algorithm)
+}
+
+/// Method `name` of the hash object `receiver`, bound to it as a native
+/// closure.
+pub fn hash_method(receiver: &HashObject, name: &str) -> Option<NativeFunction> {
+    let hash = receiver.clone();
+    let method = match name {
+        "update" => NativeFunction::new("update", 1, move |_, args| {
+            hash.update(data_arg(&args[0])?);
+            Ok(Value::None)
+        }),
+        "digest" => NativeFunction::new("digest", 0, move |_, _| {
+            Ok(Value::Bytes(hash.digest().into()))
+        }),
+        "hexdigest" => NativeFunction::new("hexdigest", 0, move |_, _| {
+            Ok(Value::String(hash.hexdigest().into()))
+        }),
+        "copy" => NativeFunction::new("copy", 0, move |_, _| {
+            Ok(Value::HashObject(hash.copy().into()))
+        }),
+        _ => return None,
+    };
+    Some(method)
+}
+
+pub fn hash_attribute(hash: &HashObject, name: &str) -> Option<Value> {
+    match name {
+        "name" => Some(Value::String(hash.algorithm.name().to_string().into())),
+        "digest_size" => Some(Value::Integer(hash.algorithm.digest_size() as i64)),
+        "block_size" => Some(Value::Integer(hash.algorithm.block_size() as i64)),
+        _ => None,
+    }
+}
@@ -0,0 +1,373 @@
+use std::cell::RefCell;
+use std::fmt::Debug;
+use std::rc::Rc;
+
+use crate::bytecode::Bytecode;
+use crate::file::File;
+use crate::gc::Container;
+use crate::native::NativeContext;
+use crate::object::Value;
+use crate::vm::{binary_op, VmError};
+
+/// Iterator object. Copies share their position, so advancing one copy
+/// advances all of them like Python iterators.
+#[derive(Clone)]
+pub struct Iterator {
+    // type name scripts see, e.g. `list_reverseiterator`
+    pub name: &'static str,
+    state: Rc<RefCell<State>>,
+}
+
+#[derive(Clone)]
+enum State {
+    Items(std::vec::IntoIter<Value>),
+    // `function` applied to the next item of every source, until the
+    // shortest one runs out
+    Map {
+        function: Value,
+        sources: Vec<Iterator>,
+    },
+    // items of `source` for which `function` is truthy, or which are truthy
+    // themselves without one
+    Filter {
+        function: Option<Value>,
+        source: Iterator,
+    },
+    // remaining lines of a file, read as they are consumed
+    Lines(File),
+    // `next`, then `next + step` and so on without end, boxed as values
+    // are large
+    Count {
+        next: Box<Value>,
+        step: Box<Value>,
+    },
+    // `value` again `times` times, or forever without a count
+    Repeat {
+        value: Value,
+        times: Option<usize>,
+    },
+    // items of the first source until it runs out, then of the next one
+    Chain {
+        sources: Vec<Iterator>,
+    },
+    // items of `source` at indices `next`, `next + step` and so on before
+    // `stop`, `position` counting the items taken from `source` so far
+    Slice {
+        source: Iterator,
+        position: usize,
+        next: usize,
+        stop: Option<usize>,
+        step: usize,
+    },
+    // tuples of the cartesian product of the pools, `indices` selecting the
+    // next one until they are exhausted
+    Product {
+        pools: Vec<Vec<Value>>,
+        indices: Option<Vec<usize>>,
+    },
+}
+
+impl Iterator {
+    pub fn new(name: &'static str, items: Vec<Value>) -> Iterator {
+        Iterator::with_state(name, State::Items(items.into_iter()))
+    }
+
+    pub fn map(function: Value, sources: Vec<Iterator>) -> Iterator {
+        Iterator::with_state("map", State::Map { function, sources })
+    }
+
+    pub fn filter(function: Option<Value>, source: Iterator) -> Iterator {
+        Iterator::with_state("filter", State::Filter { function, source })
+    }
+
+    pub fn lines(file: File) -> Iterator {
+        Iterator::with_state("TextIOWrapper", State::Lines(file))
+    }
+
+    pub fn count(next: Value, step: Value) -> Iterator {
+        let state = State::Count {
+            next: Box::new(next),
+            step: Box::new(step),
+        };
+        Iterator::with_state("count", state)
+    }
+
+    pub fn repeat(value: Value, times: Option<usize>) -> Iterator {
+        Iterator::with_state("repeat", State::Repeat { value, times })
+    }
+
+    pub fn chain(sources: Vec<Iterator>) -> Iterator {
+        Iterator::with_state("chain", State::Chain { sources })
+    }
+
+    pub fn slice(source: Iterator, start: usize, stop: Option<usize>, step: usize) -> Iterator {
+        let state = State::Slice {
+            source,
+            position: 0,
+            next: start,
+            stop,
+            step,
+        };
+        Iterator::with_state("islice", state)
+    }
+
+    pub fn product(pools: Vec<Vec<Value>>) -> Iterator {
+        // the single empty tuple of no pools is returned like any other
+        let indices = match pools.iter().any(|pool| pool.is_empty()) {
+            true => None,
+            false => Some(vec![0; pools.len()]),
+        };
+        Iterator::with_state("product", State::Product { pools, indices })
+    }
+
+    fn with_state(name: &'static str, state: State) -> Iterator {
+        Iterator {
+            name,
+            state: Rc::new(RefCell::new(state)),
+        }
+    }
+
+    /// Advances the iterator, calling back into the VM for lazy ones. The
+    /// state isn't borrowed during those calls, so the callback may use the
+    /// iterator itself.
+    pub fn next_item(&self, context: &mut NativeContext) -> Result<Option<Value>, VmError> {
+        let state = match &mut *self.state.borrow_mut() {
+            State::Items(items) => return Ok(items.next()),
+            State::Lines(file) => {
+                let line = file.readline()?;
+                return Ok((!line.is_empty()).then_some(Value::String(line.into())));
+            }
+            State::Count { next, step } => {
+                let value = next.as_ref().clone();
+                **next = binary_op(&Bytecode::Add, &value, step)?;
+                return Ok(Some(value));
+            }
+            State::Repeat { value, times } => {
+                return Ok(match times {
+                    Some(0) => None,
+                    Some(times) => {
+                        *times -= 1;
+                        Some(value.clone())
+                    }
+                    None => Some(value.clone()),
+                })
+            }
+            State::Product { pools, indices } => return Ok(next_product(pools, indices)),
+            state => state.clone(),
+        };
+        match state {
+            State::Map { function, sources } => next_mapped(context, &function, &sources),
+            State::Filter { function, source } => {
+                next_filtered(context, function.as_ref(), &source)
+            }
+            State::Chain { sources } => self.next_chained(context, sources),
+            State::Slice {
+                source,
+                mut position,
+                next,
+                stop,
+                step,
+            } => {
+                if stop.is_some_and(|stop| next >= stop) {
+                    return Ok(None);
+                }
+                let mut item = None;
+                while position <= next {
+                    item = source.next_item(context)?;
+                    if item.is_none() {
+                        break;
+                    }
+                    position += 1;
+                }
+                if let State::Slice {
+                    position: shared_position,
+                    next: shared_next,
+                    ..
+                } = &mut *self.state.borrow_mut()
+                {
+                    *shared_position = position;
+                    *shared_next = next + step;
+                }
+                Ok(item)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // the next item of the first source that still has one, dropping the
+    // exhausted sources from the shared state
+    fn next_chained(
+        &self,
+        context: &mut NativeContext,
+        sources: Vec<Iterator>,
+    ) -> Result<Option<Value>, VmError> {
+        for source in sources {
+            if let Some(item) = source.next_item(context)? {
+                return Ok(Some(item));
+            }
+            if let State::Chain { sources } = &mut *self.state.borrow_mut() {
+                if sources.first() == Some(&source) {
+                    sources.remove(0);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    // identity of the shared state, for equality and hashing
+    pub fn id(&self) -> usize {
+        Rc::as_ptr(&self.state) as usize
+    }
+
+    /// The state copies share, for the collector to look into.
+    pub(crate) fn container(&self) -> Rc<dyn Container> {
+        self.state.clone()
+    }
+}
+
+// Lazy iterators hold the function they apply, so a cached function whose
+// results include them closes a cycle through them
+impl Container for RefCell<State> {
+    fn visit(&self, visit: &mut dyn FnMut(&Value)) -> bool {
+        let Ok(state) = self.try_borrow() else {
+            return false;
+        };
+        match &*state {
+            State::Items(items) => items.as_slice().iter().for_each(visit),
+            State::Map { function, .. } => visit(function),
+            State::Filter { function, .. } => function.iter().for_each(visit),
+            State::Count { next, step } => {
+                visit(next);
+                visit(step);
+            }
+            State::Repeat { value, .. } => visit(value),
+            State::Product { pools, .. } => pools.iter().flatten().for_each(visit),
+            State::Lines(_) | State::Chain { .. } | State::Slice { .. } => (),
+        }
+        true
+    }
+
+    fn visit_containers(&self, visit: &mut dyn FnMut(Rc<dyn Container>)) {
+        let Ok(state) = self.try_borrow() else {
+            return;
+        };
+        let sources = match &*state {
+            State::Map { sources, .. } | State::Chain { sources } => sources.as_slice(),
+            State::Filter { source, .. } | State::Slice { source, .. } => {
+                std::slice::from_ref(source)
+            }
+            _ => &[],
+        };
+        sources.iter().for_each(|source| visit(source.container()));
+    }
+
+    // never tracked, clearing the cached functions breaks the cycles
+    fn clear(&self) {}
+}
+
+// tuple of the pool items `indices` select, advancing them like an odometer
+// with the rightmost index moving fastest
+fn next_product(pools: &[Vec<Value>], indices: &mut Option<Vec<usize>>) -> Option<Value> {
+    let current = indices.as_mut()?;
+    let item = Value::Tuple(
+        current
+            .iter()
+            .zip(pools)
+            .map(|(index, pool)| pool[*index].clone())
+            .collect::<Vec<_>>()
+            .into(),
+    );
+    let mut position = current.len();
+    loop {
+        if position == 0 {
+            *indices = None;
+            break;
+        }
+        position -= 1;
+        current[position] += 1;
+        if current[position] < pools[position].len() {
+            break;
+        }
+        current[position] = 0;
+    }
+    Some(item)
+}
+
+fn next_mapped(
+    context: &mut NativeContext,
+    function: &Value,
+    sources: &[Iterator],
+) -> Result<Option<Value>, VmError> {
+    let mut args = Vec::new();
+    for source in sources {
+        match source.next_item(context)? {
+            Some(item) => args.push(item),
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(context.call(function, args)?))
+}
+
+fn next_filtered(
+    context: &mut NativeContext,
+    function: Option<&Value>,
+    source: &Iterator,
+) -> Result<Option<Value>, VmError> {
+    while let Some(item) = source.next_item(context)? {
+        let keep = match function {
+            Some(function) => context.call(function, vec![item.clone()])?.is_truthy(),
+            None => item.is_truthy(),
+        };
+        if keep {
+            return Ok(Some(item));
+        }
+    }
+    Ok(None)
+}
+
+impl PartialEq for Iterator {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.state, &other.state)
+    }
+}
+
+impl PartialOrd for Iterator {
+    fn partial_cmp(&self, _other: &Self) -> Option<std::cmp::Ordering> {
+        None
+    }
+}
+
+impl Debug for Iterator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<{} object at {:#x}>", self.name, self.id())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbol_table::SymbolTable;
+    use crate::vm::Vm;
+
+    #[test]
+    fn test_shared_position() {
+        let mut vm = Vm::new();
+        let mut globals = SymbolTable::new();
+        let builtins = SymbolTable::new();
+        let mut context = NativeContext::new(&mut vm, &mut globals, &builtins, Vec::new());
+        let iterator = Iterator::new("list_iterator", vec![Value::Integer(1), Value::Integer(2)]);
+        let copy = iterator.clone();
+        let filtered = Iterator::filter(None, iterator.clone());
+        assert_eq!(
+            iterator.next_item(&mut context).unwrap(),
+            Some(Value::Integer(1))
+        );
+        assert_eq!(
+            filtered.next_item(&mut context).unwrap(),
+            Some(Value::Integer(2))
+        );
+        assert_eq!(copy.next_item(&mut context).unwrap(), None);
+        assert_eq!(iterator, copy);
+        assert_ne!(iterator, filtered);
+    }
+}
@@ -0,0 +1,158 @@
+use crate::iterator::Iterator;
+use crate::native::{
+    check_arity, iterate, iterator_of, NativeContext, NativeFunction, NativeResult, VARIADIC,
+};
+use crate::object::Value;
+use crate::vm::VmError;
+
+pub const MODULE_NAME: &str = "itertools";
+
+/// Attributes of the built-in `itertools` module.
+pub fn attributes() -> Vec<(String, Value)> {
+    vec![
+        NativeFunction::new("count", VARIADIC, count).with_kwargs(&["start", "step"]),
+        NativeFunction::new("islice", VARIADIC, islice),
+        NativeFunction::new("chain", VARIADIC, chain),
+        NativeFunction::new("repeat", VARIADIC, repeat).with_kwargs(&["times"]),
+        NativeFunction::new("product", VARIADIC, product).with_kwargs(&["repeat"]),
+    ]
+    .into_iter()
+    .map(|function| {
+        (
+            function.name.to_string(),
+            Value::NativeFunction(function.into()),
+        )
+    })
+    .collect()
+}
+
+// positional argument `index` or keyword argument `name`
+fn optional_arg<'a>(
+    context: &'a NativeContext,
+    args: &'a [Value],
+    index: usize,
+    name: &str,
+) -> Option<&'a Value> {
+    args.get(index).or(context.kwarg(name))
+}
+
+// `count(start=0, step=1)`
+fn count(context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    check_arity("count", &args, 0, 2)?;
+    let mut bounds = Vec::new();
+    for (index, name) in ["start", "step"].into_iter().enumerate() {
+        let value = match optional_arg(context, &args, index, name) {
+            None => Value::Integer(if index == 0 { 0 } else { 1 }),
+            Some(value @ (Value::Integer(_) | Value::Float(_))) => value.clone(),
+            Some(Value::Bool(value)) => Value::Integer(*value as i64),
+            Some(_) => {
+                return Err(VmError::InvalidOperand(String::from(
+                    "TypeError: a number is required",
+                )))
+            }
+        };
+        bounds.push(value);
+    }
+    let step = bounds.pop().unwrap_or(Value::Integer(1));
+    let start = bounds.pop().unwrap_or(Value::Integer(0));
+    Ok(Value::Iterator(Iterator::count(start, step).into()))
+}
+
+// islice index argument: None or a non-negative integer
+fn slice_index(value: &Value, message: &str) -> Result<Option<usize>, VmError> {
+    match value {
+        Value::None => Ok(None),
+        Value::Integer(index) if *index >= 0 => Ok(Some(*index as usize)),
+        _ => Err(VmError::ValueError(format!("ValueError: {}", message))),
+    }
+}
+
+// `islice(iterable, stop)` or `islice(iterable, start, stop[, step])`
+fn islice(context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    check_arity("islice", &args, 2, 4)?;
+    const INDICES: &str = "Indices for islice() must be None or an integer: 0 <= x <= sys.maxsize.";
+    let (start, stop, step) = match &args[1..] {
+        [stop] => (
+            None,
+            slice_index(
+                stop,
+                "Stop argument for islice() must be None or an integer: 0 <= x <= sys.maxsize.",
+            )?,
+            None,
+        ),
+        [start, stop, rest @ ..] => (
+            slice_index(start, INDICES)?,
+            slice_index(stop, INDICES)?,
+            match rest.first() {
+                Some(step) => slice_index(
+                    step,
+                    "Step for islice() must be a positive integer or None.",
+                )?,
+                None => None,
+            },
+        ),
+        _ => unreachable!(),
+    };
+    if step == Some(0) {
+        return Err(VmError::ValueError(String::from(
+            "ValueError: Step for islice() must be a positive integer or None.",
+        )));
+    }
+    let source = iterator_of(context, &args[0])?;
+    Ok(Value::Iterator(
+        Iterator::slice(source, start.unwrap_or(0), stop, step.unwrap_or(1)).into(),
+    ))
+}
+
+// `chain(*iterables)`
+fn chain(context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    let sources = args
+        .iter()
+        .map(|arg| iterator_of(context, arg))
+        .collect::<Result<Vec<Iterator>, VmError>>()?;
+    Ok(Value::Iterator(Iterator::chain(sources).into()))
+}
+
+// `repeat(object[, times])`, negative counts repeating nothing
+fn repeat(context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    check_arity("repeat", &args, 1, 2)?;
+    let times = match optional_arg(context, &args, 1, "times") {
+        None => None,
+        Some(Value::Integer(times)) => Some((*times).max(0) as usize),
+        Some(other) => {
+            return Err(VmError::InvalidOperand(format!(
+                "TypeError: '{}' object cannot be interpreted as an integer",
+                other.type_name()
+            )))
+        }
+    };
+    Ok(Value::Iterator(
+        Iterator::repeat(args[0].clone(), times).into(),
+    ))
+}
+
+// `product(*iterables, repeat=1)`, consuming the iterables up front like
+// CPython since every item is used many times
+fn product(context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    let repeat = match context.kwarg("repeat") {
+        None => 1,
+        Some(Value::Integer(repeat)) if *repeat >= 0 => *repeat as usize,
+        Some(Value::Integer(_)) => {
+            return Err(VmError::ValueError(String::from(
+                "ValueError: repeat argument cannot be negative",
+            )))
+        }
+        Some(other) => {
+            return Err(VmError::InvalidOperand(format!(
+                "TypeError: '{}' object cannot be interpreted as an integer",
+                other.type_name()
+            )))
+        }
+    };
+    let pools = args
+        .iter()
+        .map(|arg| iterate(context, arg))
+        .collect::<Result<Vec<Vec<Value>>, VmError>>()?;
+    let pools = (0..repeat).flat_map(|_| pools.iter().cloned()).collect();
+    Ok(Value::Iterator(Iterator::product(pools).into()))
+}
@@ -0,0 +1,452 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::{types, AbiParam, Block, InstBuilder, MemFlags, Value};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::Module;
+
+use crate::bytecode::Bytecode;
+use crate::function::Function;
+use crate::object;
+use crate::register::{self, Instruction, Operand, RegisterCode};
+
+/// Native code of a function: it reads the arguments from the first
+/// pointer and writes the result to the second, returning `COMPLETED`, or
+/// `GAVE_UP` when an operation needs the interpreter, like an overflowing
+/// integer or a modulo by a negative number.
+pub type NativeCode = extern "C" fn(*const i64, *mut i64) -> i8;
+
+const COMPLETED: i8 = 0;
+const GAVE_UP: i8 = 1;
+
+/// Compiles the functions called often enough to native code.
+///
+/// Only functions computing on integers in their locals are compiled, so
+/// running their native code has no effect besides its result and giving
+/// up half way through can be followed by running them in the interpreter
+/// from the start.
+pub struct Jit {
+    // created with the first function compiled
+    module: Option<JITModule>,
+    // by function address, each entry holding its function so the address
+    // isn't reused
+    functions: HashMap<*const Function, Entry>,
+}
+
+struct Entry {
+    _function: Rc<Function>,
+    calls: usize,
+    // None until compiled, then None inside when it couldn't be
+    code: Option<Option<NativeCode>>,
+}
+
+impl Jit {
+    pub fn new() -> Jit {
+        Jit {
+            module: None,
+            functions: HashMap::new(),
+        }
+    }
+
+    /// Counts a call of `function`, returning its native code from the
+    /// `threshold`th call on. None while it isn't hot or when it can't be
+    /// compiled.
+    pub fn hot_code(&mut self, function: &Rc<Function>, threshold: usize) -> Option<NativeCode> {
+        let entry = self
+            .functions
+            .entry(Rc::as_ptr(function))
+            .or_insert_with(|| Entry {
+                _function: function.clone(),
+                calls: 0,
+                code: None,
+            });
+        if let Some(code) = entry.code {
+            return code;
+        }
+        entry.calls += 1;
+        if entry.calls < threshold {
+            return None;
+        }
+        let code = self.compile(function);
+        if let Some(entry) = self.functions.get_mut(&Rc::as_ptr(function)) {
+            entry.code = Some(code);
+        }
+        code
+    }
+
+    fn compile(&mut self, function: &Function) -> Option<NativeCode> {
+        if function.return_type.is_some() {
+            return None;
+        }
+        let registers = match &function.registers {
+            Some(registers) => registers.clone(),
+            None => register::lower(&function.chunk, function.arity)?,
+        };
+        if self.module.is_none() {
+            self.module = Some(new_module()?);
+        }
+        let module = self.module.as_mut()?;
+        compile_function(module, &registers, function.arity)
+    }
+}
+
+/// Runs `code` with `args`, None when it gave up.
+pub fn run(code: NativeCode, args: &[i64]) -> Option<i64> {
+    let mut result = 0;
+    match code(args.as_ptr(), &mut result) {
+        COMPLETED => Some(result),
+        _ => None,
+    }
+}
+
+fn new_module() -> Option<JITModule> {
+    let mut flags = settings::builder();
+    flags.set("use_colocated_libcalls", "false").ok()?;
+    flags.set("is_pic", "false").ok()?;
+    flags.set("opt_level", "speed").ok()?;
+    let isa = cranelift_native::builder()
+        .ok()?
+        .finish(settings::Flags::new(flags))
+        .ok()?;
+    let builder = JITBuilder::with_isa(isa, cranelift_module::default_libcall_names());
+    Some(JITModule::new(builder))
+}
+
+fn compile_function(
+    module: &mut JITModule,
+    code: &RegisterCode,
+    arity: usize,
+) -> Option<NativeCode> {
+    let mut context = module.make_context();
+    let pointer = module.target_config().pointer_type();
+    let signature = &mut context.func.signature;
+    signature.params.push(AbiParam::new(pointer));
+    signature.params.push(AbiParam::new(pointer));
+    signature.returns.push(AbiParam::new(types::I8));
+
+    let mut builder_context = FunctionBuilderContext::new();
+    let mut builder = FunctionBuilder::new(&mut context.func, &mut builder_context);
+    Translation::new(&mut builder, code, arity).translate(code)?;
+    builder.finalize();
+
+    let id = module
+        .declare_anonymous_function(&context.func.signature)
+        .ok()?;
+    module.define_function(id, &mut context).ok()?;
+    module.clear_context(&mut context);
+    module.finalize_definitions().ok()?;
+    let pointer = module.get_finalized_function(id);
+    // SAFETY: the function was just compiled with the signature of
+    // `NativeCode` and lives as long as the module
+    Some(unsafe { std::mem::transmute::<*const u8, NativeCode>(pointer) })
+}
+
+// builds the native code of register code, a Cranelift variable standing
+// for each register
+struct Translation<'a, 'b> {
+    builder: &'a mut FunctionBuilder<'b>,
+    // block starting at each jump target
+    blocks: HashMap<usize, Block>,
+    // returns `GAVE_UP`
+    give_up: Block,
+    result: Value,
+}
+
+impl<'a, 'b> Translation<'a, 'b> {
+    fn new(builder: &'a mut FunctionBuilder<'b>, code: &RegisterCode, arity: usize) -> Self {
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        let args = builder.block_params(entry)[0];
+        let result = builder.block_params(entry)[1];
+        for register in 0..code.registers {
+            let variable = variable(register);
+            builder.declare_var(variable, types::I64);
+            let value = match register < arity {
+                true => {
+                    builder
+                        .ins()
+                        .load(types::I64, MemFlags::trusted(), args, register as i32 * 8)
+                }
+                false => builder.ins().iconst(types::I64, 0),
+            };
+            builder.def_var(variable, value);
+        }
+        let mut blocks = HashMap::new();
+        for instruction in &code.instructions {
+            if let Instruction::Jump { target }
+            | Instruction::JumpIfFalse { target, .. }
+            | Instruction::CompareJump { target, .. } = instruction
+            {
+                blocks
+                    .entry(*target)
+                    .or_insert_with(|| builder.create_block());
+            }
+        }
+        let give_up = builder.create_block();
+        Translation {
+            builder,
+            blocks,
+            give_up,
+            result,
+        }
+    }
+
+    fn translate(mut self, code: &RegisterCode) -> Option<()> {
+        let mut terminated = false;
+        for (pc, instruction) in code.instructions.iter().enumerate() {
+            match self.blocks.get(&pc) {
+                Some(block) => {
+                    if !terminated {
+                        self.builder.ins().jump(*block, &[]);
+                    }
+                    self.builder.switch_to_block(*block);
+                }
+                // unreachable code still goes in a block
+                None if terminated => {
+                    let block = self.builder.create_block();
+                    self.builder.switch_to_block(block);
+                }
+                None => {}
+            }
+            terminated = self.translate_instruction(code, instruction)?;
+        }
+        if !terminated {
+            return None;
+        }
+        self.builder.switch_to_block(self.give_up);
+        let status = self.builder.ins().iconst(types::I8, GAVE_UP as i64);
+        self.builder.ins().return_(&[status]);
+        self.builder.seal_all_blocks();
+        Some(())
+    }
+
+    // true when the instruction ends its block
+    fn translate_instruction(
+        &mut self,
+        code: &RegisterCode,
+        instruction: &Instruction,
+    ) -> Option<bool> {
+        match instruction {
+            Instruction::Move { dst, src } => {
+                let value = self.operand(code, src)?;
+                self.builder.def_var(variable(*dst), value);
+            }
+            Instruction::InplaceAdd { dst, rhs } => {
+                let lhs = self.builder.use_var(variable(*dst));
+                let rhs = self.operand(code, rhs)?;
+                let value = self.arithmetic(&Bytecode::Add, lhs, rhs)?;
+                self.builder.def_var(variable(*dst), value);
+            }
+            Instruction::Unary {
+                op: Bytecode::Neg,
+                dst,
+                src,
+            } => {
+                let zero = self.builder.ins().iconst(types::I64, 0);
+                let src = self.operand(code, src)?;
+                let value = self.arithmetic(&Bytecode::Sub, zero, src)?;
+                self.builder.def_var(variable(*dst), value);
+            }
+            Instruction::Binary { op, dst, lhs, rhs } => {
+                let lhs = self.operand(code, lhs)?;
+                let rhs = self.operand(code, rhs)?;
+                let value = self.arithmetic(op, lhs, rhs)?;
+                self.builder.def_var(variable(*dst), value);
+            }
+            Instruction::Jump { target } => {
+                let block = *self.blocks.get(target)?;
+                self.builder.ins().jump(block, &[]);
+                return Some(true);
+            }
+            Instruction::JumpIfFalse { condition, target } => {
+                let condition = self.operand(code, condition)?;
+                self.branch_unless(condition, *target)?;
+            }
+            Instruction::CompareJump {
+                op,
+                lhs,
+                rhs,
+                target,
+            } => {
+                let lhs = self.operand(code, lhs)?;
+                let rhs = self.operand(code, rhs)?;
+                let condition = self.builder.ins().icmp(comparison(op)?, lhs, rhs);
+                self.branch_unless(condition, *target)?;
+            }
+            Instruction::Return { src } => {
+                let value = self.operand(code, src)?;
+                self.builder
+                    .ins()
+                    .store(MemFlags::trusted(), value, self.result, 0);
+                let status = self.builder.ins().iconst(types::I8, COMPLETED as i64);
+                self.builder.ins().return_(&[status]);
+                return Some(true);
+            }
+            // anything reaching outside the locals or producing something
+            // else than an integer
+            _ => return None,
+        }
+        Some(false)
+    }
+
+    // integer constants and registers, which only ever hold integers
+    fn operand(&mut self, code: &RegisterCode, operand: &Operand) -> Option<Value> {
+        match operand {
+            Operand::Register(register) => Some(self.builder.use_var(variable(*register))),
+            Operand::Constant(index) => match code.constants.get(*index)? {
+                object::Value::Integer(value) => {
+                    Some(self.builder.ins().iconst(types::I64, *value))
+                }
+                _ => None,
+            },
+        }
+    }
+
+    // `lhs op rhs` as `int_op` computes it, giving up where it wouldn't
+    fn arithmetic(&mut self, op: &Bytecode, lhs: Value, rhs: Value) -> Option<Value> {
+        let (value, overflow) = match op {
+            Bytecode::Add => self.builder.ins().sadd_overflow(lhs, rhs),
+            Bytecode::Sub => self.builder.ins().ssub_overflow(lhs, rhs),
+            Bytecode::Mul => self.builder.ins().smul_overflow(lhs, rhs),
+            Bytecode::Mod => {
+                let negative = self
+                    .builder
+                    .ins()
+                    .icmp_imm(IntCC::SignedLessThanOrEqual, rhs, 0);
+                self.give_up_if(negative);
+                let remainder = self.builder.ins().srem(lhs, rhs);
+                let wrapped = self.builder.ins().iadd(remainder, rhs);
+                let below = self
+                    .builder
+                    .ins()
+                    .icmp_imm(IntCC::SignedLessThan, remainder, 0);
+                return Some(self.builder.ins().select(below, wrapped, remainder));
+            }
+            _ => return None,
+        };
+        self.give_up_if(overflow);
+        Some(value)
+    }
+
+    fn give_up_if(&mut self, condition: Value) {
+        let next = self.builder.create_block();
+        self.builder
+            .ins()
+            .brif(condition, self.give_up, &[], next, &[]);
+        self.builder.switch_to_block(next);
+    }
+
+    // jumps to `target` when `condition` is zero
+    fn branch_unless(&mut self, condition: Value, target: usize) -> Option<()> {
+        let block = *self.blocks.get(&target)?;
+        let next = self.builder.create_block();
+        self.builder.ins().brif(condition, next, &[], block, &[]);
+        self.builder.switch_to_block(next);
+        Some(())
+    }
+}
+
+fn variable(register: usize) -> Variable {
+    Variable::from_u32(register as u32)
+}
+
+fn comparison(op: &Bytecode) -> Option<IntCC> {
+    let condition = match op {
+        Bytecode::Equal => IntCC::Equal,
+        Bytecode::NotEqual => IntCC::NotEqual,
+        Bytecode::Less => IntCC::SignedLessThan,
+        Bytecode::LessEqual => IntCC::SignedLessThanOrEqual,
+        Bytecode::Greater => IntCC::SignedGreaterThan,
+        Bytecode::GreaterEqual => IntCC::SignedGreaterThanOrEqual,
+        _ => return None,
+    };
+    Some(condition)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+
+    type Emitter = fn(&mut Chunk);
+
+    fn binary(op: Bytecode) -> Rc<Function> {
+        let mut function = Function::new(String::from("binary"));
+        function.arity = 2;
+        function.chunk.emit_with_index(Bytecode::GetLocal, 0);
+        function.chunk.emit_with_index(Bytecode::GetLocal, 1);
+        function.chunk.emit(op);
+        function.chunk.emit(Bytecode::Return);
+        Rc::new(function)
+    }
+
+    #[test]
+    fn test_run() {
+        let mut jit = Jit::new();
+        vec![
+            (Bytecode::Add, [2, 3], Some(5)),
+            (Bytecode::Sub, [2, 3], Some(-1)),
+            (Bytecode::Mul, [-4, 3], Some(-12)),
+            (Bytecode::Mod, [-7, 3], Some(2)),
+            // left to the interpreter
+            (Bytecode::Add, [i64::MAX, 1], None),
+            (Bytecode::Mul, [i64::MIN, -1], None),
+            (Bytecode::Mod, [7, -3], None),
+            (Bytecode::Mod, [7, 0], None),
+        ]
+        .into_iter()
+        .for_each(|(op, args, expected)| {
+            let code = jit.hot_code(&binary(op), 1).unwrap();
+            assert_eq!(run(code, &args), expected);
+        });
+    }
+
+    #[test]
+    fn test_hot_code() {
+        let mut jit = Jit::new();
+        let function = binary(Bytecode::Add);
+        assert!(jit.hot_code(&function, 3).is_none());
+        assert!(jit.hot_code(&function, 3).is_none());
+        assert!(jit.hot_code(&function, 3).is_some());
+        assert!(jit.hot_code(&function, 3).is_some());
+
+        let emitters: Vec<Emitter> = vec![
+            // globals
+            |chunk| {
+                chunk.emit_with_index(Bytecode::GetGlobal, 1);
+                chunk.emit(Bytecode::Return);
+            },
+            // values that aren't integers
+            |chunk| {
+                chunk.emit(Bytecode::PushTrue);
+                chunk.emit(Bytecode::Return);
+            },
+            // next to an equal int
+            |chunk| {
+                let one = chunk.add_constant(object::Value::Integer(1));
+                chunk.emit_with_index(Bytecode::GetLocal, 0);
+                chunk.emit_with_index(Bytecode::Const, one);
+                chunk.emit(Bytecode::Add);
+                chunk.emit(Bytecode::PushTrue);
+                chunk.emit(Bytecode::Add);
+                chunk.emit(Bytecode::Return);
+            },
+            |chunk| {
+                chunk.emit_with_index(Bytecode::GetLocal, 0);
+                chunk.emit_with_index(Bytecode::GetLocal, 0);
+                chunk.emit(Bytecode::Div);
+                chunk.emit(Bytecode::Return);
+            },
+        ];
+        emitters.into_iter().for_each(|emitter| {
+            let mut function = Function::new(String::from("uncompiled"));
+            function.arity = 1;
+            emitter(&mut function.chunk);
+            assert!(jit.hot_code(&Rc::new(function), 1).is_none());
+        });
+    }
+}
@@ -1,3 +1,4 @@
+use crate::diagnostic::Span;
 use crate::token::Token;
 
 impl Iterator for Lexer {
@@ -8,6 +9,8 @@ impl Iterator for Lexer {
             match self.last_token {
                 Token::Eof => return None,
                 _ => {
+                    let end = self.offsets[self.chars.len()];
+                    self.spans.push(Span::new(end, end));
                     if !self.indentation_stack.is_empty() {
                         self.indentation_stack.pop();
                         return Some(Token::Dedent);
@@ -18,7 +21,12 @@ impl Iterator for Lexer {
             }
         }
 
+        self.token_start = self.index;
         self.last_token = self.parse_token();
+        self.spans.push(Span::new(
+            self.offsets[self.token_start],
+            self.offsets[self.index.min(self.chars.len())],
+        ));
         Some(self.last_token.clone())
     }
 }
@@ -26,117 +34,354 @@ impl Iterator for Lexer {
 pub struct Lexer {
     index: usize,
     chars: Vec<char>,
-    indentation_stack: Vec<Token>,
+    // indentation widths of the open blocks, innermost last
+    indentation_stack: Vec<usize>,
+    // indentation width of the current line
     indentation_level: usize,
+    // whether the source indents with tabs, set by its first indented line
+    indents_with_tabs: Option<bool>,
+    // brackets left open, inside which newlines are just whitespace
+    bracket_depth: usize,
     last_token: Token,
+    // byte offset of every char, and of the end of the source
+    offsets: Vec<usize>,
+    // char index where the token being lexed starts
+    token_start: usize,
+    spans: Vec<Span>,
 }
 
 impl Lexer {
     pub fn new(source: &str) -> Lexer {
+        let mut offsets: Vec<usize> = source.char_indices().map(|(offset, _)| offset).collect();
+        offsets.push(source.len());
         Lexer {
             index: 0,
             chars: source.chars().collect(),
             indentation_stack: Vec::new(),
             indentation_level: 0,
+            indents_with_tabs: None,
+            bracket_depth: 0,
             last_token: Token::Empty,
+            offsets,
+            token_start: 0,
+            spans: Vec::new(),
         }
     }
 
+    /// All the tokens of the source along with their spans.
+    pub fn tokenize(mut self) -> (Vec<Token>, Vec<Span>) {
+        let tokens: Vec<Token> = self.by_ref().collect();
+        (tokens, self.spans)
+    }
+
     fn parse_token(&mut self) -> Token {
         let mut chr: char = match self.chars.get(self.index) {
             Some(chr) => *chr,
             None => return Token::Eof,
         };
 
-        if chr.is_whitespace() {
+        // comments and blank lines are whitespace, so they never indent
+        if chr.is_whitespace() || chr == '#' || self.is_line_continuation() {
             let start: usize = self.index;
-            while chr.is_whitespace() {
-                self.index += 1;
-                if chr == '\n' {
-                    return Token::NewLine;
+            loop {
+                if chr == '#' {
+                    while self.chars.get(self.index).is_some_and(|chr| *chr != '\n') {
+                        self.index += 1;
+                    }
+                } else if self.is_line_continuation() {
+                    // a backslash joins the next line to this one
+                    self.index += match self.chars.get(self.index + 1) {
+                        Some('\r') => 3,
+                        _ => 2,
+                    };
+                } else if chr.is_whitespace() {
+                    self.index += 1;
+                    // newlines inside brackets don't end the line either
+                    if chr == '\n' && self.bracket_depth == 0 {
+                        self.token_start = self.index - 1;
+                        return Token::NewLine;
+                    }
+                } else {
+                    break;
                 }
                 chr = match self.chars.get(self.index) {
                     Some(chr) => *chr,
-                    None => return Token::Eof,
+                    // the source ends the line, the blocks still open are
+                    // closed next
+                    None => return Token::NewLine,
                 };
             }
             if self.last_token == Token::NewLine {
-                let new_indentation_level = self.index - start;
-                if new_indentation_level > self.indentation_level {
-                    self.indentation_level = new_indentation_level;
-                    self.indentation_stack.push(Token::Indent);
-                    return Token::Indent;
-                }
-                if !self.indentation_stack.is_empty()
-                    && new_indentation_level < self.indentation_level
-                {
-                    self.indentation_level = new_indentation_level;
-                    self.indentation_stack.pop();
-                    return Token::Dedent;
+                if let Some(token) = self.parse_indentation(start) {
+                    return token;
                 }
             }
-        } else if !self.indentation_stack.is_empty()
-            && (self.indentation_level == 0 || self.last_token == Token::NewLine)
-        {
-            self.indentation_level = 0;
-            self.indentation_stack.pop();
-            return Token::Dedent;
+        } else {
+            if self.last_token == Token::NewLine {
+                self.indentation_level = 0;
+            }
+            // a line closing several blocks gets a Dedent for each of them
+            if self
+                .indentation_stack
+                .last()
+                .is_some_and(|width| *width > self.indentation_level)
+            {
+                self.indentation_stack.pop();
+                return Token::Dedent;
+            }
         }
 
+        // an indentation token spans the whitespace, any other starts here
+        self.token_start = self.index;
         if chr == '"' {
-            return self.parse_string();
+            return self.parse_string(false, false);
+        }
+        if let Some((raw, bytes, length)) = self.string_prefix() {
+            self.index += length;
+            return self.parse_string(raw, bytes);
         }
 
         if chr.is_ascii_punctuation() {
-            match self.parse_operator(chr) {
-                Some(token) => return token,
-                None => (),
+            if let Some(token) = self.parse_operator(chr) {
+                match token {
+                    Token::LeftParen | Token::LeftBracket | Token::LeftBrace => {
+                        self.bracket_depth += 1
+                    }
+                    Token::RightParen | Token::RightBracket | Token::RightBrace => {
+                        self.bracket_depth = self.bracket_depth.saturating_sub(1)
+                    }
+                    _ => {}
+                }
+                return token;
             };
         }
 
-        if chr.is_digit(10) {
+        if chr.is_ascii_digit() {
             return self.parse_numeric(chr);
         }
 
-        if chr == '_' || chr.is_alphabetic() {
-            match self.parse_keyword(chr) {
-                Some(token) => return token,
-                None => (),
-            }
-
+        if is_identifier_start(chr) {
             return self.parse_identifier(chr);
         }
 
+        self.index += 1;
         Token::Error(format!("Error: invalid character: {}", chr))
     }
 
-    fn parse_string(&mut self) -> Token {
+    // backslash at the end of a line
+    fn is_line_continuation(&self) -> bool {
+        self.chars.get(self.index) == Some(&'\\')
+            && match self.chars.get(self.index + 1) {
+                Some('\n') => true,
+                Some('\r') => self.chars.get(self.index + 2) == Some(&'\n'),
+                _ => false,
+            }
+    }
+
+    // Indent or Dedent for a line indented by the whitespace from `start`,
+    // None when it stays in the current block
+    fn parse_indentation(&mut self, start: usize) -> Option<Token> {
+        let whitespace = &self.chars[start..self.index];
+        let tabs = whitespace.contains(&'\t');
+        let indents_with_tabs = *self.indents_with_tabs.get_or_insert(tabs);
+        if tabs != indents_with_tabs || (tabs && whitespace.contains(&' ')) {
+            return Some(
+                self.indentation_error(start, "inconsistent use of tabs and spaces in indentation"),
+            );
+        }
+        let width = whitespace.len();
+        let current = self.indentation_stack.last().copied().unwrap_or(0);
+        if width > current {
+            self.indentation_level = width;
+            self.indentation_stack.push(width);
+            return Some(Token::Indent);
+        }
+        if width < current {
+            if !self.indentation_stack.contains(&width) {
+                return Some(self.indentation_error(
+                    start,
+                    "unindent does not match any outer indentation level",
+                ));
+            }
+            self.indentation_level = width;
+            self.indentation_stack.pop();
+            return Some(Token::Dedent);
+        }
+        None
+    }
+
+    fn indentation_error(&self, start: usize, message: &str) -> Token {
+        let (line, _) = self.line_column(start);
+        Token::Error(format!("IndentationError: {} at line {}", message, line))
+    }
+
+    // `r`, `b`, `rb` or `br` in any case right before the opening quote,
+    // returns whether it's raw, bytes and the prefix length
+    fn string_prefix(&self) -> Option<(bool, bool, usize)> {
+        let mut raw = false;
+        let mut bytes = false;
+        for length in 0..3 {
+            match self.chars.get(self.index + length)? {
+                '"' if length > 0 => return Some((raw, bytes, length)),
+                'r' | 'R' if !raw => raw = true,
+                'b' | 'B' if !bytes => bytes = true,
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    // raw strings keep backslashes, they still can't end on an escaped quote
+    fn parse_string(&mut self, raw: bool, bytes: bool) -> Token {
         let mut buffer = String::new();
+        // reported once the whole literal is consumed
+        let mut error: Option<&str> = None;
+        let start = self.index;
         self.index += 1;
         loop {
             let c: char = match self.chars.get(self.index) {
                 Some(c) => *c,
-                None => '"',
+                None => {
+                    let (line, column) = self.line_column(start);
+                    return Token::Error(format!(
+                        "SyntaxError: unterminated string literal at line {}, column {}",
+                        line, column
+                    ));
+                }
             };
             if c == '"' {
                 self.index += 1;
                 break;
             }
-            buffer.push(c);
             self.index += 1;
+            if bytes && !c.is_ascii() {
+                error = Some("SyntaxError: bytes can only contain ASCII literal characters");
+            }
+            if c != '\\' {
+                buffer.push(c);
+                continue;
+            }
+            let escaped = match self.chars.get(self.index) {
+                Some(escaped) => *escaped,
+                None => {
+                    buffer.push(c);
+                    continue;
+                }
+            };
+            self.index += 1;
+            match (raw, escaped) {
+                (false, 'n') => buffer.push('\n'),
+                (false, 't') => buffer.push('\t'),
+                (false, 'r') => buffer.push('\r'),
+                (false, '0') => buffer.push('\0'),
+                (false, '\\' | '"' | '\'') => buffer.push(escaped),
+                (false, 'x') => match self.parse_hex_escape(2) {
+                    Some(value) => buffer.push(char::from(value as u8)),
+                    None => error = Some("SyntaxError: truncated \\xXX escape"),
+                },
+                // bytes keep the unicode escapes as written, like in Python
+                (false, 'u' | 'U' | 'N') if !bytes => match self.parse_unicode_escape(escaped) {
+                    Ok(chr) => buffer.push(chr),
+                    Err(message) => error = Some(message),
+                },
+                // unknown escapes are kept as written, like in Python
+                _ => {
+                    buffer.push(c);
+                    buffer.push(escaped);
+                }
+            }
+        }
+        if let Some(error) = error {
+            return Token::Error(error.to_string());
+        }
+        if bytes {
+            // every char is ASCII or a \x escape, so it fits a byte
+            return Token::Bytes(buffer.chars().map(|chr| chr as u8).collect());
         }
         Token::String(buffer)
     }
 
+    // 1-based line and column of the char at `index`
+    fn line_column(&self, index: usize) -> (usize, usize) {
+        let before = &self.chars[..index];
+        let line = before.iter().filter(|chr| **chr == '\n').count() + 1;
+        let column = before.iter().rev().take_while(|chr| **chr != '\n').count() + 1;
+        (line, column)
+    }
+
+    // exactly `count` hex digits, consumed only when they are all there
+    fn parse_hex_escape(&mut self, count: usize) -> Option<u32> {
+        let digits: String = self.chars.iter().skip(self.index).take(count).collect();
+        if digits.len() != count || !digits.chars().all(|chr| chr.is_ascii_hexdigit()) {
+            return None;
+        }
+        self.index += count;
+        u32::from_str_radix(&digits, 16).ok()
+    }
+
+    // `\uXXXX`, `\UXXXXXXXX` or `\N{name}`, the letter already consumed
+    fn parse_unicode_escape(&mut self, kind: char) -> Result<char, &'static str> {
+        let code = match kind {
+            'u' => self
+                .parse_hex_escape(4)
+                .ok_or("SyntaxError: truncated \\uXXXX escape")?,
+            'U' => self
+                .parse_hex_escape(8)
+                .ok_or("SyntaxError: truncated \\UXXXXXXXX escape")?,
+            _ => {
+                const MALFORMED: &str = "SyntaxError: malformed \\N character escape";
+                if self.chars.get(self.index) != Some(&'{') {
+                    return Err(MALFORMED);
+                }
+                let name: String = self.chars[self.index + 1..]
+                    .iter()
+                    .take_while(|chr| **chr != '}' && **chr != '"')
+                    .collect();
+                let length = name.chars().count();
+                if length == 0 || self.chars.get(self.index + 1 + length) != Some(&'}') {
+                    return Err(MALFORMED);
+                }
+                self.index += length + 2;
+                return unicode_names2::character(&name)
+                    .ok_or("SyntaxError: unknown Unicode character name");
+            }
+        };
+        // surrogates and codes past U+10FFFF aren't scalar values
+        char::from_u32(code).ok_or("SyntaxError: illegal Unicode character")
+    }
+
     fn parse_operator(&mut self, chr: char) -> Option<Token> {
         let op = match chr {
             '+' => Some(Token::Plus),
-            '-' => Some(Token::Minus),
+            '-' => match self.chars.get(self.index + 1) {
+                Some(&'>') => {
+                    self.index += 1;
+                    Some(Token::Arrow)
+                }
+                _ => Some(Token::Minus),
+            },
             '*' => Some(Token::Star),
-            '/' => Some(Token::Slash),
+            '/' => match self.chars.get(self.index + 1) {
+                Some(&'/') => {
+                    self.index += 1;
+                    Some(Token::SlashSlash)
+                }
+                _ => Some(Token::Slash),
+            },
+            '%' => Some(Token::Percent),
             ':' => Some(Token::Colon),
+            ';' => Some(Token::Semicolon),
             ',' => Some(Token::Comma),
-            '.' => Some(Token::Dot),
+            '.' => match (
+                self.chars.get(self.index + 1),
+                self.chars.get(self.index + 2),
+            ) {
+                (Some(&'.'), Some(&'.')) => {
+                    self.index += 2;
+                    Some(Token::Ellipsis)
+                }
+                _ => Some(Token::Dot),
+            },
             '(' => Some(Token::LeftParen),
             ')' => Some(Token::RightParen),
             '[' => Some(Token::LeftBracket),
@@ -182,41 +427,11 @@ impl Lexer {
         }
     }
 
-    fn parse_keyword(&mut self, chr: char) -> Option<Token> {
-        match chr {
-            'a' => self.consume(Token::And, "and"),
-            'o' => self.consume(Token::Or, "or"),
-            'i' => match self.chars.get(self.index + 1) {
-                Some('f') => self.consume(Token::If, "if"),
-                Some('n') => self.consume(Token::In, "in"),
-                _ => None,
-            },
-            'e' => match self.chars.get(self.index + 1) {
-                Some('l') => match self.chars.get(self.index + 2) {
-                    Some('i') => self.consume(Token::Elif, "elif"),
-                    Some('s') => self.consume(Token::Else, "else"),
-                    _ => None,
-                },
-                _ => None,
-            },
-            'd' => self.consume(Token::Def, "def"),
-            'f' => self.consume(Token::For, "for"),
-            'F' => self.consume(Token::False, "False"),
-            'N' => self.consume(Token::None, "None"),
-            'w' => self.consume(Token::While, "while"),
-            'c' => self.consume(Token::Continue, "continue"),
-            'b' => self.consume(Token::Break, "break"),
-            'r' => self.consume(Token::Return, "return"),
-            'T' => self.consume(Token::True, "True"),
-            _ => None,
-        }
-    }
-
     fn parse_identifier(&mut self, chr: char) -> Token {
         let mut buffer = String::new();
         let mut c = chr;
         let mut idx = self.index;
-        while c.is_alphanumeric() || c == '_' {
+        while is_identifier_continue(c) {
             buffer.push(c);
             idx += 1;
             c = match self.chars.get(idx) {
@@ -224,15 +439,28 @@ impl Lexer {
                 None => break,
             };
         }
-        self.index += buffer.len();
-        Token::Identifier(buffer)
+        self.index = idx;
+        match KEYWORDS.iter().find(|(keyword, _)| *keyword == buffer) {
+            Some((_, token)) => token.clone(),
+            None => Token::Identifier(buffer),
+        }
     }
 
     fn parse_numeric(&mut self, chr: char) -> Token {
+        let radix = match (chr, self.chars.get(self.index + 1)) {
+            ('0', Some('x' | 'X')) => Some(16),
+            ('0', Some('o' | 'O')) => Some(8),
+            ('0', Some('b' | 'B')) => Some(2),
+            _ => None,
+        };
+        if let Some(radix) = radix {
+            return self.parse_radix_integer(radix);
+        }
+
         let mut buffer = String::new();
         let mut c = chr;
         let mut idx = self.index;
-        while c.is_numeric() || c == '.' {
+        while c.is_numeric() || c == '.' || c == '_' {
             buffer.push(c);
             idx += 1;
             c = match self.chars.get(idx) {
@@ -243,42 +471,104 @@ impl Lexer {
         self.index += buffer.len();
         match buffer.find('.') {
             Some(_) => {
-                let number: f64 = match buffer.parse() {
-                    Ok(number) => number,
-                    Err(_) => {
+                let number: f64 = match buffer
+                    .split('.')
+                    .map(|part| strip_separators(part, false))
+                    .collect::<Option<Vec<String>>>()
+                    .and_then(|parts| parts.join(".").parse().ok())
+                {
+                    Some(number) => number,
+                    None => {
                         return Token::Error(format!("Invalid float: {}", buffer));
                     }
                 };
                 Token::Float(number)
             }
             None => {
-                let number: i64 = match buffer.parse() {
-                    Ok(number) => number,
-                    Err(_) => {
-                        return Token::Error(format!("Invalid integer: {}", buffer));
-                    }
-                };
+                let number: i64 =
+                    match strip_separators(&buffer, false).and_then(|digits| digits.parse().ok()) {
+                        Some(number) => number,
+                        None => {
+                            return Token::Error(format!("Invalid integer: {}", buffer));
+                        }
+                    };
                 Token::Integer(number)
             }
         }
     }
 
-    fn consume(&mut self, token: Token, keyword: &str) -> Option<Token> {
-        if self.index + keyword.len() > self.chars.len() {
-            return None;
+    // `0x`, `0o` and `0b` literals; invalid digits are consumed so the
+    // whole literal is reported
+    fn parse_radix_integer(&mut self, radix: u32) -> Token {
+        let mut buffer: String = self.chars[self.index..self.index + 2].iter().collect();
+        let mut idx = self.index + 2;
+        while let Some(c) = self.chars.get(idx) {
+            if !c.is_ascii_alphanumeric() && *c != '_' {
+                break;
+            }
+            buffer.push(*c);
+            idx += 1;
         }
-        let sub_str: String = self.chars[self.index..(self.index + keyword.len())]
-            .into_iter()
-            .collect();
-        if sub_str == keyword.to_string() {
-            self.index += keyword.len();
-            Some(token)
-        } else {
-            None
+        self.index = idx;
+        match strip_separators(&buffer[2..], true)
+            .filter(|digits| !digits.is_empty())
+            .and_then(|digits| i64::from_str_radix(&digits, radix).ok())
+        {
+            Some(number) => Token::Integer(number),
+            None => Token::Error(format!("Invalid integer: {}", buffer)),
         }
     }
 }
 
+// Reserved words. Whole identifiers are looked up, so names merely starting
+// with one like `format` or `iffy` stay identifiers.
+const KEYWORDS: &[(&str, Token)] = &[
+    ("and", Token::And),
+    ("as", Token::As),
+    ("assert", Token::Assert),
+    ("break", Token::Break),
+    ("continue", Token::Continue),
+    ("def", Token::Def),
+    ("elif", Token::Elif),
+    ("else", Token::Else),
+    ("except", Token::Except),
+    ("False", Token::False),
+    ("for", Token::For),
+    ("from", Token::From),
+    ("if", Token::If),
+    ("import", Token::Import),
+    ("in", Token::In),
+    ("None", Token::None),
+    ("or", Token::Or),
+    ("raise", Token::Raise),
+    ("return", Token::Return),
+    ("True", Token::True),
+    ("try", Token::Try),
+    ("while", Token::While),
+];
+
+// identifiers follow Python's rules, XID_Start or an underscore and then
+// XID_Continue characters
+fn is_identifier_start(chr: char) -> bool {
+    chr == '_' || unicode_ident::is_xid_start(chr)
+}
+
+fn is_identifier_continue(chr: char) -> bool {
+    unicode_ident::is_xid_continue(chr)
+}
+
+/// Digits without their `_` separators, None when one is misplaced. A
+/// separator must sit between two digits, or right after a base prefix.
+fn strip_separators(digits: &str, after_prefix: bool) -> Option<String> {
+    let misplaced = digits.contains("__")
+        || digits.ends_with('_')
+        || (digits.starts_with('_') && !after_prefix);
+    if misplaced {
+        return None;
+    }
+    Some(digits.replace('_', ""))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -286,13 +576,14 @@ mod tests {
     #[test]
     fn test_operators() {
         let test_cases = vec![(
-            "+-*/:,.()[]{}===<=<>=>!=!",
+            "+-*/:;,.()[]{}===<=<>=>!=!->//...",
             vec![
                 Token::Plus,
                 Token::Minus,
                 Token::Star,
                 Token::Slash,
                 Token::Colon,
+                Token::Semicolon,
                 Token::Comma,
                 Token::Dot,
                 Token::LeftParen,
@@ -309,11 +600,14 @@ mod tests {
                 Token::Greater,
                 Token::BangEqual,
                 Token::Bang,
+                Token::Arrow,
+                Token::SlashSlash,
+                Token::Ellipsis,
                 Token::Eof,
             ],
         )];
         for (source, expected) in test_cases {
-            let actual: Vec<Token> = Lexer::new(source).into_iter().collect();
+            let actual: Vec<Token> = Lexer::new(source).collect();
             assert_eq!(actual, expected);
         }
     }
@@ -331,6 +625,47 @@ mod tests {
             ("continue", vec![Token::Continue, Token::Eof]),
             ("break", vec![Token::Break, Token::Eof]),
             ("return", vec![Token::Return, Token::Eof]),
+            ("import", vec![Token::Import, Token::Eof]),
+            ("assert", vec![Token::Assert, Token::Eof]),
+            (
+                "try except raise from as",
+                vec![
+                    Token::Try,
+                    Token::Except,
+                    Token::Raise,
+                    Token::From,
+                    Token::As,
+                    Token::Eof,
+                ],
+            ),
+            ("for while", vec![Token::For, Token::While, Token::Eof]),
+            (
+                "format iffy android inner definitely elsewhere whiley Nonesuch Truest x_if",
+                vec![
+                    Token::Identifier(String::from("format")),
+                    Token::Identifier(String::from("iffy")),
+                    Token::Identifier(String::from("android")),
+                    Token::Identifier(String::from("inner")),
+                    Token::Identifier(String::from("definitely")),
+                    Token::Identifier(String::from("elsewhere")),
+                    Token::Identifier(String::from("whiley")),
+                    Token::Identifier(String::from("Nonesuch")),
+                    Token::Identifier(String::from("Truest")),
+                    Token::Identifier(String::from("x_if")),
+                    Token::Eof,
+                ],
+            ),
+            (
+                "tryst exceptional raised fromage ask",
+                vec![
+                    Token::Identifier(String::from("tryst")),
+                    Token::Identifier(String::from("exceptional")),
+                    Token::Identifier(String::from("raised")),
+                    Token::Identifier(String::from("fromage")),
+                    Token::Identifier(String::from("ask")),
+                    Token::Eof,
+                ],
+            ),
             ("None", vec![Token::None, Token::Eof]),
             ("True", vec![Token::True, Token::Eof]),
             ("False", vec![Token::False, Token::Eof]),
@@ -356,29 +691,61 @@ mod tests {
         ]
         .into_iter()
         .for_each(|(source, expected)| {
-            let actual: Vec<Token> = Lexer::new(source).into_iter().collect();
+            let actual: Vec<Token> = Lexer::new(source).collect();
             assert_eq!(actual, expected);
         });
     }
 
     #[test]
     fn test_identifiers() {
-        vec![(
-            "if test s98_foo_bar elif s_100 else _",
-            vec![
-                Token::If,
-                Token::Identifier(String::from("test")),
-                Token::Identifier(String::from("s98_foo_bar")),
-                Token::Elif,
-                Token::Identifier(String::from("s_100")),
-                Token::Else,
-                Token::Identifier(String::from("_")),
-                Token::Eof,
-            ],
-        )]
+        vec![
+            (
+                "if test s98_foo_bar elif s_100 else _",
+                vec![
+                    Token::If,
+                    Token::Identifier(String::from("test")),
+                    Token::Identifier(String::from("s98_foo_bar")),
+                    Token::Elif,
+                    Token::Identifier(String::from("s_100")),
+                    Token::Else,
+                    Token::Identifier(String::from("_")),
+                    Token::Eof,
+                ],
+            ),
+            (
+                "format int",
+                vec![
+                    Token::Identifier(String::from("format")),
+                    Token::Identifier(String::from("int")),
+                    Token::Eof,
+                ],
+            ),
+            (
+                "größe = π + 変数 if ifé",
+                vec![
+                    Token::Identifier(String::from("größe")),
+                    Token::Equal,
+                    Token::Identifier(String::from("π")),
+                    Token::Plus,
+                    Token::Identifier(String::from("変数")),
+                    Token::If,
+                    Token::Identifier(String::from("ifé")),
+                    Token::Eof,
+                ],
+            ),
+            (
+                "a€b",
+                vec![
+                    Token::Identifier(String::from("a")),
+                    Token::Error(String::from("Error: invalid character: €")),
+                    Token::Identifier(String::from("b")),
+                    Token::Eof,
+                ],
+            ),
+        ]
         .into_iter()
         .for_each(|(source, expected)| {
-            let actual: Vec<Token> = Lexer::new(source).into_iter().collect();
+            let actual: Vec<Token> = Lexer::new(source).collect();
             assert_eq!(actual, expected);
         });
     }
@@ -399,10 +766,50 @@ mod tests {
                     Token::Eof,
                 ],
             ),
+            ("1_000_000", vec![Token::Integer(1000000), Token::Eof]),
+            ("1_000.000_1", vec![Token::Float(1000.0001), Token::Eof]),
+            ("0x_FF_ff", vec![Token::Integer(0xffff), Token::Eof]),
+            ("0o17", vec![Token::Integer(0o17), Token::Eof]),
+            ("0B1010", vec![Token::Integer(10), Token::Eof]),
+            (
+                "1__000",
+                vec![
+                    Token::Error(String::from("Invalid integer: 1__000")),
+                    Token::Eof,
+                ],
+            ),
+            (
+                "1000_",
+                vec![
+                    Token::Error(String::from("Invalid integer: 1000_")),
+                    Token::Eof,
+                ],
+            ),
+            (
+                "1_.5",
+                vec![
+                    Token::Error(String::from("Invalid float: 1_.5")),
+                    Token::Eof,
+                ],
+            ),
+            (
+                "0x_",
+                vec![
+                    Token::Error(String::from("Invalid integer: 0x_")),
+                    Token::Eof,
+                ],
+            ),
+            (
+                "0b102",
+                vec![
+                    Token::Error(String::from("Invalid integer: 0b102")),
+                    Token::Eof,
+                ],
+            ),
         ]
         .into_iter()
         .for_each(|(source, expected)| {
-            let actual: Vec<Token> = Lexer::new(source).into_iter().collect();
+            let actual: Vec<Token> = Lexer::new(source).collect();
             assert_eq!(actual, expected);
         });
     }
@@ -423,10 +830,92 @@ mod tests {
                     Token::Eof,
                 ],
             ),
+            (
+                r#""a\tb\n\"c\"\\ \d""#,
+                vec![Token::String(String::from("a\tb\n\"c\"\\ \\d")), Token::Eof],
+            ),
+            (
+                r#"r"regex\d+\n" R"\"""#,
+                vec![
+                    Token::String(String::from("regex\\d+\\n")),
+                    Token::String(String::from("\\\"")),
+                    Token::Eof,
+                ],
+            ),
+            (
+                r#"b"a\x00\xff" Rb"\x" bR"" "\x41""#,
+                vec![
+                    Token::Bytes(vec![b'a', 0, 0xff]),
+                    Token::Bytes(b"\\x".to_vec()),
+                    Token::Bytes(vec![]),
+                    Token::String(String::from("A")),
+                    Token::Eof,
+                ],
+            ),
+            (
+                "b\"é\" 1",
+                vec![
+                    Token::Error(String::from(
+                        "SyntaxError: bytes can only contain ASCII literal characters",
+                    )),
+                    Token::Integer(1),
+                    Token::Eof,
+                ],
+            ),
+            (
+                r#""\x4" 1"#,
+                vec![
+                    Token::Error(String::from("SyntaxError: truncated \\xXX escape")),
+                    Token::Integer(1),
+                    Token::Eof,
+                ],
+            ),
+            (
+                r#""é\U0001F600\N{GREEK SMALL LETTER ALPHA}" b"\u00e9""#,
+                vec![
+                    Token::String(String::from("é😀α")),
+                    Token::Bytes(b"\\u00e9".to_vec()),
+                    Token::Eof,
+                ],
+            ),
+            (
+                r#""\u12" "\ud800" "\N{NO SUCH NAME}" "\N""#,
+                vec![
+                    Token::Error(String::from("SyntaxError: truncated \\uXXXX escape")),
+                    Token::Error(String::from("SyntaxError: illegal Unicode character")),
+                    Token::Error(String::from("SyntaxError: unknown Unicode character name")),
+                    Token::Error(String::from("SyntaxError: malformed \\N character escape")),
+                    Token::Eof,
+                ],
+            ),
+            (
+                "x = 1\ny = b\"ab\\",
+                vec![
+                    Token::Identifier(String::from("x")),
+                    Token::Equal,
+                    Token::Integer(1),
+                    Token::NewLine,
+                    Token::Identifier(String::from("y")),
+                    Token::Equal,
+                    Token::Error(String::from(
+                        "SyntaxError: unterminated string literal at line 2, column 6",
+                    )),
+                    Token::Eof,
+                ],
+            ),
+            (
+                "r + return",
+                vec![
+                    Token::Identifier(String::from("r")),
+                    Token::Plus,
+                    Token::Return,
+                    Token::Eof,
+                ],
+            ),
         ]
         .into_iter()
         .for_each(|(source, expected)| {
-            let actual: Vec<Token> = Lexer::new(source).into_iter().collect();
+            let actual: Vec<Token> = Lexer::new(source).collect();
             assert_eq!(actual, expected);
         });
     }
@@ -535,11 +1024,190 @@ def test():
                     Token::Eof,
                 ],
             ),
+            (
+                "if a:\n  if b:\n    if c:\n      d\n  e\nf\n",
+                vec![
+                    Token::If,
+                    Token::Identifier(String::from("a")),
+                    Token::Colon,
+                    Token::NewLine,
+                    Token::Indent,
+                    Token::If,
+                    Token::Identifier(String::from("b")),
+                    Token::Colon,
+                    Token::NewLine,
+                    Token::Indent,
+                    Token::If,
+                    Token::Identifier(String::from("c")),
+                    Token::Colon,
+                    Token::NewLine,
+                    Token::Indent,
+                    Token::Identifier(String::from("d")),
+                    Token::NewLine,
+                    Token::Dedent,
+                    Token::Dedent,
+                    Token::Identifier(String::from("e")),
+                    Token::NewLine,
+                    Token::Dedent,
+                    Token::Identifier(String::from("f")),
+                    Token::NewLine,
+                    Token::Eof,
+                ],
+            ),
         ]
         .into_iter()
         .for_each(|(source, expected)| {
-            let actual: Vec<Token> = Lexer::new(source).into_iter().collect();
+            let actual: Vec<Token> = Lexer::new(source).collect();
             assert_eq!(actual, expected);
         });
     }
+
+    #[test]
+    fn test_line_continuation() {
+        vec![
+            (
+                "f(1,\n  2)\n",
+                vec![
+                    Token::Identifier(String::from("f")),
+                    Token::LeftParen,
+                    Token::Integer(1),
+                    Token::Comma,
+                    Token::Integer(2),
+                    Token::RightParen,
+                    Token::NewLine,
+                    Token::Eof,
+                ],
+            ),
+            (
+                "x = 1 + \\\n    2\ny\n",
+                vec![
+                    Token::Identifier(String::from("x")),
+                    Token::Equal,
+                    Token::Integer(1),
+                    Token::Plus,
+                    Token::Integer(2),
+                    Token::NewLine,
+                    Token::Identifier(String::from("y")),
+                    Token::NewLine,
+                    Token::Eof,
+                ],
+            ),
+            (
+                "x = 1 \\\r\n+ 2\r\n",
+                vec![
+                    Token::Identifier(String::from("x")),
+                    Token::Equal,
+                    Token::Integer(1),
+                    Token::Plus,
+                    Token::Integer(2),
+                    Token::NewLine,
+                    Token::Eof,
+                ],
+            ),
+            // the bracket closes before the next line, which is a block
+            (
+                "if (a\n):\n  b\n",
+                vec![
+                    Token::If,
+                    Token::LeftParen,
+                    Token::Identifier(String::from("a")),
+                    Token::RightParen,
+                    Token::Colon,
+                    Token::NewLine,
+                    Token::Indent,
+                    Token::Identifier(String::from("b")),
+                    Token::NewLine,
+                    Token::Dedent,
+                    Token::Eof,
+                ],
+            ),
+        ]
+        .into_iter()
+        .for_each(|(source, expected)| {
+            let actual: Vec<Token> = Lexer::new(source).collect();
+            assert_eq!(actual, expected, "{:?}", source);
+        });
+    }
+
+    #[test]
+    fn test_comments_and_blank_lines() {
+        let source =
+            "# f\ndef f():\n    # body\n\n        # deeper\n    x = 1  # one\n  \n    x\n# end";
+        let actual: Vec<Token> = Lexer::new(source)
+            .filter(|token| *token != Token::NewLine)
+            .collect();
+        assert_eq!(
+            actual,
+            vec![
+                Token::Def,
+                Token::Identifier(String::from("f")),
+                Token::LeftParen,
+                Token::RightParen,
+                Token::Colon,
+                Token::Indent,
+                Token::Identifier(String::from("x")),
+                Token::Equal,
+                Token::Integer(1),
+                Token::Identifier(String::from("x")),
+                Token::Dedent,
+                Token::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_indentation_errors() {
+        vec![
+            (
+                "if x:\n    y\n  z\n",
+                "IndentationError: unindent does not match any outer indentation level at line 3",
+            ),
+            (
+                "if x:\n\t y\n",
+                "IndentationError: inconsistent use of tabs and spaces in indentation at line 2",
+            ),
+            (
+                "if x:\n    y\nif z:\n\tw\n",
+                "IndentationError: inconsistent use of tabs and spaces in indentation at line 4",
+            ),
+        ]
+        .into_iter()
+        .for_each(|(source, expected)| {
+            let tokens: Vec<Token> = Lexer::new(source).collect();
+            assert!(
+                tokens.contains(&Token::Error(expected.to_string())),
+                "{:?}",
+                tokens
+            );
+        });
+
+        // consistent tabs are fine
+        let tokens: Vec<Token> = Lexer::new("if x:\n\tif y:\n\t\tz\n\tw\n").collect();
+        assert!(!tokens.iter().any(|token| matches!(token, Token::Error(_))));
+    }
+
+    #[test]
+    fn test_spans() {
+        // spans are byte ranges, 'é' taking two bytes
+        let (tokens, spans) = Lexer::new("x = \"é\"\nif y:\n  z").tokenize();
+        assert_eq!(tokens.len(), spans.len());
+        let actual: Vec<(usize, usize)> = spans.iter().map(|span| (span.start, span.end)).collect();
+        assert_eq!(
+            actual,
+            vec![
+                (0, 1),
+                (2, 3),
+                (4, 8),
+                (8, 9),
+                (9, 11),
+                (12, 13),
+                (13, 14),
+                (14, 15),
+                (15, 17),
+                (17, 18),
+                (18, 18),
+                (18, 18),
+            ]
+        );
+    }
 }
@@ -1,36 +1,81 @@
 mod ast;
+mod base64;
 mod bytecode;
+mod cache;
 mod chunk;
 mod compiler;
 pub mod config;
+pub mod diagnostic;
+pub mod diff;
 mod disassembler;
+mod exception;
+mod file;
+mod format;
 mod function;
+mod functools;
+mod gc;
+mod hashlib;
+mod iterator;
+mod itertools;
+#[cfg(feature = "jit")]
+mod jit;
 mod lexer;
+mod methods;
+mod module;
 mod native;
 pub mod object;
+mod optimizer;
 mod parser;
+pub mod policy;
+pub mod profile;
+pub mod program;
+mod random;
+mod re;
+mod register;
+pub mod replay;
+mod rpyc;
 mod symbol_table;
+mod sys;
+mod testing;
 mod token;
+mod typecheck;
+mod verifier;
 mod vm;
 
-use native::init_native_function_registry;
+use native::native_function_registry;
 use object::Object;
 use symbol_table::SymbolTable;
 
+use crate::ast::Program;
 use crate::compiler::{Compiler, CompilerError};
 use crate::config::Config;
+use crate::diagnostic::{Diagnostic, Span};
 use crate::disassembler::Disassembler;
 use crate::function::Function;
 use crate::lexer::Lexer;
 use crate::object::Value;
 use crate::parser::{Parser, ParserError};
+use crate::policy::{Decision, PolicyRequest};
+use crate::profile::Profile;
+use crate::program::{definitions, CompiledProgram};
+use crate::replay::ExecutionLog;
 use crate::token::Token;
-use crate::vm::{Vm, VmError};
+use crate::typecheck::TypeChecker;
+use crate::vm::Vm;
+
+pub use crate::cache::CACHE_DIR;
+pub use crate::native::{register_global_native, NativeContext, NativeFunction, NativeResult};
+pub use crate::rpyc::{is_compiled, EXTENSION as RPYC_EXTENSION};
+pub use crate::sys::VERSION;
+pub use crate::vm::VmError;
 use log::{trace, LevelFilter};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
 
 #[derive(Clone, Debug)]
 pub enum InterpreterError {
-    LexerError(String),
+    LexerError(String, Span),
     ParserError(ParserError),
     CompilerError(CompilerError),
     VmError(VmError),
@@ -39,83 +84,417 @@ pub enum InterpreterError {
 pub struct Interpreter {
     config: Config,
     globals: SymbolTable,
+    // looked up after globals, so scripts can shadow them
+    builtins: SymbolTable,
     source: String,
+    // line the VM error of the last run was raised at
+    error_line: Option<usize>,
     vm: Vm,
 }
 
 impl Interpreter {
     pub fn new(config: Config) -> Interpreter {
-        let native_function_registry = init_native_function_registry();
         let mut interpreter = Interpreter {
             config,
             globals: SymbolTable::new(),
+            builtins: SymbolTable::new(),
             source: String::new(),
+            error_line: None,
             vm: Vm::new(),
         };
-        for (name, native_function_obj) in native_function_registry {
-            interpreter.globals.insert(
-                &name,
-                Some(Object::new_with_name(
-                    name.to_string(),
-                    Value::NativeFunction(native_function_obj),
-                )),
-            );
+        for (_, native_function) in native_function_registry() {
+            interpreter.register_native(native_function);
+        }
+        // singletons scripts can only refer to by name
+        for value in [Value::Ellipsis, Value::NotImplemented] {
+            interpreter.builtins.insert(&value.to_string(), Some(value));
+        }
+        if interpreter.config.deterministic {
+            interpreter.vm.start_recording();
         }
 
         interpreter
     }
 
+    /// Capabilities of this build, for hosts that adapt to the interpreter
+    /// they embed. Optional cargo features are listed once enabled.
+    pub fn features() -> Vec<&'static str> {
+        vec![
+            "base64",
+            "files",
+            "functools",
+            "hashlib",
+            "imports",
+            "itertools",
+            "policy",
+            "profile",
+            "random",
+            "re",
+            "replay",
+            "sandbox-limits",
+            "sys",
+            "testing",
+            "typecheck",
+        ]
+    }
+
+    /// Registers a native builtin for this interpreter only, replacing any
+    /// builtin with the same name.
+    pub fn register_native(&mut self, native_function: NativeFunction) {
+        let name = native_function.name.to_string();
+        let value = Value::NativeFunction(native_function.into());
+        if self.builtins.contains_name(&name) {
+            let index = self.builtins.get_index(&name);
+            self.builtins
+                .set(index, Object::new_with_id(index, name, value));
+        } else {
+            self.builtins.insert(&name, Some(value));
+        }
+    }
+
+    /// Removes a builtin from this interpreter. Code compiled earlier that
+    /// still refers to it fails with a NameError when executed.
+    pub fn remove_builtin(&mut self, name: &str) -> bool {
+        if !self.builtins.contains_name(name) {
+            return false;
+        }
+        let index = self.builtins.get_index(name);
+        self.builtins.remove(index).is_some()
+    }
+
+    /// Results of the nondeterministic natives called so far, recorded when
+    /// `Config::deterministic` is set.
+    pub fn execution_log(&self) -> Option<&ExecutionLog> {
+        self.vm.execution_log()
+    }
+
+    /// Replays a recorded run: the following runs get the results in `log`
+    /// instead of calling nondeterministic natives, and fail once the script
+    /// calls them in a different order.
+    pub fn replay(&mut self, log: ExecutionLog) {
+        self.vm.start_replay(log);
+    }
+
+    /// Line of the source whose run last failed with a VM error the error
+    /// was raised at, None when it is unknown or the run didn't get that far.
+    pub fn error_line(&self) -> Option<usize> {
+        self.error_line
+    }
+
+    /// Diagnostic of `err` as returned by the last `run`, runtime errors
+    /// pointing at the line they were raised at.
+    pub fn diagnostic(&self, err: &InterpreterError) -> Diagnostic {
+        let diagnostic = err.diagnostic();
+        match self
+            .error_line
+            .and_then(|line| Span::line(&self.source, line))
+        {
+            Some(span) if diagnostic.span.is_none() => diagnostic.with_span(span),
+            _ => diagnostic,
+        }
+    }
+
+    /// Opcode and call site counts of the runs so far, collected when
+    /// `Config::profile_opcodes` is set.
+    pub fn profile(&self) -> &Profile {
+        self.vm.profile()
+    }
+
+    /// Installs a callback deciding on every native call and import of the
+    /// following runs; a denial raises PermissionError in the script.
+    pub fn set_policy<F>(&mut self, policy: F)
+    where
+        F: FnMut(&PolicyRequest) -> Decision + 'static,
+    {
+        self.vm.set_policy(Some(Box::new(policy)));
+    }
+
+    pub fn clear_policy(&mut self) {
+        self.vm.set_policy(None);
+    }
+
+    /// Redirects the output of `print` for the following runs, stdout by
+    /// default.
+    pub fn set_stdout(&mut self, stdout: Box<dyn Write>) {
+        self.vm.set_stdout(stdout);
+    }
+
+    /// Redirects what scripts write to `sys.stderr`, stderr by default.
+    pub fn set_stderr(&mut self, stderr: Box<dyn Write>) {
+        self.vm.set_stderr(stderr);
+    }
+
+    /// Frees the values scripts left only reachable through reference
+    /// cycles, returning how many containers were cleared to break them.
+    /// Runs by itself as scripts store into containers.
+    pub fn collect_garbage(&mut self) -> usize {
+        self.vm.collect_garbage()
+    }
+
+    /// Names of the modules imported so far, in no particular order.
+    pub fn loaded_modules(&self) -> Vec<String> {
+        self.vm.loaded_modules()
+    }
+
+    /// Names the scripts run so far defined at the top level, in the order
+    /// they were defined, for hosts completing REPL input.
+    pub fn global_names(&self) -> Vec<String> {
+        self.globals
+            .iter()
+            // module attributes are qualified by their module name
+            .filter(|object| !object.name.contains('.'))
+            .map(|object| object.name.to_string())
+            .collect()
+    }
+
+    /// Compiles `source` without running it or changing this interpreter's
+    /// globals.
+    pub fn check(&self, source: &str) -> Result<(), InterpreterError> {
+        let mut globals = self.globals.clone();
+        compile_source(source, &self.config, &mut globals, &self.builtins, None)?;
+        Ok(())
+    }
+
+    /// Reports the type errors found in `source` without running it. Only
+    /// syntax errors fail, code the checker can't infer types for passes.
+    pub fn typecheck(&self, source: &str) -> Result<Vec<Diagnostic>, InterpreterError> {
+        let program = parse_source(source, &self.config)?;
+        Ok(TypeChecker::new().check(&program))
+    }
+
+    /// Compiles `source` and lists the bytecode of the script followed by
+    /// every function it can call.
+    pub fn disassemble(&self, source: &str) -> Result<Vec<String>, InterpreterError> {
+        let mut globals = self.globals.clone();
+        let function = compile_source(source, &self.config, &mut globals, &self.builtins, None)?;
+        let mut functions: Vec<&Function> = globals
+            .iter()
+            .filter_map(|object| match &object.value {
+                Value::Function(function) => Some(function.as_ref()),
+                _ => None,
+            })
+            .chain(function.nested_functions())
+            .collect();
+        functions.sort_by(|lhs, rhs| lhs.name.cmp(&rhs.name));
+
+        let mut listing = Vec::new();
+        for function in std::iter::once(&function).chain(functions) {
+            listing.push(format!("{}:", function.name));
+            Disassembler::new(function.chunk.clone())
+                .disassemble()
+                .iter()
+                .for_each(|instr| listing.push(format!("  {:?}", instr)));
+        }
+        Ok(listing)
+    }
+
+    /// Compiles `source` into a program holding only the globals its code
+    /// can reach, without changing this interpreter's globals.
+    pub fn compile_program(&self, source: &str) -> Result<CompiledProgram, InterpreterError> {
+        let mut globals = self.globals.clone();
+        let function = compile_source(source, &self.config, &mut globals, &self.builtins, None)?;
+        let mut program = CompiledProgram::new(function, globals, self.config.opt_level);
+        program.prune();
+        program.link_builtins(&self.builtins);
+        Ok(program)
+    }
+
+    /// Recompiles `source` and swaps in its function definitions without
+    /// running it, so every other global keeps its current value. Returns
+    /// the names of the functions that were added or changed.
+    pub fn reload(&mut self, source: &str) -> Result<Vec<String>, InterpreterError> {
+        let mut globals = self.globals.clone();
+        let function = compile_source(source, &self.config, &mut globals, &self.builtins, None)?;
+        for definition in definitions(&function.chunk) {
+            if let Some(value) = function.chunk.constants.get(definition.constant as usize) {
+                globals.assign(definition.global, value.clone());
+            }
+        }
+
+        let mut reloaded = Vec::new();
+        for object in globals.iter() {
+            let current = self.globals.get(object.id);
+            match &object.value {
+                Value::Function(_) if current != Some(object) => {
+                    reloaded.push(object.name.to_string());
+                    self.globals.set(object.id, object.clone());
+                }
+                _ if current.is_none() && globals.is_bound(object.id) => {
+                    self.globals.set(object.id, object.clone())
+                }
+                // names first seen here, their assignments only run later
+                _ if current.is_none() => self.globals.declare(object.id, &object.name),
+                _ => (),
+            }
+        }
+        reloaded.sort();
+        Ok(reloaded)
+    }
+
+    /// Runs `source`, evaluating to the value of its last top-level
+    /// expression statement like the REPL echoes, None without one.
     pub fn run(&mut self, source: &str) -> Result<Value, InterpreterError> {
         self.source = String::from(source);
+        self.error_line = None;
         trace!("Config: {:?}", self.config);
 
-        let tokens: Vec<Token> = Lexer::new(&self.source).into_iter().collect();
-        trace!("Tokens: {:?}", tokens);
+        let function = compile_source(
+            &self.source,
+            &self.config,
+            &mut self.globals,
+            &self.builtins,
+            None,
+        )?;
+        self.execute(function)
+    }
+
+    /// Runs a program compiled ahead of time, the bytes
+    /// `CompiledProgram::to_bytes` wrote, without lexing or parsing. The
+    /// bytecode is verified and linked against this interpreter's globals
+    /// and builtins first, rejecting files that don't fit them.
+    pub fn run_bytecode(&mut self, bytes: &[u8]) -> Result<Value, InterpreterError> {
+        self.source = String::new();
+        self.error_line = None;
+        trace!("Config: {:?}", self.config);
 
-        self.check_lexer_errors(&tokens)?;
+        let function = self
+            .load_bytecode(bytes)
+            .map_err(|err| InterpreterError::VmError(VmError::InvalidBytecode(err)))?;
+        self.execute(function)
+    }
 
-        let mut parser = Parser::new(tokens);
-        let program = match parser.parse() {
-            Ok(program) => program,
-            Err(parser_error) => return Err(InterpreterError::ParserError(parser_error)),
+    /// Runs `source`, the script at `path`, like `run`. With
+    /// `Config::cache_bytecode` its bytecode is cached in `__rustpycache__`
+    /// next to it and reused while the source stays the same, on
+    /// interpreters that haven't run anything yet.
+    pub fn run_script(&mut self, path: &Path, source: &str) -> Result<Value, InterpreterError> {
+        let cache_path = cache::cache_path(path, source, &self.config);
+        let Some(cache_path) = cache_path.filter(|_| self.config.cache_bytecode) else {
+            return self.run(source);
         };
-        trace!("Program: {:?}", program);
+        self.source = String::from(source);
+        self.error_line = None;
+        trace!("Config: {:?}", self.config);
 
-        let mut compiler = Compiler::new(program, &mut self.globals);
-        let function = match compiler.compile() {
-            Ok(function) => function,
-            Err(compiler_error) => return Err(InterpreterError::CompilerError(compiler_error)),
-        };
+        // stale or corrupt entries are compiled again
+        if let Some(function) = fs::read(&cache_path)
+            .ok()
+            .and_then(|bytes| self.load_bytecode(&bytes).ok())
+        {
+            trace!("Cached: {}", cache_path.display());
+            return self.execute(function);
+        }
+        let fresh = self.globals.iter().next().is_none();
+        let mut globals = self.globals.clone();
+        let function = compile_source(source, &self.config, &mut globals, &self.builtins, None)?;
+        if fresh {
+            // unpruned, as scripts can see every global with `globals()`
+            let mut program =
+                CompiledProgram::new(function.clone(), globals.clone(), self.config.opt_level);
+            program.link_builtins(&self.builtins);
+            if let Err(err) = program
+                .to_bytes()
+                .and_then(|bytes| cache::write(&cache_path, &bytes).map_err(|err| err.to_string()))
+            {
+                trace!("Not cached: {}", err);
+            }
+        }
+        self.globals = globals;
+        self.execute(function)
+    }
+
+    // reads, verifies and links a program for `run_bytecode`
+    fn load_bytecode(&mut self, bytes: &[u8]) -> Result<Function, String> {
+        let program = CompiledProgram::from_bytes(bytes)?;
+        verifier::verify(&program)?;
+        // linked against a copy, so a failure doesn't leave names behind
+        let mut globals = self.globals.clone();
+        let function = program.link(&mut globals, &self.builtins, self.config.register_backend)?;
+        self.globals = globals;
 
         if log::max_level() == LevelFilter::Trace {
             disassemble_function(&function);
         }
+        Ok(function)
+    }
 
-        let result = match self
-            .vm
-            .interpret(self.config.clone(), &mut self.globals, function)
-        {
+    fn execute(&mut self, function: Function) -> Result<Value, InterpreterError> {
+        let result = match self.vm.interpret(
+            self.config.clone(),
+            &mut self.globals,
+            &self.builtins,
+            function,
+        ) {
             Ok(result) => result,
-            Err(vm_error) => return Err(InterpreterError::VmError(vm_error)),
+            Err(vm_error) => {
+                self.error_line = self.vm.error_line();
+                return Err(InterpreterError::VmError(vm_error));
+            }
         };
         trace!("Result: {:?}", result);
 
-        Ok(result.value)
+        Ok(result)
     }
+}
 
-    fn check_lexer_errors(&self, tokens: &Vec<Token>) -> Result<(), InterpreterError> {
-        if let Some(token_error) = tokens.iter().find(|&token| match token {
-            Token::Error(_) => true,
-            _ => false,
-        }) {
-            let error_message: String = match token_error {
-                Token::Error(error_message) => error_message.to_string(),
-                _ => String::from("Unknown lexer error"),
-            };
-            return Err(InterpreterError::LexerError(error_message));
-        }
-        Ok(())
+/// Lexes, parses and compiles `source` against `globals` and `builtins`.
+/// Module sources store their globals qualified by the module name.
+pub(crate) fn compile_source(
+    source: &str,
+    config: &Config,
+    globals: &mut SymbolTable,
+    builtins: &SymbolTable,
+    module: Option<&str>,
+) -> Result<Function, InterpreterError> {
+    let program = parse_source(source, config)?;
+    let mut compiler = match module {
+        Some(module) => Compiler::new_module(program, globals, builtins, module),
+        None => Compiler::new(program, globals, builtins),
+    }
+    .with_source(source)
+    .with_peephole(config.peephole)
+    .with_opt_level(config.opt_level)
+    .with_register_backend(config.register_backend);
+    let function = match compiler.compile() {
+        Ok(function) => function,
+        Err(compiler_error) => return Err(InterpreterError::CompilerError(compiler_error)),
+    };
+
+    if log::max_level() == LevelFilter::Trace {
+        disassemble_function(&function);
+    }
+    Ok(function)
+}
+
+fn parse_source(source: &str, config: &Config) -> Result<Program, InterpreterError> {
+    let (tokens, spans) = Lexer::new(source).tokenize();
+    trace!("Tokens: {:?}", tokens);
+
+    check_lexer_errors(&tokens, &spans)?;
+
+    let mut parser =
+        Parser::new_with_limits(tokens, spans, config.max_parse_depth, config.max_tokens);
+    let program = match parser.parse() {
+        Ok(program) => program,
+        Err(parser_error) => return Err(InterpreterError::ParserError(parser_error)),
+    };
+    trace!("Program: {:?}", program);
+    Ok(program)
+}
+
+fn check_lexer_errors(tokens: &[Token], spans: &[Span]) -> Result<(), InterpreterError> {
+    if let Some((token_error, span)) = tokens
+        .iter()
+        .zip(spans)
+        .find(|(token, _)| matches!(token, Token::Error(_)))
+    {
+        let error_message: String = match token_error {
+            Token::Error(error_message) => error_message.to_string(),
+            _ => String::from("Unknown lexer error"),
+        };
+        return Err(InterpreterError::LexerError(error_message, *span));
     }
+    Ok(())
 }
 
 fn disassemble_function(function: &Function) {
@@ -1,21 +1,146 @@
-use clap::Parser;
-use log::{error, trace, LevelFilter};
-use rustpy::config::Config;
+use clap::{Parser, Subcommand};
+use log::{error, info, trace, LevelFilter};
+use rustpy::config::{Config, DEFAULT_MAX_STACK_SIZE, DEFAULT_OPT_LEVEL, MAX_OPT_LEVEL};
 use simple_logger::SimpleLogger;
 use std::fs;
-use std::io::{self, Error, Write};
+use std::io::{self, Error, ErrorKind, Write};
+use std::path::Path;
+use std::process;
+use std::time::{Duration, Instant};
 
-use rustpy::Interpreter;
+use rustpy::diagnostic::Diagnostic;
+use rustpy::object::Value;
+use rustpy::replay::ExecutionLog;
+use rustpy::{is_compiled, Interpreter, InterpreterError, VmError, RPYC_EXTENSION};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about=None)]
-struct Args {
-    // Load script
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    // Load script, same as `run <path>`; script arguments named like a
+    // subcommand need the explicit `run`
     path: Option<String>,
 
+    // Script arguments, `sys.argv[1:]`
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true, requires = "path")]
+    args: Vec<String>,
+
+    #[command(flatten)]
+    config: ConfigArgs,
+}
+
+// Shared by every subcommand
+#[derive(clap::Args, Debug)]
+struct ConfigArgs {
     // dump trace information, instructions, disassembly, etc.
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     trace: bool,
+
+    // sandbox: maximum bytes scripts may write to stdout
+    #[arg(long, global = true)]
+    max_output_bytes: Option<usize>,
+
+    // sandbox: maximum number of print calls
+    #[arg(long, global = true)]
+    max_print_calls: Option<usize>,
+
+    // sandbox: maximum number of values on the VM operand stack
+    #[arg(long, global = true)]
+    max_stack_size: Option<usize>,
+
+    // raise TypeError on calls not matching the function annotations
+    #[arg(long, global = true)]
+    check_annotations: bool,
+
+    // write the results of nondeterministic calls to this file
+    #[arg(long, global = true, conflicts_with = "replay")]
+    record: Option<String>,
+
+    // rerun a script with the results recorded by --record
+    #[arg(long, global = true)]
+    replay: Option<String>,
+
+    // print per-opcode and per-call-site execution counts to stderr
+    #[arg(long, global = true)]
+    profile: bool,
+
+    // sandbox: let scripts read and write files with open()
+    #[arg(long, global = true)]
+    allow_file_io: bool,
+
+    // optimization level: -O0 compiles as written, -O1 folds constants and
+    // drops dead code, -O2 also runs the peephole optimizer and strips asserts
+    #[arg(short = 'O', global = true, default_value_t = DEFAULT_OPT_LEVEL,
+          value_parser = clap::value_parser!(u8).range(0..=MAX_OPT_LEVEL as i64))]
+    opt_level: u8,
+
+    // rewrite the compiled bytecode with the peephole optimizer
+    #[arg(long, global = true)]
+    peephole: bool,
+
+    // don't cache compiled scripts in __rustpycache__
+    #[arg(long, global = true)]
+    no_cache: bool,
+
+    // run functions on the experimental register VM where it can
+    #[arg(long, global = true)]
+    registers: bool,
+
+    // compile functions to native code after this many calls
+    #[cfg(feature = "jit")]
+    #[arg(long, global = true)]
+    jit_threshold: Option<usize>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run a script
+    Run {
+        path: String,
+        // Script arguments, `sys.argv[1:]`
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Start the interactive interpreter
+    Repl,
+    /// Print the bytecode of a script
+    Dis { path: String },
+    /// Compile a script without running it
+    Check { path: String },
+    /// Report type errors in a script without running it
+    Typecheck { path: String },
+    /// Time repeated runs of a script
+    Bench {
+        path: String,
+        #[arg(short = 'n', long, default_value_t = 10)]
+        iterations: usize,
+    },
+    /// Compile a script ahead of time
+    Build { path: String },
+}
+
+impl ConfigArgs {
+    fn to_config(&self) -> Config {
+        Config {
+            trace: self.trace,
+            max_output_bytes: self.max_output_bytes,
+            max_print_calls: self.max_print_calls,
+            max_stack_size: self.max_stack_size.or(Some(DEFAULT_MAX_STACK_SIZE)),
+            check_annotations: self.check_annotations,
+            deterministic: self.record.is_some(),
+            profile_opcodes: self.profile,
+            allow_file_io: self.allow_file_io,
+            peephole: self.peephole,
+            opt_level: self.opt_level,
+            register_backend: self.registers,
+            cache_bytecode: !self.no_cache,
+            #[cfg(feature = "jit")]
+            jit_threshold: self.jit_threshold,
+            ..Config::new()
+        }
+    }
 }
 
 fn read_source() -> Result<String, Error> {
@@ -37,23 +162,75 @@ fn read_source() -> Result<String, Error> {
     Ok(source.trim().to_string())
 }
 
-fn exec(path: String, config: Config) -> io::Result<()> {
-    let source = fs::read_to_string(path)?;
+// modules next to the script take precedence, like in CPython
+fn script_config(path: &str, mut config: Config) -> Config {
+    if let Some(script_dir) = Path::new(path).parent() {
+        config.search_path.insert(0, script_dir.to_path_buf());
+    }
+    config
+}
+
+fn exec(
+    path: String,
+    script_args: Vec<String>,
+    mut config: Config,
+    args: &ConfigArgs,
+) -> io::Result<()> {
+    let bytes = fs::read(&path)?;
 
-    let mut interpreter = Interpreter::new(config.clone());
-    match interpreter.run(&source) {
+    config.argv = [vec![path.clone()], script_args].concat();
+    let mut interpreter = Interpreter::new(script_config(&path, config));
+    if let Some(replay_path) = &args.replay {
+        match ExecutionLog::parse(&fs::read_to_string(replay_path)?) {
+            Ok(log) => interpreter.replay(log),
+            Err(err) => {
+                error!("Error: {}", err);
+                process::exit(1);
+            }
+        }
+    }
+    // `.rpyc` files built ahead of time run without their source
+    let (result, source) = match is_compiled(&bytes) {
+        true => (interpreter.run_bytecode(&bytes), String::new()),
+        false => {
+            let source =
+                String::from_utf8(bytes).map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+            (interpreter.run_script(Path::new(&path), &source), source)
+        }
+    };
+    let mut status = 0;
+    match result {
         Ok(value) => {
             trace!("Result: {:?}", value);
         }
+        Err(InterpreterError::VmError(VmError::SystemExit(exit_status))) => {
+            status = exit_status;
+        }
         Err(err) => {
-            error!("Error: {:?}", err);
+            eprint!("{}", interpreter.diagnostic(&err).render(&path, &source));
+            status = 1;
         }
     }
+    // also written for failed runs, those are the ones worth replaying
+    if let (Some(record_path), Some(log)) = (&args.record, interpreter.execution_log()) {
+        fs::write(record_path, log.to_string())?;
+    }
+    if args.profile {
+        eprint!("{}", interpreter.profile());
+    }
+    if status != 0 {
+        process::exit(status);
+    }
     Ok(())
 }
 
 fn repl(config: Config) -> io::Result<()> {
-    let mut interpreter = Interpreter::new(config.clone());
+    println!("Rust Python Interpreter");
+
+    let mut interpreter = Interpreter::new(Config {
+        argv: vec![String::new()],
+        ..config
+    });
     loop {
         print!("> ");
         io::stdout().flush()?;
@@ -65,33 +242,158 @@ fn repl(config: Config) -> io::Result<()> {
         match interpreter.run(&source) {
             Ok(value) => {
                 trace!("Result: {:?}", value);
+                // like Python, None results aren't echoed
+                if value != Value::None {
+                    println!("{}", value.repr());
+                }
             }
-            Err(err) => {
-                error!("Error: {:?}", err);
-            }
+            Err(InterpreterError::VmError(VmError::SystemExit(status))) => process::exit(status),
+            Err(err) => eprint!(
+                "{}",
+                interpreter.diagnostic(&err).render("<stdin>", &source)
+            ),
         };
     }
     Ok(())
 }
 
-fn main() -> io::Result<()> {
-    println!("Rust Python Interpreter");
+fn dis(path: String, config: Config) -> io::Result<()> {
+    let source = fs::read_to_string(&path)?;
 
-    SimpleLogger::new().init().unwrap();
+    let interpreter = Interpreter::new(script_config(&path, config));
+    match interpreter.disassemble(&source) {
+        Ok(listing) => listing.iter().for_each(|line| println!("{}", line)),
+        Err(err) => {
+            eprint!("{}", err.diagnostic().render(&path, &source));
+            process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+fn check(path: String, config: Config) -> io::Result<()> {
+    let source = fs::read_to_string(&path)?;
 
-    let cli = Args::parse();
+    let interpreter = Interpreter::new(script_config(&path, config));
+    if let Err(err) = interpreter.check(&source) {
+        eprint!("{}", err.diagnostic().render(&path, &source));
+        process::exit(1);
+    }
+    Ok(())
+}
+
+fn typecheck(path: String, config: Config) -> io::Result<()> {
+    let source = fs::read_to_string(&path)?;
+
+    let interpreter = Interpreter::new(script_config(&path, config));
+    match interpreter.typecheck(&source) {
+        Ok(diagnostics) => {
+            diagnostics.iter().for_each(|diagnostic| {
+                println!("{}: {}", location(&path, &source, diagnostic), diagnostic)
+            });
+            if !diagnostics.is_empty() {
+                process::exit(1);
+            }
+        }
+        Err(err) => {
+            let diagnostic = err.diagnostic();
+            println!("{}: {}", location(&path, &source, &diagnostic), diagnostic);
+            process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+// `path:line:column` where the diagnostic starts, or just the path
+fn location(path: &str, source: &str, diagnostic: &Diagnostic) -> String {
+    match diagnostic.span {
+        Some(span) => {
+            let (start, _) = span.line_col(source);
+            format!("{}:{}:{}", path, start.line, start.column)
+        }
+        None => path.to_string(),
+    }
+}
 
-    let config = Config { trace: cli.trace };
+fn bench(path: String, iterations: usize, config: Config) -> io::Result<()> {
+    let source = fs::read_to_string(&path)?;
+    let config = script_config(&path, config);
+
+    let mut timings: Vec<Duration> = Vec::new();
+    for _ in 0..iterations {
+        let mut interpreter = Interpreter::new(config.clone());
+        let start = Instant::now();
+        if let Err(err) = interpreter.run(&source) {
+            eprint!("{}", interpreter.diagnostic(&err).render(&path, &source));
+            process::exit(1);
+        }
+        timings.push(start.elapsed());
+    }
+    if let (Some(min), Some(max)) = (timings.iter().min(), timings.iter().max()) {
+        let total: Duration = timings.iter().sum();
+        info!(
+            "{} iterations, min: {:?} mean: {:?} max: {:?}",
+            iterations,
+            min,
+            total / iterations as u32,
+            max
+        );
+    }
+    Ok(())
+}
+
+// writes the compiled script next to it, `script.py` to `script.rpyc`
+fn build(path: String, config: Config) -> io::Result<()> {
+    let source = fs::read_to_string(&path)?;
+
+    let interpreter = Interpreter::new(script_config(&path, config));
+    let program = match interpreter.compile_program(&source) {
+        Ok(program) => program,
+        Err(err) => {
+            eprint!("{}", err.diagnostic().render(&path, &source));
+            process::exit(1);
+        }
+    };
+    match program.to_bytes() {
+        Ok(bytes) => fs::write(Path::new(&path).with_extension(RPYC_EXTENSION), bytes),
+        Err(err) => {
+            error!("Error: {}", err);
+            process::exit(1);
+        }
+    }
+}
+
+fn main() -> io::Result<()> {
+    // Cranelift logs every function the JIT compiles
+    SimpleLogger::new()
+        .with_module_level("cranelift", LevelFilter::Warn)
+        .init()
+        .unwrap();
+
+    let cli = Cli::parse();
+
+    let config = cli.config.to_config();
     if config.trace {
         log::set_max_level(LevelFilter::Trace);
     } else {
         log::set_max_level(LevelFilter::Info);
     }
 
-    if let Some(path) = cli.path {
-        exec(path, config)?;
-    } else {
-        repl(config)?;
+    let command = match (cli.command, cli.path) {
+        (Some(command), _) => command,
+        (None, Some(path)) => Command::Run {
+            path,
+            args: cli.args,
+        },
+        (None, None) => Command::Repl,
+    };
+    match command {
+        Command::Run { path, args } => exec(path, args, config, &cli.config),
+        Command::Repl => repl(config),
+        Command::Dis { path } => dis(path, config),
+        Command::Check { path } => check(path, config),
+        Command::Typecheck { path } => typecheck(path, config),
+        Command::Bench { path, iterations } => bench(path, iterations, config),
+        Command::Build { path } => build(path, config),
     }
-    Ok(())
 }
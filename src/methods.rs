@@ -0,0 +1,351 @@
+use crate::file::File;
+use crate::format::str_format;
+use crate::native::{check_arity, NativeFunction, NativeResult, VARIADIC};
+use crate::object::Value;
+use crate::vm::VmError;
+
+type StringMethod = fn(&str, &[Value]) -> NativeResult;
+
+/// Names of the string methods, as listed by `dir()`.
+pub const STRING_METHODS: &[&str] = &[
+    "format",
+    "encode",
+    "upper",
+    "lower",
+    "strip",
+    "split",
+    "join",
+    "replace",
+    "find",
+    "startswith",
+    "endswith",
+];
+
+/// Names of the bytes methods, as listed by `dir()`.
+pub const BYTES_METHODS: &[&str] = &["decode"];
+
+/// Names of the file methods, as listed by `dir()`.
+pub const FILE_METHODS: &[&str] = &["read", "readline", "write", "close"];
+
+/// Method `name` of the string `receiver`, bound to it as a native closure.
+pub fn string_method(receiver: &str, name: &str) -> Option<NativeFunction> {
+    let method: StringMethod = match name {
+        "format" => format,
+        "encode" => encode,
+        "upper" => upper,
+        "lower" => lower,
+        "strip" => strip,
+        "split" => split,
+        "join" => join,
+        "replace" => replace,
+        "find" => find,
+        "startswith" => startswith,
+        "endswith" => endswith,
+        _ => return None,
+    };
+    let receiver = receiver.to_string();
+    Some(NativeFunction::new(name, VARIADIC, move |_, args| {
+        method(&receiver, &args)
+    }))
+}
+
+fn format(receiver: &str, args: &[Value]) -> NativeResult {
+    Ok(Value::String(str_format(receiver, args)?.into()))
+}
+
+fn encode(receiver: &str, args: &[Value]) -> NativeResult {
+    let encoding = check_encoding("encode", args)?;
+    if encoding == "ascii" {
+        if let Some((position, chr)) = receiver
+            .chars()
+            .enumerate()
+            .find(|(_, chr)| !chr.is_ascii())
+        {
+            return Err(VmError::ValueError(format!(
+                "UnicodeEncodeError: 'ascii' codec can't encode character '{}' in position {}",
+                chr, position
+            )));
+        }
+    }
+    Ok(Value::Bytes(receiver.as_bytes().to_vec().into()))
+}
+
+fn upper(receiver: &str, args: &[Value]) -> NativeResult {
+    check_arity("upper", args, 0, 0)?;
+    Ok(Value::String(receiver.to_uppercase().into()))
+}
+
+fn lower(receiver: &str, args: &[Value]) -> NativeResult {
+    check_arity("lower", args, 0, 0)?;
+    Ok(Value::String(receiver.to_lowercase().into()))
+}
+
+// `chars` is a set of characters to remove, whitespace by default
+fn strip(receiver: &str, args: &[Value]) -> NativeResult {
+    check_arity("strip", args, 0, 1)?;
+    let stripped = match str_arg("strip", args, 0)? {
+        Some(chars) => receiver.trim_matches(|chr| chars.contains(chr)),
+        None => receiver.trim(),
+    };
+    Ok(Value::String(stripped.to_string().into()))
+}
+
+// `split(sep=None, maxsplit=-1)`, splitting on runs of whitespace without
+// `sep`
+fn split(receiver: &str, args: &[Value]) -> NativeResult {
+    check_arity("split", args, 0, 2)?;
+    let maxsplit = int_arg("split", args, 1)?.unwrap_or(-1);
+    let parts: Vec<&str> = match str_arg("split", args, 0)? {
+        Some("") => {
+            return Err(VmError::ValueError(String::from(
+                "ValueError: empty separator",
+            )))
+        }
+        Some(sep) if maxsplit < 0 => receiver.split(sep).collect(),
+        Some(sep) => receiver.splitn(maxsplit as usize + 1, sep).collect(),
+        None => split_whitespace(receiver, maxsplit),
+    };
+    Ok(Value::List(
+        parts
+            .into_iter()
+            .map(|part| Value::String(part.to_string().into()))
+            .collect::<Vec<_>>()
+            .into(),
+    ))
+}
+
+fn split_whitespace(text: &str, maxsplit: i64) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        if maxsplit >= 0 && parts.len() as i64 == maxsplit {
+            parts.push(rest);
+            break;
+        }
+        match rest.find(char::is_whitespace) {
+            Some(end) => {
+                parts.push(&rest[..end]);
+                rest = rest[end..].trim_start();
+            }
+            None => {
+                parts.push(rest);
+                break;
+            }
+        }
+    }
+    parts
+}
+
+fn join(receiver: &str, args: &[Value]) -> NativeResult {
+    check_arity("join", args, 1, 1)?;
+    let items: Vec<String> = match &args[0] {
+        Value::List(items) | Value::Tuple(items) => items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| match item {
+                Value::String(item) => Ok(item.to_string()),
+                other => Err(VmError::InvalidOperand(format!(
+                    "TypeError: sequence item {}: expected str instance, {} found",
+                    index,
+                    other.type_name()
+                ))),
+            })
+            .collect::<Result<_, _>>()?,
+        Value::String(text) => text.chars().map(String::from).collect(),
+        _ => {
+            return Err(VmError::InvalidOperand(String::from(
+                "TypeError: can only join an iterable",
+            )))
+        }
+    };
+    Ok(Value::String(items.join(receiver).into()))
+}
+
+// `replace(old, new, count=-1)`
+fn replace(receiver: &str, args: &[Value]) -> NativeResult {
+    check_arity("replace", args, 2, 3)?;
+    let (Some(old), Some(new)) = (str_arg("replace", args, 0)?, str_arg("replace", args, 1)?)
+    else {
+        return Err(VmError::InvalidOperand(String::from(
+            "TypeError: replace() argument must be str, not None",
+        )));
+    };
+    let replaced = match int_arg("replace", args, 2)? {
+        Some(count) if count >= 0 => receiver.replacen(old, new, count as usize),
+        _ => receiver.replace(old, new),
+    };
+    Ok(Value::String(replaced.into()))
+}
+
+// `find(sub, start=0)`, positions count characters like string indexing
+fn find(receiver: &str, args: &[Value]) -> NativeResult {
+    check_arity("find", args, 1, 2)?;
+    let sub = required_str_arg("find", args, 0)?;
+    let length = receiver.chars().count() as i64;
+    let start = match int_arg("find", args, 1)?.unwrap_or(0) {
+        start if start < 0 => (start + length).max(0),
+        start => start,
+    };
+    if start > length {
+        return Ok(Value::Integer(-1));
+    }
+    let offset = receiver
+        .char_indices()
+        .nth(start as usize)
+        .map_or(receiver.len(), |(offset, _)| offset);
+    let position = match receiver[offset..].find(sub) {
+        Some(found) => receiver[..offset + found].chars().count() as i64,
+        None => -1,
+    };
+    Ok(Value::Integer(position))
+}
+
+fn startswith(receiver: &str, args: &[Value]) -> NativeResult {
+    check_arity("startswith", args, 1, 1)?;
+    let prefix = required_str_arg("startswith", args, 0)?;
+    Ok(Value::new_from_bool(receiver.starts_with(prefix)))
+}
+
+fn endswith(receiver: &str, args: &[Value]) -> NativeResult {
+    check_arity("endswith", args, 1, 1)?;
+    let suffix = required_str_arg("endswith", args, 0)?;
+    Ok(Value::new_from_bool(receiver.ends_with(suffix)))
+}
+
+// optional string argument, None when missing or None
+fn str_arg<'a>(method: &str, args: &'a [Value], index: usize) -> Result<Option<&'a str>, VmError> {
+    match args.get(index) {
+        None | Some(Value::None) => Ok(None),
+        Some(Value::String(value)) => Ok(Some(value)),
+        Some(other) => Err(VmError::InvalidOperand(format!(
+            "TypeError: {}() argument {} must be str or None, not {}",
+            method,
+            index + 1,
+            other.type_name()
+        ))),
+    }
+}
+
+fn required_str_arg<'a>(method: &str, args: &'a [Value], index: usize) -> Result<&'a str, VmError> {
+    match args.get(index) {
+        Some(Value::String(value)) => Ok(value),
+        other => Err(VmError::InvalidOperand(format!(
+            "TypeError: {}() argument {} must be str, not {}",
+            method,
+            index + 1,
+            other.map_or("nothing", |value| value.type_name())
+        ))),
+    }
+}
+
+fn int_arg(method: &str, args: &[Value], index: usize) -> Result<Option<i64>, VmError> {
+    match args.get(index) {
+        None | Some(Value::None) => Ok(None),
+        Some(Value::Integer(value)) => Ok(Some(*value)),
+        Some(other) => Err(VmError::InvalidOperand(format!(
+            "TypeError: {}() argument {} must be int, not {}",
+            method,
+            index + 1,
+            other.type_name()
+        ))),
+    }
+}
+
+/// Method `name` of the bytes `receiver`, bound to it as a native closure.
+pub fn bytes_method(receiver: &[u8], name: &str) -> Option<NativeFunction> {
+    let receiver = receiver.to_vec();
+    match name {
+        "decode" => Some(NativeFunction::new("decode", VARIADIC, move |_, args| {
+            let encoding = check_encoding("decode", &args)?;
+            if encoding == "ascii" {
+                if let Some(position) = receiver.iter().position(|byte| !byte.is_ascii()) {
+                    return Err(VmError::ValueError(format!(
+                        "UnicodeDecodeError: 'ascii' codec can't decode byte {:#04x} in position {}",
+                        receiver[position], position
+                    )));
+                }
+            }
+            match String::from_utf8(receiver.clone()) {
+                Ok(text) => Ok(Value::String(text.into())),
+                Err(err) => {
+                    let position = err.utf8_error().valid_up_to();
+                    Err(VmError::ValueError(format!(
+                        "UnicodeDecodeError: 'utf-8' codec can't decode byte {:#04x} in position {}",
+                        receiver[position], position
+                    )))
+                }
+            }
+        })),
+        _ => None,
+    }
+}
+
+/// Method `name` of the file `receiver`, bound to it as a native closure.
+pub fn file_method(receiver: &File, name: &str) -> Option<NativeFunction> {
+    let file = receiver.clone();
+    let method = match name {
+        "read" => NativeFunction::new("read", VARIADIC, move |_, args| {
+            check_arity("read", &args, 0, 1)?;
+            let size = match args.first() {
+                None | Some(Value::None) => None,
+                Some(Value::Integer(size)) if *size < 0 => None,
+                Some(Value::Integer(size)) => Some(*size as usize),
+                Some(other) => {
+                    return Err(VmError::InvalidOperand(format!(
+                        "TypeError: argument should be integer or None, not '{}'",
+                        other.type_name()
+                    )))
+                }
+            };
+            Ok(Value::String(file.read(size)?.into()))
+        }),
+        "readline" => NativeFunction::new("readline", 0, move |_, _| {
+            Ok(Value::String(file.readline()?.into()))
+        }),
+        "write" => NativeFunction::new("write", 1, move |_, args| match &args[0] {
+            Value::String(text) => Ok(Value::Integer(file.write(text)? as i64)),
+            other => Err(VmError::InvalidOperand(format!(
+                "TypeError: write() argument must be str, not {}",
+                other.type_name()
+            ))),
+        }),
+        "close" => NativeFunction::new("close", 0, move |_, _| {
+            file.close();
+            Ok(Value::None)
+        }),
+        _ => return None,
+    };
+    Some(method)
+}
+
+// strings are stored as UTF-8, so only codecs that are a subset of it apply
+fn check_encoding(method: &str, args: &[Value]) -> Result<String, VmError> {
+    let encoding = match args {
+        [] => return Ok(String::from("utf-8")),
+        [encoding] => match encoding {
+            Value::String(encoding) => encoding.to_lowercase().replace('_', "-"),
+            other => {
+                return Err(VmError::InvalidOperand(format!(
+                    "TypeError: {}() argument 'encoding' must be str, not {}",
+                    method,
+                    other.type_name()
+                )))
+            }
+        },
+        _ => {
+            return Err(VmError::WrongArgumentCount(format!(
+                "TypeError: {}() takes at most 1 argument ({} given)",
+                method,
+                args.len()
+            )))
+        }
+    };
+    match encoding.as_str() {
+        "utf-8" | "utf8" => Ok(String::from("utf-8")),
+        "ascii" | "us-ascii" => Ok(String::from("ascii")),
+        _ => Err(VmError::ValueError(format!(
+            "LookupError: unknown encoding: {}",
+            encoding
+        ))),
+    }
+}
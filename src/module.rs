@@ -0,0 +1,127 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::base64;
+use crate::config::Config;
+use crate::function::Function;
+use crate::functools;
+use crate::hashlib;
+use crate::itertools;
+use crate::object::Object;
+use crate::random;
+use crate::re;
+use crate::symbol_table::SymbolTable;
+use crate::sys;
+use crate::testing;
+use crate::{compile_source, InterpreterError};
+
+pub const SEARCH_PATH_ENV: &str = "RUSTPYPATH";
+pub const MAIN_MODULE_NAME: &str = "__main__";
+pub const MODULE_NAME_GLOBAL: &str = "__name__";
+pub const DOC_GLOBAL: &str = "__doc__";
+pub const BUILTIN_MODULE_PATH: &str = "<built-in>";
+const SOURCE_EXTENSION: &str = "py";
+
+#[derive(Clone, Debug, PartialEq, PartialOrd)]
+pub struct Module {
+    pub name: String,
+    pub path: String,
+}
+
+impl Module {
+    pub fn new(name: &str, path: &str) -> Module {
+        Module {
+            name: name.to_string(),
+            path: path.to_string(),
+        }
+    }
+
+    pub fn attribute_name(&self, attribute: &str) -> String {
+        format!("{}.{}", self.name, attribute)
+    }
+
+    /// Whether `global_name` is `attribute_name(attribute)`, without
+    /// building it.
+    pub fn is_attribute_name(&self, global_name: &str, attribute: &str) -> bool {
+        global_name
+            .strip_prefix(self.name.as_str())
+            .and_then(|rest| rest.strip_prefix('.'))
+            == Some(attribute)
+    }
+}
+
+/// Directories searched for `import`: the configured paths, then the
+/// `RUSTPYPATH` entries, then the working directory.
+pub fn search_path(config: &Config) -> Vec<PathBuf> {
+    let mut paths = config.search_path.clone();
+    if let Some(env_paths) = env::var_os(SEARCH_PATH_ENV) {
+        paths.extend(env::split_paths(&env_paths));
+    }
+    paths.push(PathBuf::from("."));
+    paths
+}
+
+pub fn find_module(name: &str, config: &Config) -> Option<PathBuf> {
+    search_path(config)
+        .into_iter()
+        .map(|mut path| {
+            path.push(name);
+            path.set_extension(SOURCE_EXTENSION);
+            path
+        })
+        .find(|path| path.is_file())
+}
+
+/// Imports a module implemented natively, defining its attributes in
+/// `globals`. None when `name` isn't one.
+pub fn load_builtin_module(
+    name: &str,
+    config: &Config,
+    globals: &mut SymbolTable,
+) -> Option<Module> {
+    let attributes = match name {
+        sys::MODULE_NAME => sys::attributes(config),
+        functools::MODULE_NAME => functools::attributes(),
+        hashlib::MODULE_NAME => hashlib::attributes(),
+        base64::MODULE_NAME => base64::attributes(),
+        itertools::MODULE_NAME => itertools::attributes(),
+        random::MODULE_NAME => random::attributes(),
+        re::MODULE_NAME => re::attributes(),
+        testing::MODULE_NAME => testing::attributes(),
+        _ => return None,
+    };
+    let module = Module::new(name, BUILTIN_MODULE_PATH);
+    for (attribute, value) in attributes {
+        let attribute_name = module.attribute_name(&attribute);
+        if globals.contains_name(&attribute_name) {
+            let index = globals.get_index(&attribute_name);
+            globals.set(index, Object::new_with_id(index, attribute_name, value));
+        } else {
+            globals.insert(&attribute_name, Some(value));
+        }
+    }
+    Some(module)
+}
+
+pub fn load_module(
+    name: &str,
+    config: &Config,
+    globals: &mut SymbolTable,
+    builtins: &SymbolTable,
+) -> Result<(Module, Function), String> {
+    let path = match find_module(name, config) {
+        Some(path) => path,
+        None => return Err(format!("No module named '{}'", name)),
+    };
+    let source = match fs::read_to_string(&path) {
+        Ok(source) => source,
+        Err(err) => return Err(format!("Unable to read {}: {}", path.display(), err)),
+    };
+    let function = match compile_source(&source, config, globals, builtins, Some(name)) {
+        Ok(function) => function,
+        Err(InterpreterError::LexerError(err, _)) => return Err(err),
+        Err(err) => return Err(format!("{:?}", err)),
+    };
+    Ok((Module::new(name, &path.to_string_lossy()), function))
+}
@@ -1,15 +1,189 @@
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::fmt::Debug;
-use std::sync::Arc;
+use std::io::Write;
+use std::sync::{Arc, OnceLock, RwLock};
 
-use crate::object::Object;
+use crate::bytecode::Bytecode;
+use crate::config::Config;
+use crate::exception::exception_classes;
+use crate::file::File;
+use crate::iterator::Iterator;
+use crate::methods::{BYTES_METHODS, FILE_METHODS, STRING_METHODS};
 use crate::object::Value;
+use crate::random::Random;
+use crate::symbol_table::SymbolTable;
+use crate::sys;
+use crate::vm::{
+    binary_op, division_overflow, floor_div, floor_mod, integer_overflow, Vm, VmError,
+};
+
+pub type NativeResult = Result<Value, VmError>;
+pub type NativeFn = dyn Fn(&mut NativeContext, Vec<Value>) -> NativeResult + Send + Sync;
+pub type NativeRegistry = HashMap<String, NativeFunction>;
+
+// Arity value for natives accepting any number of arguments
+pub const VARIADIC: usize = usize::MAX;
+
+static GLOBAL_REGISTRY: OnceLock<RwLock<NativeRegistry>> = OnceLock::new();
 
 #[derive(Clone)]
 pub struct NativeFunction {
     pub name: String,
     pub arity: usize,
-    pub function: Arc<dyn Fn(Vec<Object>) -> Object + Send + Sync>,
+    // keyword arguments the native accepts, anything else is a TypeError
+    pub kwargs: Vec<String>,
+    // accepts any keyword argument, like `**kwargs`
+    pub any_kwargs: bool,
+    // results differ between runs (time, randomness, host state), so they
+    // are recorded and replayed in deterministic mode
+    pub nondeterministic: bool,
+    pub function: Arc<NativeFn>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct OutputStats {
+    pub bytes_written: usize,
+    pub print_calls: usize,
+}
+
+/// Interpreter state a native function is allowed to see, and the way back
+/// into the VM for natives taking callables.
+pub struct NativeContext<'a> {
+    vm: &'a mut Vm,
+    globals: &'a mut SymbolTable,
+    builtins: &'a SymbolTable,
+    // keyword arguments of the current call, in call order
+    pub kwargs: Vec<(String, Value)>,
+}
+
+impl NativeFunction {
+    pub fn new<F>(name: &str, arity: usize, function: F) -> NativeFunction
+    where
+        F: Fn(&mut NativeContext, Vec<Value>) -> NativeResult + Send + Sync + 'static,
+    {
+        NativeFunction {
+            name: name.to_string(),
+            arity,
+            kwargs: Vec::new(),
+            any_kwargs: false,
+            nondeterministic: false,
+            function: Arc::new(function),
+        }
+    }
+
+    pub fn with_kwargs(mut self, names: &[&str]) -> NativeFunction {
+        self.kwargs = names.iter().map(|name| name.to_string()).collect();
+        self
+    }
+
+    pub fn with_any_kwargs(mut self) -> NativeFunction {
+        self.any_kwargs = true;
+        self
+    }
+
+    pub fn nondeterministic(mut self) -> NativeFunction {
+        self.nondeterministic = true;
+        self
+    }
+}
+
+impl<'a> NativeContext<'a> {
+    pub(crate) fn new(
+        vm: &'a mut Vm,
+        globals: &'a mut SymbolTable,
+        builtins: &'a SymbolTable,
+        kwargs: Vec<(String, Value)>,
+    ) -> NativeContext<'a> {
+        NativeContext {
+            vm,
+            globals,
+            builtins,
+            kwargs,
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        self.vm.config()
+    }
+
+    pub fn output(&mut self) -> &mut OutputStats {
+        self.vm.output_mut()
+    }
+
+    /// Where `print` writes when no `file` is given.
+    pub fn stdout(&mut self) -> &mut dyn Write {
+        self.vm.stdout_mut()
+    }
+
+    /// Where `sys.stderr` and `print(file=sys.stderr)` write.
+    pub fn stderr(&mut self) -> &mut dyn Write {
+        self.vm.stderr_mut()
+    }
+
+    /// Calls a script function or native with positional `args` and returns
+    /// its result, e.g. the `key` of `max`.
+    pub fn call(&mut self, callable: &Value, args: Vec<Value>) -> NativeResult {
+        self.vm
+            .call_value(callable, args, Vec::new(), self.globals, self.builtins)
+    }
+
+    /// Generator shared by the `random` module functions.
+    pub(crate) fn random(&mut self) -> &mut Random {
+        self.vm.random_mut()
+    }
+
+    /// Names defined at the top level of the module the current function
+    /// belongs to, or of the main script.
+    pub fn global_names(&self) -> Vec<String> {
+        let module = self
+            .vm
+            .current_function()
+            .and_then(|function| function.module.clone());
+        self.module_names(module.as_deref())
+    }
+
+    /// Names bound at the top level of `module`, or of the main script for
+    /// None. Names the code only assigns later aren't included.
+    pub fn module_names(&self, module: Option<&str>) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .globals
+            .iter()
+            .filter(|object| self.globals.is_bound(object.id))
+            .filter_map(|object| match module {
+                Some(module) => object
+                    .name
+                    .strip_prefix(module)
+                    .and_then(|name| name.strip_prefix('.')),
+                None => Some(object.name.as_str()),
+            })
+            // module attributes are qualified by their module name
+            .filter(|name| !name.contains('.'))
+            .map(|name| name.to_string())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Names local to the current function that hold a value at the
+    /// current instruction, the global names at the top level of a script
+    /// or module.
+    pub fn local_names(&self) -> Vec<String> {
+        match self.vm.current_function() {
+            Some(function) if !function.is_global_scope() => {
+                function.chunk.locals_at(self.vm.current_ip() as u64)
+            }
+            _ => self.global_names(),
+        }
+    }
+
+    pub fn kwarg(&self, name: &str) -> Option<&Value> {
+        self.kwargs
+            .iter()
+            .find(|(kwarg, _)| kwarg == name)
+            .map(|(_, object)| object)
+    }
 }
 
 impl PartialEq for NativeFunction {
@@ -30,72 +204,1160 @@ impl Debug for NativeFunction {
     }
 }
 
-fn abs(args: Vec<Object>) -> Object {
-    let obj = &args[0];
-    match obj.value {
-        Value::Integer(value) => {
-            let value = if value < 0 { -value } else { value };
-            Object::new(Value::Integer(value))
-        }
+fn abs(_context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    let result = match args[0] {
+        Value::Integer(value) => Value::Integer(value.checked_abs().ok_or_else(integer_overflow)?),
         Value::Float(value) => {
             let value = if value < 0.0 { -value } else { value };
-            Object::new(Value::Float(value))
+            Value::Float(value)
+        }
+        _ => Value::Integer(0),
+    };
+    Ok(result)
+}
+
+/// Checks the number of arguments given to a variadic native or method.
+pub(crate) fn check_arity(
+    method: &str,
+    args: &[Value],
+    min: usize,
+    max: usize,
+) -> Result<(), VmError> {
+    if args.len() >= min && args.len() <= max {
+        return Ok(());
+    }
+    let expected = match (min, max) {
+        (0, 0) => String::from("no arguments"),
+        (min, max) if min == max => format!("exactly {} argument(s)", min),
+        (_, max) if args.len() > max => format!("at most {} argument(s)", max),
+        (min, _) => format!("at least {} argument(s)", min),
+    };
+    Err(VmError::WrongArgumentCount(format!(
+        "TypeError: {}() takes {} ({} given)",
+        method,
+        expected,
+        args.len()
+    )))
+}
+
+fn int(_context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    check_arity("int", &args, 0, 2)?;
+    let base = match args.get(1) {
+        None => None,
+        Some(Value::Integer(base)) if *base == 0 || (2..=36).contains(base) => Some(*base as u32),
+        Some(Value::Integer(_)) => {
+            return Err(VmError::ValueError(String::from(
+                "ValueError: int() base must be >= 2 and <= 36, or 0",
+            )))
+        }
+        Some(other) => {
+            return Err(VmError::InvalidOperand(format!(
+                "TypeError: '{}' object cannot be interpreted as an integer",
+                other.type_name()
+            )))
+        }
+    };
+    let value = match (args.first(), base) {
+        (None, _) => 0,
+        (Some(Value::String(text)), base) => match parse_int(text, base.unwrap_or(10)) {
+            Some(value) => value,
+            None => {
+                return Err(VmError::ValueError(format!(
+                    "ValueError: invalid literal for int() with base {}: {}",
+                    base.unwrap_or(10),
+                    Value::String(text.to_string().into()).repr()
+                )))
+            }
+        },
+        (Some(_), Some(_)) => {
+            return Err(VmError::InvalidOperand(String::from(
+                "TypeError: int() can't convert non-string with explicit base",
+            )))
+        }
+        (Some(Value::Integer(value)), None) => *value,
+        (Some(Value::Bool(value)), None) => *value as i64,
+        (Some(Value::Float(value)), None) => float_to_int(*value)?,
+        (Some(other), None) => {
+            return Err(VmError::InvalidOperand(format!(
+                "TypeError: int() argument must be a string, a bytes-like object or a real number, not '{}'",
+                other.type_name()
+            )))
+        }
+    };
+    Ok(Value::Integer(value))
+}
+
+// `int(text, base)`: surrounding whitespace, a sign, a base prefix matching
+// `base` (any prefix for base 0) and `_` between digits are accepted
+fn parse_int(text: &str, base: u32) -> Option<i64> {
+    let text = text.trim();
+    let (sign, digits) = match text.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", text.strip_prefix('+').unwrap_or(text)),
+    };
+    let prefix = digits.get(..2).map(|prefix| prefix.to_ascii_lowercase());
+    let (base, digits) = match (base, prefix.as_deref()) {
+        (0 | 16, Some("0x")) => (16, digits[2..].strip_prefix('_').unwrap_or(&digits[2..])),
+        (0 | 8, Some("0o")) => (8, digits[2..].strip_prefix('_').unwrap_or(&digits[2..])),
+        (0 | 2, Some("0b")) => (2, digits[2..].strip_prefix('_').unwrap_or(&digits[2..])),
+        (0, _) => (10, digits),
+        (base, _) => (base, digits),
+    };
+    let digits = strip_underscores(digits)?;
+    if digits.is_empty() || !digits.chars().all(|chr| chr.is_ascii_alphanumeric()) {
+        return None;
+    }
+    i64::from_str_radix(&format!("{}{}", sign, digits), base).ok()
+}
+
+// `_` may only separate two digits, like in numeric literals
+fn strip_underscores(text: &str) -> Option<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let is_digit = |index: Option<usize>| {
+        index
+            .and_then(|index| chars.get(index))
+            .is_some_and(|chr| chr.is_ascii_alphanumeric())
+    };
+    let misplaced = chars.iter().enumerate().any(|(index, chr)| {
+        *chr == '_' && !(is_digit(index.checked_sub(1)) && is_digit(Some(index + 1)))
+    });
+    if misplaced {
+        return None;
+    }
+    Some(text.replace('_', ""))
+}
+
+fn float_to_int(value: f64) -> Result<i64, VmError> {
+    if value.is_nan() {
+        return Err(VmError::ValueError(String::from(
+            "ValueError: cannot convert float NaN to integer",
+        )));
+    }
+    let truncated = value.trunc();
+    if truncated.is_infinite() || truncated < i64::MIN as f64 || truncated >= i64::MAX as f64 {
+        return Err(VmError::ValueError(String::from(
+            "OverflowError: cannot convert float to integer",
+        )));
+    }
+    Ok(truncated as i64)
+}
+
+fn float(_context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    check_arity("float", &args, 0, 1)?;
+    let value = match args.first() {
+        None => 0.0,
+        Some(Value::Float(value)) => *value,
+        Some(Value::Integer(value)) => *value as f64,
+        Some(Value::Bool(value)) => *value as i64 as f64,
+        Some(Value::String(text)) => match strip_underscores(text.trim())
+            .filter(|text| !text.is_empty())
+            .and_then(|text| text.parse::<f64>().ok())
+        {
+            Some(value) => value,
+            None => {
+                return Err(VmError::ValueError(format!(
+                    "ValueError: could not convert string to float: {}",
+                    Value::String(text.to_string().into()).repr()
+                )))
+            }
+        },
+        Some(other) => {
+            return Err(VmError::InvalidOperand(format!(
+                "TypeError: float() argument must be a string or a real number, not '{}'",
+                other.type_name()
+            )))
+        }
+    };
+    Ok(Value::Float(value))
+}
+
+fn str(_context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    check_arity("str", &args, 0, 1)?;
+    let text = match args.first() {
+        Some(arg) => arg.to_string(),
+        None => String::new(),
+    };
+    Ok(Value::String(text.into()))
+}
+
+fn bool(_context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    check_arity("bool", &args, 0, 1)?;
+    let value = args.first().is_some_and(|arg| arg.is_truthy());
+    Ok(Value::new_from_bool(value))
+}
+
+fn len(_context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    let length = match &args[0] {
+        Value::String(value) => value.chars().count(),
+        Value::Bytes(value) => value.len(),
+        Value::List(items) | Value::Tuple(items) => items.len(),
+        other => {
+            return Err(VmError::InvalidOperand(format!(
+                "TypeError: object of type '{}' has no len()",
+                other.type_name()
+            )))
         }
-        _ => Object::new(Value::Integer(0)),
+    };
+    Ok(Value::Integer(length as i64))
+}
+
+// `sep` and `end` default to their Python values when missing or None
+fn string_kwarg(context: &NativeContext, name: &str, default: &str) -> Result<String, VmError> {
+    match context.kwarg(name) {
+        None | Some(Value::None) => Ok(default.to_string()),
+        Some(Value::String(value)) => Ok(value.to_string()),
+        Some(other) => Err(VmError::InvalidOperand(format!(
+            "TypeError: {} must be None or a string, not {}",
+            name,
+            other.type_name()
+        ))),
     }
 }
 
-fn print(args: Vec<Object>) -> Object {
+fn print(context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    let sep = string_kwarg(context, "sep", " ")?;
+    let end = string_kwarg(context, "end", "\n")?;
     let message = args
         .iter()
-        .map(|obj| obj.value.to_string())
+        .map(|obj| obj.to_string())
         .collect::<Vec<String>>()
-        .join(" ");
-    println!("{}", message);
-    Object::new_none()
+        .join(&sep)
+        + &end;
+    // the output limits only apply to stdout
+    match context.kwarg("file") {
+        None | Some(Value::None) => (),
+        Some(Value::Module(module)) if module.name == sys::STDOUT => (),
+        Some(Value::Module(module)) if module.name == sys::STDERR => {
+            write_stderr(context, &message)?;
+            return Ok(Value::None);
+        }
+        Some(Value::File(file)) => {
+            file.write(&message)?;
+            return Ok(Value::None);
+        }
+        Some(other) => {
+            return Err(VmError::AttributeError(format!(
+                "AttributeError: '{}' object has no attribute 'write'",
+                other.type_name()
+            )))
+        }
+    }
+    if let Some(max_print_calls) = context.config().max_print_calls {
+        if context.output().print_calls >= max_print_calls {
+            return Err(VmError::ResourceLimitExceeded(format!(
+                "print call limit of {} exceeded",
+                max_print_calls
+            )));
+        }
+    }
+    write_stdout(context, &message)?;
+    context.output().print_calls += 1;
+    Ok(Value::None)
+}
+
+/// Writes `message` to the VM stdout within the sandbox output limit.
+pub(crate) fn write_stdout(context: &mut NativeContext, message: &str) -> Result<(), VmError> {
+    let message_size = message.len();
+    if let Some(max_output_bytes) = context.config().max_output_bytes {
+        if context.output().bytes_written + message_size > max_output_bytes {
+            return Err(VmError::ResourceLimitExceeded(format!(
+                "output limit of {} bytes exceeded",
+                max_output_bytes
+            )));
+        }
+    }
+
+    let stdout = context.stdout();
+    stdout
+        .write_all(message.as_bytes())
+        .and_then(|_| stdout.flush())
+        .map_err(|err| VmError::OsError(format!("OSError: {}", err)))?;
+    context.output().bytes_written += message_size;
+    Ok(())
+}
+
+/// Writes `message` to the VM stderr, which the output limit doesn't cover.
+pub(crate) fn write_stderr(context: &mut NativeContext, message: &str) -> Result<(), VmError> {
+    let stderr = context.stderr();
+    stderr
+        .write_all(message.as_bytes())
+        .and_then(|_| stderr.flush())
+        .map_err(|err| VmError::OsError(format!("OSError: {}", err)))
+}
+
+fn help(context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    let text = match &args[0] {
+        Value::Function(function) => {
+            let mut text = format!(
+                "Help on function {}:\n\n{}\n",
+                function.name,
+                function.signature()
+            );
+            if let Some(doc) = &function.doc {
+                text.push_str(&format!("    {}\n", doc));
+            }
+            text
+        }
+        Value::NativeFunction(native_function) => format!(
+            "Help on built-in function {}:\n\n{}(...)\n",
+            native_function.name, native_function.name
+        ),
+        other => format!("Help on {} object\n", other.type_name()),
+    };
+    context
+        .stdout()
+        .write_all(text.as_bytes())
+        .map_err(|err| VmError::OsError(format!("OSError: {}", err)))?;
+    Ok(Value::None)
+}
+
+/// Items of an iterable value, in iteration order. Iterators are drained,
+/// calling back into the VM for the lazy ones.
+pub(crate) fn iterate(context: &mut NativeContext, value: &Value) -> Result<Vec<Value>, VmError> {
+    match value {
+        Value::String(text) => Ok(text
+            .chars()
+            .map(|chr| Value::String(chr.to_string().into()))
+            .collect()),
+        Value::Bytes(bytes) => Ok(bytes
+            .iter()
+            .map(|byte| Value::Integer(*byte as i64))
+            .collect()),
+        Value::List(items) | Value::Tuple(items) => Ok(items.to_vec()),
+        Value::File(file) => iterate(
+            context,
+            &Value::Iterator(Iterator::lines(file.as_ref().clone()).into()),
+        ),
+        Value::Iterator(iterator) => {
+            let mut items = Vec::new();
+            while let Some(item) = iterator.next_item(context)? {
+                items.push(item);
+            }
+            Ok(items)
+        }
+        other => Err(VmError::InvalidOperand(format!(
+            "TypeError: '{}' object is not iterable",
+            other.type_name()
+        ))),
+    }
+}
+
+/// Orders `lhs` against `rhs` for the `op` comparison, a TypeError when
+/// their types can't be ordered.
+pub(crate) fn compare(op: &str, lhs: &Value, rhs: &Value) -> Result<Ordering, VmError> {
+    lhs.compare(rhs).ok_or_else(|| {
+        VmError::InvalidOperand(format!(
+            "TypeError: '{}' not supported between instances of '{}' and '{}'",
+            op,
+            lhs.type_name(),
+            rhs.type_name()
+        ))
+    })
+}
+
+// `key` applied to `item`, or the item itself without one
+fn apply_key(
+    context: &mut NativeContext,
+    key: Option<&Value>,
+    item: &Value,
+) -> Result<Value, VmError> {
+    match key {
+        Some(key) => context.call(key, vec![item.clone()]),
+        None => Ok(item.clone()),
+    }
+}
+
+// `key=None` is the same as no key
+fn key_kwarg(context: &NativeContext) -> Option<Value> {
+    context
+        .kwarg("key")
+        .filter(|key| !matches!(key, Value::None))
+        .cloned()
+}
+
+// `min`/`max` over one iterable or two or more arguments: the first item
+// whose key orders as `wanted` against all the others
+fn extreme(
+    name: &str,
+    wanted: Ordering,
+    context: &mut NativeContext,
+    args: Vec<Value>,
+) -> NativeResult {
+    let items = match args.len() {
+        0 => {
+            return Err(VmError::WrongArgumentCount(format!(
+                "TypeError: {} expected at least 1 argument, got 0",
+                name
+            )))
+        }
+        1 => iterate(context, &args[0])?,
+        _ if context.kwarg("default").is_some() => {
+            return Err(VmError::InvalidOperand(format!(
+                "TypeError: Cannot specify a default for {}() with multiple positional arguments",
+                name
+            )))
+        }
+        _ => args,
+    };
+    let key = key_kwarg(context);
+    let op = if wanted == Ordering::Less { "<" } else { ">" };
+    let mut best: Option<(Value, Value)> = None;
+    for item in items {
+        let item_key = apply_key(context, key.as_ref(), &item)?;
+        let replace = match &best {
+            Some((_, best_key)) => compare(op, &item_key, best_key)? == wanted,
+            None => true,
+        };
+        if replace {
+            best = Some((item, item_key));
+        }
+    }
+    match (best, context.kwarg("default")) {
+        (Some((item, _)), _) => Ok(item),
+        (None, Some(default)) => Ok(default.clone()),
+        (None, None) => Err(VmError::ValueError(format!(
+            "ValueError: {}() arg is an empty sequence",
+            name
+        ))),
+    }
+}
+
+fn min(context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    extreme("min", Ordering::Less, context, args)
+}
+
+fn max(context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    extreme("max", Ordering::Greater, context, args)
+}
+
+// `sum(iterable, start=0)`, adding like the `+` operator
+fn sum(context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    check_arity("sum", &args, 1, 2)?;
+    let start = match (args.get(1), context.kwarg("start")) {
+        (Some(start), _) | (None, Some(start)) => start.clone(),
+        (None, None) => Value::Integer(0),
+    };
+    match &start {
+        Value::String(_) => {
+            return Err(VmError::InvalidOperand(String::from(
+                "TypeError: sum() can't sum strings [use ''.join(seq) instead]",
+            )))
+        }
+        Value::Bytes(_) => {
+            return Err(VmError::InvalidOperand(String::from(
+                "TypeError: sum() can't sum bytes [use b''.join(seq) instead]",
+            )))
+        }
+        _ => (),
+    }
+    iterate(context, &args[0])?
+        .into_iter()
+        .try_fold(start, |total, item| {
+            binary_op(&Bytecode::Add, &total, &item)
+        })
 }
 
-pub fn init_native_function_registry() -> HashMap<String, NativeFunction> {
+// `sorted(iterable, key=None, reverse=False)`, a stable sort into a new list
+fn sorted(context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    check_arity("sorted", &args, 1, 1)?;
+    let key = key_kwarg(context);
+    let reverse = context
+        .kwarg("reverse")
+        .is_some_and(|reverse| reverse.is_truthy());
+    let mut keyed = Vec::new();
+    for item in iterate(context, &args[0])? {
+        let item_key = apply_key(context, key.as_ref(), &item)?;
+        keyed.push((item_key, item));
+    }
+    // the first failed comparison is reported once the sort is over
+    let mut error = None;
+    keyed.sort_by(|(lhs, _), (rhs, _)| {
+        let ordering = match compare("<", lhs, rhs) {
+            Ok(ordering) => ordering,
+            Err(err) => {
+                error.get_or_insert(err);
+                Ordering::Equal
+            }
+        };
+        // reversing the comparison keeps equal items in their original order
+        if reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+    if let Some(err) = error {
+        return Err(err);
+    }
+    Ok(Value::List(
+        keyed
+            .into_iter()
+            .map(|(_, item)| item)
+            .collect::<Vec<_>>()
+            .into(),
+    ))
+}
+
+fn reversed(context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    let (name, mut items) = match &args[0] {
+        Value::List(_) => ("list_reverseiterator", iterate(context, &args[0])?),
+        Value::Tuple(_) => ("reversed", iterate(context, &args[0])?),
+        Value::String(_) | Value::Bytes(_) => ("reversed", iterate(context, &args[0])?),
+        other => {
+            return Err(VmError::InvalidOperand(format!(
+                "TypeError: '{}' object is not reversible",
+                other.type_name()
+            )))
+        }
+    };
+    items.reverse();
+    Ok(Value::Iterator(Iterator::new(name, items).into()))
+}
+
+// `list(iterable=())`
+fn list(context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    check_arity("list", &args, 0, 1)?;
+    let items = match args.first() {
+        Some(iterable) => iterate(context, iterable)?,
+        None => Vec::new(),
+    };
+    Ok(Value::List(items.into()))
+}
+
+// an iterator over `value`, the value itself when it already is one
+pub(crate) fn iterator_of(context: &mut NativeContext, value: &Value) -> Result<Iterator, VmError> {
+    match value {
+        Value::Iterator(iterator) => Ok(iterator.as_ref().clone()),
+        Value::File(file) => Ok(Iterator::lines(file.as_ref().clone())),
+        Value::List(_) => Ok(Iterator::new("list_iterator", iterate(context, value)?)),
+        Value::Tuple(_) => Ok(Iterator::new("tuple_iterator", iterate(context, value)?)),
+        Value::String(_) => Ok(Iterator::new("str_iterator", iterate(context, value)?)),
+        Value::Bytes(_) => Ok(Iterator::new("bytes_iterator", iterate(context, value)?)),
+        other => Err(VmError::InvalidOperand(format!(
+            "TypeError: '{}' object is not iterable",
+            other.type_name()
+        ))),
+    }
+}
+
+// `map(function, iterable, ...)`, calling `function` as items are consumed
+fn map(context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    if args.len() < 2 {
+        return Err(VmError::WrongArgumentCount(String::from(
+            "TypeError: map() must have at least two arguments.",
+        )));
+    }
+    let mut args = args.into_iter();
+    let function = args.next().unwrap();
+    let sources = args
+        .map(|iterable| iterator_of(context, &iterable))
+        .collect::<Result<_, _>>()?;
+    Ok(Value::Iterator(Iterator::map(function, sources).into()))
+}
+
+// `filter(function, iterable)`, `function` None keeping the truthy items
+fn filter(context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    let function = match &args[0] {
+        Value::None => None,
+        _ => Some(args[0].clone()),
+    };
+    let source = iterator_of(context, &args[1])?;
+    Ok(Value::Iterator(Iterator::filter(function, source).into()))
+}
+
+// whether any item of the iterable has truthiness `wanted`, stopping at the
+// first one so lazy iterators are only advanced that far
+fn find_truthiness(
+    context: &mut NativeContext,
+    iterable: &Value,
+    wanted: bool,
+) -> Result<bool, VmError> {
+    let items = iterator_of(context, iterable)?;
+    while let Some(item) = items.next_item(context)? {
+        if item.is_truthy() == wanted {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn any(context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    let found = find_truthiness(context, &args[0], true)?;
+    Ok(Value::new_from_bool(found))
+}
+
+fn all(context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    let found = find_truthiness(context, &args[0], false)?;
+    Ok(Value::new_from_bool(!found))
+}
+
+// `open(file, mode="r")`, text files only
+fn open(context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    check_arity("open", &args, 1, 2)?;
+    if !context.config().allow_file_io {
+        return Err(VmError::PermissionError(String::from(
+            "PermissionError: file access is disabled in this sandbox",
+        )));
+    }
+    let mode = match args.get(1).or(context.kwarg("mode")) {
+        None => "r",
+        Some(Value::String(mode)) => mode.as_str(),
+        Some(other) => {
+            return Err(VmError::InvalidOperand(format!(
+                "TypeError: open() argument 'mode' must be str, not {}",
+                other.type_name()
+            )))
+        }
+    };
+    match &args[0] {
+        Value::String(name) => Ok(Value::File(File::open(name, mode)?.into())),
+        other => Err(VmError::InvalidOperand(format!(
+            "TypeError: expected str, bytes or os.PathLike object, not {}",
+            other.type_name()
+        ))),
+    }
+}
+
+fn repr(_context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    Ok(Value::String(args[0].repr().into()))
+}
+
+fn sorted_names(names: Vec<String>) -> Value {
+    Value::List(
+        names
+            .into_iter()
+            .map(|name| Value::String(name.into()))
+            .collect::<Vec<_>>()
+            .into(),
+    )
+}
+
+// `dir([object])`, the names in the current scope without an argument
+fn dir(context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    check_arity("dir", &args, 0, 1)?;
+    let names = match args.first() {
+        None => context.local_names(),
+        Some(Value::Module(module)) => context.module_names(Some(&module.name)),
+        Some(Value::String(_)) => names_of(STRING_METHODS),
+        Some(Value::Bytes(_)) => names_of(BYTES_METHODS),
+        Some(Value::File(_)) => names_of(FILE_METHODS),
+        Some(_) => Vec::new(),
+    };
+    Ok(sorted_names(names))
+}
+
+fn names_of(methods: &[&str]) -> Vec<String> {
+    let mut names: Vec<String> = methods.iter().map(|name| name.to_string()).collect();
+    names.sort();
+    names
+}
+
+// without a dict type the scope introspection builtins return the sorted
+// names rather than a mapping to their values
+fn globals(context: &mut NativeContext, _args: Vec<Value>) -> NativeResult {
+    Ok(sorted_names(context.global_names()))
+}
+
+fn locals(context: &mut NativeContext, _args: Vec<Value>) -> NativeResult {
+    Ok(sorted_names(context.local_names()))
+}
+
+// integer argument of natives taking an index-like value, bools included
+fn integer_arg(value: &Value) -> Result<i64, VmError> {
+    match value {
+        Value::Integer(value) => Ok(*value),
+        Value::Bool(value) => Ok(*value as i64),
+        other => Err(VmError::InvalidOperand(format!(
+            "TypeError: '{}' object cannot be interpreted as an integer",
+            other.type_name()
+        ))),
+    }
+}
+
+fn ord(_context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    let (code_point, length) = match &args[0] {
+        Value::String(text) => (
+            text.chars().next().map(|chr| chr as i64),
+            text.chars().count(),
+        ),
+        Value::Bytes(bytes) => (bytes.first().map(|byte| *byte as i64), bytes.len()),
+        other => {
+            return Err(VmError::InvalidOperand(format!(
+                "TypeError: ord() expected string of length 1, but {} found",
+                other.type_name()
+            )))
+        }
+    };
+    match code_point {
+        Some(code_point) if length == 1 => Ok(Value::Integer(code_point)),
+        _ => Err(VmError::InvalidOperand(format!(
+            "TypeError: ord() expected a character, but string of length {} found",
+            length
+        ))),
+    }
+}
+
+fn chr(_context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    let code_point = integer_arg(&args[0])?;
+    if !(0..=0x10FFFF).contains(&code_point) {
+        return Err(VmError::ValueError(String::from(
+            "ValueError: chr() arg not in range(0x110000)",
+        )));
+    }
+    // strings are UTF-8, which has no encoding for lone surrogates
+    match char::from_u32(code_point as u32) {
+        Some(chr) => Ok(Value::String(chr.to_string().into())),
+        None => Err(VmError::ValueError(format!(
+            "ValueError: chr() arg {:#x} is a surrogate code point",
+            code_point
+        ))),
+    }
+}
+
+// `value` in base `radix` after its Python prefix, the sign going first
+fn radix_string(args: &[Value], prefix: &str, radix: u32) -> NativeResult {
+    let value = integer_arg(&args[0])?;
+    let magnitude = value.unsigned_abs();
+    let digits = match radix {
+        2 => format!("{:b}", magnitude),
+        8 => format!("{:o}", magnitude),
+        _ => format!("{:x}", magnitude),
+    };
+    let sign = if value < 0 { "-" } else { "" };
+    Ok(Value::String(
+        format!("{}{}{}", sign, prefix, digits).into(),
+    ))
+}
+
+fn hex(_context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    radix_string(&args, "0x", 16)
+}
+
+fn bin(_context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    radix_string(&args, "0b", 2)
+}
+
+fn oct(_context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    radix_string(&args, "0o", 8)
+}
+
+// `round(number, ndigits=None)`, halfway cases going to the even side
+fn round(_context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    check_arity("round", &args, 1, 2)?;
+    let ndigits = match args.get(1) {
+        None | Some(Value::None) => None,
+        Some(ndigits) => Some(integer_arg(ndigits)?),
+    };
+    let rounded = match (&args[0], ndigits) {
+        (Value::Float(value), None) => Value::Integer(float_to_int(value.round_ties_even())?),
+        (Value::Float(value), Some(ndigits)) => Value::Float(round_float(*value, ndigits)),
+        (Value::Integer(_) | Value::Bool(_), ndigits) => {
+            let value = integer_arg(&args[0])?;
+            match ndigits {
+                Some(ndigits) if ndigits < 0 => Value::Integer(round_integer(value, ndigits)?),
+                _ => Value::Integer(value),
+            }
+        }
+        (other, _) => {
+            return Err(VmError::InvalidOperand(format!(
+                "TypeError: type {} doesn't define __round__ method",
+                other.type_name()
+            )))
+        }
+    };
+    Ok(rounded)
+}
+
+// formatting rounds the exact binary value half to even, which is what
+// CPython's correctly rounded `round` does too
+fn round_float(value: f64, ndigits: i64) -> f64 {
+    if !value.is_finite() || ndigits > 323 {
+        return value;
+    }
+    if ndigits >= 0 {
+        return format!("{:.*}", ndigits as usize, value)
+            .parse()
+            .unwrap_or(value);
+    }
+    if ndigits < -308 {
+        return 0.0_f64.copysign(value);
+    }
+    let factor = 10_f64.powi(-ndigits as i32);
+    (value / factor).round_ties_even() * factor
+}
+
+// rounds to a multiple of 10^-ndigits for a negative `ndigits`
+fn round_integer(value: i64, ndigits: i64) -> Result<i64, VmError> {
+    let Some(unit) = 10_i64.checked_pow(ndigits.unsigned_abs().min(u32::MAX as u64) as u32) else {
+        return Ok(0);
+    };
+    let remainder = value.rem_euclid(unit);
+    let base = value - remainder;
+    let round_up = match (remainder * 2).cmp(&unit) {
+        Ordering::Greater => true,
+        Ordering::Equal => (base / unit) % 2 != 0,
+        Ordering::Less => false,
+    };
+    if !round_up {
+        return Ok(base);
+    }
+    base.checked_add(unit).ok_or_else(|| {
+        VmError::ValueError(String::from("OverflowError: rounded integer too large"))
+    })
+}
+
+// `divmod(a, b)`, the floored quotient and remainder as a tuple
+fn divmod(_context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    let (lhs, rhs) = (&args[0], &args[1]);
+    let (quotient, remainder) = match (integer_arg(lhs), integer_arg(rhs)) {
+        (Ok(lhs), Ok(rhs)) => {
+            if rhs == 0 {
+                return Err(VmError::ZeroDivisionError(String::from(
+                    "ZeroDivisionError: integer division or modulo by zero",
+                )));
+            }
+            let quotient = floor_div(lhs, rhs).ok_or_else(division_overflow)?;
+            let remainder = floor_mod(lhs, rhs).ok_or_else(division_overflow)?;
+            (Value::Integer(quotient), Value::Integer(remainder))
+        }
+        _ => {
+            let (Some(lhs), Some(rhs)) = (float_arg(lhs), float_arg(rhs)) else {
+                return Err(VmError::InvalidOperand(format!(
+                    "TypeError: unsupported operand type(s) for divmod(): '{}' and '{}'",
+                    lhs.type_name(),
+                    rhs.type_name()
+                )));
+            };
+            if rhs == 0.0 {
+                return Err(VmError::ZeroDivisionError(String::from(
+                    "ZeroDivisionError: float divmod()",
+                )));
+            }
+            let (quotient, remainder) = float_divmod(lhs, rhs);
+            (Value::Float(quotient), Value::Float(remainder))
+        }
+    };
+    Ok(Value::Tuple(vec![quotient, remainder].into()))
+}
+
+fn float_arg(value: &Value) -> Option<f64> {
+    match value {
+        Value::Float(value) => Some(*value),
+        other => integer_arg(other).ok().map(|value| value as f64),
+    }
+}
+
+// same as CPython's float_divmod, the remainder taking the divisor's sign
+fn float_divmod(lhs: f64, rhs: f64) -> (f64, f64) {
+    let mut remainder = lhs % rhs;
+    let mut quotient = (lhs - remainder) / rhs;
+    if remainder != 0.0 && (rhs < 0.0) != (remainder < 0.0) {
+        remainder += rhs;
+        quotient -= 1.0;
+    }
+    if remainder == 0.0 {
+        remainder = 0.0_f64.copysign(rhs);
+    }
+    let floored = quotient.floor();
+    if quotient - floored > 0.5 {
+        (floored + 1.0, remainder)
+    } else {
+        (floored, remainder)
+    }
+}
+
+// `pow(base, exp, mod=None)`
+fn pow(_context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    check_arity("pow", &args, 2, 3)?;
+    let (base, exp) = (&args[0], &args[1]);
+    if let Some(modulus) = args.get(2).filter(|arg| !matches!(arg, Value::None)) {
+        let (Ok(base), Ok(exp), Ok(modulus)) =
+            (integer_arg(base), integer_arg(exp), integer_arg(modulus))
+        else {
+            return Err(VmError::InvalidOperand(String::from(
+                "TypeError: pow() 3rd argument not allowed unless all arguments are integers",
+            )));
+        };
+        return Ok(Value::Integer(modular_pow(base, exp, modulus)?));
+    }
+    let result = match (integer_arg(base), integer_arg(exp)) {
+        (Ok(base), Ok(exp)) if exp >= 0 => {
+            let result = u32::try_from(exp)
+                .ok()
+                .and_then(|exp| base.checked_pow(exp))
+                .ok_or_else(|| {
+                    VmError::ValueError(String::from(
+                        "OverflowError: integer pow() result too large",
+                    ))
+                })?;
+            Value::Integer(result)
+        }
+        _ => {
+            let (Some(base), Some(exp)) = (float_arg(base), float_arg(exp)) else {
+                return Err(VmError::InvalidOperand(format!(
+                    "TypeError: unsupported operand type(s) for ** or pow(): '{}' and '{}'",
+                    base.type_name(),
+                    exp.type_name()
+                )));
+            };
+            if base == 0.0 && exp < 0.0 {
+                return Err(VmError::ZeroDivisionError(String::from(
+                    "ZeroDivisionError: 0.0 cannot be raised to a negative power",
+                )));
+            }
+            if base < 0.0 && exp.fract() != 0.0 {
+                return Err(VmError::ValueError(String::from(
+                    "ValueError: negative number cannot be raised to a fractional power",
+                )));
+            }
+            Value::Float(base.powf(exp))
+        }
+    };
+    Ok(result)
+}
+
+// `base ** exp % modulus` by squaring, a negative `exp` going through the
+// modular inverse of `base`
+fn modular_pow(base: i64, exp: i64, modulus: i64) -> Result<i64, VmError> {
+    if modulus == 0 {
+        return Err(VmError::ValueError(String::from(
+            "ValueError: pow() 3rd argument cannot be 0",
+        )));
+    }
+    let modulus = modulus as i128;
+    let mut base = (base as i128).rem_euclid(modulus.abs());
+    if exp < 0 {
+        base = modular_inverse(base, modulus.abs()).ok_or_else(|| {
+            VmError::ValueError(String::from(
+                "ValueError: base is not invertible for the given modulus",
+            ))
+        })?;
+    }
+    let mut exp = exp.unsigned_abs();
+    let mut result: i128 = 1 % modulus.abs();
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = result * base % modulus.abs();
+        }
+        base = base * base % modulus.abs();
+        exp >>= 1;
+    }
+    // the result takes the sign of the modulus, like `%`
+    if modulus < 0 && result != 0 {
+        result += modulus;
+    }
+    Ok(result as i64)
+}
+
+fn modular_inverse(value: i128, modulus: i128) -> Option<i128> {
+    let (mut old_r, mut r) = (value, modulus);
+    let (mut old_s, mut s) = (1_i128, 0_i128);
+    while r != 0 {
+        let quotient = old_r / r;
+        (old_r, r) = (r, old_r - quotient * r);
+        (old_s, s) = (s, old_s - quotient * s);
+    }
+    if old_r != 1 {
+        return None;
+    }
+    Some(old_s.rem_euclid(modulus))
+}
+
+pub fn init_native_function_registry() -> NativeRegistry {
     let mut native_functions = HashMap::new();
 
-    native_functions.insert(
-        String::from("print"),
-        NativeFunction {
-            name: String::from("print"),
-            arity: usize::MAX,
-            function: Arc::new(print),
-        },
-    );
-    native_functions.insert(
-        String::from("abs"),
-        NativeFunction {
-            name: String::from("abs"),
-            arity: 1,
-            function: Arc::new(abs),
-        },
-    );
+    vec![
+        NativeFunction::new("print", VARIADIC, print).with_kwargs(&["sep", "end", "file"]),
+        NativeFunction::new("abs", 1, abs),
+        NativeFunction::new("help", 1, help),
+        NativeFunction::new("len", 1, len),
+        NativeFunction::new("int", VARIADIC, int),
+        NativeFunction::new("float", VARIADIC, float),
+        NativeFunction::new("str", VARIADIC, str),
+        NativeFunction::new("bool", VARIADIC, bool),
+        NativeFunction::new("min", VARIADIC, min).with_kwargs(&["key", "default"]),
+        NativeFunction::new("max", VARIADIC, max).with_kwargs(&["key", "default"]),
+        NativeFunction::new("sum", VARIADIC, sum).with_kwargs(&["start"]),
+        NativeFunction::new("sorted", VARIADIC, sorted).with_kwargs(&["key", "reverse"]),
+        NativeFunction::new("reversed", 1, reversed),
+        NativeFunction::new("list", VARIADIC, list),
+        NativeFunction::new("map", VARIADIC, map),
+        NativeFunction::new("filter", 2, filter),
+        NativeFunction::new("any", 1, any),
+        NativeFunction::new("all", 1, all),
+        NativeFunction::new("ord", 1, ord),
+        NativeFunction::new("chr", 1, chr),
+        NativeFunction::new("hex", 1, hex),
+        NativeFunction::new("bin", 1, bin),
+        NativeFunction::new("oct", 1, oct),
+        NativeFunction::new("round", VARIADIC, round),
+        NativeFunction::new("divmod", 2, divmod),
+        NativeFunction::new("pow", VARIADIC, pow),
+        NativeFunction::new("repr", 1, repr),
+        NativeFunction::new("open", VARIADIC, open).with_kwargs(&["mode"]),
+        NativeFunction::new("dir", VARIADIC, dir),
+        NativeFunction::new("globals", 0, globals),
+        NativeFunction::new("locals", 0, locals),
+    ]
+    .into_iter()
+    .chain(exception_classes())
+    .for_each(|native_function| {
+        native_functions.insert(native_function.name.to_string(), native_function);
+    });
 
     native_functions
 }
 
+fn global_registry() -> &'static RwLock<NativeRegistry> {
+    GLOBAL_REGISTRY.get_or_init(|| RwLock::new(init_native_function_registry()))
+}
+
+/// Snapshot of the process-wide registry. The builtins are only built once,
+/// later snapshots share the native function bodies through their `Arc`.
+pub fn native_function_registry() -> NativeRegistry {
+    match global_registry().read() {
+        Ok(registry) => registry.clone(),
+        Err(poisoned) => poisoned.into_inner().clone(),
+    }
+}
+
+/// Registers a native for every interpreter created after this call.
+pub fn register_global_native(native_function: NativeFunction) {
+    let mut registry = match global_registry().write() {
+        Ok(registry) => registry,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    registry.insert(native_function.name.to_string(), native_function);
+}
+
 #[cfg(test)]
 mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
     use super::*;
 
+    // stdout for a test VM that can be read after the VM has taken it
+    #[derive(Clone, Default)]
+    struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn test_vm(config: Config, stdout: Buffer) -> Vm {
+        let mut vm = Vm::new();
+        vm.set_config(config);
+        vm.set_stdout(Box::new(stdout));
+        vm
+    }
+
     #[test]
     fn test_native_registry() {
+        let mut vm = test_vm(Config::new(), Buffer::default());
+        let mut globals = SymbolTable::new();
+        let builtins = SymbolTable::new();
+        let mut context = NativeContext::new(&mut vm, &mut globals, &builtins, Vec::new());
         let nfr = init_native_function_registry();
         let result = nfr.get("print");
         assert!(result.is_some());
         let print_func_obj = result.unwrap();
         assert_eq!(
-            (print_func_obj.function.as_ref())(vec![Object::new_true()]),
-            Object::new_none()
+            (print_func_obj.function.as_ref())(&mut context, vec![Value::Bool(true)]).unwrap(),
+            Value::None
         );
     }
 
+    #[test]
+    fn test_print_limits() {
+        let nfr = init_native_function_registry();
+        let print_func = nfr.get("print").unwrap().function.as_ref();
+        vec![
+            (
+                Config {
+                    max_print_calls: Some(2),
+                    ..Config::new()
+                },
+                2,
+            ),
+            (
+                Config {
+                    // "True\n" is 5 bytes
+                    max_output_bytes: Some(12),
+                    ..Config::new()
+                },
+                2,
+            ),
+        ]
+        .into_iter()
+        .for_each(|(config, allowed_calls)| {
+            let mut vm = test_vm(config, Buffer::default());
+            let mut globals = SymbolTable::new();
+            let builtins = SymbolTable::new();
+            let mut context = NativeContext::new(&mut vm, &mut globals, &builtins, Vec::new());
+            for _ in 0..allowed_calls {
+                assert!(print_func(&mut context, vec![Value::Bool(true)]).is_ok());
+            }
+            match print_func(&mut context, vec![Value::Bool(true)]) {
+                Err(VmError::ResourceLimitExceeded(_)) => (),
+                other => panic!("Expected ResourceLimitExceeded, got {:?}", other),
+            }
+        });
+    }
+
+    #[test]
+    fn test_print_kwargs() {
+        let nfr = init_native_function_registry();
+        let print_func = nfr.get("print").unwrap().function.as_ref();
+        let string = |value: &str| Value::String(value.to_string().into());
+        vec![
+            (vec![], "1 2\n"),
+            (vec![("sep", string(", "))], "1, 2\n"),
+            (vec![("end", string(""))], "1 2"),
+            (vec![("sep", string("")), ("end", string("!\n"))], "12!\n"),
+            (vec![("sep", Value::None), ("end", Value::None)], "1 2\n"),
+            (vec![("file", Value::None)], "1 2\n"),
+        ]
+        .into_iter()
+        .for_each(|(kwargs, expected)| {
+            let stdout = Buffer::default();
+            let mut vm = test_vm(Config::new(), stdout.clone());
+            let mut globals = SymbolTable::new();
+            let builtins = SymbolTable::new();
+            let kwargs = kwargs
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect();
+            let mut context = NativeContext::new(&mut vm, &mut globals, &builtins, kwargs);
+            let args = vec![Value::Integer(1), Value::Integer(2)];
+            assert!(print_func(&mut context, args).is_ok());
+            assert_eq!(context.output().bytes_written, expected.len());
+            assert_eq!(String::from_utf8(stdout.0.take()).unwrap(), expected);
+        });
+    }
+
+    #[test]
+    fn test_global_registry() {
+        register_global_native(NativeFunction::new("test_global", 0, |_, _| {
+            Ok(Value::Bool(true))
+        }));
+        let registry = native_function_registry();
+        assert!(registry.contains_key("print"));
+        assert!(registry.contains_key("test_global"));
+    }
+
     #[test]
     fn test_abs() {
+        let mut vm = test_vm(Config::new(), Buffer::default());
+        let mut globals = SymbolTable::new();
+        let builtins = SymbolTable::new();
+        let mut context = NativeContext::new(&mut vm, &mut globals, &builtins, Vec::new());
         let nfr = init_native_function_registry();
         let result = nfr.get("abs");
         assert!(result.is_some());
@@ -104,9 +1366,9 @@ mod tests {
         vec![(0, 0), (-1, 1), (1, 1)]
             .into_iter()
             .for_each(|(value, expected)| {
-                let args = vec![Object::new(Value::Integer(value))];
-                let actual = abs_func(args);
-                if let Value::Integer(actual) = actual.value {
+                let args = vec![Value::Integer(value)];
+                let actual = abs_func(&mut context, args).unwrap();
+                if let Value::Integer(actual) = actual {
                     assert_eq!(actual, expected);
                 } else {
                     panic!("Result not an integer");
@@ -115,9 +1377,9 @@ mod tests {
         vec![(0.0, 0.0), (-1.0, 1.0), (1.0, 1.0)]
             .into_iter()
             .for_each(|(value, expected)| {
-                let args = vec![Object::new(Value::Float(value))];
-                let actual = abs_func(args);
-                if let Value::Float(actual) = actual.value {
+                let args = vec![Value::Float(value)];
+                let actual = abs_func(&mut context, args).unwrap();
+                if let Value::Float(actual) = actual {
                     assert_eq!(actual, expected);
                 } else {
                     panic!("Result not a float");
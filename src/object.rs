@@ -1,10 +1,21 @@
 use crate::ast::Literal;
+use crate::exception::Exception;
+use crate::file::File;
+use crate::format::{bytes_repr, float_repr, repr};
 use crate::function::Function;
+use crate::functools::{CachedFunction, Partial};
+use crate::hashlib::HashObject;
+use crate::iterator::Iterator;
+use crate::module::Module;
 use crate::native::NativeFunction;
-use std::collections::hash_map::DefaultHasher;
+use crate::re::{Match, Pattern};
+use std::cmp::Ordering;
 use std::fmt::Display;
 use std::hash::{Hash, Hasher};
+use std::rc::Rc;
 
+/// Named entry of a symbol table. The VM stack holds bare values, so only
+/// variables, builtins and module attributes carry an id and a name.
 #[derive(Clone, Debug, PartialEq, PartialOrd, Hash)]
 pub struct Object {
     pub id: u64,
@@ -12,96 +23,265 @@ pub struct Object {
     pub value: Value,
 }
 
-#[derive(Clone, Debug, PartialEq, PartialOrd)]
+/// A value is a tag plus at most one word: scalars are stored inline and
+/// everything else behind an `Rc`, so copying a value between the stack and
+/// the symbol tables never copies the data. Appending to a string that is
+/// shared copies it first, so strings keep behaving as values.
+#[derive(Clone, Debug, PartialOrd)]
 pub enum Value {
     None,
-    True,
-    False,
+    Bool(bool),
+    Ellipsis,
+    NotImplemented,
     Integer(i64),
     Float(f64),
-    String(String),
-    Function(Function),
-    NativeFunction(NativeFunction),
+    String(Rc<String>),
+    Bytes(Rc<Vec<u8>>),
+    List(Rc<Vec<Value>>),
+    Tuple(Rc<Vec<Value>>),
+    Iterator(Rc<Iterator>),
+    File(Rc<File>),
+    Pattern(Rc<Pattern>),
+    Match(Rc<Match>),
+    HashObject(Rc<HashObject>),
+    Exception(Rc<Exception>),
+    Function(Rc<Function>),
+    NativeFunction(Rc<NativeFunction>),
+    Partial(Rc<Partial>),
+    CachedFunction(Rc<CachedFunction>),
+    Module(Rc<Module>),
 }
 
 impl Value {
-    pub fn new_from_bool(value: bool) -> Value {
-        if value {
-            Value::True
-        } else {
-            Value::False
+    pub fn from_literal(literal: &Literal) -> Value {
+        match literal {
+            Literal::None => Value::None,
+            Literal::True => Value::Bool(true),
+            Literal::False => Value::Bool(false),
+            Literal::Ellipsis => Value::Ellipsis,
+            Literal::Integer(value) => Value::Integer(*value),
+            Literal::Float(value) => Value::Float(*value),
+            Literal::String(value) => Value::String(value.to_string().into()),
+            Literal::Bytes(value) => Value::Bytes(value.to_vec().into()),
         }
     }
 
+    pub fn new_from_bool(value: bool) -> Value {
+        Value::Bool(value)
+    }
+
     pub fn is_truthy(&self) -> bool {
         match self {
             Value::None => false,
-            Value::True => true,
-            Value::False => false,
+            Value::Bool(value) => *value,
+            Value::Ellipsis | Value::NotImplemented => true,
             Value::Integer(value) => *value != 0,
             Value::Float(value) => *value != 0.0,
             Value::String(value) => !value.is_empty(),
+            Value::Bytes(value) => !value.is_empty(),
+            Value::List(items) | Value::Tuple(items) => !items.is_empty(),
+            Value::Iterator(_) => true,
+            Value::File(_) => true,
+            Value::Pattern(_) | Value::Match(_) => true,
+            Value::HashObject(_) => true,
+            Value::Exception(_) => true,
             Value::Function(_) => true,
             Value::NativeFunction(_) => true,
+            Value::Partial(_) | Value::CachedFunction(_) => true,
+            Value::Module(_) => true,
         }
     }
 
     pub fn is_falsey(&self) -> bool {
         match self {
             Value::None => true,
-            Value::True => false,
-            Value::False => true,
+            Value::Bool(value) => !value,
+            Value::Ellipsis | Value::NotImplemented => false,
             Value::Integer(value) => *value == 0,
             Value::Float(value) => *value == 0.0,
             Value::String(value) => value.is_empty(),
+            Value::Bytes(value) => value.is_empty(),
+            Value::List(items) | Value::Tuple(items) => items.is_empty(),
+            Value::Iterator(_) => false,
+            Value::File(_) => false,
+            Value::Pattern(_) | Value::Match(_) => false,
+            Value::HashObject(_) => false,
+            Value::Exception(_) => false,
             Value::Function(_) => false,
             Value::NativeFunction(_) => false,
+            Value::Partial(_) | Value::CachedFunction(_) => false,
+            Value::Module(_) => false,
         }
     }
 
     pub fn is_integer(&self) -> bool {
-        match self {
-            Value::Integer(_) => true,
-            _ => false,
-        }
+        matches!(self, Value::Integer(_))
     }
 
     pub fn is_float(&self) -> bool {
+        matches!(self, Value::Float(_))
+    }
+
+    pub fn is_string(&self) -> bool {
+        matches!(self, Value::String(_))
+    }
+
+    pub fn is_callable(&self) -> bool {
+        matches!(
+            self,
+            Value::Function(_)
+                | Value::NativeFunction(_)
+                | Value::Partial(_)
+                | Value::CachedFunction(_)
+        )
+    }
+
+    /// Text of `repr(value)`, also echoed by the REPL.
+    pub fn repr(&self) -> String {
+        repr(self)
+    }
+
+    pub fn type_name(&self) -> &'static str {
         match self {
-            Value::Float(_) => true,
-            _ => false,
+            Value::None => "NoneType",
+            Value::Bool(_) => "bool",
+            Value::Ellipsis => "ellipsis",
+            Value::NotImplemented => "NotImplementedType",
+            Value::Integer(_) => "int",
+            Value::Float(_) => "float",
+            Value::String(_) => "str",
+            Value::Bytes(_) => "bytes",
+            Value::List(_) => "list",
+            Value::Tuple(_) => "tuple",
+            Value::Iterator(iterator) => iterator.name,
+            Value::File(_) => "TextIOWrapper",
+            Value::Pattern(_) => "re.Pattern",
+            Value::Match(_) => "re.Match",
+            Value::HashObject(_) => "_hashlib.HASH",
+            Value::Exception(exception) => exception.class,
+            Value::Function(_) => "function",
+            Value::NativeFunction(_) => "builtin_function",
+            Value::Partial(_) => "functools.partial",
+            Value::CachedFunction(_) => "functools._lru_cache_wrapper",
+            Value::Module(_) => "module",
         }
     }
 
-    pub fn is_string(&self) -> bool {
+    /// Ordering of two values of comparable types, None otherwise. Numbers
+    /// compare by value whatever their type, sequences item by item.
+    pub fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::String(lhs), Value::String(rhs)) => Some(lhs.cmp(rhs)),
+            (Value::Bytes(lhs), Value::Bytes(rhs)) => Some(lhs.cmp(rhs)),
+            (Value::List(lhs), Value::List(rhs)) | (Value::Tuple(lhs), Value::Tuple(rhs)) => {
+                for (lhs_item, rhs_item) in lhs.iter().zip(rhs.iter()) {
+                    match lhs_item.compare(rhs_item)? {
+                        Ordering::Equal => continue,
+                        ordering => return Some(ordering),
+                    }
+                }
+                Some(lhs.len().cmp(&rhs.len()))
+            }
+            _ => match (self.as_integer(), other.as_integer()) {
+                (Some(lhs), Some(rhs)) => Some(lhs.cmp(&rhs)),
+                // NaN is neither smaller nor larger than anything
+                _ => match (self.as_float(), other.as_float()) {
+                    (Some(lhs), Some(rhs)) => {
+                        Some(lhs.partial_cmp(&rhs).unwrap_or(Ordering::Equal))
+                    }
+                    _ => None,
+                },
+            },
+        }
+    }
+
+    // bools count as the integers 0 and 1 in arithmetic and comparisons
+    fn as_integer(&self) -> Option<i64> {
         match self {
-            Value::String(_) => true,
-            _ => false,
+            Value::Integer(value) => Some(*value),
+            Value::Bool(value) => Some(*value as i64),
+            _ => None,
         }
     }
 
-    pub fn is_callable(&self) -> bool {
+    fn as_float(&self) -> Option<f64> {
         match self {
-            Value::Function(_) => true,
-            Value::NativeFunction(_) => true,
-            _ => false,
+            Value::Float(value) => Some(*value),
+            other => other.as_integer().map(|value| value as f64),
         }
     }
 }
 
+// Python compares an int and a float exactly, without rounding the int
+fn int_equals_float(int: i64, float: f64) -> bool {
+    float.fract() == 0.0
+        && float >= i64::MIN as f64
+        && float < i64::MAX as f64
+        && int == float as i64
+}
+
+/// Python equality: numbers are equal by value whatever their type, so
+/// `1 == 1.0 == True`, while other values of different types never are.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::None, Value::None) => true,
+            (Value::Ellipsis, Value::Ellipsis) => true,
+            (Value::NotImplemented, Value::NotImplemented) => true,
+            // copies of one value share the data, so need no comparing
+            (Value::String(lhs), Value::String(rhs)) => Rc::ptr_eq(lhs, rhs) || lhs == rhs,
+            (Value::Bytes(lhs), Value::Bytes(rhs)) => Rc::ptr_eq(lhs, rhs) || lhs == rhs,
+            (Value::List(lhs), Value::List(rhs)) | (Value::Tuple(lhs), Value::Tuple(rhs)) => {
+                lhs == rhs
+            }
+            (Value::Iterator(lhs), Value::Iterator(rhs)) => lhs == rhs,
+            (Value::File(lhs), Value::File(rhs)) => lhs == rhs,
+            (Value::Pattern(lhs), Value::Pattern(rhs)) => lhs == rhs,
+            (Value::Match(lhs), Value::Match(rhs)) => lhs == rhs,
+            (Value::HashObject(lhs), Value::HashObject(rhs)) => lhs == rhs,
+            (Value::Exception(lhs), Value::Exception(rhs)) => lhs == rhs,
+            (Value::Function(lhs), Value::Function(rhs)) => lhs == rhs,
+            (Value::NativeFunction(lhs), Value::NativeFunction(rhs)) => lhs == rhs,
+            (Value::Partial(lhs), Value::Partial(rhs)) => lhs == rhs,
+            (Value::CachedFunction(lhs), Value::CachedFunction(rhs)) => lhs == rhs,
+            (Value::Module(lhs), Value::Module(rhs)) => lhs == rhs,
+            (Value::Float(lhs), Value::Float(rhs)) => lhs == rhs,
+            (Value::Float(float), other) | (other, Value::Float(float)) => other
+                .as_integer()
+                .is_some_and(|int| int_equals_float(int, *float)),
+            _ => match (self.as_integer(), other.as_integer()) {
+                (Some(lhs), Some(rhs)) => lhs == rhs,
+                _ => false,
+            },
+        }
+    }
+}
+
+/// Consistent with equality: equal numbers hash alike whatever their type.
 impl Hash for Value {
     fn hash<H: Hasher>(&self, state: &mut H) {
         match self {
-            Self::None => (),
-            Self::True => (),
-            Self::False => (),
-            Self::Float(value) => {
-                state.write_u128(*value as u128);
+            Self::None | Self::Ellipsis | Self::NotImplemented => (),
+            Self::Bool(value) => (*value as i64).hash(state),
+            Self::Float(value) if int_equals_float(*value as i64, *value) => {
+                (*value as i64).hash(state)
             }
+            Self::Float(value) => value.to_bits().hash(state),
             Self::Integer(value) => value.hash(state),
             Self::String(value) => value.hash(state),
+            Self::Bytes(value) => value.hash(state),
+            Self::List(items) | Self::Tuple(items) => items.hash(state),
+            Self::Iterator(iterator) => iterator.id().hash(state),
+            Self::File(file) => file.id().hash(state),
+            Self::Pattern(pattern) => pattern.source.hash(state),
+            Self::Match(found) => found.group(0).hash(state),
+            Self::HashObject(hash) => hash.id().hash(state),
+            Self::Exception(exception) => exception.class.hash(state),
             Self::Function(function) => function.name.hash(state),
             Self::NativeFunction(function) => function.name.hash(state),
+            Self::Partial(partial) => partial.function.hash(state),
+            Self::CachedFunction(cached) => cached.id().hash(state),
+            Self::Module(module) => module.name.hash(state),
         }
     }
 }
@@ -110,17 +290,39 @@ impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::None => write!(f, "None"),
-            Self::True => write!(f, "True"),
-            Self::False => write!(f, "False"),
+            Self::Bool(true) => write!(f, "True"),
+            Self::Bool(false) => write!(f, "False"),
+            Self::Ellipsis => write!(f, "Ellipsis"),
+            Self::NotImplemented => write!(f, "NotImplemented"),
             Self::Integer(value) => write!(f, "{}", value),
-            Self::Float(value) => write!(f, "{}", value),
+            Self::Float(value) => write!(f, "{}", float_repr(*value)),
             Self::String(value) => write!(f, "{}", value),
+            Self::Bytes(value) => write!(f, "{}", bytes_repr(value)),
+            Self::List(items) => {
+                let items: Vec<String> = items.iter().map(repr).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+            // a single item keeps its trailing comma
+            Self::Tuple(items) if items.len() == 1 => write!(f, "({},)", repr(&items[0])),
+            Self::Tuple(items) => {
+                let items: Vec<String> = items.iter().map(repr).collect();
+                write!(f, "({})", items.join(", "))
+            }
+            Self::Iterator(iterator) => write!(f, "{:?}", iterator),
+            Self::File(file) => write!(f, "{:?}", file),
+            Self::Pattern(pattern) => write!(f, "{:?}", pattern),
+            Self::Match(found) => write!(f, "{:?}", found),
+            Self::HashObject(hash) => write!(f, "{:?}", hash),
+            Self::Exception(exception) => write!(f, "{}", exception.message()),
             Self::Function(function) => {
                 write!(f, "<function:{}:{}>", function.name, function.arity)
             }
             Self::NativeFunction(function) => {
                 write!(f, "<native:{}:{}>", function.name, function.arity)
             }
+            Self::Partial(partial) => write!(f, "{:?}", partial),
+            Self::CachedFunction(cached) => write!(f, "{:?}", cached),
+            Self::Module(module) => write!(f, "<module '{}'>", module.name),
         }
     }
 }
@@ -129,96 +331,41 @@ impl Object {
     pub fn new_with_id(id: u64, name: String, value: Value) -> Object {
         Object { id, name, value }
     }
+}
 
-    pub fn new_with_name(name: String, value: Value) -> Object {
-        let mut hasher = DefaultHasher::new();
-        name.hash(&mut hasher);
-        Object {
-            id: hasher.finish(),
-            name,
-            value,
-        }
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
 
-    pub fn new(value: Value) -> Object {
+    fn hash_of(value: &Value) -> u64 {
         let mut hasher = DefaultHasher::new();
         value.hash(&mut hasher);
-        let mut object = Object {
-            id: 0,
-            name: String::new(),
-            value,
-        };
-        object.id = hasher.finish();
-        object.name = String::from("$") + &object.id.to_string();
-        object
-    }
-
-    pub fn new_none() -> Object {
-        Object {
-            id: 0,
-            name: String::from("None"),
-            value: Value::None,
-        }
-    }
-
-    pub fn new_true() -> Object {
-        Object {
-            id: 1,
-            name: String::from("True"),
-            value: Value::True,
-        }
-    }
-
-    pub fn new_false() -> Object {
-        Object {
-            id: 2,
-            name: String::from("False"),
-            value: Value::False,
-        }
-    }
-
-    pub fn from_literal(literal: &Literal) -> Object {
-        match literal {
-            Literal::None => Object::new_none(),
-            Literal::True => Object::new_true(),
-            Literal::False => Object::new_false(),
-            Literal::Integer(value) => Object::new(Value::Integer(*value)),
-            Literal::Float(value) => Object::new(Value::Float(*value)),
-            Literal::String(value) => Object::new(Value::String(value.to_string())),
-        }
-    }
-
-    pub fn get_hash(&self) -> u64 {
-        let mut hasher = DefaultHasher::new();
-        self.hash(&mut hasher);
         hasher.finish()
     }
 
-    pub fn is_none(&self) -> bool {
-        self.value == Value::None
+    #[test]
+    fn test_equal_numbers_hash_alike() {
+        vec![
+            (Value::Integer(1), Value::Float(1.0)),
+            (Value::Integer(1), Value::Bool(true)),
+            (Value::Float(-0.0), Value::Bool(false)),
+            (
+                Value::Tuple(vec![Value::Integer(2)].into()),
+                Value::Tuple(vec![Value::Float(2.0)].into()),
+            ),
+        ]
+        .into_iter()
+        .for_each(|(lhs, rhs)| {
+            assert_eq!(lhs, rhs);
+            assert_eq!(hash_of(&lhs), hash_of(&rhs), "{:?} {:?}", lhs, rhs);
+        });
+        assert_ne!(Value::Float(f64::NAN), Value::Float(f64::NAN));
+        assert_ne!(Value::Integer(1), Value::String(String::from("1").into()));
     }
 
-    pub fn is_truthy(&self) -> bool {
-        self.value.is_truthy()
-    }
-
-    pub fn is_falsey(&self) -> bool {
-        self.value.is_falsey()
-    }
-
-    pub fn is_integer(&self) -> bool {
-        self.value.is_integer()
-    }
-
-    pub fn is_float(&self) -> bool {
-        self.value.is_float()
-    }
-
-    pub fn is_string(&self) -> bool {
-        self.value.is_string()
-    }
-
-    pub fn is_callable(&self) -> bool {
-        self.value.is_callable()
+    #[test]
+    fn test_value_size() {
+        assert_eq!(std::mem::size_of::<Value>(), 16);
     }
 }
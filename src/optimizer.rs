@@ -0,0 +1,559 @@
+use crate::bytecode::{
+    Bytecode, SIZE_INDEX, SIZE_INSTRUCTION, SIZE_JUMP, SIZE_NARROW_INDEX, SIZE_WIDE_INDEX,
+};
+use crate::chunk::{Chunk, InlineCache};
+
+// decoded instruction, jumps holding their absolute target
+struct Instruction {
+    addr: usize,
+    op: Bytecode,
+    operand: u64,
+    // constant index of superinstructions
+    constant: u64,
+}
+
+fn is_jump(op: &Bytecode) -> bool {
+    matches!(
+        op,
+        Bytecode::Jump
+            | Bytecode::JumpIfFalse
+            | Bytecode::Loop
+            | Bytecode::JumpLong
+            | Bytecode::SetupTry
+            | Bytecode::ConstLessJumpIfFalse
+    )
+}
+
+fn is_unconditional_jump(op: &Bytecode) -> bool {
+    matches!(op, Bytecode::Jump | Bytecode::Loop | Bytecode::JumpLong)
+}
+
+fn is_constant_push(op: &Bytecode) -> bool {
+    matches!(
+        op,
+        Bytecode::Const | Bytecode::None | Bytecode::PushTrue | Bytecode::PushFalse
+    )
+}
+
+// whether a short jump at `addr` reaches `target`
+fn fits_short(addr: usize, target: usize) -> bool {
+    i16::try_from(target as i64 - addr as i64).is_ok()
+}
+
+/// Rewrites `chunk` with peephole passes: jumps to unconditional jumps go
+/// straight to the final target, jumps to the next instruction, `Not Not`
+/// before a conditional jump and constants popped right away are removed,
+/// and hot sequences are fused into superinstructions. Chunks that don't
+/// decode are left alone.
+pub fn optimize(chunk: &mut Chunk) {
+    let Some(mut instructions) = decode(chunk) else {
+        return;
+    };
+    thread_jumps(&mut instructions);
+    let mut removed = removable(&instructions);
+    fuse(&mut instructions, &mut removed);
+    if let Some(optimized) = encode(chunk, &instructions, &removed) {
+        *chunk = optimized;
+    }
+}
+
+/// Removes the instructions no path from the start of `chunk` reaches.
+pub fn strip_unreachable(chunk: &mut Chunk) {
+    let Some(instructions) = decode(chunk) else {
+        return;
+    };
+    let removed = unreachable(&instructions);
+    if !removed.contains(&true) {
+        return;
+    }
+    if let Some(stripped) = encode(chunk, &instructions, &removed) {
+        *chunk = stripped;
+    }
+}
+
+fn decode(chunk: &Chunk) -> Option<Vec<Instruction>> {
+    let mut instructions = Vec::new();
+    let mut addr = 0;
+    while addr < chunk.data.len() {
+        let decoded = chunk.decode(addr)?;
+        let byte = |offset| chunk.read_operand(decoded.operand_addr + offset, SIZE_NARROW_INDEX);
+        let operand = match decoded.op {
+            Bytecode::JumpLong => {
+                let offset = chunk.read_operand(decoded.operand_addr, SIZE_INDEX)? as i64;
+                addr.checked_add_signed(offset as isize)? as u64
+            }
+            _ if is_jump(&decoded.op) => {
+                let offset = chunk.get_jump_offset_safe(decoded.operand_addr)?;
+                addr.checked_add_signed(offset as isize)? as u64
+            }
+            Bytecode::IncrementGlobal | Bytecode::IncrementLocal => byte(0)?,
+            _ if decoded.op.has_index() => {
+                chunk.read_operand(decoded.operand_addr, decoded.operand_size)?
+            }
+            _ => 0,
+        };
+        let constant = match decoded.op {
+            Bytecode::IncrementGlobal | Bytecode::IncrementLocal => byte(SIZE_NARROW_INDEX)?,
+            Bytecode::ConstLessJumpIfFalse => byte(SIZE_JUMP)?,
+            _ => 0,
+        };
+        let next_addr = decoded.next_addr();
+        instructions.push(Instruction {
+            addr,
+            op: decoded.op,
+            operand,
+            constant,
+        });
+        addr = next_addr;
+    }
+    Some(instructions)
+}
+
+// encoded size, the index operands taking the narrowest form that holds them
+fn size(instruction: &Instruction) -> usize {
+    match instruction.operand {
+        operand if instruction.op.has_index() && operand > u16::MAX as u64 => {
+            2 * SIZE_INSTRUCTION + SIZE_INDEX
+        }
+        operand if instruction.op.has_index() && operand > u8::MAX as u64 => {
+            2 * SIZE_INSTRUCTION + SIZE_WIDE_INDEX
+        }
+        _ => SIZE_INSTRUCTION + instruction.op.operand_size(),
+    }
+}
+
+fn find(instructions: &[Instruction], addr: usize) -> Option<&Instruction> {
+    instructions
+        .binary_search_by_key(&addr, |instruction| instruction.addr)
+        .ok()
+        .map(|index| &instructions[index])
+}
+
+// chains of unconditional jumps are followed up to a cycle or a target short
+// jumps can't reach
+fn thread_jumps(instructions: &mut [Instruction]) {
+    for index in 0..instructions.len() {
+        let Instruction {
+            addr, op, operand, ..
+        } = &instructions[index];
+        if !is_jump(op) || matches!(op, Bytecode::SetupTry) {
+            continue;
+        }
+        let (addr, long) = (*addr, matches!(op, Bytecode::JumpLong));
+        let mut target = *operand as usize;
+        for _ in 0..instructions.len() {
+            match find(instructions, target) {
+                Some(next)
+                    if is_unconditional_jump(&next.op)
+                        && next.operand as usize != target
+                        && (long || fits_short(addr, next.operand as usize)) =>
+                {
+                    target = next.operand as usize
+                }
+                _ => break,
+            }
+        }
+        instructions[index].operand = target as u64;
+    }
+}
+
+fn targets(instructions: &[Instruction]) -> Vec<usize> {
+    instructions
+        .iter()
+        .filter(|instruction| is_jump(&instruction.op))
+        .map(|instruction| instruction.operand as usize)
+        .collect()
+}
+
+fn removable(instructions: &[Instruction]) -> Vec<bool> {
+    let targets = targets(instructions);
+    let is_target = |index: usize| targets.contains(&instructions[index].addr);
+    let mut removed = vec![false; instructions.len()];
+    let mut index = 0;
+    while index < instructions.len() {
+        let op = &instructions[index].op;
+        let next = instructions.get(index + 1).map(|next| &next.op);
+        let after_next = instructions.get(index + 2).map(|next| &next.op);
+        match (op, next, after_next) {
+            (op, _, _)
+                if is_unconditional_jump(op)
+                    && instructions.get(index + 1).map(|next| next.addr)
+                        == Some(instructions[index].operand as usize) =>
+            {
+                removed[index] = true;
+                index += 1;
+            }
+            // truthiness is all the jump looks at
+            (Bytecode::Not, Some(Bytecode::Not), Some(Bytecode::JumpIfFalse))
+                if !is_target(index + 1) =>
+            {
+                removed[index] = true;
+                removed[index + 1] = true;
+                index += 2;
+            }
+            (op, Some(Bytecode::Pop), _) if is_constant_push(op) && !is_target(index + 1) => {
+                removed[index] = true;
+                removed[index + 1] = true;
+                index += 2;
+            }
+            _ => index += 1,
+        }
+    }
+    removed
+}
+
+// `Const` + `InplaceAdd*` and `Const` + `Less` + `JumpIfFalse` become
+// superinstructions, the constant and the comparison making way for the
+// last instruction. Only sequences no jump enters midway are fused, and
+// only when their indexes fit a byte.
+fn fuse(instructions: &mut [Instruction], removed: &mut [bool]) {
+    let targets = targets(instructions);
+    let mut index = 0;
+    while index < instructions.len() {
+        let Some((op, count)) = superinstruction(&instructions[index..]) else {
+            index += 1;
+            continue;
+        };
+        // the sequence must run as a whole
+        let sequence = index..index + count;
+        if sequence.clone().any(|index| removed[index])
+            || sequence
+                .clone()
+                .skip(1)
+                .any(|index| targets.contains(&instructions[index].addr))
+        {
+            index += 1;
+            continue;
+        }
+        let constant = instructions[index].operand;
+        let last = &mut instructions[index + count - 1];
+        last.op = op;
+        last.constant = constant;
+        removed[index..index + count - 1].fill(true);
+        index += count;
+    }
+}
+
+// superinstruction the instructions start with and how many it replaces
+fn superinstruction(instructions: &[Instruction]) -> Option<(Bytecode, usize)> {
+    let fits = |instruction: &Instruction| u8::try_from(instruction.operand).is_ok();
+    match instructions {
+        [constant, ..] if !matches!(constant.op, Bytecode::Const) || !fits(constant) => None,
+        [_, add, ..] if matches!(add.op, Bytecode::InplaceAddGlobal) && fits(add) => {
+            Some((Bytecode::IncrementGlobal, 2))
+        }
+        [_, add, ..] if matches!(add.op, Bytecode::InplaceAddLocal) && fits(add) => {
+            Some((Bytecode::IncrementLocal, 2))
+        }
+        [_, less, jump, ..]
+            if matches!(
+                (&less.op, &jump.op),
+                (Bytecode::Less, Bytecode::JumpIfFalse)
+            ) =>
+        {
+            Some((Bytecode::ConstLessJumpIfFalse, 3))
+        }
+        _ => None,
+    }
+}
+
+// whether control never continues to the next instruction
+fn is_terminator(op: &Bytecode) -> bool {
+    is_unconditional_jump(op) || matches!(op, Bytecode::Return | Bytecode::Raise)
+}
+
+fn unreachable(instructions: &[Instruction]) -> Vec<bool> {
+    let mut removed = vec![true; instructions.len()];
+    let mut pending = vec![0];
+    while let Some(index) = pending.pop() {
+        if index >= instructions.len() || !removed[index] {
+            continue;
+        }
+        removed[index] = false;
+        let instruction = &instructions[index];
+        if is_jump(&instruction.op) {
+            let target = instructions
+                .binary_search_by_key(&(instruction.operand as usize), |instruction| {
+                    instruction.addr
+                });
+            // jumps to the end of the chunk need nothing kept
+            if let Ok(target) = target {
+                pending.push(target);
+            }
+        }
+        if !is_terminator(&instruction.op) {
+            pending.push(index + 1);
+        }
+    }
+    removed
+}
+
+// None if a short jump no longer reaches its target
+fn encode(chunk: &Chunk, instructions: &[Instruction], removed: &[bool]) -> Option<Chunk> {
+    // new address of each instruction, removed ones standing for the next
+    // kept one, and of the end of the chunk
+    let mut new_addrs = vec![0; instructions.len() + 1];
+    let mut addr = 0;
+    for (index, instruction) in instructions.iter().enumerate() {
+        new_addrs[index] = addr;
+        if !removed[index] {
+            addr += size(instruction);
+        }
+    }
+    new_addrs[instructions.len()] = addr;
+    let new_addr = |old_addr: usize| match instructions
+        .binary_search_by_key(&old_addr, |instruction| instruction.addr)
+    {
+        Ok(index) => Some(new_addrs[index]),
+        Err(index) if index == instructions.len() && old_addr == chunk.data.len() => {
+            Some(new_addrs[index])
+        }
+        Err(_) => None,
+    };
+
+    let locals = chunk
+        .locals
+        .iter()
+        .map(|(name, start, end)| {
+            let start = new_addr(*start as usize)? as u64;
+            Some((name.to_string(), start, new_addr(*end as usize)? as u64))
+        })
+        .collect::<Option<_>>()?;
+    let mut optimized = Chunk {
+        data: Vec::with_capacity(addr),
+        lines: Vec::new(),
+        locals,
+        // the addresses change
+        cache: InlineCache::default(),
+        ..chunk.clone()
+    };
+    for (index, instruction) in instructions.iter().enumerate() {
+        if removed[index] {
+            continue;
+        }
+        if let Some(line) = chunk.line_at(instruction.addr as u64) {
+            optimized.set_line(line);
+        }
+        let operand = instruction.operand;
+        if is_jump(&instruction.op) {
+            optimized.emit(instruction.op.clone());
+            let offset = new_addr(operand as usize)? as i64 - new_addrs[index] as i64;
+            match instruction.op {
+                Bytecode::JumpLong => {
+                    optimized.data.extend_from_slice(&offset.to_ne_bytes());
+                }
+                _ => {
+                    let offset = i16::try_from(offset).ok()?;
+                    optimized.data.extend_from_slice(&offset.to_ne_bytes());
+                }
+            }
+            if matches!(instruction.op, Bytecode::ConstLessJumpIfFalse) {
+                optimized.data.push(instruction.constant as u8);
+            }
+        } else if matches!(
+            instruction.op,
+            Bytecode::IncrementGlobal | Bytecode::IncrementLocal
+        ) {
+            optimized.emit(instruction.op.clone());
+            optimized.data.push(operand as u8);
+            optimized.data.push(instruction.constant as u8);
+        } else if instruction.op.has_index() {
+            optimized.emit_with_index(instruction.op.clone(), operand);
+        } else {
+            optimized.emit(instruction.op.clone());
+        }
+    }
+    Some(optimized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Emitter = fn(&mut Chunk);
+
+    fn ops(chunk: &Chunk) -> Vec<String> {
+        decode(chunk)
+            .unwrap()
+            .iter()
+            .map(|instruction| match &instruction.op {
+                op @ (Bytecode::IncrementGlobal
+                | Bytecode::IncrementLocal
+                | Bytecode::ConstLessJumpIfFalse) => {
+                    format!("{:?} {} {}", op, instruction.operand, instruction.constant)
+                }
+                op if is_jump(op) => format!("{:?} {}", op, instruction.operand),
+                op => format!("{:?}", op),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_optimize() {
+        let emitters: Vec<(Emitter, Vec<&str>)> = vec![
+            // jump threading
+            (
+                |chunk| {
+                    let first = chunk.emit_jump(Bytecode::JumpIfFalse);
+                    chunk.emit(Bytecode::PushTrue);
+                    let second = chunk.emit_jump(Bytecode::Jump);
+                    let _ = chunk.patch_jump(first, second - 1);
+                    chunk.emit(Bytecode::Add);
+                    let _ = chunk.patch_jump(second, chunk.size());
+                    chunk.emit(Bytecode::Return);
+                },
+                vec!["JumpIfFalse 8", "PushTrue", "Jump 8", "Add", "Return"],
+            ),
+            // jumps to the next instruction
+            (
+                |chunk| {
+                    let jump = chunk.emit_jump(Bytecode::Jump);
+                    let _ = chunk.patch_jump(jump, chunk.size());
+                    chunk.emit(Bytecode::Return);
+                },
+                vec!["Return"],
+            ),
+            (
+                |chunk| {
+                    chunk.emit(Bytecode::PushTrue);
+                    chunk.emit(Bytecode::Not);
+                    chunk.emit(Bytecode::Not);
+                    let jump = chunk.emit_jump(Bytecode::JumpIfFalse);
+                    chunk.emit(Bytecode::None);
+                    let _ = chunk.patch_jump(jump, chunk.size());
+                    chunk.emit(Bytecode::Return);
+                },
+                vec!["PushTrue", "JumpIfFalse 5", "None", "Return"],
+            ),
+            // `not not` is kept where the value itself is used
+            (
+                |chunk| {
+                    chunk.emit(Bytecode::PushTrue);
+                    chunk.emit(Bytecode::Not);
+                    chunk.emit(Bytecode::Not);
+                    chunk.emit(Bytecode::Return);
+                },
+                vec!["PushTrue", "Not", "Not", "Return"],
+            ),
+            (
+                |chunk| {
+                    chunk.emit_with_index(Bytecode::Const, 0);
+                    chunk.emit(Bytecode::Pop);
+                    chunk.emit(Bytecode::None);
+                    chunk.emit(Bytecode::Pop);
+                    chunk.emit(Bytecode::Return);
+                },
+                vec!["Return"],
+            ),
+            // a jump to the pop keeps the push
+            (
+                |chunk| {
+                    let jump = chunk.emit_jump(Bytecode::JumpIfFalse);
+                    chunk.emit(Bytecode::None);
+                    let _ = chunk.patch_jump(jump, chunk.size());
+                    chunk.emit(Bytecode::Pop);
+                },
+                vec!["JumpIfFalse 4", "None", "Pop"],
+            ),
+            // superinstructions
+            (
+                |chunk| {
+                    chunk.emit_with_index(Bytecode::GetLocal, 0);
+                    chunk.emit_with_index(Bytecode::Const, 1);
+                    chunk.emit(Bytecode::Less);
+                    let jump = chunk.emit_jump(Bytecode::JumpIfFalse);
+                    chunk.emit_with_index(Bytecode::Const, 2);
+                    chunk.emit_with_index(Bytecode::InplaceAddLocal, 0);
+                    chunk.emit_with_index(Bytecode::Const, 2);
+                    chunk.emit_with_index(Bytecode::InplaceAddGlobal, 3);
+                    chunk.emit_loop(0);
+                    let _ = chunk.patch_jump(jump, chunk.size());
+                    chunk.emit(Bytecode::Return);
+                },
+                vec![
+                    "GetLocal",
+                    "ConstLessJumpIfFalse 15 1",
+                    "IncrementLocal 0 2",
+                    "IncrementGlobal 3 2",
+                    "Loop 0",
+                    "Return",
+                ],
+            ),
+            // not when a jump enters the sequence or an index needs a prefix
+            (
+                |chunk| {
+                    chunk.emit_with_index(Bytecode::Const, 1);
+                    let jump = chunk.emit_jump(Bytecode::JumpIfFalse);
+                    let _ = chunk.patch_jump(jump, chunk.size());
+                    chunk.emit_with_index(Bytecode::InplaceAddLocal, 0);
+                    chunk.emit_with_index(Bytecode::Const, 256);
+                    chunk.emit_with_index(Bytecode::InplaceAddLocal, 0);
+                    chunk.emit(Bytecode::Return);
+                },
+                vec![
+                    "Const",
+                    "JumpIfFalse 5",
+                    "InplaceAddLocal",
+                    "Const",
+                    "InplaceAddLocal",
+                    "Return",
+                ],
+            ),
+        ];
+        emitters.into_iter().for_each(|(emit, expected)| {
+            let mut chunk = Chunk::new();
+            emit(&mut chunk);
+            optimize(&mut chunk);
+            assert_eq!(ops(&chunk), expected);
+        });
+    }
+
+    #[test]
+    fn test_optimize_lines() {
+        let mut chunk = Chunk::new();
+        chunk.set_line(1);
+        chunk.emit(Bytecode::None);
+        chunk.emit(Bytecode::Pop);
+        chunk.set_line(2);
+        chunk.emit(Bytecode::PushTrue);
+        chunk.set_line(3);
+        chunk.emit(Bytecode::Return);
+        optimize(&mut chunk);
+        assert_eq!(chunk.lines, vec![(0, 2), (1, 3)]);
+    }
+
+    #[test]
+    fn test_strip_unreachable() {
+        let mut chunk = Chunk::new();
+        let handler = chunk.emit_jump(Bytecode::SetupTry);
+        chunk.emit(Bytecode::PushTrue);
+        let jump = chunk.emit_jump(Bytecode::JumpIfFalse);
+        chunk.emit(Bytecode::None);
+        chunk.emit(Bytecode::Return);
+        // after a return
+        chunk.emit(Bytecode::Pop);
+        let _ = chunk.patch_jump(jump, chunk.size());
+        chunk.emit(Bytecode::PushFalse);
+        chunk.emit(Bytecode::Return);
+        // only reached by the exception handler
+        let _ = chunk.patch_jump(handler, chunk.size());
+        chunk.emit(Bytecode::Pop);
+        chunk.emit_loop(0);
+        chunk.emit(Bytecode::Nop);
+        strip_unreachable(&mut chunk);
+        assert_eq!(
+            ops(&chunk),
+            vec![
+                "SetupTry 11",
+                "PushTrue",
+                "JumpIfFalse 9",
+                "None",
+                "Return",
+                "PushFalse",
+                "Return",
+                "Pop",
+                "Loop 0"
+            ]
+        );
+    }
+}
@@ -1,50 +1,132 @@
 use crate::ast::{
-    AssignmentExpression, BinaryExpression, BlockExpression, CallExpression, ElifExpression,
-    Expression, FunctionExpression, IfExpression, Literal, Operator, Program, ReturnExpression,
+    AnnotationExpression, AssertExpression, AssignmentExpression, AttributeExpression,
+    BinaryExpression, BlockExpression, CallExpression, ElifExpression, ExceptExpression,
+    Expression, FunctionExpression, IfExpression, ImportExpression, IndexExpression, Literal, Node,
+    Operator, Program, RaiseExpression, ReturnExpression, TryExpression, TypeAnnotation,
     UnaryExpression, WhileExpression,
 };
+use crate::diagnostic::Span;
 use crate::token::Token;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
+#[allow(clippy::enum_variant_names)]
 pub enum ParserError {
-    InvalidOperator(String),
-    InvalidPrimary(String),
-    InvalidExpression(String),
+    InvalidOperator(String, Span),
+    InvalidPrimary(String, Span),
+    InvalidExpression(String, Span),
+    LimitExceeded(String, Span),
+    // `def f(a, a):`
+    DuplicateArgument(String, Span),
+    // `f(x=1, x=2)`
+    DuplicateKeyword(String, Span),
 }
 
 pub struct Parser {
     tokens: Vec<Token>,
+    // one per token, empty when parsing tokens without a source
+    spans: Vec<Span>,
     index: usize,
     program: Program,
-    loop_count: usize,
+    // current nesting of recursive parse calls
+    depth: usize,
+    max_depth: Option<usize>,
+    max_tokens: Option<usize>,
 }
 
 impl Parser {
+    #[cfg(test)]
     pub fn new(tokens: Vec<Token>) -> Parser {
+        Parser::new_with_spans(tokens, Vec::new())
+    }
+
+    /// Parser whose expressions and errors point at the source, `spans`
+    /// holding the span of every token as returned by the lexer.
+    pub fn new_with_spans(tokens: Vec<Token>, spans: Vec<Span>) -> Parser {
+        let mut spans = spans.into_iter();
+        // filter Token::NewLine
+        let (tokens, spans) = tokens
+            .into_iter()
+            .map(|tok| (tok, spans.next().unwrap_or_default()))
+            .filter(|(tok, _)| tok != &Token::NewLine)
+            .unzip();
         Parser {
-            // filter Token::NewLine
-            tokens: tokens
-                .into_iter()
-                .filter(|tok| tok != &Token::NewLine)
-                .collect(),
+            tokens,
+            spans,
             index: 0,
             program: Program::new(),
-            loop_count: 0,
+            depth: 0,
+            max_depth: None,
+            max_tokens: None,
         }
     }
 
+    /// Parser that rejects input nesting deeper than `max_depth` or longer
+    /// than `max_tokens`, so hostile input can't overflow the stack.
+    pub fn new_with_limits(
+        tokens: Vec<Token>,
+        spans: Vec<Span>,
+        max_depth: Option<usize>,
+        max_tokens: Option<usize>,
+    ) -> Parser {
+        let mut parser = Parser::new_with_spans(tokens, spans);
+        parser.max_depth = max_depth;
+        parser.max_tokens = max_tokens;
+        parser
+    }
+
     pub fn parse(&mut self) -> Result<Program, ParserError> {
         self.index = 0;
+        self.depth = 0;
+
+        if let Some(max_tokens) = self.max_tokens {
+            if self.tokens.len() > max_tokens {
+                return Err(ParserError::LimitExceeded(
+                    format!(
+                        "Source has {} tokens, the limit is {}",
+                        self.tokens.len(),
+                        max_tokens
+                    ),
+                    self.current_span(),
+                ));
+            }
+        }
 
         while self.index < self.tokens.len() && self.tokens[self.index] != Token::Eof {
-            let expr = self.parse_expression()?;
+            let expr = self.parse_statement()?;
             self.program.stmts.push(expr);
         }
 
-        Ok(self.program.clone())
+        Ok(std::mem::replace(&mut self.program, Program::new()))
     }
 
-    fn parse_expression(&mut self) -> Result<Box<Expression>, ParserError> {
+    // a statement of a program or block, optionally ended by a semicolon
+    // so that several fit on a line
+    fn parse_statement(&mut self) -> Result<Node, ParserError> {
+        // `name: type` only annotates at the start of a statement, elsewhere
+        // the colon ends a condition
+        let stmt = if matches!(self.current_token(), Token::Identifier(_))
+            && self.tokens.get(self.index + 1) == Some(&Token::Colon)
+        {
+            self.nested(Parser::parse_annotation_expression)?
+        } else {
+            self.parse_expression()?
+        };
+        self.match_token(&Token::Semicolon);
+        Ok(stmt)
+    }
+
+    fn parse_expression(&mut self) -> Result<Node, ParserError> {
+        self.nested(Parser::parse_statement_or_expression)
+    }
+
+    // condition of an if, elif or while, followed by the colon of its body
+    fn parse_condition(&mut self) -> Result<Node, ParserError> {
+        self.nested(Parser::parse_assignment)
+    }
+
+    // the statement parsers are called with their keyword already matched,
+    // so their nodes start at the previous token
+    fn parse_statement_or_expression(&mut self) -> Result<Node, ParserError> {
         if self.match_token(&Token::Indent) {
             self.parse_block_expression()
         } else if self.match_token(&Token::Def) {
@@ -53,46 +135,61 @@ impl Parser {
             self.parse_if_expression()
         } else if self.match_token(&Token::While) {
             self.parse_while_expression()
+        } else if self.match_token(&Token::Try) {
+            self.parse_try_expression()
+        } else if self.match_token(&Token::Raise) {
+            self.parse_raise_expression()
+        } else if self.match_token(&Token::Assert) {
+            self.parse_assert_expression()
         } else if self.match_token(&Token::Continue) {
             self.parse_continue_expression()
         } else if self.match_token(&Token::Break) {
             self.parse_break_expression()
         } else if self.match_token(&Token::Return) {
             self.parse_return_expression()
+        } else if self.match_token(&Token::Import) {
+            self.parse_import_expression()
         } else {
             self.parse_assignment()
         }
     }
 
-    fn parse_block_expression(&mut self) -> Result<Box<Expression>, ParserError> {
-        let mut exprs: Vec<Box<Expression>> = Vec::new();
+    fn parse_block_expression(&mut self) -> Result<Node, ParserError> {
+        let start = self.index;
+        let mut exprs: Vec<Node> = Vec::new();
         while self.index < self.tokens.len()
             && self.tokens[self.index] != Token::Dedent
             && self.tokens[self.index] != Token::Eof
         {
-            let expr = self.parse_expression()?;
+            let expr = self.parse_statement()?;
             exprs.push(expr);
         }
         self.match_token(&Token::Dedent);
-        Ok(Box::new(Expression::Block(BlockExpression { exprs })))
+        Ok(self.node(Expression::Block(BlockExpression { exprs }), start))
     }
 
-    fn parse_function_expression(&mut self) -> Result<Box<Expression>, ParserError> {
+    fn parse_function_expression(&mut self) -> Result<Node, ParserError> {
+        let start = self.index - 1;
         let function_name = match self.current_token() {
             Token::Identifier(function_name) => function_name.to_string(),
             _ => {
-                return Err(ParserError::InvalidExpression(String::from(
-                    "Missing function name",
-                )))
+                return Err(ParserError::InvalidExpression(
+                    String::from("Missing function name"),
+                    self.current_span(),
+                ))
             }
         };
         self.advance_token();
 
         if !self.match_token(&Token::LeftParen) {
-            return Err(ParserError::InvalidExpression(String::from("Missing '('")));
+            return Err(ParserError::InvalidExpression(
+                String::from("Missing '('"),
+                self.current_span(),
+            ));
         }
 
         let mut args: Vec<String> = Vec::new();
+        let mut arg_types: Vec<Option<TypeAnnotation>> = Vec::new();
         loop {
             let arg_name = match self.current_token() {
                 Token::Identifier(arg_name) => arg_name.to_string(),
@@ -102,70 +199,106 @@ impl Parser {
                 }
                 _ => break,
             };
+            if args.contains(&arg_name) {
+                return Err(ParserError::DuplicateArgument(
+                    format!("duplicate argument '{}' in function definition", arg_name),
+                    self.current_span(),
+                ));
+            }
             args.push(arg_name);
             self.advance_token();
+            if self.match_token(&Token::Colon) {
+                arg_types.push(Some(self.parse_type_annotation()?));
+            } else {
+                arg_types.push(None);
+            }
 
             if self.match_token(&Token::Comma) || self.match_token(&Token::RightParen) {
                 if self.previous_token() == &Token::RightParen {
                     break;
                 }
             } else {
-                return Err(ParserError::InvalidExpression(String::from(
-                    "Expected argument or ')'",
-                )));
+                return Err(ParserError::InvalidExpression(
+                    String::from("Expected argument or ')'"),
+                    self.current_span(),
+                ));
             }
         }
 
+        let return_type = if self.match_token(&Token::Arrow) {
+            Some(self.parse_type_annotation()?)
+        } else {
+            None
+        };
+
         if !self.match_token(&Token::Colon) {
-            return Err(ParserError::InvalidExpression(String::from("Missing ':'")));
+            return Err(ParserError::InvalidExpression(
+                String::from("Missing ':'"),
+                self.current_span(),
+            ));
         }
 
-        let body_expr = self.parse_expression()?;
-        let block_expression = match *body_expr {
-            Expression::Block(block_expression) => block_expression,
+        let mut body_expr = self.parse_expression()?;
+        let block_expression = match &mut *body_expr {
+            // stubs such as `def f(x: int) -> int: ...` keep it on one line
+            Expression::Literal(Literal::Ellipsis) => BlockExpression {
+                exprs: vec![body_expr],
+            },
+            Expression::Block(block_expression) => BlockExpression {
+                exprs: std::mem::take(&mut block_expression.exprs),
+            },
             _ => {
-                return Err(ParserError::InvalidExpression(String::from(
-                    "Bad function definition, expected block",
-                )))
+                return Err(ParserError::InvalidExpression(
+                    String::from("Bad function definition, expected block"),
+                    self.current_span(),
+                ))
             }
         };
 
-        Ok(Box::new(Expression::Function(FunctionExpression {
-            name: function_name,
-            args,
-            body: block_expression,
-        })))
+        Ok(self.node(
+            Expression::Function(FunctionExpression {
+                name: function_name,
+                args,
+                arg_types,
+                return_type,
+                body: block_expression,
+            }),
+            start,
+        ))
     }
 
-    fn parse_if_expression(&mut self) -> Result<Box<Expression>, ParserError> {
+    fn parse_if_expression(&mut self) -> Result<Node, ParserError> {
+        let start = self.index - 1;
         let mut if_expression = IfExpression {
-            condition: Box::new(Expression::Empty),
-            then_branch: Box::new(Expression::Empty),
+            condition: Node::from(Expression::Empty),
+            then_branch: Node::from(Expression::Empty),
             elif_branches: Vec::new(),
-            else_branch: Box::new(Expression::Empty),
+            else_branch: Node::from(Expression::Empty),
         };
         // Parse conditional
-        if_expression.condition = self.parse_expression()?;
+        if_expression.condition = self.parse_condition()?;
 
         // Check colon ':'
         if !self.match_token(&Token::Colon) {
-            return Err(ParserError::InvalidExpression(String::from(
-                "If expression missing colon ':'",
-            )));
+            return Err(ParserError::InvalidExpression(
+                String::from("If expression missing colon ':'"),
+                self.current_span(),
+            ));
         }
 
         // Required a then branch
         if self.match_token(&Token::Indent) {
             if_expression.then_branch = self.parse_block_expression()?;
         } else {
-            return Err(ParserError::InvalidExpression(format!(
-                "If expression without body"
-            )));
+            return Err(ParserError::InvalidExpression(
+                "If expression without body".to_string(),
+                self.current_span(),
+            ));
         }
 
         // Optional Elif branches
         while self.match_token(&Token::Elif) {
-            let condition = self.parse_expression()?;
+            let condition = self.parse_condition()?;
             if self.match_token(&Token::Colon) {
                 if self.match_token(&Token::Indent) {
                     let then_branch = self.parse_block_expression()?;
@@ -174,124 +307,343 @@ impl Parser {
                         then_branch,
                     });
                 } else {
-                    return Err(ParserError::InvalidExpression(String::from(
-                        "Elif expression without body",
-                    )));
+                    return Err(ParserError::InvalidExpression(
+                        String::from("Elif expression without body"),
+                        self.current_span(),
+                    ));
                 }
             } else {
-                return Err(ParserError::InvalidExpression(String::from(
-                    "Elif expression missing colon ':'",
-                )));
+                return Err(ParserError::InvalidExpression(
+                    String::from("Elif expression missing colon ':'"),
+                    self.current_span(),
+                ));
             }
         }
 
-        // Optional Else branch
-        if self.match_token(&Token::Else) {
-            if self.match_token(&Token::Colon) {
-                if self.match_token(&Token::Indent) {
-                    if_expression.else_branch = self.parse_block_expression()?;
-                } else {
-                    return Err(ParserError::InvalidExpression(String::from(
-                        "Else expression without body",
-                    )));
-                }
-            } else {
-                return Err(ParserError::InvalidExpression(String::from(
-                    "Else expression missing colon ':'",
-                )));
-            }
-        }
+        if_expression.else_branch = self.parse_else_branch()?;
 
-        Ok(Box::new(Expression::If(if_expression)))
+        Ok(self.node(Expression::If(if_expression), start))
     }
 
-    fn parse_while_expression(&mut self) -> Result<Box<Expression>, ParserError> {
-        let condition = self.parse_expression()?;
+    // optional `else:` block of an if or a loop, Empty without one
+    fn parse_else_branch(&mut self) -> Result<Node, ParserError> {
+        if !self.match_token(&Token::Else) {
+            return Ok(Node::from(Expression::Empty));
+        }
+        if !self.match_token(&Token::Colon) {
+            return Err(ParserError::InvalidExpression(
+                String::from("Else expression missing colon ':'"),
+                self.current_span(),
+            ));
+        }
+        if !self.match_token(&Token::Indent) {
+            return Err(ParserError::InvalidExpression(
+                String::from("Else expression without body"),
+                self.current_span(),
+            ));
+        }
+        self.parse_block_expression()
+    }
 
-        self.loop_count += 1;
+    fn parse_while_expression(&mut self) -> Result<Node, ParserError> {
+        let start = self.index - 1;
+        let condition = self.parse_condition()?;
 
-        let body;
-        if self.match_token(&Token::Colon) {
-            body = self.parse_expression()?;
+        let body = if self.match_token(&Token::Colon) {
+            self.parse_expression()?
         } else {
-            return Err(ParserError::InvalidExpression(String::from(
-                "While expression missing colon ':'",
-            )));
-        }
+            return Err(ParserError::InvalidExpression(
+                String::from("While expression missing colon ':'"),
+                self.current_span(),
+            ));
+        };
 
-        self.loop_count -= 1;
+        let else_branch = self.parse_else_branch()?;
 
-        Ok(Box::new(Expression::While(WhileExpression {
-            condition,
-            body,
-        })))
+        Ok(self.node(
+            Expression::While(WhileExpression {
+                condition,
+                body,
+                else_branch,
+            }),
+            start,
+        ))
     }
 
-    fn parse_continue_expression(&mut self) -> Result<Box<Expression>, ParserError> {
-        if self.loop_count == 0 {
-            return Err(ParserError::InvalidExpression(String::from(
-                "continue without loop",
-            )));
+    fn parse_try_expression(&mut self) -> Result<Node, ParserError> {
+        let start = self.index - 1;
+        if !self.match_token(&Token::Colon) {
+            return Err(ParserError::InvalidExpression(
+                String::from("Try expression missing colon ':'"),
+                self.current_span(),
+            ));
+        }
+        if !self.match_token(&Token::Indent) {
+            return Err(ParserError::InvalidExpression(
+                String::from("Try expression without body"),
+                self.current_span(),
+            ));
         }
-        Ok(Box::new(Expression::Continue))
+        let body = self.parse_block_expression()?;
+
+        let mut handlers: Vec<ExceptExpression> = Vec::new();
+        while self.match_token(&Token::Except) {
+            if handlers
+                .last()
+                .is_some_and(|handler| *handler.exception_type == Expression::Empty)
+            {
+                return Err(ParserError::InvalidExpression(
+                    String::from("default 'except:' must be last"),
+                    self.current_span(),
+                ));
+            }
+            handlers.push(self.parse_except_expression()?);
+        }
+        if handlers.is_empty() {
+            return Err(ParserError::InvalidExpression(
+                String::from("Try expression without except clause"),
+                self.current_span(),
+            ));
+        }
+
+        let else_branch = self.parse_else_branch()?;
+
+        Ok(self.node(
+            Expression::Try(TryExpression {
+                body,
+                handlers,
+                else_branch,
+            }),
+            start,
+        ))
     }
 
-    fn parse_break_expression(&mut self) -> Result<Box<Expression>, ParserError> {
-        if self.loop_count == 0 {
-            return Err(ParserError::InvalidExpression(String::from(
-                "break without loop",
-            )));
+    // `except:`, `except type:` or `except type as name:` and its body
+    fn parse_except_expression(&mut self) -> Result<ExceptExpression, ParserError> {
+        let exception_type = match self.current_token() {
+            Token::Colon => Node::from(Expression::Empty),
+            _ => self.parse_logic_operator()?,
+        };
+        let name = if self.match_token(&Token::As) {
+            match self.current_token() {
+                Token::Identifier(name) if *exception_type != Expression::Empty => {
+                    let name = name.to_string();
+                    self.advance_token();
+                    Some(name)
+                }
+                _ => {
+                    return Err(ParserError::InvalidExpression(
+                        String::from("Expected exception name after 'as'"),
+                        self.current_span(),
+                    ))
+                }
+            }
+        } else {
+            None
+        };
+        if !self.match_token(&Token::Colon) {
+            return Err(ParserError::InvalidExpression(
+                String::from("Except expression missing colon ':'"),
+                self.current_span(),
+            ));
         }
-        Ok(Box::new(Expression::Break))
+        if !self.match_token(&Token::Indent) {
+            return Err(ParserError::InvalidExpression(
+                String::from("Except expression without body"),
+                self.current_span(),
+            ));
+        }
+        let body = self.parse_block_expression()?;
+        Ok(ExceptExpression {
+            exception_type,
+            name,
+            body,
+        })
     }
 
-    fn parse_return_expression(&mut self) -> Result<Box<Expression>, ParserError> {
+    fn parse_raise_expression(&mut self) -> Result<Node, ParserError> {
+        let start = self.index - 1;
+        let exception = match self.current_token() {
+            &Token::Dedent | &Token::Eof => Node::from(Expression::Empty),
+            _ => self.parse_logic_operator()?,
+        };
+        let cause = if *exception != Expression::Empty && self.match_token(&Token::From) {
+            self.parse_logic_operator()?
+        } else {
+            Node::from(Expression::Empty)
+        };
+        Ok(self.node(
+            Expression::Raise(RaiseExpression { exception, cause }),
+            start,
+        ))
+    }
+
+    fn parse_assert_expression(&mut self) -> Result<Node, ParserError> {
+        let start = self.index - 1;
+        let test = self.parse_logic_operator()?;
+        let message = if self.match_token(&Token::Comma) {
+            self.parse_logic_operator()?
+        } else {
+            Node::from(Expression::Empty)
+        };
+        Ok(self.node(
+            Expression::Assert(AssertExpression { test, message }),
+            start,
+        ))
+    }
+
+    // placement in a loop is checked by the compiler
+    fn parse_continue_expression(&mut self) -> Result<Node, ParserError> {
+        let start = self.index - 1;
+        Ok(self.node(Expression::Continue, start))
+    }
+
+    fn parse_break_expression(&mut self) -> Result<Node, ParserError> {
+        let start = self.index - 1;
+        Ok(self.node(Expression::Break, start))
+    }
+
+    fn parse_return_expression(&mut self) -> Result<Node, ParserError> {
+        let start = self.index - 1;
         let expr = match self.current_token() {
-            &Token::Dedent | &Token::Eof => Box::new(Expression::Empty),
+            &Token::Dedent | &Token::Eof => Node::from(Expression::Empty),
             _ => self.parse_assignment()?,
         };
-        Ok(Box::new(Expression::Return(ReturnExpression { expr })))
+        Ok(self.node(Expression::Return(ReturnExpression { expr }), start))
+    }
+
+    fn parse_import_expression(&mut self) -> Result<Node, ParserError> {
+        let start = self.index - 1;
+        let name = match self.current_token() {
+            Token::Identifier(name) => name.to_string(),
+            _ => {
+                return Err(ParserError::InvalidExpression(
+                    String::from("Missing module name"),
+                    self.current_span(),
+                ))
+            }
+        };
+        self.advance_token();
+        Ok(self.node(Expression::Import(ImportExpression { name }), start))
     }
 
-    fn parse_assignment(&mut self) -> Result<Box<Expression>, ParserError> {
+    fn parse_annotation_expression(&mut self) -> Result<Node, ParserError> {
+        let start = self.index;
+        let name = match self.current_token() {
+            Token::Identifier(name) => name.to_string(),
+            _ => {
+                return Err(ParserError::InvalidExpression(
+                    String::from("Expected annotated name"),
+                    self.current_span(),
+                ))
+            }
+        };
+        self.advance_token();
+        self.match_token(&Token::Colon);
+        let annotation = self.parse_type_annotation()?;
+        let value = if self.match_token(&Token::Equal) {
+            Some(self.parse_assignment()?)
+        } else {
+            None
+        };
+        Ok(self.node(
+            Expression::Annotation(AnnotationExpression {
+                name,
+                annotation,
+                value,
+            }),
+            start,
+        ))
+    }
+
+    fn parse_type_annotation(&mut self) -> Result<TypeAnnotation, ParserError> {
+        let mut name = match self.current_token() {
+            Token::Identifier(name) => name.to_string(),
+            Token::None => String::from("None"),
+            // forward references are written as strings
+            Token::String(name) => name.to_string(),
+            token => {
+                return Err(ParserError::InvalidExpression(
+                    format!("Invalid type annotation: {:?}", token),
+                    self.current_span(),
+                ))
+            }
+        };
+        self.advance_token();
+        while self.match_token(&Token::Dot) {
+            match self.current_token() {
+                Token::Identifier(attribute) => name = format!("{}.{}", name, attribute),
+                _ => {
+                    return Err(ParserError::InvalidExpression(
+                        String::from("Expected name after '.' in type annotation"),
+                        self.current_span(),
+                    ))
+                }
+            }
+            self.advance_token();
+        }
+
+        let mut args = Vec::new();
+        if self.match_token(&Token::LeftBracket) {
+            loop {
+                args.push(self.parse_type_annotation()?);
+                if self.match_token(&Token::RightBracket) {
+                    break;
+                }
+                if !self.match_token(&Token::Comma) {
+                    return Err(ParserError::InvalidExpression(
+                        String::from("Expected ',' or ']' in type annotation"),
+                        self.current_span(),
+                    ));
+                }
+            }
+        }
+        Ok(TypeAnnotation { name, args })
+    }
+
+    fn parse_assignment(&mut self) -> Result<Node, ParserError> {
+        let start = self.index;
         let expr = self.parse_logic_operator()?;
 
         if self.match_token(&Token::Equal) {
-            let rhs = self.parse_assignment()?;
-            return Ok(Box::new(Expression::Assignment(AssignmentExpression {
-                lhs: expr,
-                rhs,
-            })));
+            let rhs = self.nested(Parser::parse_assignment)?;
+            return Ok(self.node(
+                Expression::Assignment(AssignmentExpression { lhs: expr, rhs }),
+                start,
+            ));
         }
 
         Ok(expr)
     }
 
-    fn parse_logic_operator(&mut self) -> Result<Box<Expression>, ParserError> {
+    fn parse_logic_operator(&mut self) -> Result<Node, ParserError> {
+        let start = self.index;
         let mut expr = self.parse_equality()?;
 
         while self.match_token(&Token::Or) || self.match_token(&Token::And) {
             let op = self.get_binary_operator(self.previous_token())?;
             let rhs = self.parse_equality()?;
-            expr = Box::new(Expression::Binary(BinaryExpression { lhs: expr, op, rhs }));
+            expr = self.binary_node(expr, op, rhs, start);
         }
 
         Ok(expr)
     }
 
-    fn parse_equality(&mut self) -> Result<Box<Expression>, ParserError> {
+    fn parse_equality(&mut self) -> Result<Node, ParserError> {
+        let start = self.index;
         let mut expr = self.parse_comparison()?;
 
         while self.match_token(&Token::EqualEqual) || self.match_token(&Token::BangEqual) {
             let op = self.get_binary_operator(self.previous_token())?;
             let rhs = self.parse_comparison()?;
-            expr = Box::new(Expression::Binary(BinaryExpression { lhs: expr, op, rhs }));
+            expr = self.binary_node(expr, op, rhs, start);
         }
 
         Ok(expr)
     }
 
-    fn parse_comparison(&mut self) -> Result<Box<Expression>, ParserError> {
+    fn parse_comparison(&mut self) -> Result<Node, ParserError> {
+        let start = self.index;
         let mut expr = self.parse_term()?;
 
         while self.match_token(&Token::Greater)
@@ -301,95 +653,194 @@ impl Parser {
         {
             let op = self.get_binary_operator(self.previous_token())?;
             let rhs = self.parse_term()?;
-            expr = Box::new(Expression::Binary(BinaryExpression { lhs: expr, op, rhs }));
+            expr = self.binary_node(expr, op, rhs, start);
         }
 
         Ok(expr)
     }
 
-    fn parse_term(&mut self) -> Result<Box<Expression>, ParserError> {
+    fn parse_term(&mut self) -> Result<Node, ParserError> {
+        let start = self.index;
         let mut expr = self.parse_factor()?;
 
         while self.match_token(&Token::Plus) || self.match_token(&Token::Minus) {
             let op = self.get_binary_operator(self.previous_token())?;
             let rhs = self.parse_factor()?;
-            expr = Box::new(Expression::Binary(BinaryExpression { lhs: expr, op, rhs }));
+            expr = self.binary_node(expr, op, rhs, start);
         }
 
         Ok(expr)
     }
 
-    fn parse_factor(&mut self) -> Result<Box<Expression>, ParserError> {
+    fn parse_factor(&mut self) -> Result<Node, ParserError> {
+        let start = self.index;
         let mut expr = self.parse_unary()?;
 
-        while self.match_token(&Token::Star) || self.match_token(&Token::Slash) {
+        while self.match_token(&Token::Star)
+            || self.match_token(&Token::Slash)
+            || self.match_token(&Token::SlashSlash)
+            || self.match_token(&Token::Percent)
+        {
             let op = self.get_binary_operator(self.previous_token())?;
             let rhs = self.parse_unary()?;
-            expr = Box::new(Expression::Binary(BinaryExpression { lhs: expr, op, rhs }));
+            expr = self.binary_node(expr, op, rhs, start);
         }
 
         Ok(expr)
     }
 
-    fn parse_unary(&mut self) -> Result<Box<Expression>, ParserError> {
+    fn parse_unary(&mut self) -> Result<Node, ParserError> {
+        let start = self.index;
         if self.match_token(&Token::Bang) || self.match_token(&Token::Minus) {
             let previous_token = self.previous_token();
             let op = match previous_token {
                 Token::Bang => Operator::Not,
                 Token::Minus => Operator::Neg,
                 _ => {
-                    return Err(ParserError::InvalidOperator(format!(
-                        "Invalid unary operator: {:?}",
-                        previous_token
-                    )))
+                    return Err(ParserError::InvalidOperator(
+                        format!("Invalid unary operator: {:?}", previous_token),
+                        self.current_span(),
+                    ))
                 }
             };
-            let rhs = self.parse_unary()?;
-            return Ok(Box::new(Expression::Unary(UnaryExpression {
-                op,
-                expr: rhs,
-            })));
+            let rhs = self.nested(Parser::parse_unary)?;
+            return Ok(self.node(Expression::Unary(UnaryExpression { op, expr: rhs }), start));
         }
         self.parse_call()
     }
 
-    fn parse_call(&mut self) -> Result<Box<Expression>, ParserError> {
-        let expr = self.parse_primary()?;
+    fn parse_call(&mut self) -> Result<Node, ParserError> {
+        let start = self.index;
+        let mut expr = self.parse_primary()?;
 
-        let mut args: Vec<Box<Expression>> = Vec::new();
-        if self.match_token(&Token::LeftParen) {
-            while !self.match_token(&Token::RightParen) {
-                let argument = self.parse_expression()?;
-                args.push(argument);
-                self.match_token(&Token::Comma);
+        loop {
+            if self.match_token(&Token::LeftParen) {
+                let mut args: Vec<Node> = Vec::new();
+                let mut kwargs: Vec<(String, Node)> = Vec::new();
+                while !self.match_token(&Token::RightParen) {
+                    match (self.current_token(), self.tokens.get(self.index + 1)) {
+                        (Token::Identifier(name), Some(Token::Equal)) => {
+                            let name = name.to_string();
+                            if kwargs.iter().any(|(kwarg, _)| *kwarg == name) {
+                                return Err(ParserError::DuplicateKeyword(
+                                    format!("keyword argument repeated: {}", name),
+                                    self.current_span(),
+                                ));
+                            }
+                            self.advance_token();
+                            self.advance_token();
+                            kwargs.push((name, self.parse_expression()?));
+                        }
+                        _ if !kwargs.is_empty() => {
+                            return Err(ParserError::InvalidExpression(
+                                String::from("Positional argument follows keyword argument"),
+                                self.current_span(),
+                            ));
+                        }
+                        _ => args.push(self.parse_expression()?),
+                    }
+                    self.match_token(&Token::Comma);
+                }
+                expr = self.node(
+                    Expression::Call(CallExpression {
+                        callable: expr,
+                        args,
+                        kwargs,
+                    }),
+                    start,
+                );
+            } else if self.match_token(&Token::LeftBracket) {
+                let index = self.parse_expression()?;
+                if !self.match_token(&Token::RightBracket) {
+                    return Err(ParserError::InvalidExpression(
+                        String::from("Expected ']' after index"),
+                        self.current_span(),
+                    ));
+                }
+                expr = self.node(
+                    Expression::Index(IndexExpression {
+                        object: expr,
+                        index,
+                    }),
+                    start,
+                );
+            } else if self.match_token(&Token::Dot) {
+                let name = match self.current_token() {
+                    Token::Identifier(name) => name.to_string(),
+                    other => {
+                        return Err(ParserError::InvalidExpression(
+                            format!("Expected attribute name, found {:?}", other),
+                            self.current_span(),
+                        ))
+                    }
+                };
+                self.advance_token();
+                expr = self.node(
+                    Expression::Attribute(AttributeExpression { object: expr, name }),
+                    start,
+                );
+            } else {
+                break;
             }
-            return Ok(Box::new(Expression::Call(CallExpression {
-                callable: expr,
-                args,
-            })));
         }
 
         Ok(expr)
     }
 
-    fn parse_primary(&mut self) -> Result<Box<Expression>, ParserError> {
+    fn parse_primary(&mut self) -> Result<Node, ParserError> {
+        if self.match_token(&Token::LeftParen) {
+            let expr = self.parse_expression()?;
+            if !self.match_token(&Token::RightParen) {
+                return Err(ParserError::InvalidExpression(
+                    String::from("Expected ')' after expression"),
+                    self.current_span(),
+                ));
+            }
+            return Ok(expr);
+        }
+        self.parse_atom()
+    }
+
+    // literals and variables, kept out of `parse_primary` as nested
+    // expressions recurse through it
+    fn parse_atom(&mut self) -> Result<Node, ParserError> {
+        let start = self.index;
         let expr = match self.current_token() {
-            Token::None => Ok(Box::new(Expression::Literal(Literal::None))),
-            Token::True => Ok(Box::new(Expression::Literal(Literal::True))),
-            Token::False => Ok(Box::new(Expression::Literal(Literal::False))),
-            Token::Integer(value) => Ok(Box::new(Expression::Literal(Literal::Integer(*value)))),
-            Token::Float(value) => Ok(Box::new(Expression::Literal(Literal::Float(*value)))),
-            Token::String(value) => Ok(Box::new(Expression::Literal(Literal::String(
-                value.to_string(),
-            )))),
-            Token::Identifier(value) => Ok(Box::new(Expression::Variable(value.to_string()))),
-            other => Err(ParserError::InvalidPrimary(format!(
-                "Invalid primary: {:?}",
-                other
-            ))),
+            Token::None => Ok(Expression::Literal(Literal::None)),
+            Token::True => Ok(Expression::Literal(Literal::True)),
+            Token::False => Ok(Expression::Literal(Literal::False)),
+            Token::Ellipsis => Ok(Expression::Literal(Literal::Ellipsis)),
+            Token::Integer(value) => Ok(Expression::Literal(Literal::Integer(*value))),
+            Token::Float(value) => Ok(Expression::Literal(Literal::Float(*value))),
+            Token::String(value) => Ok(Expression::Literal(Literal::String(value.to_string()))),
+            Token::Bytes(value) => Ok(Expression::Literal(Literal::Bytes(value.to_vec()))),
+            Token::Identifier(value) => Ok(Expression::Variable(value.to_string())),
+            other => Err(ParserError::InvalidPrimary(
+                format!("Invalid primary: {:?}", other),
+                self.current_span(),
+            )),
         };
         self.advance_token();
-        expr
+        expr.map(|expr| self.node(expr, start))
+    }
+
+    // Runs `parse` one nesting level deeper, enforcing `max_depth`
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Parser) -> Result<T, ParserError>,
+    ) -> Result<T, ParserError> {
+        if let Some(max_depth) = self.max_depth {
+            if self.depth >= max_depth {
+                return Err(ParserError::LimitExceeded(
+                    format!("Maximum nesting depth of {} exceeded", max_depth),
+                    self.current_span(),
+                ));
+            }
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
     }
 
     fn get_binary_operator(&self, token: &Token) -> Result<Operator, ParserError> {
@@ -407,10 +858,45 @@ impl Parser {
             Token::Minus => Ok(Operator::Sub),
             Token::Star => Ok(Operator::Mul),
             Token::Slash => Ok(Operator::Div),
-            _ => Err(ParserError::InvalidOperator(format!(
-                "Invalid binary operator: {:?}",
-                token
-            ))),
+            Token::SlashSlash => Ok(Operator::FloorDiv),
+            Token::Percent => Ok(Operator::Mod),
+            _ => Err(ParserError::InvalidOperator(
+                format!("Invalid binary operator: {:?}", token),
+                self.current_span(),
+            )),
+        }
+    }
+
+    // node for `expr` spanning from the token at `start` to the last one
+    // consumed, leaving out the dedents closing a block
+    fn node(&self, expr: Expression, start: usize) -> Node {
+        let mut end = self.index.saturating_sub(1).max(start);
+        while end > start && self.tokens[end] == Token::Dedent {
+            end -= 1;
+        }
+        Node::new(
+            expr,
+            Span::new(self.span_at(start).start, self.span_at(end).end),
+        )
+    }
+
+    fn binary_node(&self, lhs: Node, op: Operator, rhs: Node, start: usize) -> Node {
+        self.node(Expression::Binary(BinaryExpression { lhs, op, rhs }), start)
+    }
+
+    fn span_at(&self, index: usize) -> Span {
+        self.spans.get(index).copied().unwrap_or_default()
+    }
+
+    // errors at the indentation of a line are reported where the previous
+    // one ends, which is what is missing something
+    fn current_span(&self) -> Span {
+        match self.tokens.get(self.index) {
+            Some(Token::Indent | Token::Dedent) if self.index > 0 => {
+                let end = self.span_at(self.index - 1).end;
+                Span::new(end, end)
+            }
+            _ => self.span_at(self.index),
         }
     }
 
@@ -451,39 +937,40 @@ impl Parser {
 mod tests {
 
     use super::*;
+    use crate::lexer::Lexer;
 
     #[test]
     fn test_primaries() {
         vec![
             (
                 vec![Token::None, Token::Eof],
-                vec![Box::new(Expression::Literal(Literal::None))],
+                vec![Node::from(Expression::Literal(Literal::None))],
             ),
             (
                 vec![Token::True, Token::Eof],
-                vec![Box::new(Expression::Literal(Literal::True))],
+                vec![Node::from(Expression::Literal(Literal::True))],
             ),
             (
                 vec![Token::False, Token::Eof],
-                vec![Box::new(Expression::Literal(Literal::False))],
+                vec![Node::from(Expression::Literal(Literal::False))],
             ),
             (
                 vec![Token::Integer(1), Token::Eof],
-                vec![Box::new(Expression::Literal(Literal::Integer(1)))],
+                vec![Node::from(Expression::Literal(Literal::Integer(1)))],
             ),
             (
                 vec![Token::Float(1.0), Token::Eof],
-                vec![Box::new(Expression::Literal(Literal::Float(1.0)))],
+                vec![Node::from(Expression::Literal(Literal::Float(1.0)))],
             ),
             (
                 vec![Token::String(String::from("test1")), Token::Eof],
-                vec![Box::new(Expression::Literal(Literal::String(
+                vec![Node::from(Expression::Literal(Literal::String(
                     String::from("test1"),
                 )))],
             ),
             (
                 vec![Token::Identifier(String::from("var1")), Token::Eof],
-                vec![Box::new(Expression::Variable(String::from("var1")))],
+                vec![Node::from(Expression::Variable(String::from("var1")))],
             ),
         ]
         .into_iter()
@@ -508,9 +995,9 @@ mod tests {
                     Token::True,
                     Token::Eof,
                 ],
-                vec![Box::new(Expression::Assignment(AssignmentExpression {
-                    lhs: Box::new(Expression::Variable(String::from("var1"))),
-                    rhs: Box::new(Expression::Literal(Literal::True)),
+                vec![Node::from(Expression::Assignment(AssignmentExpression {
+                    lhs: Node::from(Expression::Variable(String::from("var1"))),
+                    rhs: Node::from(Expression::Literal(Literal::True)),
                 }))],
             ),
             // Chained Assignment
@@ -523,14 +1010,38 @@ mod tests {
                     Token::True,
                     Token::Eof,
                 ],
-                vec![Box::new(Expression::Assignment(AssignmentExpression {
-                    lhs: Box::new(Expression::Variable(String::from("var1"))),
-                    rhs: Box::new(Expression::Assignment(AssignmentExpression {
-                        lhs: Box::new(Expression::Variable(String::from("var2"))),
-                        rhs: Box::new(Expression::Literal(Literal::True)),
+                vec![Node::from(Expression::Assignment(AssignmentExpression {
+                    lhs: Node::from(Expression::Variable(String::from("var1"))),
+                    rhs: Node::from(Expression::Assignment(AssignmentExpression {
+                        lhs: Node::from(Expression::Variable(String::from("var2"))),
+                        rhs: Node::from(Expression::Literal(Literal::True)),
                     })),
                 }))],
             ),
+            // Statements separated by semicolons, the last one too
+            (
+                vec![
+                    Token::Identifier(String::from("var1")),
+                    Token::Equal,
+                    Token::True,
+                    Token::Semicolon,
+                    Token::Identifier(String::from("var2")),
+                    Token::Equal,
+                    Token::False,
+                    Token::Semicolon,
+                    Token::Eof,
+                ],
+                vec![
+                    Node::from(Expression::Assignment(AssignmentExpression {
+                        lhs: Node::from(Expression::Variable(String::from("var1"))),
+                        rhs: Node::from(Expression::Literal(Literal::True)),
+                    })),
+                    Node::from(Expression::Assignment(AssignmentExpression {
+                        lhs: Node::from(Expression::Variable(String::from("var2"))),
+                        rhs: Node::from(Expression::Literal(Literal::False)),
+                    })),
+                ],
+            ),
         ]
         .into_iter()
         .for_each(|(tokens, exprs)| {
@@ -548,16 +1059,16 @@ mod tests {
         vec![
             (
                 vec![Token::Bang, Token::True, Token::Eof],
-                vec![Box::new(Expression::Unary(UnaryExpression {
+                vec![Node::from(Expression::Unary(UnaryExpression {
                     op: Operator::Not,
-                    expr: Box::new(Expression::Literal(Literal::True)),
+                    expr: Node::from(Expression::Literal(Literal::True)),
                 }))],
             ),
             (
                 vec![Token::Minus, Token::Integer(1), Token::Eof],
-                vec![Box::new(Expression::Unary(UnaryExpression {
+                vec![Node::from(Expression::Unary(UnaryExpression {
                     op: Operator::Neg,
-                    expr: Box::new(Expression::Literal(Literal::Integer(1))),
+                    expr: Node::from(Expression::Literal(Literal::Integer(1))),
                 }))],
             ),
         ]
@@ -577,18 +1088,18 @@ mod tests {
         vec![
             (
                 vec![Token::True, Token::And, Token::False, Token::Eof],
-                vec![Box::new(Expression::Binary(BinaryExpression {
-                    lhs: Box::new(Expression::Literal(Literal::True)),
+                vec![Node::from(Expression::Binary(BinaryExpression {
+                    lhs: Node::from(Expression::Literal(Literal::True)),
                     op: Operator::And,
-                    rhs: Box::new(Expression::Literal(Literal::False)),
+                    rhs: Node::from(Expression::Literal(Literal::False)),
                 }))],
             ),
             (
                 vec![Token::True, Token::Or, Token::False, Token::Eof],
-                vec![Box::new(Expression::Binary(BinaryExpression {
-                    lhs: Box::new(Expression::Literal(Literal::True)),
+                vec![Node::from(Expression::Binary(BinaryExpression {
+                    lhs: Node::from(Expression::Literal(Literal::True)),
                     op: Operator::Or,
-                    rhs: Box::new(Expression::Literal(Literal::False)),
+                    rhs: Node::from(Expression::Literal(Literal::False)),
                 }))],
             ),
             (
@@ -598,10 +1109,10 @@ mod tests {
                     Token::Integer(2),
                     Token::Eof,
                 ],
-                vec![Box::new(Expression::Binary(BinaryExpression {
-                    lhs: Box::new(Expression::Literal(Literal::Integer(1))),
+                vec![Node::from(Expression::Binary(BinaryExpression {
+                    lhs: Node::from(Expression::Literal(Literal::Integer(1))),
                     op: Operator::Less,
-                    rhs: Box::new(Expression::Literal(Literal::Integer(2))),
+                    rhs: Node::from(Expression::Literal(Literal::Integer(2))),
                 }))],
             ),
         ]
@@ -629,13 +1140,13 @@ mod tests {
                     Token::True,
                     Token::Eof,
                 ],
-                vec![Box::new(Expression::If(IfExpression {
-                    condition: Box::new(Expression::Literal(Literal::True)),
-                    then_branch: Box::new(Expression::Block(BlockExpression {
-                        exprs: vec![Box::new(Expression::Literal(Literal::True))],
+                vec![Node::from(Expression::If(IfExpression {
+                    condition: Node::from(Expression::Literal(Literal::True)),
+                    then_branch: Node::from(Expression::Block(BlockExpression {
+                        exprs: vec![Node::from(Expression::Literal(Literal::True))],
                     })),
                     elif_branches: Vec::new(),
-                    else_branch: Box::new(Expression::Empty),
+                    else_branch: Node::from(Expression::Empty),
                 }))],
             ),
             // If-Else
@@ -655,14 +1166,14 @@ mod tests {
                     Token::Dedent,
                     Token::Eof,
                 ],
-                vec![Box::new(Expression::If(IfExpression {
-                    condition: Box::new(Expression::Literal(Literal::True)),
-                    then_branch: Box::new(Expression::Block(BlockExpression {
-                        exprs: vec![Box::new(Expression::Literal(Literal::True))],
+                vec![Node::from(Expression::If(IfExpression {
+                    condition: Node::from(Expression::Literal(Literal::True)),
+                    then_branch: Node::from(Expression::Block(BlockExpression {
+                        exprs: vec![Node::from(Expression::Literal(Literal::True))],
                     })),
                     elif_branches: Vec::new(),
-                    else_branch: Box::new(Expression::Block(BlockExpression {
-                        exprs: vec![Box::new(Expression::Literal(Literal::False))],
+                    else_branch: Node::from(Expression::Block(BlockExpression {
+                        exprs: vec![Node::from(Expression::Literal(Literal::False))],
                     })),
                 }))],
             ),
@@ -698,27 +1209,27 @@ mod tests {
                     Token::Dedent,
                     Token::Eof,
                 ],
-                vec![Box::new(Expression::If(IfExpression {
-                    condition: Box::new(Expression::Literal(Literal::True)),
-                    then_branch: Box::new(Expression::Block(BlockExpression {
-                        exprs: vec![Box::new(Expression::Literal(Literal::True))],
+                vec![Node::from(Expression::If(IfExpression {
+                    condition: Node::from(Expression::Literal(Literal::True)),
+                    then_branch: Node::from(Expression::Block(BlockExpression {
+                        exprs: vec![Node::from(Expression::Literal(Literal::True))],
                     })),
                     elif_branches: vec![
                         ElifExpression {
-                            condition: Box::new(Expression::Literal(Literal::False)),
-                            then_branch: Box::new(Expression::Block(BlockExpression {
-                                exprs: vec![Box::new(Expression::Literal(Literal::False))],
+                            condition: Node::from(Expression::Literal(Literal::False)),
+                            then_branch: Node::from(Expression::Block(BlockExpression {
+                                exprs: vec![Node::from(Expression::Literal(Literal::False))],
                             })),
                         },
                         ElifExpression {
-                            condition: Box::new(Expression::Literal(Literal::True)),
-                            then_branch: Box::new(Expression::Block(BlockExpression {
-                                exprs: vec![Box::new(Expression::Literal(Literal::True))],
+                            condition: Node::from(Expression::Literal(Literal::True)),
+                            then_branch: Node::from(Expression::Block(BlockExpression {
+                                exprs: vec![Node::from(Expression::Literal(Literal::True))],
                             })),
                         },
                     ],
-                    else_branch: Box::new(Expression::Block(BlockExpression {
-                        exprs: vec![Box::new(Expression::Literal(Literal::False))],
+                    else_branch: Node::from(Expression::Block(BlockExpression {
+                        exprs: vec![Node::from(Expression::Literal(Literal::False))],
                     })),
                 }))],
             ),
@@ -749,14 +1260,15 @@ mod tests {
                     Token::Dedent,
                     Token::Eof,
                 ],
-                vec![Box::new(Expression::While(WhileExpression {
-                    condition: Box::new(Expression::Literal(Literal::True)),
-                    body: Box::new(Expression::Block(BlockExpression {
+                vec![Node::from(Expression::While(WhileExpression {
+                    condition: Node::from(Expression::Literal(Literal::True)),
+                    body: Node::from(Expression::Block(BlockExpression {
                         exprs: vec![
-                            Box::new(Expression::Literal(Literal::True)),
-                            Box::new(Expression::Literal(Literal::True)),
+                            Node::from(Expression::Literal(Literal::True)),
+                            Node::from(Expression::Literal(Literal::True)),
                         ],
                     })),
+                    else_branch: Node::from(Expression::Empty),
                 }))],
             ),
             (
@@ -771,10 +1283,41 @@ mod tests {
                     Token::Dedent,
                     Token::Eof,
                 ],
-                vec![Box::new(Expression::While(WhileExpression {
-                    condition: Box::new(Expression::Literal(Literal::True)),
-                    body: Box::new(Expression::Block(BlockExpression {
-                        exprs: vec![Box::new(Expression::Break), Box::new(Expression::Continue)],
+                vec![Node::from(Expression::While(WhileExpression {
+                    condition: Node::from(Expression::Literal(Literal::True)),
+                    body: Node::from(Expression::Block(BlockExpression {
+                        exprs: vec![
+                            Node::from(Expression::Break),
+                            Node::from(Expression::Continue),
+                        ],
+                    })),
+                    else_branch: Node::from(Expression::Empty),
+                }))],
+            ),
+            (
+                vec![
+                    Token::While,
+                    Token::True,
+                    Token::Colon,
+                    Token::NewLine,
+                    Token::Indent,
+                    Token::Break,
+                    Token::Dedent,
+                    Token::Else,
+                    Token::Colon,
+                    Token::NewLine,
+                    Token::Indent,
+                    Token::True,
+                    Token::Dedent,
+                    Token::Eof,
+                ],
+                vec![Node::from(Expression::While(WhileExpression {
+                    condition: Node::from(Expression::Literal(Literal::True)),
+                    body: Node::from(Expression::Block(BlockExpression {
+                        exprs: vec![Node::from(Expression::Break)],
+                    })),
+                    else_branch: Node::from(Expression::Block(BlockExpression {
+                        exprs: vec![Node::from(Expression::Literal(Literal::True))],
                     })),
                 }))],
             ),
@@ -791,30 +1334,351 @@ mod tests {
     }
 
     #[test]
-    fn test_break_continue_without_loop() {
+    fn test_try_expression() {
+        let tokens = vec![
+            Token::Try,
+            Token::Colon,
+            Token::Indent,
+            Token::Raise,
+            Token::Identifier(String::from("KeyError")),
+            Token::From,
+            Token::None,
+            Token::Dedent,
+            Token::Except,
+            Token::Identifier(String::from("KeyError")),
+            Token::As,
+            Token::Identifier(String::from("err")),
+            Token::Colon,
+            Token::Indent,
+            Token::Raise,
+            Token::Dedent,
+            Token::Except,
+            Token::Colon,
+            Token::Indent,
+            Token::True,
+            Token::Dedent,
+            Token::Else,
+            Token::Colon,
+            Token::Indent,
+            Token::False,
+            Token::Dedent,
+            Token::Eof,
+        ];
+        let block = |expr: Expression| {
+            Node::from(Expression::Block(BlockExpression {
+                exprs: vec![Node::from(expr)],
+            }))
+        };
+        let expected = vec![Node::from(Expression::Try(TryExpression {
+            body: block(Expression::Raise(RaiseExpression {
+                exception: Node::from(Expression::Variable(String::from("KeyError"))),
+                cause: Node::from(Expression::Literal(Literal::None)),
+            })),
+            handlers: vec![
+                ExceptExpression {
+                    exception_type: Node::from(Expression::Variable(String::from("KeyError"))),
+                    name: Some(String::from("err")),
+                    body: block(Expression::Raise(RaiseExpression {
+                        exception: Node::from(Expression::Empty),
+                        cause: Node::from(Expression::Empty),
+                    })),
+                },
+                ExceptExpression {
+                    exception_type: Node::from(Expression::Empty),
+                    name: None,
+                    body: block(Expression::Literal(Literal::True)),
+                },
+            ],
+            else_branch: block(Expression::Literal(Literal::False)),
+        }))];
+        let program = Parser::new(tokens).parse().unwrap();
+        assert_eq!(program.stmts, expected);
+
         vec![
             (
-                vec![Token::Integer(1), Token::NewLine, Token::Continue],
-                "continue",
+                vec![
+                    Token::Try,
+                    Token::Colon,
+                    Token::Indent,
+                    Token::True,
+                    Token::Dedent,
+                    Token::Eof,
+                ],
+                "without except",
             ),
             (
-                vec![Token::Integer(1), Token::NewLine, Token::Break],
-                "break",
+                vec![
+                    Token::Try,
+                    Token::Colon,
+                    Token::Indent,
+                    Token::True,
+                    Token::Dedent,
+                    Token::Except,
+                    Token::Colon,
+                    Token::Indent,
+                    Token::True,
+                    Token::Dedent,
+                    Token::Except,
+                    Token::Identifier(String::from("KeyError")),
+                    Token::Colon,
+                    Token::Indent,
+                    Token::True,
+                    Token::Dedent,
+                    Token::Eof,
+                ],
+                "must be last",
             ),
         ]
         .into_iter()
-        .for_each(|(tokens, error_substr)| {
+        .for_each(|(tokens, error_substr)| match Parser::new(tokens).parse() {
+            Err(ParserError::InvalidExpression(message, _)) => {
+                assert!(message.contains(error_substr), "{}", message)
+            }
+            other => panic!("Expected InvalidExpression, got {:?}", other),
+        });
+    }
+
+    #[test]
+    fn test_assert_expression() {
+        let tokens = vec![
+            Token::Assert,
+            Token::Identifier(String::from("x")),
+            Token::Comma,
+            Token::String(String::from("message")),
+            Token::Assert,
+            Token::False,
+            Token::Eof,
+        ];
+        let expected = vec![
+            Node::from(Expression::Assert(AssertExpression {
+                test: Node::from(Expression::Variable(String::from("x"))),
+                message: Node::from(Expression::Literal(Literal::String(String::from(
+                    "message",
+                )))),
+            })),
+            Node::from(Expression::Assert(AssertExpression {
+                test: Node::from(Expression::Literal(Literal::False)),
+                message: Node::from(Expression::Empty),
+            })),
+        ];
+        let program = Parser::new(tokens).parse().unwrap();
+        assert_eq!(program.stmts, expected);
+
+        let tokens = vec![Token::Assert, Token::Eof];
+        assert!(Parser::new(tokens).parse().is_err());
+    }
+
+    #[test]
+    fn test_duplicate_arguments() {
+        let ident = |name: &str| Token::Identifier(String::from(name));
+        vec![
+            vec![
+                Token::Def,
+                ident("f"),
+                Token::LeftParen,
+                ident("a"),
+                Token::Comma,
+                ident("a"),
+                Token::RightParen,
+                Token::Colon,
+                Token::Ellipsis,
+                Token::Eof,
+            ],
+            vec![
+                ident("f"),
+                Token::LeftParen,
+                ident("x"),
+                Token::Equal,
+                Token::Integer(1),
+                Token::Comma,
+                ident("x"),
+                Token::Equal,
+                Token::Integer(2),
+                Token::RightParen,
+                Token::Eof,
+            ],
+        ]
+        .into_iter()
+        .zip([
+            ParserError::DuplicateArgument(
+                String::from("duplicate argument 'a' in function definition"),
+                Span::default(),
+            ),
+            ParserError::DuplicateKeyword(
+                String::from("keyword argument repeated: x"),
+                Span::default(),
+            ),
+        ])
+        .for_each(|(tokens, expected)| {
+            assert_eq!(Parser::new(tokens).parse().unwrap_err(), expected);
+        });
+    }
+
+    #[test]
+    fn test_import_and_attribute_expressions() {
+        vec![
+            (
+                vec![
+                    Token::Import,
+                    Token::Identifier(String::from("math")),
+                    Token::Eof,
+                ],
+                vec![Node::from(Expression::Import(ImportExpression {
+                    name: String::from("math"),
+                }))],
+            ),
+            (
+                vec![
+                    Token::Identifier(String::from("math")),
+                    Token::Dot,
+                    Token::Identifier(String::from("abs")),
+                    Token::LeftParen,
+                    Token::Integer(1),
+                    Token::RightParen,
+                    Token::Eof,
+                ],
+                vec![Node::from(Expression::Call(CallExpression {
+                    callable: Node::from(Expression::Attribute(AttributeExpression {
+                        object: Node::from(Expression::Variable(String::from("math"))),
+                        name: String::from("abs"),
+                    })),
+                    args: vec![Node::from(Expression::Literal(Literal::Integer(1)))],
+                    kwargs: vec![],
+                }))],
+            ),
+        ]
+        .into_iter()
+        .for_each(|(tokens, exprs)| {
             let mut parser = Parser::new(tokens);
-            let result = parser.parse();
-            assert!(result.is_err());
-            let err = result.unwrap_err();
-            match err {
-                ParserError::InvalidExpression(error_message) => {
-                    assert!(error_message.contains(error_substr));
-                }
-                _ => assert!(false),
+            let program = match parser.parse() {
+                Ok(program) => program,
+                Err(err) => panic!("ParseError: {:?}", err),
             };
-        })
+            assert_eq!(program.stmts, exprs);
+        });
+    }
+
+    #[test]
+    fn test_type_annotations() {
+        let int_type = TypeAnnotation {
+            name: String::from("int"),
+            args: vec![],
+        };
+        vec![
+            (
+                vec![
+                    Token::Identifier(String::from("x")),
+                    Token::Colon,
+                    Token::Identifier(String::from("int")),
+                    Token::Equal,
+                    Token::Integer(3),
+                    Token::Eof,
+                ],
+                vec![Node::from(Expression::Annotation(AnnotationExpression {
+                    name: String::from("x"),
+                    annotation: int_type.clone(),
+                    value: Some(Node::from(Expression::Literal(Literal::Integer(3)))),
+                }))],
+            ),
+            (
+                vec![
+                    Token::Identifier(String::from("x")),
+                    Token::Colon,
+                    Token::Identifier(String::from("dict")),
+                    Token::LeftBracket,
+                    Token::Identifier(String::from("str")),
+                    Token::Comma,
+                    Token::Identifier(String::from("int")),
+                    Token::RightBracket,
+                    Token::Eof,
+                ],
+                vec![Node::from(Expression::Annotation(AnnotationExpression {
+                    name: String::from("x"),
+                    annotation: TypeAnnotation {
+                        name: String::from("dict"),
+                        args: vec![
+                            TypeAnnotation {
+                                name: String::from("str"),
+                                args: vec![],
+                            },
+                            int_type.clone(),
+                        ],
+                    },
+                    value: None,
+                }))],
+            ),
+            (
+                vec![
+                    Token::Def,
+                    Token::Identifier(String::from("test")),
+                    Token::LeftParen,
+                    Token::Identifier(String::from("arg1")),
+                    Token::Colon,
+                    Token::Identifier(String::from("int")),
+                    Token::Comma,
+                    Token::Identifier(String::from("arg2")),
+                    Token::RightParen,
+                    Token::Arrow,
+                    Token::None,
+                    Token::Colon,
+                    Token::NewLine,
+                    Token::Indent,
+                    Token::Return,
+                    Token::Dedent,
+                    Token::Eof,
+                ],
+                vec![Node::from(Expression::Function(FunctionExpression {
+                    name: String::from("test"),
+                    args: vec![String::from("arg1"), String::from("arg2")],
+                    arg_types: vec![Some(int_type.clone()), None],
+                    return_type: Some(TypeAnnotation {
+                        name: String::from("None"),
+                        args: vec![],
+                    }),
+                    body: BlockExpression {
+                        exprs: vec![Node::from(Expression::Return(ReturnExpression {
+                            expr: Node::from(Expression::Empty),
+                        }))],
+                    },
+                }))],
+            ),
+        ]
+        .into_iter()
+        .for_each(|(tokens, exprs)| {
+            let mut parser = Parser::new(tokens);
+            let program = match parser.parse() {
+                Ok(program) => program,
+                Err(err) => panic!("ParseError: {:?}", err),
+            };
+            assert_eq!(program.stmts, exprs);
+        });
+    }
+
+    #[test]
+    fn test_bare_name_conditions() {
+        let flag = Expression::Variable(String::from("flag"));
+        vec![
+            "if flag:\n  x\n",
+            "if x:\n  x\nelif flag:\n  x\n",
+            "while flag:\n  x\n",
+        ]
+        .into_iter()
+        .for_each(|source| {
+            let (tokens, spans) = Lexer::new(source).tokenize();
+            let program = match Parser::new_with_spans(tokens, spans).parse() {
+                Ok(program) => program,
+                Err(err) => panic!("{}: ParseError: {:?}", source, err),
+            };
+            let condition = match &*program.stmts[0] {
+                Expression::If(if_expression) => match if_expression.elif_branches.first() {
+                    Some(elif) => &elif.condition,
+                    None => &if_expression.condition,
+                },
+                Expression::While(while_expression) => &while_expression.condition,
+                other => panic!("{}: unexpected {:?}", source, other),
+            };
+            assert_eq!(**condition, flag, "{}", source);
+        });
     }
 
     #[test]
@@ -834,12 +1698,14 @@ mod tests {
                     Token::Dedent,
                     Token::Eof,
                 ],
-                vec![Box::new(Expression::Function(FunctionExpression {
+                vec![Node::from(Expression::Function(FunctionExpression {
                     name: String::from("test"),
                     args: vec![],
+                    arg_types: vec![],
+                    return_type: None,
                     body: BlockExpression {
-                        exprs: vec![Box::new(Expression::Return(ReturnExpression {
-                            expr: Box::new(Expression::Literal(Literal::True)),
+                        exprs: vec![Node::from(Expression::Return(ReturnExpression {
+                            expr: Node::from(Expression::Literal(Literal::True)),
                         }))],
                     },
                 }))],
@@ -861,12 +1727,14 @@ mod tests {
                     Token::Dedent,
                     Token::Eof,
                 ],
-                vec![Box::new(Expression::Function(FunctionExpression {
+                vec![Node::from(Expression::Function(FunctionExpression {
                     name: String::from("test"),
                     args: vec![String::from("arg1"), String::from("arg2")],
+                    arg_types: vec![None, None],
+                    return_type: None,
                     body: BlockExpression {
-                        exprs: vec![Box::new(Expression::Return(ReturnExpression {
-                            expr: Box::new(Expression::Literal(Literal::True)),
+                        exprs: vec![Node::from(Expression::Return(ReturnExpression {
+                            expr: Node::from(Expression::Literal(Literal::True)),
                         }))],
                     },
                 }))],
@@ -887,12 +1755,14 @@ mod tests {
                     Token::Dedent,
                     Token::Eof,
                 ],
-                vec![Box::new(Expression::Function(FunctionExpression {
+                vec![Node::from(Expression::Function(FunctionExpression {
                     name: String::from("test"),
                     args: vec![String::from("arg1"), String::from("arg2")],
+                    arg_types: vec![None, None],
+                    return_type: None,
                     body: BlockExpression {
-                        exprs: vec![Box::new(Expression::Return(ReturnExpression {
-                            expr: Box::new(Expression::Empty),
+                        exprs: vec![Node::from(Expression::Return(ReturnExpression {
+                            expr: Node::from(Expression::Empty),
                         }))],
                     },
                 }))],
@@ -924,28 +1794,52 @@ mod tests {
                     Token::Eof,
                 ],
                 vec![
-                    Box::new(Expression::Function(FunctionExpression {
+                    Node::from(Expression::Function(FunctionExpression {
                         name: String::from("test"),
                         args: vec![String::from("arg1"), String::from("arg2")],
+                        arg_types: vec![None, None],
+                        return_type: None,
                         body: BlockExpression {
-                            exprs: vec![Box::new(Expression::Return(ReturnExpression {
-                                expr: Box::new(Expression::Binary(BinaryExpression {
-                                    lhs: Box::new(Expression::Variable(String::from("arg1"))),
+                            exprs: vec![Node::from(Expression::Return(ReturnExpression {
+                                expr: Node::from(Expression::Binary(BinaryExpression {
+                                    lhs: Node::from(Expression::Variable(String::from("arg1"))),
                                     op: Operator::Add,
-                                    rhs: Box::new(Expression::Variable(String::from("arg2"))),
+                                    rhs: Node::from(Expression::Variable(String::from("arg2"))),
                                 })),
                             }))],
                         },
                     })),
-                    Box::new(Expression::Call(CallExpression {
-                        callable: Box::new(Expression::Variable(String::from("test"))),
+                    Node::from(Expression::Call(CallExpression {
+                        callable: Node::from(Expression::Variable(String::from("test"))),
                         args: vec![
-                            Box::new(Expression::Literal(Literal::Integer(1))),
-                            Box::new(Expression::Literal(Literal::Integer(2))),
+                            Node::from(Expression::Literal(Literal::Integer(1))),
+                            Node::from(Expression::Literal(Literal::Integer(2))),
                         ],
+                        kwargs: vec![],
                     })),
                 ],
             ),
+            (
+                vec![
+                    Token::Identifier(String::from("print")),
+                    Token::LeftParen,
+                    Token::Integer(1),
+                    Token::Comma,
+                    Token::Identifier(String::from("end")),
+                    Token::Equal,
+                    Token::String(String::from("")),
+                    Token::RightParen,
+                    Token::Eof,
+                ],
+                vec![Node::from(Expression::Call(CallExpression {
+                    callable: Node::from(Expression::Variable(String::from("print"))),
+                    args: vec![Node::from(Expression::Literal(Literal::Integer(1)))],
+                    kwargs: vec![(
+                        String::from("end"),
+                        Node::from(Expression::Literal(Literal::String(String::from("")))),
+                    )],
+                }))],
+            ),
         ]
         .into_iter()
         .for_each(|(tokens, exprs)| {
@@ -956,5 +1850,55 @@ mod tests {
             };
             assert_eq!(program.stmts, exprs);
         });
+
+        // keyword arguments must come last
+        let mut parser = Parser::new(vec![
+            Token::Identifier(String::from("print")),
+            Token::LeftParen,
+            Token::Identifier(String::from("end")),
+            Token::Equal,
+            Token::String(String::from("")),
+            Token::Comma,
+            Token::Integer(1),
+            Token::RightParen,
+            Token::Eof,
+        ]);
+        assert!(matches!(
+            parser.parse(),
+            Err(ParserError::InvalidExpression(..))
+        ));
+    }
+
+    #[test]
+    fn test_spans() {
+        let parse = |source: &str| {
+            let (tokens, spans) = Lexer::new(source).tokenize();
+            Parser::new_with_spans(tokens, spans).parse()
+        };
+        let program = parse("total = f(a, b) + 1\nif x > 0:\n  y\n").unwrap();
+        assert_eq!(program.stmts[0].span, Span::new(0, 19));
+        assert_eq!(program.stmts[1].span, Span::new(20, 33));
+        let call = match &*program.stmts[0] {
+            Expression::Assignment(assignment) => match &*assignment.rhs {
+                Expression::Binary(binary) => &binary.lhs,
+                other => panic!("Expected binary expression, got {:?}", other),
+            },
+            other => panic!("Expected assignment, got {:?}", other),
+        };
+        assert_eq!(call.span, Span::new(8, 15));
+
+        // errors point at the token where parsing failed
+        vec![
+            ("x = (1 2", Span::new(7, 8)),
+            ("def f(a, a):\n  a", Span::new(9, 10)),
+        ]
+        .into_iter()
+        .for_each(|(source, expected)| match parse(source) {
+            Err(ParserError::InvalidExpression(_, span))
+            | Err(ParserError::DuplicateArgument(_, span)) => {
+                assert_eq!(span, expected, "{}", source)
+            }
+            other => panic!("{}: expected an error, got {:?}", source, other),
+        });
     }
 }
@@ -0,0 +1,43 @@
+use crate::object::Value;
+
+/// Sensitive operation a script is about to perform.
+#[derive(Debug)]
+pub enum Action<'a> {
+    /// Call to a native function with its positional and keyword arguments
+    CallNative {
+        name: &'a str,
+        args: &'a [Value],
+        kwargs: &'a [(String, Value)],
+    },
+    /// `import` of a module, including modules imported before
+    Import { module: &'a str },
+}
+
+/// An action and the instruction performing it.
+#[derive(Debug)]
+pub struct PolicyRequest<'a> {
+    pub action: Action<'a>,
+    // name of the function executing the instruction
+    pub function: &'a str,
+    // offset of the instruction in that function's chunk
+    pub ip: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Decision {
+    Allow,
+    // the reason ends up in the PermissionError raised in the script
+    Deny(String),
+}
+
+/// Embedder callback consulted before every sensitive action.
+pub type Policy = dyn FnMut(&PolicyRequest) -> Decision;
+
+impl Action<'_> {
+    pub fn describe(&self) -> String {
+        match self {
+            Action::CallNative { name, .. } => format!("call to {}()", name),
+            Action::Import { module } => format!("import of module '{}'", module),
+        }
+    }
+}
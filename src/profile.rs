@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use crate::bytecode::Bytecode;
+
+/// A call instruction: the function it is in, its offset in that function's
+/// chunk and the name of what it called.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CallSite {
+    pub caller: String,
+    pub ip: usize,
+    pub callee: String,
+}
+
+/// Execution counts per opcode and per call site, collected while
+/// `Config::profile_opcodes` is set.
+#[derive(Clone, Debug)]
+pub struct Profile {
+    opcodes: [u64; 256],
+    call_sites: HashMap<CallSite, u64>,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile {
+            opcodes: [0; 256],
+            call_sites: HashMap::new(),
+        }
+    }
+}
+
+impl Profile {
+    pub fn new() -> Profile {
+        Profile::default()
+    }
+
+    pub(crate) fn count_opcode(&mut self, op: &Bytecode) {
+        self.opcodes[u8::from(op.clone()) as usize] += 1;
+    }
+
+    pub(crate) fn count_call(&mut self, call_site: CallSite) {
+        *self.call_sites.entry(call_site).or_insert(0) += 1;
+    }
+
+    /// Executed opcodes by name, most frequent first.
+    pub fn opcode_counts(&self) -> Vec<(String, u64)> {
+        let mut counts: Vec<(String, u64)> = self
+            .opcodes
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .filter_map(|(opcode, count)| {
+                Bytecode::try_from(opcode as u8)
+                    .ok()
+                    .map(|op| (format!("{:?}", op), *count))
+            })
+            .collect();
+        counts.sort_by(|lhs, rhs| rhs.1.cmp(&lhs.1).then_with(|| lhs.0.cmp(&rhs.0)));
+        counts
+    }
+
+    /// Call sites with the number of calls made from them, most frequent
+    /// first.
+    pub fn call_site_counts(&self) -> Vec<(CallSite, u64)> {
+        let mut counts: Vec<(CallSite, u64)> = self
+            .call_sites
+            .iter()
+            .map(|(call_site, count)| (call_site.clone(), *count))
+            .collect();
+        counts.sort_by(|lhs, rhs| {
+            rhs.1
+                .cmp(&lhs.1)
+                .then_with(|| lhs.0.caller.cmp(&rhs.0.caller))
+                .then_with(|| lhs.0.ip.cmp(&rhs.0.ip))
+        });
+        counts
+    }
+
+    pub fn total_opcodes(&self) -> u64 {
+        self.opcodes.iter().sum()
+    }
+}
+
+// width of the bar drawn for the most frequent entry
+const HISTOGRAM_WIDTH: u64 = 40;
+
+fn bar(count: u64, max: u64) -> String {
+    let width = if max == 0 {
+        0
+    } else {
+        (count * HISTOGRAM_WIDTH).div_ceil(max)
+    };
+    "#".repeat(width as usize)
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let opcodes = self.opcode_counts();
+        let max = opcodes.first().map_or(0, |(_, count)| *count);
+        writeln!(f, "Opcodes ({} executed):", self.total_opcodes())?;
+        for (name, count) in &opcodes {
+            writeln!(f, "  {:<18} {:>10} {}", name, count, bar(*count, max))?;
+        }
+
+        let call_sites = self.call_site_counts();
+        let max = call_sites.first().map_or(0, |(_, count)| *count);
+        writeln!(f, "Call sites:")?;
+        for (call_site, count) in &call_sites {
+            let location = format!(
+                "{}+{:08X} -> {}",
+                call_site.caller, call_site.ip, call_site.callee
+            );
+            writeln!(f, "  {:<32} {:>10} {}", location, count, bar(*count, max))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_counts() {
+        let mut profile = Profile::new();
+        [Bytecode::Add, Bytecode::GetLocal, Bytecode::GetLocal]
+            .iter()
+            .for_each(|op| profile.count_opcode(op));
+        let call_site = CallSite {
+            caller: String::from("<main>"),
+            ip: 9,
+            callee: String::from("f"),
+        };
+        profile.count_call(call_site.clone());
+        profile.count_call(call_site.clone());
+
+        assert_eq!(
+            profile.opcode_counts(),
+            vec![(String::from("GetLocal"), 2), (String::from("Add"), 1)]
+        );
+        assert_eq!(profile.total_opcodes(), 3);
+        assert_eq!(profile.call_site_counts(), vec![(call_site, 2)]);
+        assert!(profile.to_string().contains("<main>+00000009 -> f"));
+    }
+}
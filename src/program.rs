@@ -0,0 +1,527 @@
+use std::collections::{BTreeSet, HashMap};
+use std::rc::Rc;
+
+use crate::bytecode::{Bytecode, SIZE_JUMP, SIZE_NARROW_INDEX};
+use crate::chunk::{Chunk, Decoded};
+use crate::function::Function;
+use crate::object::{Object, Value};
+use crate::register;
+use crate::rpyc::{self, Payload};
+use crate::symbol_table::SymbolTable;
+
+/// A compiled script together with the globals its bytecode refers to, the
+/// unit that gets serialized for ahead-of-time builds.
+pub struct CompiledProgram {
+    pub(crate) main: Function,
+    pub(crate) globals: SymbolTable,
+    // (index, name) of the builtins the bytecode uses, so a loader can
+    // check its builtins are numbered the same
+    pub(crate) builtins: Vec<(u64, String)>,
+    // `Config::opt_level` the program was compiled at
+    pub(crate) opt_level: u8,
+}
+
+impl CompiledProgram {
+    pub(crate) fn new(main: Function, globals: SymbolTable, opt_level: u8) -> CompiledProgram {
+        CompiledProgram {
+            main,
+            globals,
+            builtins: Vec::new(),
+            opt_level,
+        }
+    }
+
+    /// The program in the `.rpyc` format, failing when a global holds a
+    /// value that can't be serialized, like a module.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        rpyc::write_program(&Payload {
+            function: self.main.clone(),
+            globals: self
+                .globals
+                .iter()
+                .map(|object| {
+                    let value = self
+                        .globals
+                        .is_bound(object.id)
+                        .then(|| object.value.clone());
+                    (object.id, object.name.to_string(), value)
+                })
+                .collect(),
+            builtins: self.builtins.clone(),
+            opt_level: self.opt_level,
+        })
+    }
+
+    /// Reads back a program written by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<CompiledProgram, String> {
+        let payload = rpyc::read_program(bytes)?;
+        let count = payload.globals.len() as u64;
+        let mut globals = SymbolTable::new();
+        for (index, name, value) in payload.globals {
+            // pruned programs number their globals densely from 1
+            if index == 0 || index > count {
+                return Err(format!("global index {} out of range", index));
+            }
+            match value {
+                Some(value) => globals.set(index, Object::new_with_id(index, name, value)),
+                None => globals.declare(index, &name),
+            }
+        }
+        Ok(CompiledProgram {
+            main: payload.function,
+            globals,
+            builtins: payload.builtins,
+            opt_level: payload.opt_level,
+        })
+    }
+
+    /// Records the names of the builtins the bytecode uses.
+    pub(crate) fn link_builtins(&mut self, builtins: &SymbolTable) {
+        let used: BTreeSet<u64> = self
+            .functions()
+            .flat_map(|function| indexes(&function.chunk, Target::Builtin))
+            .collect();
+        self.builtins = used
+            .into_iter()
+            .filter_map(|index| Some((index, builtins.get(index)?.name.to_string())))
+            .collect();
+    }
+
+    /// Renumbers the globals and builtins the bytecode uses to their slots
+    /// in `globals` and `builtins`, matched by name, and returns the script
+    /// ready to run, lowered for the register VM with `register_backend`.
+    /// Names `globals` doesn't have yet are added with the program's
+    /// values, the others keep theirs until the code assigns them. Fails
+    /// when a builtin is missing or a slot doesn't fit the operand of the
+    /// one it replaces.
+    pub(crate) fn link(
+        mut self,
+        globals: &mut SymbolTable,
+        builtins: &SymbolTable,
+        register_backend: bool,
+    ) -> Result<Function, String> {
+        let mut builtin_remap = HashMap::new();
+        for (index, name) in &self.builtins {
+            if !builtins.contains_name(name) {
+                return Err(format!("builtin '{}' is not available", name));
+            }
+            builtin_remap.insert(*index, builtins.get_index(name));
+        }
+        let mut remap = HashMap::new();
+        let mut added = Vec::new();
+        for object in self.globals.iter() {
+            let index = match globals.contains_name(&object.name) {
+                true => globals.get_index(&object.name),
+                false => {
+                    let index = globals.insert(&object.name, None);
+                    added.push((index, object.id));
+                    index
+                }
+            };
+            remap.insert(object.id, index);
+        }
+
+        let linker = Linker {
+            globals: &remap,
+            builtins: &builtin_remap,
+            register_backend,
+        };
+        linker.link(&mut self.main)?;
+        for (index, id) in added {
+            // declared names stay unbound until the code assigns them
+            if !self.globals.is_bound(id) {
+                continue;
+            }
+            let Some(object) = self.globals.get_mut(id) else {
+                continue;
+            };
+            if let Value::Function(function) = &mut object.value {
+                linker.link(Rc::make_mut(function))?;
+            }
+            let object = Object::new_with_id(index, object.name.to_string(), object.value.clone());
+            globals.set(index, object);
+        }
+        Ok(self.main)
+    }
+
+    /// Drops the globals no reachable code refers to, the definitions of
+    /// those that are functions and the constants no instruction uses, then
+    /// renumbers the rest densely and patches the bytecode operands. Dropped
+    /// definitions become `Nop`s and only operands change, so jump offsets
+    /// stay valid.
+    pub(crate) fn prune(&mut self) {
+        let reachable = self.reachable_globals();
+
+        let mut remap: HashMap<u64, u64> = HashMap::new();
+        let mut globals = SymbolTable::new();
+        for index in &reachable {
+            if let Some(object) = self.globals.get(*index) {
+                let name = object.name.to_string();
+                let value = self.globals.is_bound(*index).then(|| object.value.clone());
+                remap.insert(*index, globals.insert(&name, value));
+            }
+        }
+
+        prune_function(&mut self.main, &reachable, &remap);
+        for index in remap.values() {
+            if let Some(Value::Function(function)) =
+                globals.get_mut(*index).map(|object| &mut object.value)
+            {
+                prune_function(Rc::make_mut(function), &reachable, &remap);
+            }
+        }
+        self.globals = globals;
+    }
+
+    /// Optimization level the program was compiled at.
+    pub fn opt_level(&self) -> u8 {
+        self.opt_level
+    }
+
+    /// Global names in index order.
+    pub fn global_names(&self) -> Vec<String> {
+        let mut objects: Vec<_> = self.globals.iter().collect();
+        objects.sort_by_key(|object| object.id);
+        objects
+            .into_iter()
+            .map(|object| object.name.to_string())
+            .collect()
+    }
+
+    /// Constants of the script and of every function it holds.
+    pub fn constant_count(&self) -> usize {
+        self.functions()
+            .map(|function| function.chunk.constants.len())
+            .sum()
+    }
+
+    pub(crate) fn functions(&self) -> impl Iterator<Item = &Function> {
+        std::iter::once(&self.main)
+            .chain(
+                self.globals
+                    .iter()
+                    .filter_map(|object| match &object.value {
+                        Value::Function(function) => Some(function.as_ref()),
+                        _ => None,
+                    }),
+            )
+            .flat_map(|function| std::iter::once(function).chain(function.nested_functions()))
+    }
+
+    // globals used by the script, following the functions it can call.
+    // Definitions are all in the script itself, so they are known before
+    // the functions they bind are followed.
+    fn reachable_globals(&self) -> BTreeSet<u64> {
+        let mut reachable = BTreeSet::new();
+        let mut pending = Vec::new();
+        let mut deferred = HashMap::new();
+        references(&self.main, &mut pending, &mut deferred);
+        while let Some(index) = pending.pop() {
+            if !reachable.insert(index) {
+                continue;
+            }
+            if let Some(object) = self.globals.get(index) {
+                if let Value::Function(function) = &object.value {
+                    references(function, &mut pending, &mut deferred);
+                }
+            }
+            for function in deferred.remove(&index).unwrap_or_default() {
+                references(function, &mut pending, &mut deferred);
+            }
+        }
+        reachable
+    }
+}
+
+/// `MakeFunction` + `SetGlobal`, a function definition binding a global.
+pub(crate) struct Definition {
+    // addresses of the two instructions
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    pub(crate) global: u64,
+    pub(crate) constant: u64,
+}
+
+/// The function definitions of `chunk` that bind globals.
+pub(crate) fn definitions(chunk: &Chunk) -> Vec<Definition> {
+    let mut result = Vec::new();
+    let mut ip = 0;
+    while let Some(instruction) = chunk.decode(ip) {
+        let next_addr = instruction.next_addr();
+        let store = chunk
+            .decode(next_addr)
+            .filter(|store| matches!(store.op, Bytecode::SetGlobal));
+        if let (Bytecode::MakeFunction, Some(store)) = (&instruction.op, store) {
+            let operand = |instruction: &Decoded| {
+                chunk.read_operand(instruction.operand_addr, instruction.operand_size)
+            };
+            if let (Some(constant), Some(global)) = (operand(&instruction), operand(&store)) {
+                result.push(Definition {
+                    start: ip,
+                    end: store.next_addr(),
+                    global,
+                    constant,
+                });
+            }
+        }
+        ip = next_addr;
+    }
+    result
+}
+
+// pushes the globals `function` refers to onto `pending`, except for the
+// ones its definitions bind: their functions are `deferred` until the
+// global turns out to be reachable
+fn references<'a>(
+    function: &'a Function,
+    pending: &mut Vec<u64>,
+    deferred: &mut HashMap<u64, Vec<&'a Function>>,
+) {
+    let chunk = &function.chunk;
+    let definitions = definitions(chunk);
+    let in_definition = |addr: usize| {
+        definitions
+            .iter()
+            .any(|definition| (definition.start..definition.end).contains(&addr))
+    };
+    pending.extend(
+        operands(chunk)
+            .iter()
+            .filter(|operand| operand.target == Target::Global && !in_definition(operand.addr))
+            .filter_map(|operand| chunk.read_operand(operand.addr, operand.size)),
+    );
+    for (index, constant) in chunk.constants.iter().enumerate() {
+        let Value::Function(nested) = constant else {
+            continue;
+        };
+        match definitions
+            .iter()
+            .find(|definition| definition.constant == index as u64)
+        {
+            Some(definition) => deferred.entry(definition.global).or_default().push(nested),
+            // bound to a local, so used as far as the enclosing function is
+            None => references(nested, pending, deferred),
+        }
+    }
+}
+
+// drops the definitions of unreachable globals, then relinks `function`
+// and the functions it defines
+fn prune_function(function: &mut Function, reachable: &BTreeSet<u64>, remap: &HashMap<u64, u64>) {
+    let chunk = &mut function.chunk;
+    for definition in definitions(chunk) {
+        if !reachable.contains(&definition.global) {
+            chunk.data[definition.start..definition.end].fill(Bytecode::Nop as u8);
+        }
+    }
+    relink_chunk(chunk, remap);
+    if function.registers.is_some() {
+        function.registers = register::lower(&function.chunk, function.arity);
+    }
+    for constant in function.chunk.constants.iter_mut() {
+        if let Value::Function(nested) = constant {
+            prune_function(Rc::make_mut(nested), reachable, remap);
+        }
+    }
+}
+
+// renumbers the operands of a loaded program, see `CompiledProgram::link`
+struct Linker<'a> {
+    globals: &'a HashMap<u64, u64>,
+    builtins: &'a HashMap<u64, u64>,
+    register_backend: bool,
+}
+
+impl Linker<'_> {
+    fn link(&self, function: &mut Function) -> Result<(), String> {
+        let chunk = &mut function.chunk;
+        for operand in operands(chunk) {
+            let table = match operand.target {
+                Target::Global => self.globals,
+                Target::Builtin => self.builtins,
+                Target::Constant | Target::Other => continue,
+            };
+            let Some(index) = chunk
+                .read_operand(operand.addr, operand.size)
+                .and_then(|index| table.get(&index))
+            else {
+                continue;
+            };
+            if !chunk.patch_index(operand.addr, operand.size, *index) {
+                return Err(format!(
+                    "{}: index {} doesn't fit the operand at byte {}",
+                    function.name, index, operand.addr
+                ));
+            }
+        }
+        if self.register_backend {
+            function.registers = register::lower(&function.chunk, function.arity);
+        }
+        for constant in function.chunk.constants.iter_mut() {
+            if let Value::Function(nested) = constant {
+                self.link(Rc::make_mut(nested))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// what an index operand refers to
+#[derive(PartialEq)]
+pub(crate) enum Target {
+    Global,
+    Builtin,
+    Constant,
+    Other,
+}
+
+// an index operand, superinstructions holding several
+pub(crate) struct Operand {
+    pub(crate) target: Target,
+    pub(crate) addr: usize,
+    pub(crate) size: usize,
+}
+
+fn target(op: &Bytecode) -> Target {
+    match op {
+        Bytecode::GetGlobal | Bytecode::SetGlobal | Bytecode::InplaceAddGlobal => Target::Global,
+        Bytecode::GetBuiltin => Target::Builtin,
+        Bytecode::Const | Bytecode::GetAttr | Bytecode::MakeFunction | Bytecode::Import => {
+            Target::Constant
+        }
+        _ => Target::Other,
+    }
+}
+
+// every index operand of the chunk
+pub(crate) fn operands(chunk: &Chunk) -> Vec<Operand> {
+    let mut result = Vec::new();
+    let mut ip = 0;
+    while let Some(instruction) = chunk.decode(ip) {
+        ip = instruction.next_addr();
+        let addr = instruction.operand_addr;
+        let byte = |target, offset| Operand {
+            target,
+            addr: addr + offset,
+            size: SIZE_NARROW_INDEX,
+        };
+        match instruction.op {
+            Bytecode::IncrementGlobal => {
+                result.push(byte(Target::Global, 0));
+                result.push(byte(Target::Constant, SIZE_NARROW_INDEX));
+            }
+            Bytecode::IncrementLocal => result.push(byte(Target::Constant, SIZE_NARROW_INDEX)),
+            Bytecode::ConstLessJumpIfFalse => result.push(byte(Target::Constant, SIZE_JUMP)),
+            ref op if op.has_index() => result.push(Operand {
+                target: target(op),
+                addr,
+                size: instruction.operand_size,
+            }),
+            _ => {}
+        }
+    }
+    result
+}
+
+// indexes the chunk's operands hold into `target`
+fn indexes(chunk: &Chunk, target: Target) -> Vec<u64> {
+    operands(chunk)
+        .iter()
+        .filter(|operand| operand.target == target)
+        .filter_map(|operand| chunk.read_operand(operand.addr, operand.size))
+        .collect()
+}
+
+// renumbers global operands through `remap` and drops unused constants.
+// Renumbering densely in order never grows an index, so the new ones fit
+// the operands of the old.
+fn relink_chunk(chunk: &mut Chunk, remap: &HashMap<u64, u64>) {
+    let used_constants: BTreeSet<u64> = indexes(chunk, Target::Constant).into_iter().collect();
+    let constant_remap: HashMap<u64, u64> = used_constants
+        .iter()
+        .enumerate()
+        .map(|(new_index, old_index)| (*old_index, new_index as u64))
+        .collect();
+    chunk.constants = used_constants
+        .iter()
+        .map(|index| chunk.constants[*index as usize].clone())
+        .collect();
+
+    for operand in operands(chunk) {
+        let table = match operand.target {
+            Target::Global => remap,
+            Target::Constant => &constant_remap,
+            Target::Builtin | Target::Other => continue,
+        };
+        if let Some(index) = chunk
+            .read_operand(operand.addr, operand.size)
+            .and_then(|index| table.get(&index))
+        {
+            let patched = chunk.patch_index(operand.addr, operand.size, *index);
+            debug_assert!(patched, "renumbered index {} grew", index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::SIZE_INSTRUCTION;
+    use crate::config::DEFAULT_OPT_LEVEL;
+
+    #[test]
+    fn test_prune() {
+        let mut globals = SymbolTable::new();
+        globals.insert("unused", Some(Value::Integer(1)));
+        let mut helper = Function::new(String::from("helper"));
+        let answer = helper.chunk.add_constant(Value::Integer(42));
+        helper.chunk.emit_with_index(Bytecode::Const, answer);
+        helper.chunk.emit(Bytecode::Return);
+        let helper = globals.insert("helper", Some(Value::Function(Rc::new(helper))));
+
+        let mut main = Function::new_global_scope();
+        main.chunk
+            .add_constant(Value::String(String::from("dead").into()));
+        main.chunk.emit_with_index(Bytecode::GetGlobal, helper);
+        main.chunk.emit_with_index(Bytecode::Call, 0);
+
+        let mut program = CompiledProgram::new(main, globals, DEFAULT_OPT_LEVEL);
+        assert_eq!(program.constant_count(), 2);
+        program.prune();
+
+        assert_eq!(program.global_names(), vec![String::from("helper")]);
+        assert_eq!(program.constant_count(), 1);
+        // `helper` moved to the first slot
+        assert_eq!(
+            program
+                .main
+                .chunk
+                .read_operand(SIZE_INSTRUCTION, SIZE_NARROW_INDEX),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn test_prune_superinstructions() {
+        let mut globals = SymbolTable::new();
+        globals.insert("unused", Some(Value::Integer(1)));
+        let counter = globals.insert("counter", Some(Value::Integer(0)));
+
+        let mut main = Function::new_global_scope();
+        main.chunk
+            .add_constant(Value::String(String::from("dead").into()));
+        let step = main.chunk.add_constant(Value::Integer(1));
+        main.chunk.emit(Bytecode::IncrementGlobal);
+        main.chunk
+            .data
+            .extend_from_slice(&[counter as u8, step as u8]);
+
+        let mut program = CompiledProgram::new(main, globals, DEFAULT_OPT_LEVEL);
+        program.prune();
+
+        assert_eq!(program.global_names(), vec![String::from("counter")]);
+        assert_eq!(program.main.chunk.constants, vec![Value::Integer(1)]);
+        // `counter` in the first slot, the step the only constant
+        assert_eq!(&program.main.chunk.data[SIZE_INSTRUCTION..], &[1, 0]);
+    }
+}
@@ -0,0 +1,265 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+use crate::native::{check_arity, iterate, NativeContext, NativeFunction, NativeResult, VARIADIC};
+use crate::object::Value;
+use crate::vm::VmError;
+
+pub const MODULE_NAME: &str = "random";
+
+const STATE_SIZE: usize = 624;
+const SHIFT_SIZE: usize = 397;
+
+/// Mersenne Twister generator seeded the way CPython seeds it, so a seeded
+/// script draws the same numbers under both interpreters.
+pub struct Random {
+    state: [u32; STATE_SIZE],
+    index: usize,
+}
+
+impl Random {
+    /// Generator seeded from OS randomness.
+    pub fn new() -> Random {
+        let mut random = Random {
+            state: [0; STATE_SIZE],
+            index: STATE_SIZE,
+        };
+        random.seed(RandomState::new().build_hasher().finish());
+        random
+    }
+
+    /// Reseeds with the magnitude of an integer seed, like `random.seed(n)`.
+    pub fn seed(&mut self, seed: u64) {
+        let mut key = Vec::new();
+        let mut rest = seed;
+        loop {
+            key.push(rest as u32);
+            rest >>= 32;
+            if rest == 0 {
+                break;
+            }
+        }
+        self.init_by_array(&key);
+    }
+
+    fn init_genrand(&mut self, seed: u32) {
+        self.state[0] = seed;
+        for i in 1..STATE_SIZE {
+            let previous = self.state[i - 1];
+            self.state[i] = 1812433253u32
+                .wrapping_mul(previous ^ (previous >> 30))
+                .wrapping_add(i as u32);
+        }
+        self.index = STATE_SIZE;
+    }
+
+    fn init_by_array(&mut self, key: &[u32]) {
+        self.init_genrand(19650218);
+        let (mut i, mut j) = (1, 0);
+        for _ in 0..STATE_SIZE.max(key.len()) {
+            let previous = self.state[i - 1];
+            self.state[i] = (self.state[i] ^ (previous ^ (previous >> 30)).wrapping_mul(1664525))
+                .wrapping_add(key[j])
+                .wrapping_add(j as u32);
+            i += 1;
+            j += 1;
+            if i >= STATE_SIZE {
+                self.state[0] = self.state[STATE_SIZE - 1];
+                i = 1;
+            }
+            if j >= key.len() {
+                j = 0;
+            }
+        }
+        for _ in 0..STATE_SIZE - 1 {
+            let previous = self.state[i - 1];
+            self.state[i] = (self.state[i]
+                ^ (previous ^ (previous >> 30)).wrapping_mul(1566083941))
+            .wrapping_sub(i as u32);
+            i += 1;
+            if i >= STATE_SIZE {
+                self.state[0] = self.state[STATE_SIZE - 1];
+                i = 1;
+            }
+        }
+        self.state[0] = 0x80000000;
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        if self.index >= STATE_SIZE {
+            for i in 0..STATE_SIZE {
+                let y =
+                    (self.state[i] & 0x80000000) | (self.state[(i + 1) % STATE_SIZE] & 0x7fffffff);
+                let mag = if y & 1 == 1 { 0x9908b0df } else { 0 };
+                self.state[i] = self.state[(i + SHIFT_SIZE) % STATE_SIZE] ^ (y >> 1) ^ mag;
+            }
+            self.index = 0;
+        }
+        let mut y = self.state[self.index];
+        self.index += 1;
+        y ^= y >> 11;
+        y ^= (y << 7) & 0x9d2c5680;
+        y ^= (y << 15) & 0xefc60000;
+        y ^ (y >> 18)
+    }
+
+    /// Float in [0.0, 1.0) with 53 random bits.
+    pub fn random(&mut self) -> f64 {
+        let high = (self.next_u32() >> 5) as f64;
+        let low = (self.next_u32() >> 6) as f64;
+        (high * 67108864.0 + low) * (1.0 / 9007199254740992.0)
+    }
+
+    // `bits` random bits, at most 64, filled from the low word up
+    fn random_bits(&mut self, bits: u32) -> u64 {
+        if bits <= 32 {
+            return (self.next_u32() >> (32 - bits)) as u64;
+        }
+        let low = self.next_u32() as u64;
+        let high = (self.next_u32() >> (64 - bits)) as u64;
+        low | (high << 32)
+    }
+
+    /// Integer in [0, n), rejecting draws of n's bit length until one fits.
+    pub fn below(&mut self, n: u64) -> u64 {
+        if n == 0 {
+            return 0;
+        }
+        let bits = u64::BITS - n.leading_zeros();
+        loop {
+            let value = self.random_bits(bits);
+            if value < n {
+                return value;
+            }
+        }
+    }
+}
+
+impl Default for Random {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Attributes of the built-in `random` module.
+pub fn attributes() -> Vec<(String, Value)> {
+    vec![
+        NativeFunction::new("random", 0, random).nondeterministic(),
+        NativeFunction::new("randint", 2, randint).nondeterministic(),
+        NativeFunction::new("choice", 1, choice).nondeterministic(),
+        NativeFunction::new("shuffle", 1, shuffle).nondeterministic(),
+        NativeFunction::new("seed", VARIADIC, seed),
+    ]
+    .into_iter()
+    .map(|function| {
+        (
+            function.name.to_string(),
+            Value::NativeFunction(function.into()),
+        )
+    })
+    .collect()
+}
+
+fn random(context: &mut NativeContext, _args: Vec<Value>) -> NativeResult {
+    Ok(Value::Float(context.random().random()))
+}
+
+fn randint(context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    let bounds: Vec<i64> = args
+        .iter()
+        .map(|arg| match arg {
+            Value::Integer(value) => Ok(*value),
+            Value::Bool(value) => Ok(*value as i64),
+            other => Err(VmError::InvalidOperand(format!(
+                "TypeError: '{}' object cannot be interpreted as an integer",
+                other.type_name()
+            ))),
+        })
+        .collect::<Result<_, _>>()?;
+    let (low, high) = (bounds[0], bounds[1]);
+    if high < low {
+        return Err(VmError::ValueError(format!(
+            "ValueError: empty range in randrange({}, {})",
+            low,
+            high as i128 + 1
+        )));
+    }
+    // the width of the full i64 range doesn't fit an u64
+    let width = (high as i128 - low as i128 + 1).min(u64::MAX as i128) as u64;
+    let offset = context.random().below(width);
+    Ok(Value::Integer((low as i128 + offset as i128) as i64))
+}
+
+fn choice(context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    let items = match &args[0] {
+        value @ (Value::List(_) | Value::Tuple(_) | Value::String(_) | Value::Bytes(_)) => {
+            iterate(context, value)?
+        }
+        other => {
+            return Err(VmError::InvalidOperand(format!(
+                "TypeError: object of type '{}' has no len()",
+                other.type_name()
+            )))
+        }
+    };
+    if items.is_empty() {
+        return Err(VmError::IndexError(String::from(
+            "IndexError: Cannot choose from an empty sequence",
+        )));
+    }
+    let index = context.random().below(items.len() as u64) as usize;
+    Ok(items[index].clone())
+}
+
+// lists are values rather than shared references, so the shuffled list is
+// returned instead of shuffling the argument in place
+fn shuffle(context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    let mut items = match &args[0] {
+        Value::List(items) => items.to_vec(),
+        other => {
+            return Err(VmError::InvalidOperand(format!(
+                "TypeError: '{}' object does not support item assignment",
+                other.type_name()
+            )))
+        }
+    };
+    for i in (1..items.len()).rev() {
+        let j = context.random().below(i as u64 + 1) as usize;
+        items.swap(i, j);
+    }
+    Ok(Value::List(items.into()))
+}
+
+// `seed([n])`, reseeding from OS randomness without an argument or None
+fn seed(context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    check_arity("seed", &args, 0, 1)?;
+    match args.first() {
+        None | Some(Value::None) => *context.random() = Random::new(),
+        Some(Value::Integer(seed)) => context.random().seed(seed.unsigned_abs()),
+        Some(Value::Bool(value)) => context.random().seed(*value as u64),
+        Some(other) => {
+            return Err(VmError::InvalidOperand(format!(
+                "TypeError: The only supported seed types are: None, int, not {}",
+                other.type_name()
+            )))
+        }
+    }
+    Ok(Value::None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_cpython() {
+        // reference values from CPython's `random` module
+        let mut random = Random::new();
+        random.seed(42);
+        assert_eq!(random.random(), 0.6394267984578837);
+        random.seed((1 << 40) + 5);
+        assert_eq!(random.below(2_000_000_000_001), 1_136_037_663_012);
+        random.seed(7);
+        assert_eq!(random.below((1 << 62) + 1), 3641603982383516983);
+    }
+}
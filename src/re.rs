@@ -0,0 +1,557 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use regex::{Captures, Regex};
+
+use crate::native::{check_arity, NativeContext, NativeFunction, NativeResult, VARIADIC};
+use crate::object::Value;
+use crate::vm::VmError;
+
+pub const MODULE_NAME: &str = "re";
+
+// flag values as in CPython
+const IGNORECASE: i64 = 2;
+const MULTILINE: i64 = 8;
+const DOTALL: i64 = 16;
+
+/// Compiled regular expression, `re.Pattern`. Patterns use the syntax of
+/// the `regex` crate, which matches Python's except for backreferences and
+/// lookaround.
+#[derive(Clone)]
+pub struct Pattern {
+    pub source: String,
+    pub flags: i64,
+    regex: Arc<Regex>,
+    // `regex` only matching at the start of the text, for `match()`
+    anchored: Arc<Regex>,
+}
+
+/// Successful match, `re.Match`.
+#[derive(Clone)]
+pub struct Match {
+    text: Arc<str>,
+    // byte offsets of every group in `text`, None for groups that didn't
+    // take part in the match
+    spans: Vec<Option<(usize, usize)>>,
+    names: Arc<Vec<Option<String>>>,
+}
+
+impl Pattern {
+    pub fn new(source: &str, flags: i64) -> Result<Pattern, VmError> {
+        if flags & !(IGNORECASE | MULTILINE | DOTALL) != 0 {
+            return Err(VmError::ValueError(format!(
+                "ValueError: unsupported re flags {}",
+                flags
+            )));
+        }
+        let mut prefix = String::new();
+        for (flag, inline) in [(IGNORECASE, "i"), (MULTILINE, "m"), (DOTALL, "s")] {
+            if flags & flag != 0 {
+                prefix.push_str(&format!("(?{})", inline));
+            }
+        }
+        let compile = |source: String| {
+            Regex::new(&source)
+                .map(Arc::new)
+                .map_err(|err| VmError::ValueError(format!("re.error: {}", err)))
+        };
+        Ok(Pattern {
+            source: source.to_string(),
+            flags,
+            regex: compile(format!("{}{}", prefix, source))?,
+            anchored: compile(format!(r"{}\A(?:{})", prefix, source))?,
+        })
+    }
+
+    /// Match at the start of `text`, like `re.match`.
+    pub fn match_start(&self, text: &str) -> Option<Match> {
+        self.anchored
+            .captures(text)
+            .map(|captures| self.new_match(text, &captures))
+    }
+
+    /// First match anywhere in `text`, like `re.search`.
+    pub fn search(&self, text: &str) -> Option<Match> {
+        self.regex
+            .captures(text)
+            .map(|captures| self.new_match(text, &captures))
+    }
+
+    /// Non-overlapping matches: the whole match without groups, the group
+    /// with one, and a tuple of the groups otherwise.
+    pub fn findall(&self, text: &str) -> Value {
+        let group_count = self.regex.captures_len() - 1;
+        let group = |captures: &Captures, index: usize| {
+            Value::String(
+                (captures
+                    .get(index)
+                    .map_or("", |group| group.as_str())
+                    .to_string())
+                .into(),
+            )
+        };
+        Value::List(
+            self.regex
+                .captures_iter(text)
+                .map(|captures| match group_count {
+                    0 => group(&captures, 0),
+                    1 => group(&captures, 1),
+                    _ => Value::Tuple(
+                        (1..=group_count)
+                            .map(|index| group(&captures, index))
+                            .collect::<Vec<_>>()
+                            .into(),
+                    ),
+                })
+                .collect::<Vec<_>>()
+                .into(),
+        )
+    }
+
+    /// `text` with the first `count` matches, all of them for 0, replaced by
+    /// `repl`: a template string or a function of the match. Negative
+    /// counts replace nothing.
+    pub fn sub(
+        &self,
+        context: &mut NativeContext,
+        repl: &Value,
+        text: &str,
+        count: i64,
+    ) -> Result<String, VmError> {
+        if count < 0 {
+            return Ok(text.to_string());
+        }
+        let count = count as usize;
+        let mut result = String::new();
+        let mut last = 0;
+        for (index, captures) in self.regex.captures_iter(text).enumerate() {
+            if count > 0 && index == count {
+                break;
+            }
+            let found = self.new_match(text, &captures);
+            let (start, end) = found.spans[0].unwrap_or_default();
+            result.push_str(&text[last..start]);
+            match repl {
+                Value::String(template) => result.push_str(&expand(template, &found)?),
+                _ if repl.is_callable() => {
+                    match context.call(repl, vec![Value::Match(found.into())])? {
+                        Value::String(replacement) => result.push_str(&replacement),
+                        other => {
+                            return Err(VmError::InvalidOperand(format!(
+                                "TypeError: expected str instance, {} found",
+                                other.type_name()
+                            )))
+                        }
+                    }
+                }
+                other => {
+                    return Err(VmError::InvalidOperand(format!(
+                        "TypeError: expected str or callable replacement, not {}",
+                        other.type_name()
+                    )))
+                }
+            }
+            last = end;
+        }
+        result.push_str(&text[last..]);
+        Ok(result)
+    }
+
+    fn new_match(&self, text: &str, captures: &Captures) -> Match {
+        Match {
+            text: Arc::from(text),
+            spans: (0..captures.len())
+                .map(|index| {
+                    captures
+                        .get(index)
+                        .map(|group| (group.start(), group.end()))
+                })
+                .collect(),
+            names: Arc::new(
+                self.regex
+                    .capture_names()
+                    .map(|name| name.map(|name| name.to_string()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl Match {
+    /// Text of group `index`, None when it didn't take part in the match.
+    pub fn group(&self, index: usize) -> Option<&str> {
+        self.spans[index].map(|(start, end)| &self.text[start..end])
+    }
+
+    // group selected by a script argument, its number or name
+    fn group_index(&self, group: &Value) -> Result<usize, VmError> {
+        let index = match group {
+            Value::Integer(index) => usize::try_from(*index).ok(),
+            Value::String(name) => self
+                .names
+                .iter()
+                .position(|group_name| group_name.as_deref() == Some(name)),
+            _ => None,
+        };
+        match index {
+            Some(index) if index < self.spans.len() => Ok(index),
+            _ => Err(VmError::IndexError(String::from(
+                "IndexError: no such group",
+            ))),
+        }
+    }
+
+    fn group_value(&self, index: usize) -> Value {
+        match self.group(index) {
+            Some(text) => Value::String(text.to_string().into()),
+            None => Value::None,
+        }
+    }
+
+    // character offsets of group `index`, (-1, -1) when it didn't match
+    fn span(&self, index: usize) -> (i64, i64) {
+        match self.spans[index] {
+            Some((start, end)) => (
+                self.text[..start].chars().count() as i64,
+                self.text[..end].chars().count() as i64,
+            ),
+            None => (-1, -1),
+        }
+    }
+}
+
+// Python replacement template: `\1` and `\g<name>` insert groups, `\n` and
+// friends the usual characters
+fn expand(template: &str, found: &Match) -> Result<String, VmError> {
+    let bad_escape = |escape: String| {
+        VmError::ValueError(format!("re.error: bad escape {} in template", escape))
+    };
+    let mut result = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(chr) = chars.next() {
+        if chr != '\\' {
+            result.push(chr);
+            continue;
+        }
+        let group = match chars.next() {
+            Some('n') => {
+                result.push('\n');
+                continue;
+            }
+            Some('t') => {
+                result.push('\t');
+                continue;
+            }
+            Some('r') => {
+                result.push('\r');
+                continue;
+            }
+            Some('\\') => {
+                result.push('\\');
+                continue;
+            }
+            Some('g') if chars.peek() == Some(&'<') => {
+                chars.next();
+                let name: String = chars.by_ref().take_while(|chr| *chr != '>').collect();
+                match name.parse::<i64>() {
+                    Ok(index) => Value::Integer(index),
+                    Err(_) => Value::String(name.into()),
+                }
+            }
+            Some(digit) if digit.is_ascii_digit() => {
+                let mut number = digit.to_string();
+                if let Some(next) = chars.peek().filter(|chr| chr.is_ascii_digit()) {
+                    number.push(*next);
+                    chars.next();
+                }
+                Value::Integer(number.parse().unwrap_or_default())
+            }
+            Some(other) if other.is_ascii_alphabetic() => {
+                return Err(bad_escape(format!("\\{}", other)))
+            }
+            Some(other) => {
+                result.push('\\');
+                result.push(other);
+                continue;
+            }
+            None => return Err(bad_escape(String::from("(end of pattern)"))),
+        };
+        let index = found.group_index(&group).map_err(|_| {
+            VmError::ValueError(format!("re.error: invalid group reference {}", group))
+        })?;
+        result.push_str(found.group(index).unwrap_or_default());
+    }
+    Ok(result)
+}
+
+/// Attributes of the built-in `re` module.
+pub fn attributes() -> Vec<(String, Value)> {
+    let functions = vec![
+        NativeFunction::new("compile", VARIADIC, compile).with_kwargs(&["flags"]),
+        NativeFunction::new("match", VARIADIC, |context, args| {
+            let (pattern, text) = pattern_and_text(context, "match", &args)?;
+            Ok(match_object(pattern.match_start(&text)))
+        })
+        .with_kwargs(&["flags"]),
+        NativeFunction::new("search", VARIADIC, |context, args| {
+            let (pattern, text) = pattern_and_text(context, "search", &args)?;
+            Ok(match_object(pattern.search(&text)))
+        })
+        .with_kwargs(&["flags"]),
+        NativeFunction::new("findall", VARIADIC, |context, args| {
+            let (pattern, text) = pattern_and_text(context, "findall", &args)?;
+            Ok(pattern.findall(&text))
+        })
+        .with_kwargs(&["flags"]),
+        NativeFunction::new("sub", VARIADIC, sub).with_kwargs(&["count", "flags"]),
+    ];
+    let mut attributes: Vec<(String, Value)> = functions
+        .into_iter()
+        .map(|function| {
+            (
+                function.name.to_string(),
+                Value::NativeFunction(function.into()),
+            )
+        })
+        .collect();
+    for (names, flag) in [
+        (["IGNORECASE", "I"], IGNORECASE),
+        (["MULTILINE", "M"], MULTILINE),
+        (["DOTALL", "S"], DOTALL),
+    ] {
+        for name in names {
+            attributes.push((name.to_string(), Value::Integer(flag)));
+        }
+    }
+    attributes
+}
+
+// optional integer argument `name` at position `index`, 0 when missing
+fn integer_option(
+    context: &NativeContext,
+    args: &[Value],
+    index: usize,
+    name: &str,
+) -> Result<i64, VmError> {
+    match args.get(index).or(context.kwarg(name)) {
+        None => Ok(0),
+        Some(Value::Integer(value)) => Ok(*value),
+        Some(other) => Err(VmError::InvalidOperand(format!(
+            "TypeError: '{}' object cannot be interpreted as an integer",
+            other.type_name()
+        ))),
+    }
+}
+
+// the pattern argument, compiled with the flags at `flags_index` unless it
+// already is
+fn pattern_arg(
+    context: &NativeContext,
+    args: &[Value],
+    flags_index: usize,
+) -> Result<Pattern, VmError> {
+    let flags = integer_option(context, args, flags_index, "flags")?;
+    match &args[0] {
+        Value::String(source) => Pattern::new(source, flags),
+        Value::Pattern(pattern) if flags == 0 => Ok(pattern.as_ref().clone()),
+        Value::Pattern(_) => Err(VmError::ValueError(String::from(
+            "ValueError: cannot process flags argument with a compiled pattern",
+        ))),
+        other => Err(VmError::InvalidOperand(format!(
+            "TypeError: first argument must be string or compiled pattern, not {}",
+            other.type_name()
+        ))),
+    }
+}
+
+fn text_arg(text: &Value) -> Result<String, VmError> {
+    match text {
+        Value::String(text) => Ok(text.to_string()),
+        other => Err(VmError::InvalidOperand(format!(
+            "TypeError: expected string, got '{}'",
+            other.type_name()
+        ))),
+    }
+}
+
+fn pattern_and_text(
+    context: &NativeContext,
+    name: &str,
+    args: &[Value],
+) -> Result<(Pattern, String), VmError> {
+    check_arity(name, args, 2, 3)?;
+    Ok((pattern_arg(context, args, 2)?, text_arg(&args[1])?))
+}
+
+fn match_object(found: Option<Match>) -> Value {
+    match found {
+        Some(found) => Value::Match(found.into()),
+        None => Value::None,
+    }
+}
+
+fn compile(context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    check_arity("compile", &args, 1, 2)?;
+    Ok(Value::Pattern(pattern_arg(context, &args, 1)?.into()))
+}
+
+// `sub(pattern, repl, string, count=0, flags=0)`
+fn sub(context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    check_arity("sub", &args, 3, 5)?;
+    let pattern = pattern_arg(context, &args, 4)?;
+    let text = text_arg(&args[2])?;
+    let count = integer_option(context, &args, 3, "count")?;
+    Ok(Value::String(
+        pattern.sub(context, &args[1], &text, count)?.into(),
+    ))
+}
+
+/// Method `name` of the compiled pattern `receiver`, bound to it as a native
+/// closure.
+pub fn pattern_method(receiver: &Pattern, name: &str) -> Option<NativeFunction> {
+    let pattern = receiver.clone();
+    let method = match name {
+        "match" => NativeFunction::new("match", 1, move |_, args| {
+            Ok(match_object(pattern.match_start(&text_arg(&args[0])?)))
+        }),
+        "search" => NativeFunction::new("search", 1, move |_, args| {
+            Ok(match_object(pattern.search(&text_arg(&args[0])?)))
+        }),
+        "findall" => NativeFunction::new("findall", 1, move |_, args| {
+            Ok(pattern.findall(&text_arg(&args[0])?))
+        }),
+        "sub" => NativeFunction::new("sub", VARIADIC, move |context, args| {
+            check_arity("sub", &args, 2, 3)?;
+            let text = text_arg(&args[1])?;
+            let count = integer_option(context, &args, 2, "count")?;
+            Ok(Value::String(
+                pattern.sub(context, &args[0], &text, count)?.into(),
+            ))
+        })
+        .with_kwargs(&["count"]),
+        _ => return None,
+    };
+    Some(method)
+}
+
+/// Method `name` of the match `receiver`, bound to it as a native closure.
+pub fn match_method(receiver: &Match, name: &str) -> Option<NativeFunction> {
+    let found = receiver.clone();
+    let method = match name {
+        "group" => NativeFunction::new("group", VARIADIC, move |_, args| {
+            let mut groups = args
+                .iter()
+                .map(|arg| Ok(found.group_value(found.group_index(arg)?)))
+                .collect::<Result<Vec<Value>, VmError>>()?;
+            Ok(match groups.len() {
+                0 => found.group_value(0),
+                1 => groups.remove(0),
+                _ => Value::Tuple(groups.into()),
+            })
+        }),
+        "groups" => NativeFunction::new("groups", VARIADIC, move |_, args| {
+            check_arity("groups", &args, 0, 1)?;
+            let default = args.first().map_or(Value::None, |arg| arg.clone());
+            Ok(Value::Tuple(
+                (1..found.spans.len())
+                    .map(|index| match found.group(index) {
+                        Some(text) => Value::String(text.to_string().into()),
+                        None => default.clone(),
+                    })
+                    .collect::<Vec<_>>()
+                    .into(),
+            ))
+        }),
+        "start" | "end" | "span" => {
+            let name = name.to_string();
+            NativeFunction::new(&name.clone(), VARIADIC, move |_, args| {
+                check_arity(&name, &args, 0, 1)?;
+                let index = match args.first() {
+                    Some(group) => found.group_index(group)?,
+                    None => 0,
+                };
+                let (start, end) = found.span(index);
+                Ok(match name.as_str() {
+                    "start" => Value::Integer(start),
+                    "end" => Value::Integer(end),
+                    _ => Value::Tuple(vec![Value::Integer(start), Value::Integer(end)].into()),
+                })
+            })
+        }
+        _ => return None,
+    };
+    Some(method)
+}
+
+/// Data attributes of patterns and matches, besides their methods.
+pub fn pattern_attribute(pattern: &Pattern, name: &str) -> Option<Value> {
+    match name {
+        "pattern" => Some(Value::String(pattern.source.to_string().into())),
+        "flags" => Some(Value::Integer(pattern.flags)),
+        _ => None,
+    }
+}
+
+pub fn match_attribute(found: &Match, name: &str) -> Option<Value> {
+    match name {
+        "string" => Some(Value::String(found.text.to_string().into())),
+        _ => None,
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source && self.flags == other.flags
+    }
+}
+
+impl PartialOrd for Pattern {
+    fn partial_cmp(&self, _other: &Self) -> Option<std::cmp::Ordering> {
+        None
+    }
+}
+
+impl Debug for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let source = Value::String(self.source.to_string().into()).repr();
+        let flags: Vec<&str> = [
+            (IGNORECASE, "re.IGNORECASE"),
+            (MULTILINE, "re.MULTILINE"),
+            (DOTALL, "re.DOTALL"),
+        ]
+        .into_iter()
+        .filter(|(flag, _)| self.flags & flag != 0)
+        .map(|(_, name)| name)
+        .collect();
+        match flags.is_empty() {
+            true => write!(f, "re.compile({})", source),
+            false => write!(f, "re.compile({}, {})", source, flags.join("|")),
+        }
+    }
+}
+
+impl PartialEq for Match {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.text, &other.text) && self.spans == other.spans
+    }
+}
+
+impl PartialOrd for Match {
+    fn partial_cmp(&self, _other: &Self) -> Option<std::cmp::Ordering> {
+        None
+    }
+}
+
+impl Debug for Match {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (start, end) = self.span(0);
+        let text = Value::String(self.group(0).unwrap_or_default().to_string().into());
+        write!(
+            f,
+            "<re.Match object; span=({}, {}), match={}>",
+            start,
+            end,
+            text.repr()
+        )
+    }
+}
@@ -0,0 +1,691 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::bytecode::{Bytecode, SIZE_JUMP, SIZE_NARROW_INDEX};
+use crate::chunk::{same_literal, Chunk, Decoded};
+use crate::object::Value;
+
+/// Where an instruction reads a value from: a register, numbered from the
+/// frame base like the stack slots they stand for, or a constant.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operand {
+    Register(usize),
+    Constant(usize),
+}
+
+/// Register instructions, each naming the registers it reads and writes
+/// rather than pushing and popping the operand stack. Jump targets are
+/// instruction indexes.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Instruction {
+    Move {
+        dst: usize,
+        src: Operand,
+    },
+    GetGlobal {
+        dst: usize,
+        index: u64,
+    },
+    SetGlobal {
+        index: u64,
+        src: Operand,
+    },
+    GetBuiltin {
+        dst: usize,
+        index: u64,
+    },
+    // `name` is the index of the constant holding it
+    GetAttr {
+        dst: usize,
+        object: Operand,
+        name: u64,
+    },
+    GetItem {
+        dst: usize,
+        object: Operand,
+        index: Operand,
+    },
+    PopResult {
+        src: Operand,
+    },
+    // `name = name + rhs` of a local or a global
+    InplaceAdd {
+        dst: usize,
+        rhs: Operand,
+    },
+    InplaceAddGlobal {
+        index: u64,
+        rhs: Operand,
+    },
+    Unary {
+        op: Bytecode,
+        dst: usize,
+        src: Operand,
+    },
+    Binary {
+        op: Bytecode,
+        dst: usize,
+        lhs: Operand,
+        rhs: Operand,
+    },
+    // calls the callable in `callable` with the `args` registers below it,
+    // the result landing in the first of them
+    Call {
+        callable: usize,
+        args: usize,
+        // bytecode address of the call and of the instruction after it
+        addr: usize,
+        next_addr: usize,
+    },
+    Jump {
+        target: usize,
+    },
+    JumpIfFalse {
+        condition: Operand,
+        target: usize,
+    },
+    // jumps unless the comparison `lhs op rhs` holds
+    CompareJump {
+        op: Bytecode,
+        lhs: Operand,
+        rhs: Operand,
+        target: usize,
+    },
+    Return {
+        src: Operand,
+    },
+}
+
+/// A function lowered to register instructions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegisterCode {
+    pub instructions: Vec<Instruction>,
+    // bytecode address each instruction was lowered from, in order, for
+    // error lines and resuming after calls
+    pub addrs: Vec<usize>,
+    // those of the chunk followed by the literals the bytecode pushes
+    pub constants: Vec<Value>,
+    // registers the frame needs, arguments included
+    pub registers: usize,
+}
+
+impl RegisterCode {
+    /// Index of the first instruction lowered from `addr` or later.
+    pub fn resume_at(&self, addr: usize) -> usize {
+        self.addrs.partition_point(|lowered| *lowered < addr)
+    }
+}
+
+/// Lowers `chunk`, the body of a function taking `arity` arguments, to
+/// register instructions, None when it uses an opcode the register VM
+/// doesn't implement or its stack depth isn't the same on every path to an
+/// instruction. Those functions stay on the stack VM.
+///
+/// Every operand stack slot becomes the register of the same number, so
+/// locals keep their slots and calls find their arguments on top of the
+/// stack. Pushing a local or a constant only records where the value is,
+/// the instruction consuming it reading it from there, so most of the
+/// stack traffic disappears. Values are moved to their own register
+/// before anything overwrites where they are and at jumps and jump
+/// targets, where every path has to agree on the registers.
+pub fn lower(chunk: &Chunk, arity: usize) -> Option<RegisterCode> {
+    let decoded = decode(chunk)?;
+    let mut lowering = Lowering {
+        code: RegisterCode {
+            instructions: Vec::new(),
+            addrs: Vec::new(),
+            constants: chunk.constants.clone(),
+            registers: arity,
+        },
+        // the arguments are the first locals
+        stack: (0..arity).map(Operand::Register).collect(),
+        addr: 0,
+        depths: HashMap::new(),
+        pcs: HashMap::new(),
+        labels: HashSet::new(),
+        reachable: true,
+    };
+    for (addr, instruction) in &decoded {
+        if let Some(target) = jump_target(chunk, *addr, instruction) {
+            lowering.labels.insert(target?);
+        }
+    }
+    for (addr, instruction) in &decoded {
+        lowering.lower_instruction(chunk, *addr, instruction)?;
+    }
+    lowering.lower_end(chunk.data.len())?;
+    lowering.link()?;
+    Some(lowering.code)
+}
+
+// the instructions of `chunk` with their addresses, None when some
+// doesn't decode or its operand is cut short
+fn decode(chunk: &Chunk) -> Option<Vec<(usize, Decoded)>> {
+    let mut result = Vec::new();
+    let mut addr = 0;
+    while addr < chunk.data.len() {
+        let instruction = chunk.decode(addr)?;
+        let next_addr = instruction.next_addr();
+        if next_addr > chunk.data.len() {
+            return None;
+        }
+        result.push((addr, instruction));
+        addr = next_addr;
+    }
+    Some(result)
+}
+
+// target of a jump, Some(None) when the offset can't be read
+fn jump_target(chunk: &Chunk, addr: usize, instruction: &Decoded) -> Option<Option<usize>> {
+    let offset = match instruction.op {
+        Bytecode::Jump
+        | Bytecode::Loop
+        | Bytecode::JumpIfFalse
+        | Bytecode::ConstLessJumpIfFalse => chunk.get_jump_offset_safe(instruction.operand_addr),
+        Bytecode::JumpLong => chunk
+            .read_operand(instruction.operand_addr, instruction.operand_size)
+            .map(|offset| offset as i64),
+        _ => return None,
+    };
+    Some(offset.and_then(|offset| addr.checked_add_signed(offset as isize)))
+}
+
+struct Lowering {
+    code: RegisterCode,
+    // where the value of each stack slot is
+    stack: Vec<Operand>,
+    // address of the instruction being lowered
+    addr: usize,
+    // stack depth at each jump target
+    depths: HashMap<usize, usize>,
+    // index of the first instruction lowered from each address
+    pcs: HashMap<usize, usize>,
+    // addresses jumps go to
+    labels: HashSet<usize>,
+    // false after a return or an unconditional jump, until a jump target
+    reachable: bool,
+}
+
+impl Lowering {
+    fn lower_instruction(
+        &mut self,
+        chunk: &Chunk,
+        addr: usize,
+        instruction: &Decoded,
+    ) -> Option<()> {
+        self.addr = addr;
+        if !self.enter(addr)? {
+            return Some(());
+        }
+        let index = || chunk.read_operand(instruction.operand_addr, instruction.operand_size);
+        let byte =
+            |offset| chunk.read_operand(instruction.operand_addr + offset, SIZE_NARROW_INDEX);
+        match instruction.op {
+            Bytecode::Nop => {}
+            Bytecode::None => self.push_constant(Value::None),
+            Bytecode::PushTrue => self.push_constant(Value::Bool(true)),
+            Bytecode::PushFalse => self.push_constant(Value::Bool(false)),
+            // functions are constants bound by the definition
+            Bytecode::Const | Bytecode::MakeFunction => {
+                let constant = index()? as usize;
+                chunk.constants.get(constant)?;
+                self.stack.push(Operand::Constant(constant));
+            }
+            Bytecode::Pop => {
+                self.stack.pop()?;
+            }
+            Bytecode::PopResult => {
+                let src = self.stack.pop()?;
+                self.emit(Instruction::PopResult { src });
+            }
+            Bytecode::GetLocal => {
+                let value = *self.stack.get(index()? as usize)?;
+                self.stack.push(value);
+            }
+            Bytecode::SetLocal => {
+                let slot = index()? as usize;
+                let value = *self.stack.last()?;
+                if slot >= self.stack.len() {
+                    return None;
+                }
+                if value != Operand::Register(slot) {
+                    self.write(slot, value);
+                }
+            }
+            Bytecode::InplaceAddLocal | Bytecode::IncrementLocal => {
+                let (slot, rhs) = match instruction.op {
+                    Bytecode::InplaceAddLocal => (index()? as usize, self.stack.pop()?),
+                    _ => (
+                        byte(0)? as usize,
+                        constant(chunk, byte(SIZE_NARROW_INDEX)?)?,
+                    ),
+                };
+                if slot >= self.stack.len() {
+                    return None;
+                }
+                self.own(slot);
+                self.spill(slot);
+                self.emit(Instruction::InplaceAdd { dst: slot, rhs });
+            }
+            Bytecode::GetGlobal | Bytecode::GetBuiltin => {
+                let dst = self.stack.len();
+                let index = index()?;
+                self.emit(match instruction.op {
+                    Bytecode::GetGlobal => Instruction::GetGlobal { dst, index },
+                    _ => Instruction::GetBuiltin { dst, index },
+                });
+                self.stack.push(Operand::Register(dst));
+            }
+            Bytecode::SetGlobal => {
+                let src = self.stack.pop()?;
+                self.emit(Instruction::SetGlobal {
+                    index: index()?,
+                    src,
+                });
+            }
+            Bytecode::InplaceAddGlobal | Bytecode::IncrementGlobal => {
+                let (index, rhs) = match instruction.op {
+                    Bytecode::InplaceAddGlobal => (index()?, self.stack.pop()?),
+                    _ => (byte(0)?, constant(chunk, byte(SIZE_NARROW_INDEX)?)?),
+                };
+                self.emit(Instruction::InplaceAddGlobal { index, rhs });
+            }
+            Bytecode::GetAttr => {
+                let name = index()?;
+                chunk.constants.get(name as usize)?;
+                let object = self.stack.pop()?;
+                let dst = self.stack.len();
+                self.emit(Instruction::GetAttr { dst, object, name });
+                self.stack.push(Operand::Register(dst));
+            }
+            Bytecode::GetItem => {
+                let index = self.stack.pop()?;
+                let object = self.stack.pop()?;
+                let dst = self.stack.len();
+                self.emit(Instruction::GetItem { dst, object, index });
+                self.stack.push(Operand::Register(dst));
+            }
+            Bytecode::Not | Bytecode::Neg => {
+                let src = self.stack.pop()?;
+                let dst = self.stack.len();
+                self.emit(Instruction::Unary {
+                    op: instruction.op.clone(),
+                    dst,
+                    src,
+                });
+                self.stack.push(Operand::Register(dst));
+            }
+            Bytecode::And
+            | Bytecode::Or
+            | Bytecode::Equal
+            | Bytecode::NotEqual
+            | Bytecode::Less
+            | Bytecode::LessEqual
+            | Bytecode::Greater
+            | Bytecode::GreaterEqual
+            | Bytecode::Add
+            | Bytecode::Sub
+            | Bytecode::Mul
+            | Bytecode::Div
+            | Bytecode::FloorDiv
+            | Bytecode::Mod => {
+                let rhs = self.stack.pop()?;
+                let lhs = self.stack.pop()?;
+                let dst = self.stack.len();
+                self.emit(Instruction::Binary {
+                    op: instruction.op.clone(),
+                    dst,
+                    lhs,
+                    rhs,
+                });
+                self.stack.push(Operand::Register(dst));
+            }
+            Bytecode::Call => {
+                let args = index()? as usize;
+                let callable = self.stack.len().checked_sub(1)?;
+                let first = callable.checked_sub(args)?;
+                (first..=callable).for_each(|slot| self.own(slot));
+                self.emit(Instruction::Call {
+                    callable,
+                    args,
+                    addr,
+                    next_addr: instruction.next_addr(),
+                });
+                self.stack.truncate(first);
+                self.stack.push(Operand::Register(first));
+            }
+            Bytecode::Return => {
+                let src = self.stack.pop()?;
+                self.emit(Instruction::Return { src });
+                self.reachable = false;
+            }
+            Bytecode::Jump | Bytecode::Loop | Bytecode::JumpLong => {
+                let target = jump_target(chunk, addr, instruction)??;
+                self.own_all();
+                self.branch(target)?;
+                self.emit(Instruction::Jump { target });
+                self.reachable = false;
+            }
+            Bytecode::JumpIfFalse => {
+                let target = jump_target(chunk, addr, instruction)??;
+                let condition = self.stack.pop()?;
+                self.own_all();
+                self.branch(target)?;
+                let jump = match self.code.instructions.last() {
+                    // a comparison only computed for the jump
+                    Some(Instruction::Binary { op, dst, lhs, rhs })
+                        if is_comparison(op)
+                            && condition == Operand::Register(*dst)
+                            && self.code.addrs.last() != Some(&addr)
+                            && !self.labels.contains(&addr) =>
+                    {
+                        let jump = Instruction::CompareJump {
+                            op: op.clone(),
+                            lhs: *lhs,
+                            rhs: *rhs,
+                            target,
+                        };
+                        self.code.instructions.pop();
+                        self.code.addrs.pop();
+                        jump
+                    }
+                    _ => Instruction::JumpIfFalse { condition, target },
+                };
+                self.emit(jump);
+            }
+            Bytecode::ConstLessJumpIfFalse => {
+                let target = jump_target(chunk, addr, instruction)??;
+                let rhs = constant(chunk, byte(SIZE_JUMP)?)?;
+                let lhs = self.stack.pop()?;
+                self.own_all();
+                self.branch(target)?;
+                self.emit(Instruction::CompareJump {
+                    op: Bytecode::Less,
+                    lhs,
+                    rhs,
+                    target,
+                });
+            }
+            _ => return None,
+        }
+        self.code.registers = self.code.registers.max(self.stack.len());
+        Some(())
+    }
+
+    // falling off the end returns the top of the stack, None when empty
+    fn lower_end(&mut self, addr: usize) -> Option<()> {
+        self.addr = addr;
+        if self.enter(addr)? {
+            let src = match self.stack.last() {
+                Some(value) => *value,
+                None => self.constant_operand(Value::None),
+            };
+            self.emit(Instruction::Return { src });
+        }
+        Some(())
+    }
+
+    // starts lowering the instruction at `addr`, false when no path
+    // reaches it
+    fn enter(&mut self, addr: usize) -> Option<bool> {
+        if self.labels.contains(&addr) {
+            if self.reachable {
+                self.own_all();
+                self.branch(addr)?;
+            } else if let Some(depth) = self.depths.get(&addr) {
+                self.stack = (0..*depth).map(Operand::Register).collect();
+                self.reachable = true;
+            }
+        }
+        if self.reachable {
+            self.pcs.insert(addr, self.code.instructions.len());
+        }
+        Some(self.reachable)
+    }
+
+    // records the stack depth at jump target `target`, None when another
+    // path reaches it with a different one
+    fn branch(&mut self, target: usize) -> Option<()> {
+        let depth = self.stack.len();
+        match *self.depths.entry(target).or_insert(depth) == depth {
+            true => Some(()),
+            false => None,
+        }
+    }
+
+    // turns the jump targets from addresses into instruction indexes
+    fn link(&mut self) -> Option<()> {
+        for instruction in self.code.instructions.iter_mut() {
+            if let Instruction::Jump { target }
+            | Instruction::JumpIfFalse { target, .. }
+            | Instruction::CompareJump { target, .. } = instruction
+            {
+                *target = *self.pcs.get(target)?;
+            }
+        }
+        Some(())
+    }
+
+    fn emit(&mut self, instruction: Instruction) {
+        self.code.instructions.push(instruction);
+        self.code.addrs.push(self.addr);
+    }
+
+    fn constant_operand(&mut self, value: Value) -> Operand {
+        let constants = &mut self.code.constants;
+        let index = match constants
+            .iter()
+            .position(|constant| same_literal(constant, &value))
+        {
+            Some(index) => index,
+            None => {
+                constants.push(value);
+                constants.len() - 1
+            }
+        };
+        Operand::Constant(index)
+    }
+
+    fn push_constant(&mut self, value: Value) {
+        let operand = self.constant_operand(value);
+        self.stack.push(operand);
+    }
+
+    // stores `value` in register `slot`, the value of that slot from now on
+    fn write(&mut self, slot: usize, value: Operand) {
+        self.spill(slot);
+        self.emit(Instruction::Move {
+            dst: slot,
+            src: value,
+        });
+        self.stack[slot] = Operand::Register(slot);
+    }
+
+    // moves the values of the other slots found in register `slot` to
+    // their own, before it is overwritten
+    fn spill(&mut self, slot: usize) {
+        for other in 0..self.stack.len() {
+            if other != slot && self.stack[other] == Operand::Register(slot) {
+                self.write(other, Operand::Register(slot));
+            }
+        }
+    }
+
+    // moves the value of `slot` to its own register
+    fn own(&mut self, slot: usize) {
+        let value = self.stack[slot];
+        if value != Operand::Register(slot) {
+            self.write(slot, value);
+        }
+    }
+
+    fn own_all(&mut self) {
+        (0..self.stack.len()).for_each(|slot| self.own(slot));
+    }
+}
+
+// constant `index` of the chunk, None when there's no such constant
+fn constant(chunk: &Chunk, index: u64) -> Option<Operand> {
+    chunk.constants.get(index as usize)?;
+    Some(Operand::Constant(index as usize))
+}
+
+fn is_comparison(op: &Bytecode) -> bool {
+    matches!(
+        op,
+        Bytecode::Equal
+            | Bytecode::NotEqual
+            | Bytecode::Less
+            | Bytecode::LessEqual
+            | Bytecode::Greater
+            | Bytecode::GreaterEqual
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Emitter = fn(&mut Chunk);
+
+    #[test]
+    fn test_lower() {
+        let emitters: Vec<(Emitter, usize, Option<Vec<Instruction>>)> = vec![
+            // operands are read from the locals they were pushed from
+            (
+                |chunk| {
+                    chunk.emit_with_index(Bytecode::GetLocal, 0);
+                    chunk.emit_with_index(Bytecode::GetLocal, 1);
+                    chunk.emit(Bytecode::Add);
+                    chunk.emit(Bytecode::Return);
+                },
+                2,
+                Some(vec![
+                    Instruction::Binary {
+                        op: Bytecode::Add,
+                        dst: 2,
+                        lhs: Operand::Register(0),
+                        rhs: Operand::Register(1),
+                    },
+                    Instruction::Return {
+                        src: Operand::Register(2),
+                    },
+                ]),
+            ),
+            // declaring a local moves its value into its register, falling
+            // off the end returns it
+            (
+                |chunk| {
+                    let index = chunk.add_constant(Value::Integer(1));
+                    chunk.emit_with_index(Bytecode::Const, index);
+                    chunk.emit_with_index(Bytecode::SetLocal, 0);
+                },
+                0,
+                Some(vec![
+                    Instruction::Move {
+                        dst: 0,
+                        src: Operand::Constant(0),
+                    },
+                    Instruction::Return {
+                        src: Operand::Register(0),
+                    },
+                ]),
+            ),
+            // comparisons only computed for a jump are fused with it
+            (
+                |chunk| {
+                    chunk.emit_with_index(Bytecode::GetLocal, 0);
+                    chunk.emit_with_index(Bytecode::GetLocal, 1);
+                    chunk.emit(Bytecode::Less);
+                    let jump = chunk.emit_jump(Bytecode::JumpIfFalse);
+                    chunk.emit(Bytecode::PushTrue);
+                    chunk.emit(Bytecode::Return);
+                    let _ = chunk.patch_jump(jump, chunk.size());
+                    chunk.emit(Bytecode::PushFalse);
+                    chunk.emit(Bytecode::Return);
+                },
+                2,
+                Some(vec![
+                    Instruction::CompareJump {
+                        op: Bytecode::Less,
+                        lhs: Operand::Register(0),
+                        rhs: Operand::Register(1),
+                        target: 2,
+                    },
+                    Instruction::Return {
+                        src: Operand::Constant(0),
+                    },
+                    Instruction::Return {
+                        src: Operand::Constant(1),
+                    },
+                ]),
+            ),
+            // arguments are moved to the registers below the callable
+            (
+                |chunk| {
+                    chunk.emit_with_index(Bytecode::GetLocal, 0);
+                    chunk.emit_with_index(Bytecode::GetGlobal, 1);
+                    chunk.emit_with_index(Bytecode::Call, 1);
+                    chunk.emit(Bytecode::Return);
+                },
+                1,
+                Some(vec![
+                    Instruction::GetGlobal { dst: 2, index: 1 },
+                    Instruction::Move {
+                        dst: 1,
+                        src: Operand::Register(0),
+                    },
+                    Instruction::Call {
+                        callable: 2,
+                        args: 1,
+                        addr: 4,
+                        next_addr: 6,
+                    },
+                    Instruction::Return {
+                        src: Operand::Register(1),
+                    },
+                ]),
+            ),
+            // try blocks stay on the stack VM
+            (
+                |chunk| {
+                    let handler = chunk.emit_jump(Bytecode::SetupTry);
+                    let _ = chunk.patch_jump(handler, chunk.size());
+                },
+                0,
+                None,
+            ),
+            // as do paths reaching an instruction with different depths
+            (
+                |chunk| {
+                    chunk.emit(Bytecode::PushTrue);
+                    let jump = chunk.emit_jump(Bytecode::JumpIfFalse);
+                    chunk.emit(Bytecode::PushTrue);
+                    let _ = chunk.patch_jump(jump, chunk.size());
+                    chunk.emit(Bytecode::Return);
+                },
+                0,
+                None,
+            ),
+        ];
+        emitters.into_iter().for_each(|(emitter, arity, expected)| {
+            let mut chunk = Chunk::new();
+            emitter(&mut chunk);
+            let code = lower(&chunk, arity);
+            assert_eq!(code.map(|code| code.instructions), expected);
+        });
+    }
+
+    #[test]
+    fn test_resume_at() {
+        let mut chunk = Chunk::new();
+        chunk.emit_with_index(Bytecode::GetGlobal, 1);
+        chunk.emit_with_index(Bytecode::Call, 0);
+        chunk.emit(Bytecode::Pop);
+        chunk.emit(Bytecode::Nop);
+        let code = lower(&chunk, 0).unwrap();
+        assert_eq!(code.addrs, vec![0, 2, 6]);
+        // after the call, the pop and the nop lowered to nothing
+        assert_eq!(code.resume_at(4), 2);
+        assert_eq!(code.resume_at(0), 0);
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::VecDeque;
+use std::fmt::Display;
+
+use crate::object::Value;
+use crate::vm::VmError;
+
+/// Result of one call to a nondeterministic native.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogEntry {
+    pub native: String,
+    pub result: Value,
+}
+
+/// Results of the nondeterministic natives of a run, in call order. Its text
+/// form has one `name<TAB>type<TAB>value` line per call.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExecutionLog {
+    pub entries: Vec<LogEntry>,
+}
+
+// Whether the VM is writing the log or reading it back
+pub(crate) enum Replay {
+    Record(ExecutionLog),
+    Replay(VecDeque<LogEntry>),
+}
+
+impl ExecutionLog {
+    pub fn new() -> ExecutionLog {
+        ExecutionLog::default()
+    }
+
+    pub fn parse(text: &str) -> Result<ExecutionLog, String> {
+        let mut log = ExecutionLog::new();
+        for (line_number, line) in text.lines().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.splitn(3, '\t').collect();
+            let result = match fields[..] {
+                [_, "None", ""] => Some(Value::None),
+                [_, "bool", "True"] => Some(Value::Bool(true)),
+                [_, "bool", "False"] => Some(Value::Bool(false)),
+                [_, "int", value] => value.parse().ok().map(Value::Integer),
+                [_, "float", value] => value.parse().ok().map(Value::Float),
+                [_, "str", value] => unescape(value).map(|text| Value::String(text.into())),
+                _ => None,
+            };
+            match result {
+                Some(result) => log.entries.push(LogEntry {
+                    native: fields[0].to_string(),
+                    result,
+                }),
+                None => {
+                    return Err(format!(
+                        "Invalid execution log entry at line {}: {}",
+                        line_number + 1,
+                        line
+                    ))
+                }
+            }
+        }
+        Ok(log)
+    }
+
+    pub(crate) fn record(&mut self, native: &str, result: &Value) -> Result<(), VmError> {
+        match result {
+            Value::None
+            | Value::Bool(_)
+            | Value::Integer(_)
+            | Value::Float(_)
+            | Value::String(_) => {
+                self.entries.push(LogEntry {
+                    native: native.to_string(),
+                    result: result.clone(),
+                });
+                Ok(())
+            }
+            other => Err(VmError::ReplayDiverged(format!(
+                "Cannot record a {} returned by {}",
+                other.type_name(),
+                native
+            ))),
+        }
+    }
+}
+
+impl Display for ExecutionLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for entry in &self.entries {
+            let (type_name, value) = match &entry.result {
+                Value::None => ("None", String::new()),
+                Value::Bool(true) => ("bool", String::from("True")),
+                Value::Bool(false) => ("bool", String::from("False")),
+                Value::Integer(value) => ("int", value.to_string()),
+                // Display is the shortest string parsing back to the same bits
+                Value::Float(value) => ("float", value.to_string()),
+                Value::String(value) => ("str", escape(value)),
+                _ => unreachable!(),
+            };
+            writeln!(f, "{}\t{}\t{}", entry.native, type_name, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Logged result of the next call, which must be to `native`.
+pub(crate) fn next_result(
+    entries: &mut VecDeque<LogEntry>,
+    native: &str,
+) -> Result<Value, VmError> {
+    match entries.pop_front() {
+        Some(entry) if entry.native == native => Ok(entry.result),
+        Some(entry) => Err(VmError::ReplayDiverged(format!(
+            "Expected a call to {}, the script called {}",
+            entry.native, native
+        ))),
+        None => Err(VmError::ReplayDiverged(format!(
+            "Execution log has no entry left for {}",
+            native
+        ))),
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn unescape(value: &str) -> Option<String> {
+    let mut result = String::new();
+    let mut chars = value.chars();
+    while let Some(chr) = chars.next() {
+        if chr != '\\' {
+            result.push(chr);
+            continue;
+        }
+        match chars.next()? {
+            '\\' => result.push('\\'),
+            't' => result.push('\t'),
+            'n' => result.push('\n'),
+            'r' => result.push('\r'),
+            _ => return None,
+        }
+    }
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_round_trip() {
+        let mut log = ExecutionLog::new();
+        vec![
+            Value::None,
+            Value::Bool(true),
+            Value::Integer(-7),
+            Value::Float(0.1 + 0.2),
+            Value::String(String::from("tab\there\nback\\slash").into()),
+        ]
+        .into_iter()
+        .for_each(|value| log.record("host", &value).unwrap());
+        assert_eq!(ExecutionLog::parse(&log.to_string()).unwrap(), log);
+    }
+
+    #[test]
+    fn test_invalid_log() {
+        vec![
+            "host\tint\tseven",
+            "host\tlist\t[]",
+            "host",
+            "host\tstr\tbad\\escape",
+        ]
+        .into_iter()
+        .for_each(|text| assert!(ExecutionLog::parse(text).is_err(), "{}", text));
+    }
+
+    #[test]
+    fn test_replay_diverged() {
+        let mut entries = VecDeque::from(vec![LogEntry {
+            native: String::from("time"),
+            result: Value::Integer(1),
+        }]);
+        assert!(next_result(&mut entries, "random").is_err());
+        assert!(next_result(&mut entries, "time").is_err());
+    }
+}
@@ -0,0 +1,571 @@
+use std::rc::Rc;
+
+use crate::ast::TypeAnnotation;
+use crate::chunk::Chunk;
+use crate::config::MAX_OPT_LEVEL;
+use crate::function::Function;
+use crate::object::Value;
+
+/// First bytes of every `.rpyc` file.
+pub const MAGIC: &[u8; 4] = b"RPYC";
+
+/// Version of the format, bumped whenever the layout changes. Files of
+/// other versions are rejected rather than misread.
+pub const FORMAT_VERSION: u16 = 3;
+
+/// Extension of compiled script files.
+pub const EXTENSION: &str = "rpyc";
+
+// header flag: the bytecode operands were written big-endian, as they are
+// stored in the byte order of the machine that compiled them
+const FLAG_BIG_ENDIAN: u8 = 1;
+
+// what follows the header
+const PAYLOAD_FUNCTION: u8 = 0;
+const PAYLOAD_PROGRAM: u8 = 1;
+
+// constant tags
+const TAG_NONE: u8 = 0;
+const TAG_BOOL: u8 = 1;
+const TAG_ELLIPSIS: u8 = 2;
+const TAG_NOT_IMPLEMENTED: u8 = 3;
+const TAG_INTEGER: u8 = 4;
+const TAG_FLOAT: u8 = 5;
+const TAG_STRING: u8 = 6;
+const TAG_BYTES: u8 = 7;
+const TAG_TUPLE: u8 = 8;
+const TAG_FUNCTION: u8 = 9;
+
+/// Functions nested deeper than this are rejected, so reading a crafted
+/// file can't overflow the stack.
+const MAX_NESTING: usize = 256;
+
+/// A serialized function, with the globals and builtins its bytecode refers
+/// to by index for a whole program.
+pub(crate) struct Payload {
+    pub(crate) function: Function,
+    // (index, name, value) of each global, without a value for the names
+    // only declared so far
+    pub(crate) globals: Vec<(u64, String, Option<Value>)>,
+    // (index, name) of each builtin
+    pub(crate) builtins: Vec<(u64, String)>,
+    // `Config::opt_level` the program was compiled at
+    pub(crate) opt_level: u8,
+}
+
+/// Whether `bytes` look like a `.rpyc` file rather than source.
+pub fn is_compiled(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Writes `function` in the `.rpyc` format.
+pub(crate) fn write_function(function: &Function) -> Result<Vec<u8>, String> {
+    let mut writer = Writer::new(PAYLOAD_FUNCTION);
+    writer.function(function, 0)?;
+    Ok(writer.bytes)
+}
+
+/// Writes a program: its optimization level, main function, globals and
+/// builtins.
+pub(crate) fn write_program(payload: &Payload) -> Result<Vec<u8>, String> {
+    let mut writer = Writer::new(PAYLOAD_PROGRAM);
+    writer.bytes.push(payload.opt_level);
+    writer.count(payload.globals.len())?;
+    for (index, name, value) in &payload.globals {
+        writer.u64(*index);
+        writer.string(name)?;
+        writer
+            .optional_value(value)
+            .map_err(|err| format!("global '{}': {}", name, err))?;
+    }
+    writer.count(payload.builtins.len())?;
+    for (index, name) in &payload.builtins {
+        writer.u64(*index);
+        writer.string(name)?;
+    }
+    writer.function(&payload.function, 0)?;
+    Ok(writer.bytes)
+}
+
+/// Reads a function written by `write_function`.
+pub(crate) fn read_function(bytes: &[u8]) -> Result<Function, String> {
+    let mut reader = Reader::new(bytes, PAYLOAD_FUNCTION)?;
+    let function = reader.function(0)?;
+    reader.end()?;
+    Ok(function)
+}
+
+/// Reads a program written by `write_program`.
+pub(crate) fn read_program(bytes: &[u8]) -> Result<Payload, String> {
+    let mut reader = Reader::new(bytes, PAYLOAD_PROGRAM)?;
+    let opt_level = reader.u8()?;
+    if opt_level > MAX_OPT_LEVEL {
+        return Err(format!("unknown optimization level {}", opt_level));
+    }
+    let mut globals = Vec::new();
+    for _ in 0..reader.count()? {
+        globals.push((reader.u64()?, reader.string()?, reader.optional_value()?));
+    }
+    let mut builtins = Vec::new();
+    for _ in 0..reader.count()? {
+        builtins.push((reader.u64()?, reader.string()?));
+    }
+    let function = reader.function(0)?;
+    reader.end()?;
+    Ok(Payload {
+        function,
+        globals,
+        builtins,
+        opt_level,
+    })
+}
+
+fn flags() -> u8 {
+    match cfg!(target_endian = "big") {
+        true => FLAG_BIG_ENDIAN,
+        false => 0,
+    }
+}
+
+// Integers are little-endian, lengths and counts u32, strings UTF-8.
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn new(payload: u8) -> Writer {
+        let mut writer = Writer {
+            bytes: MAGIC.to_vec(),
+        };
+        writer
+            .bytes
+            .extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        writer.bytes.push(flags());
+        writer.bytes.push(payload);
+        writer
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn count(&mut self, count: usize) -> Result<(), String> {
+        let count = u32::try_from(count).map_err(|_| String::from("too many items"))?;
+        self.bytes.extend_from_slice(&count.to_le_bytes());
+        Ok(())
+    }
+
+    fn data(&mut self, data: &[u8]) -> Result<(), String> {
+        self.count(data.len())?;
+        self.bytes.extend_from_slice(data);
+        Ok(())
+    }
+
+    fn string(&mut self, text: &str) -> Result<(), String> {
+        self.data(text.as_bytes())
+    }
+
+    fn strings(&mut self, texts: &[String]) -> Result<(), String> {
+        self.count(texts.len())?;
+        texts.iter().try_for_each(|text| self.string(text))
+    }
+
+    fn optional_string(&mut self, text: &Option<String>) -> Result<(), String> {
+        match text {
+            Some(text) => {
+                self.bytes.push(1);
+                self.string(text)
+            }
+            None => {
+                self.bytes.push(0);
+                Ok(())
+            }
+        }
+    }
+
+    fn optional_value(&mut self, value: &Option<Value>) -> Result<(), String> {
+        match value {
+            Some(value) => {
+                self.bytes.push(1);
+                self.value(value, 0)
+            }
+            None => {
+                self.bytes.push(0);
+                Ok(())
+            }
+        }
+    }
+
+    fn optional_annotation(&mut self, annotation: &Option<TypeAnnotation>) -> Result<(), String> {
+        match annotation {
+            Some(annotation) => {
+                self.bytes.push(1);
+                self.annotation(annotation)
+            }
+            None => {
+                self.bytes.push(0);
+                Ok(())
+            }
+        }
+    }
+
+    fn annotation(&mut self, annotation: &TypeAnnotation) -> Result<(), String> {
+        self.string(&annotation.name)?;
+        self.count(annotation.args.len())?;
+        annotation
+            .args
+            .iter()
+            .try_for_each(|arg| self.annotation(arg))
+    }
+
+    fn function(&mut self, function: &Function, depth: usize) -> Result<(), String> {
+        if depth > MAX_NESTING {
+            return Err(String::from("functions nested too deeply"));
+        }
+        self.string(&function.name)?;
+        self.count(function.arity)?;
+        self.optional_string(&function.doc)?;
+        self.strings(&function.params)?;
+        self.count(function.arg_types.len())?;
+        for annotation in &function.arg_types {
+            self.optional_annotation(annotation)?;
+        }
+        self.optional_annotation(&function.return_type)?;
+        self.optional_string(&function.module)?;
+        self.chunk(&function.chunk, depth)
+    }
+
+    fn chunk(&mut self, chunk: &Chunk, depth: usize) -> Result<(), String> {
+        self.string(&chunk.name)?;
+        self.data(&chunk.data)?;
+        self.count(chunk.constants.len())?;
+        for constant in &chunk.constants {
+            self.value(constant, depth)?;
+        }
+        self.count(chunk.lines.len())?;
+        for (offset, line) in &chunk.lines {
+            self.u64(*offset);
+            self.u64(*line as u64);
+        }
+        self.count(chunk.locals.len())?;
+        for (name, start, end) in &chunk.locals {
+            self.string(name)?;
+            self.u64(*start);
+            self.u64(*end);
+        }
+        Ok(())
+    }
+
+    fn value(&mut self, value: &Value, depth: usize) -> Result<(), String> {
+        match value {
+            Value::None => self.bytes.push(TAG_NONE),
+            Value::Bool(value) => {
+                self.bytes.push(TAG_BOOL);
+                self.bytes.push(*value as u8);
+            }
+            Value::Ellipsis => self.bytes.push(TAG_ELLIPSIS),
+            Value::NotImplemented => self.bytes.push(TAG_NOT_IMPLEMENTED),
+            Value::Integer(value) => {
+                self.bytes.push(TAG_INTEGER);
+                self.bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            Value::Float(value) => {
+                self.bytes.push(TAG_FLOAT);
+                self.u64(value.to_bits());
+            }
+            Value::String(value) => {
+                self.bytes.push(TAG_STRING);
+                self.string(value)?;
+            }
+            Value::Bytes(value) => {
+                self.bytes.push(TAG_BYTES);
+                self.data(value)?;
+            }
+            Value::Tuple(items) => {
+                self.bytes.push(TAG_TUPLE);
+                self.count(items.len())?;
+                for item in items.iter() {
+                    self.value(item, depth)?;
+                }
+            }
+            Value::Function(function) => {
+                self.bytes.push(TAG_FUNCTION);
+                self.function(function, depth + 1)?;
+            }
+            other => return Err(format!("cannot serialize a {}", other.type_name())),
+        }
+        Ok(())
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    // checks the header, which has to announce `payload`
+    fn new(bytes: &'a [u8], payload: u8) -> Result<Reader<'a>, String> {
+        let mut reader = Reader { bytes, offset: 0 };
+        if reader.take(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err(String::from("not a compiled rustpy file"));
+        }
+        let version = u16::from_le_bytes([reader.u8()?, reader.u8()?]);
+        if version != FORMAT_VERSION {
+            return Err(format!(
+                "compiled with format version {}, expected {}",
+                version, FORMAT_VERSION
+            ));
+        }
+        if reader.u8()? != flags() {
+            return Err(String::from("compiled on a machine of another byte order"));
+        }
+        if reader.u8()? != payload {
+            return Err(String::from("unexpected content"));
+        }
+        Ok(reader)
+    }
+
+    fn take(&mut self, size: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .offset
+            .checked_add(size)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| format!("truncated at byte {}", self.offset))?;
+        let bytes = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn end(&self) -> Result<(), String> {
+        match self.offset == self.bytes.len() {
+            true => Ok(()),
+            false => Err(format!("trailing data at byte {}", self.offset)),
+        }
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        let bytes = self.take(8)?;
+        Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn count(&mut self) -> Result<usize, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+    }
+
+    fn data(&mut self) -> Result<&'a [u8], String> {
+        let size = self.count()?;
+        self.take(size)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let offset = self.offset;
+        String::from_utf8(self.data()?.to_vec())
+            .map_err(|_| format!("invalid string at byte {}", offset))
+    }
+
+    // items are at least a byte each, so a corrupt count fails on the data
+    // rather than allocating for it
+    fn strings(&mut self) -> Result<Vec<String>, String> {
+        (0..self.count()?).map(|_| self.string()).collect()
+    }
+
+    fn flag(&mut self) -> Result<bool, String> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(format!(
+                "invalid flag {} at byte {}",
+                other,
+                self.offset - 1
+            )),
+        }
+    }
+
+    fn optional_string(&mut self) -> Result<Option<String>, String> {
+        match self.flag()? {
+            true => Ok(Some(self.string()?)),
+            false => Ok(None),
+        }
+    }
+
+    fn optional_value(&mut self) -> Result<Option<Value>, String> {
+        match self.flag()? {
+            true => Ok(Some(self.value(0)?)),
+            false => Ok(None),
+        }
+    }
+
+    fn optional_annotation(&mut self) -> Result<Option<TypeAnnotation>, String> {
+        match self.flag()? {
+            true => Ok(Some(self.annotation(0)?)),
+            false => Ok(None),
+        }
+    }
+
+    fn annotation(&mut self, depth: usize) -> Result<TypeAnnotation, String> {
+        if depth > MAX_NESTING {
+            return Err(String::from("annotations nested too deeply"));
+        }
+        let name = self.string()?;
+        let args = (0..self.count()?)
+            .map(|_| self.annotation(depth + 1))
+            .collect::<Result<_, _>>()?;
+        Ok(TypeAnnotation { name, args })
+    }
+
+    fn function(&mut self, depth: usize) -> Result<Function, String> {
+        if depth > MAX_NESTING {
+            return Err(String::from("functions nested too deeply"));
+        }
+        let mut function = Function::new(self.string()?);
+        function.arity = self.count()?;
+        function.doc = self.optional_string()?;
+        function.params = self.strings()?;
+        function.arg_types = (0..self.count()?)
+            .map(|_| self.optional_annotation())
+            .collect::<Result<_, _>>()?;
+        function.return_type = self.optional_annotation()?;
+        function.module = self.optional_string()?;
+        function.chunk = self.chunk(depth)?;
+        Ok(function)
+    }
+
+    fn chunk(&mut self, depth: usize) -> Result<Chunk, String> {
+        let mut chunk = Chunk::new();
+        chunk.name = self.string()?;
+        chunk.data = self.data()?.to_vec();
+        chunk.constants = (0..self.count()?)
+            .map(|_| self.value(depth))
+            .collect::<Result<_, _>>()?;
+        chunk.lines = (0..self.count()?)
+            .map(|_| Ok((self.u64()?, self.u64()? as usize)))
+            .collect::<Result<_, String>>()?;
+        chunk.locals = (0..self.count()?)
+            .map(|_| Ok((self.string()?, self.u64()?, self.u64()?)))
+            .collect::<Result<_, String>>()?;
+        Ok(chunk)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, String> {
+        let offset = self.offset;
+        let value = match self.u8()? {
+            TAG_NONE => Value::None,
+            TAG_BOOL => Value::Bool(self.flag()?),
+            TAG_ELLIPSIS => Value::Ellipsis,
+            TAG_NOT_IMPLEMENTED => Value::NotImplemented,
+            TAG_INTEGER => Value::Integer(self.u64()? as i64),
+            TAG_FLOAT => Value::Float(f64::from_bits(self.u64()?)),
+            TAG_STRING => Value::String(self.string()?.into()),
+            TAG_BYTES => Value::Bytes(self.data()?.to_vec().into()),
+            TAG_TUPLE => {
+                if depth > MAX_NESTING {
+                    return Err(String::from("tuples nested too deeply"));
+                }
+                let items: Vec<Value> = (0..self.count()?)
+                    .map(|_| self.value(depth + 1))
+                    .collect::<Result<_, _>>()?;
+                Value::Tuple(items.into())
+            }
+            TAG_FUNCTION => Value::Function(Rc::new(self.function(depth + 1)?)),
+            tag => return Err(format!("invalid constant tag {} at byte {}", tag, offset)),
+        };
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::Bytecode;
+
+    fn function() -> Function {
+        let mut nested = Function::new(String::from("nested"));
+        nested.arity = 1;
+        nested.params = vec![String::from("n")];
+        nested.arg_types = vec![Some(TypeAnnotation {
+            name: String::from("Optional"),
+            args: vec![TypeAnnotation {
+                name: String::from("int"),
+                args: Vec::new(),
+            }],
+        })];
+        nested.chunk.emit_with_index(Bytecode::GetLocal, 0);
+        nested.chunk.emit(Bytecode::Return);
+        nested.chunk.locals = vec![(String::from("n"), 0, nested.chunk.size())];
+
+        let mut function = Function::new_module_scope("module");
+        function.doc = Some(String::from("doc"));
+        function.chunk.set_line(3);
+        [
+            Value::Integer(-1),
+            Value::Float(0.1),
+            Value::String(String::from("text").into()),
+            Value::Bytes(vec![0, 255].into()),
+            Value::Tuple(vec![Value::None, Value::Bool(true), Value::Ellipsis].into()),
+            Value::Function(Rc::new(nested)),
+        ]
+        .into_iter()
+        .for_each(|constant| {
+            let index = function.chunk.add_constant(constant);
+            function.chunk.emit_with_index(Bytecode::Const, index);
+        });
+        function
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let function = function();
+        let bytes = function.to_bytes().unwrap();
+        assert_eq!(&bytes[..MAGIC.len()], MAGIC);
+        assert_eq!(Function::from_bytes(&bytes), Ok(function));
+
+        let payload = Payload {
+            function: Function::new_global_scope(),
+            globals: vec![
+                (3, String::from("limit"), Some(Value::Integer(10))),
+                (4, String::from("total"), None),
+            ],
+            builtins: vec![(1, String::from("print"))],
+            opt_level: 2,
+        };
+        let read = read_program(&write_program(&payload).unwrap()).unwrap();
+        assert_eq!(read.globals, payload.globals);
+        assert_eq!(read.builtins, payload.builtins);
+        assert_eq!(read.opt_level, payload.opt_level);
+        // programs and functions aren't mistaken for each other
+        assert!(read_function(&write_program(&payload).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        let bytes = function().to_bytes().unwrap();
+        let mut wrong_version = bytes.clone();
+        wrong_version[MAGIC.len()] += 1;
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        let mut list = Function::new(String::from("list"));
+        list.chunk.add_constant(Value::List(Vec::new().into()));
+        vec![
+            (b"RPY".to_vec(), "not a compiled rustpy file"),
+            (b"print(1)\n".to_vec(), "not a compiled rustpy file"),
+            (wrong_version, "compiled with format version 4, expected 3"),
+            (bytes[..bytes.len() - 1].to_vec(), "truncated at byte"),
+            (trailing, "trailing data at byte"),
+        ]
+        .into_iter()
+        .for_each(|(bytes, expected)| {
+            let err = Function::from_bytes(&bytes).unwrap_err();
+            assert!(err.starts_with(expected), "{}", err);
+        });
+        assert_eq!(
+            list.to_bytes(),
+            Err(String::from("cannot serialize a list"))
+        );
+    }
+}
@@ -1,58 +1,165 @@
 use crate::object::{Object, Value};
 use std::collections::HashMap;
 
+/// Objects by id, with an index of their names. Ids start at 1 and are
+/// never reused, so the compiled code referring to an id either finds the
+/// object it was compiled against or nothing.
 #[derive(Clone, Debug)]
 pub struct SymbolTable {
-    data: HashMap<u64, Object>,
-    last_idx: u64,
+    // slot `id` holds the object with that id, the first one is never used
+    objects: Vec<Option<Object>>,
+    names: HashMap<String, u64>,
+    // whether slot `id` was assigned: the compiler declares names before
+    // the code binding them runs
+    bound: Vec<bool>,
+    // names of the removed objects, for errors of code still using their ids
+    removed: HashMap<u64, String>,
 }
 
 impl SymbolTable {
     pub fn new() -> SymbolTable {
         SymbolTable {
-            data: HashMap::new(),
-            last_idx: 0,
+            objects: vec![None],
+            names: HashMap::new(),
+            bound: vec![false],
+            removed: HashMap::new(),
         }
     }
 
     pub fn get(&self, id: u64) -> Option<&Object> {
-        self.data.get(&id)
+        self.objects.get(id as usize)?.as_ref()
     }
 
-    pub fn get_mut(&mut self, id: u64) -> &mut Object {
-        self.data.get_mut(&id).unwrap()
+    pub fn get_mut(&mut self, id: u64) -> Option<&mut Object> {
+        self.objects.get_mut(id as usize)?.as_mut()
     }
 
     pub fn set(&mut self, id: u64, obj: Object) {
-        self.data.insert(id, obj);
+        // later inserts must not reuse an index set directly
+        if self.objects.len() <= id as usize {
+            self.objects.resize(id as usize + 1, None);
+            self.bound.resize(id as usize + 1, false);
+        }
+        self.unname(id);
+        self.names.insert(obj.name.to_string(), id);
+        self.objects[id as usize] = Some(obj);
+        self.bound[id as usize] = true;
     }
 
-    pub fn insert(&mut self, name: &str, obj: Option<Object>) -> u64 {
-        self.last_idx += 1;
-        match obj {
-            Some(mut obj) => {
-                obj.id = self.last_idx;
-                obj.name = name.to_string();
-                self.data.insert(self.last_idx, obj);
-            }
-            None => {
-                let obj = Object::new_with_id(self.last_idx, name.to_string(), Value::None);
-                self.data.insert(self.last_idx, obj);
+    /// Names `id` without binding it, as `insert` without a value does.
+    pub fn declare(&mut self, id: u64, name: &str) {
+        self.set(id, Object::new_with_id(id, name.to_string(), Value::None));
+        self.bound[id as usize] = false;
+    }
+
+    /// Binds the object with `id` to `value`, false if there is none.
+    pub fn assign(&mut self, id: u64, value: Value) -> bool {
+        match self.objects.get_mut(id as usize) {
+            Some(Some(obj)) => {
+                obj.value = value;
+                self.bound[id as usize] = true;
+                true
             }
-        };
-        self.last_idx
+            _ => false,
+        }
+    }
+
+    /// Whether the object with `id` was assigned a value, rather than only
+    /// declared.
+    pub fn is_bound(&self, id: u64) -> bool {
+        self.bound.get(id as usize).copied().unwrap_or(false)
+    }
+
+    pub fn remove(&mut self, id: u64) -> Option<Object> {
+        self.unname(id);
+        let obj = self.objects.get_mut(id as usize)?.take()?;
+        self.bound[id as usize] = false;
+        self.removed.insert(id, obj.name.to_string());
+        Some(obj)
+    }
+
+    /// Name the object with `id` had before it was removed.
+    pub fn removed_name(&self, id: u64) -> Option<&str> {
+        self.removed.get(&id).map(|name| name.as_str())
+    }
+
+    pub fn insert(&mut self, name: &str, value: Option<Value>) -> u64 {
+        let id = self.objects.len() as u64;
+        let bound = value.is_some();
+        let obj = Object::new_with_id(id, name.to_string(), value.unwrap_or(Value::None));
+        self.names.insert(obj.name.to_string(), id);
+        self.objects.push(Some(obj));
+        self.bound.push(bound);
+        id
+    }
+
+    /// The objects in id order, which is the order they were defined in.
+    pub fn iter(&self) -> impl Iterator<Item = &Object> {
+        self.objects.iter().flatten()
     }
 
     pub fn contains_name(&self, name: &str) -> bool {
-        self.data.iter().any(|(_, v)| v.name == name)
+        self.names.contains_key(name)
     }
 
     pub fn get_index(&self, name: &str) -> u64 {
-        for (idx, obj) in &self.data {
-            if obj.name == name {
-                return *idx;
+        match self.names.get(name) {
+            Some(id) => *id,
+            None => unreachable!(),
+        }
+    }
+
+    // drops the name of the object with `id` from the index
+    fn unname(&mut self, id: u64) {
+        if let Some(Some(obj)) = self.objects.get(id as usize) {
+            if self.names.get(&obj.name) == Some(&id) {
+                self.names.remove(&obj.name);
             }
         }
-        unreachable!();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symbol_table() {
+        let mut table = SymbolTable::new();
+        let first = table.insert("first", None);
+        let second = table.insert("second", Some(Value::Integer(2)));
+        assert_eq!((first, second), (1, 2));
+        assert_eq!(table.get_index("second"), second);
+        assert!(!table.is_bound(first) && table.is_bound(second));
+        assert!(table.assign(first, Value::Integer(1)));
+        assert!(table.is_bound(first));
+        assert!(!table.assign(4, Value::Integer(4)));
+
+        table.set(
+            5,
+            Object::new_with_id(5, String::from("fifth"), Value::None),
+        );
+        assert_eq!(table.insert("sixth", None), 6);
+        table.declare(8, "eighth");
+        assert!(table.contains_name("eighth") && !table.is_bound(8));
+        table.set(
+            second,
+            Object::new_with_id(second, String::from("renamed"), Value::None),
+        );
+        assert!(!table.contains_name("second"));
+        assert_eq!(table.get_index("renamed"), second);
+
+        assert!(table.remove(first).is_some());
+        assert!(table.remove(first).is_none());
+        assert_eq!(table.removed_name(first), Some("first"));
+        assert_eq!(table.removed_name(second), None);
+        assert!(!table.contains_name("first"));
+        assert_eq!(table.get(3), None);
+        assert_eq!(
+            table.iter().map(|obj| obj.id).collect::<Vec<_>>(),
+            vec![2, 5, 6, 8]
+        );
+        // ids of removed objects aren't handed out again
+        assert_eq!(table.insert("first", None), 9);
     }
 }
@@ -0,0 +1,121 @@
+use crate::config::Config;
+use crate::module::{Module, BUILTIN_MODULE_PATH};
+use crate::native::{
+    check_arity, write_stderr, write_stdout, NativeContext, NativeFunction, NativeResult, VARIADIC,
+};
+use crate::object::Value;
+use crate::vm::VmError;
+
+pub const MODULE_NAME: &str = "sys";
+pub const IMPLEMENTATION_NAME: &str = "rustpy";
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Module names of the `sys.stdout` and `sys.stderr` streams.
+pub const STDOUT: &str = "sys.stdout";
+pub const STDERR: &str = "sys.stderr";
+
+/// Attributes of the built-in `sys` module. `implementation` and the
+/// streams are modules of their own so their fields resolve like any other
+/// attribute.
+pub fn attributes(config: &Config) -> Vec<(String, Value)> {
+    let implementation = Module::new(
+        &format!("{}.implementation", MODULE_NAME),
+        BUILTIN_MODULE_PATH,
+    );
+    vec![
+        (
+            String::from("version"),
+            Value::String(format!("{} ({})", VERSION, IMPLEMENTATION_NAME).into()),
+        ),
+        (
+            String::from("implementation"),
+            Value::Module(implementation.into()),
+        ),
+        (
+            String::from("implementation.name"),
+            Value::String(IMPLEMENTATION_NAME.to_string().into()),
+        ),
+        // [major, minor, patch] until there are tuples
+        (
+            String::from("implementation.version"),
+            Value::List(
+                VERSION
+                    .split('.')
+                    .map(|part| Value::Integer(part.parse().unwrap_or(0)))
+                    .collect::<Vec<_>>()
+                    .into(),
+            ),
+        ),
+        (
+            String::from("argv"),
+            Value::List(
+                config
+                    .argv
+                    .iter()
+                    .map(|arg| Value::String(arg.clone().into()))
+                    .collect::<Vec<_>>()
+                    .into(),
+            ),
+        ),
+        (
+            String::from("exit"),
+            Value::NativeFunction(NativeFunction::new("exit", VARIADIC, exit).into()),
+        ),
+        (
+            String::from("stdout"),
+            Value::Module(Module::new(STDOUT, BUILTIN_MODULE_PATH).into()),
+        ),
+        (
+            String::from("stdout.write"),
+            Value::NativeFunction(
+                (NativeFunction::new("write", 1, |context, args| {
+                    let text = stream_text(&args)?;
+                    write_stdout(context, &text)?;
+                    Ok(Value::Integer(text.chars().count() as i64))
+                }))
+                .into(),
+            ),
+        ),
+        (
+            String::from("stderr"),
+            Value::Module(Module::new(STDERR, BUILTIN_MODULE_PATH).into()),
+        ),
+        (
+            String::from("stderr.write"),
+            Value::NativeFunction(
+                (NativeFunction::new("write", 1, |context, args| {
+                    let text = stream_text(&args)?;
+                    write_stderr(context, &text)?;
+                    Ok(Value::Integer(text.chars().count() as i64))
+                }))
+                .into(),
+            ),
+        ),
+    ]
+}
+
+fn stream_text(args: &[Value]) -> Result<String, VmError> {
+    match &args[0] {
+        Value::String(text) => Ok(text.to_string()),
+        other => Err(VmError::InvalidOperand(format!(
+            "TypeError: write() argument must be str, not {}",
+            other.type_name()
+        ))),
+    }
+}
+
+// `exit([status])`: None means success, other non-integers are written to
+// stderr and exit with 1
+fn exit(context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    check_arity("exit", &args, 0, 1)?;
+    let status = match args.first() {
+        None | Some(Value::None) => 0,
+        Some(Value::Integer(status)) => (*status).clamp(i32::MIN as i64, i32::MAX as i64) as i32,
+        Some(Value::Bool(value)) => *value as i32,
+        Some(other) => {
+            write_stderr(context, &format!("{}\n", other))?;
+            1
+        }
+    };
+    Err(VmError::SystemExit(status))
+}
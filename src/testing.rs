@@ -0,0 +1,46 @@
+use crate::exception::Exception;
+use crate::native::{NativeContext, NativeFunction, NativeResult};
+use crate::object::Value;
+use crate::vm::VmError;
+
+pub const MODULE_NAME: &str = "testing";
+const ASSERTION_ERROR: &str = "AssertionError";
+
+/// Attributes of the built-in `testing` module, structural comparisons for
+/// test frameworks written in Python.
+pub fn attributes() -> Vec<(String, Value)> {
+    vec![
+        NativeFunction::new("diff", 2, diff),
+        NativeFunction::new("assert_equal", 2, assert_equal),
+    ]
+    .into_iter()
+    .map(|function| {
+        (
+            function.name.to_string(),
+            Value::NativeFunction(function.into()),
+        )
+    })
+    .collect()
+}
+
+// the first mismatch between `expected` and `actual`, or None when equal
+fn diff(_context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    let diff = args[0].diff(&args[1]);
+    if diff.is_equal() {
+        Ok(Value::None)
+    } else {
+        Ok(Value::String(diff.to_string().into()))
+    }
+}
+
+fn assert_equal(_context: &mut NativeContext, args: Vec<Value>) -> NativeResult {
+    let diff = args[0].diff(&args[1]);
+    if diff.is_equal() {
+        Ok(Value::None)
+    } else {
+        Err(VmError::Exception(Exception::new(
+            ASSERTION_ERROR,
+            vec![Value::String(diff.to_string().into())],
+        )))
+    }
+}
@@ -11,6 +11,8 @@ pub enum Token {
     Minus,
     Star,
     Slash,
+    SlashSlash,
+    Percent,
     Bang,
     BangEqual,
     Equal,
@@ -20,8 +22,11 @@ pub enum Token {
     Greater,
     GreaterEqual,
     Colon,
+    Semicolon,
+    Arrow,
     Comma,
     Dot,
+    Ellipsis,
     LeftParen,
     RightParen,
     LeftBracket,
@@ -40,10 +45,18 @@ pub enum Token {
     Continue,
     Break,
     Return,
+    Import,
+    Try,
+    Except,
+    Raise,
+    Assert,
+    From,
+    As,
     Integer(i64),
     Float(f64),
     Identifier(String),
     String(String),
+    Bytes(Vec<u8>),
     Eof,
     Error(String),
 }
@@ -0,0 +1,592 @@
+use std::collections::HashMap;
+
+use crate::ast::{
+    AnnotationExpression, BinaryExpression, CallExpression, Expression, FunctionExpression,
+    IfExpression, Literal, Node, Operator, Program, TryExpression, TypeAnnotation,
+};
+use crate::diagnostic::{Diagnostic, Span};
+use crate::function::annotation_accepts;
+use crate::object::Value;
+
+// What the checker knows about an expression, Unknown is never reported
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Type {
+    None,
+    Bool,
+    Int,
+    Float,
+    Str,
+    Bytes,
+    Unknown,
+}
+
+struct Signature {
+    arity: usize,
+    arg_types: Vec<Option<TypeAnnotation>>,
+    return_type: Option<TypeAnnotation>,
+}
+
+type Scope = HashMap<String, Type>;
+
+/// Gradual type checker: infers types from literals and annotations and
+/// reports operations that would fail at runtime, e.g. `1 + "a"` or calls
+/// with the wrong number of arguments. Anything it can't infer is accepted.
+pub(crate) struct TypeChecker {
+    signatures: HashMap<String, Signature>,
+    // innermost last, the module scope first
+    scopes: Vec<Scope>,
+    return_types: Vec<Option<TypeAnnotation>>,
+    diagnostics: Vec<Diagnostic>,
+    // source of the expression being checked, where errors point
+    span: Span,
+}
+
+impl Type {
+    fn name(&self) -> &'static str {
+        match self {
+            Type::None => "NoneType",
+            Type::Bool => "bool",
+            Type::Int => "int",
+            Type::Float => "float",
+            Type::Str => "str",
+            Type::Bytes => "bytes",
+            Type::Unknown => "object",
+        }
+    }
+
+    // a runtime value of this type, to share the runtime annotation rules
+    fn sample(&self) -> Option<Value> {
+        match self {
+            Type::None => Some(Value::None),
+            Type::Bool => Some(Value::Bool(true)),
+            Type::Int => Some(Value::Integer(0)),
+            Type::Float => Some(Value::Float(0.0)),
+            Type::Str => Some(Value::String(String::new().into())),
+            Type::Bytes => Some(Value::Bytes(Vec::new().into())),
+            Type::Unknown => None,
+        }
+    }
+
+    fn from_annotation(annotation: &Option<TypeAnnotation>) -> Type {
+        match annotation
+            .as_ref()
+            .map(|annotation| annotation.name.as_str())
+        {
+            Some("None") => Type::None,
+            Some("bool") => Type::Bool,
+            Some("int") => Type::Int,
+            Some("float") => Type::Float,
+            Some("str") => Type::Str,
+            Some("bytes") => Type::Bytes,
+            _ => Type::Unknown,
+        }
+    }
+
+    fn is_numeric(&self) -> bool {
+        matches!(self, Type::Bool | Type::Int | Type::Float)
+    }
+
+    // types differing between branches are no longer known
+    fn join(self, other: Type) -> Type {
+        if self == other {
+            self
+        } else {
+            Type::Unknown
+        }
+    }
+}
+
+impl TypeChecker {
+    pub(crate) fn new() -> TypeChecker {
+        TypeChecker {
+            signatures: HashMap::new(),
+            scopes: vec![Scope::new()],
+            return_types: Vec::new(),
+            diagnostics: Vec::new(),
+            span: Span::default(),
+        }
+    }
+
+    pub(crate) fn check(mut self, program: &Program) -> Vec<Diagnostic> {
+        let mut redefined: Vec<String> = Vec::new();
+        program
+            .stmts
+            .iter()
+            .for_each(|stmt| self.collect_signatures(stmt, &mut redefined));
+        // a name defined twice can refer to either definition
+        redefined.iter().for_each(|name| {
+            self.signatures.remove(name);
+        });
+
+        program.stmts.iter().for_each(|stmt| {
+            self.check_expression(stmt);
+        });
+        self.diagnostics
+    }
+
+    fn collect_signatures(&mut self, expr: &Node, redefined: &mut Vec<String>) {
+        match &**expr {
+            Expression::Function(function) => {
+                let signature = Signature {
+                    arity: function.args.len(),
+                    arg_types: function.arg_types.clone(),
+                    return_type: function.return_type.clone(),
+                };
+                if self
+                    .signatures
+                    .insert(function.name.to_string(), signature)
+                    .is_some()
+                {
+                    redefined.push(function.name.to_string());
+                }
+                function
+                    .body
+                    .exprs
+                    .iter()
+                    .for_each(|expr| self.collect_signatures(expr, redefined));
+            }
+            Expression::Block(block) => block
+                .exprs
+                .iter()
+                .for_each(|expr| self.collect_signatures(expr, redefined)),
+            Expression::If(if_expr) => {
+                self.collect_signatures(&if_expr.then_branch, redefined);
+                if_expr
+                    .elif_branches
+                    .iter()
+                    .for_each(|elif| self.collect_signatures(&elif.then_branch, redefined));
+                self.collect_signatures(&if_expr.else_branch, redefined);
+            }
+            Expression::While(while_expr) => {
+                self.collect_signatures(&while_expr.body, redefined);
+                self.collect_signatures(&while_expr.else_branch, redefined);
+            }
+            Expression::Try(try_expr) => {
+                self.collect_signatures(&try_expr.body, redefined);
+                try_expr
+                    .handlers
+                    .iter()
+                    .for_each(|handler| self.collect_signatures(&handler.body, redefined));
+                self.collect_signatures(&try_expr.else_branch, redefined);
+            }
+            _ => (),
+        }
+    }
+
+    fn error(&mut self, message: String) {
+        self.diagnostics
+            .push(Diagnostic::error("TypeError", &message).with_span(self.span));
+    }
+
+    fn lookup(&self, name: &str) -> Option<Type> {
+        self.scopes
+            .last()
+            .and_then(|scope| scope.get(name))
+            .copied()
+    }
+
+    fn assign(&mut self, name: &str, value_type: Type) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), value_type);
+        }
+    }
+
+    fn check_expression(&mut self, expr: &Node) -> Type {
+        let enclosing_span = std::mem::replace(&mut self.span, expr.span);
+        let expr_type = self.infer_type(expr);
+        self.span = enclosing_span;
+        expr_type
+    }
+
+    fn infer_type(&mut self, expr: &Expression) -> Type {
+        match expr {
+            Expression::Empty | Expression::Continue | Expression::Break => Type::None,
+            Expression::Literal(literal) => match literal {
+                Literal::None => Type::None,
+                Literal::True | Literal::False => Type::Bool,
+                Literal::Ellipsis => Type::Unknown,
+                Literal::Integer(_) => Type::Int,
+                Literal::Float(_) => Type::Float,
+                Literal::String(_) => Type::Str,
+                Literal::Bytes(_) => Type::Bytes,
+            },
+            Expression::Variable(name) => self.lookup(name).unwrap_or(Type::Unknown),
+            Expression::Block(block) => {
+                let mut last = Type::None;
+                for expr in &block.exprs {
+                    last = self.check_expression(expr);
+                }
+                last
+            }
+            Expression::Function(function) => {
+                self.check_function(function);
+                Type::None
+            }
+            Expression::Call(call) => self.check_call(call),
+            Expression::If(if_expr) => {
+                self.check_if(if_expr);
+                Type::None
+            }
+            Expression::While(while_expr) => {
+                self.check_expression(&while_expr.condition);
+                // the body may run or not before the else branch, which a
+                // break may skip as well
+                self.check_branches(&[&while_expr.body, &while_expr.else_branch]);
+                Type::None
+            }
+            Expression::Try(try_expr) => {
+                self.check_try(try_expr);
+                Type::None
+            }
+            Expression::Raise(raise_expr) => {
+                self.check_expression(&raise_expr.exception);
+                self.check_expression(&raise_expr.cause);
+                Type::None
+            }
+            Expression::Assert(assert_expr) => {
+                self.check_expression(&assert_expr.test);
+                self.check_expression(&assert_expr.message);
+                Type::None
+            }
+            Expression::Return(return_expr) => {
+                let value_type = self.check_expression(&return_expr.expr);
+                if let Some(Some(annotation)) = self.return_types.last().cloned() {
+                    self.check_annotation(&annotation, value_type, "return value");
+                }
+                value_type
+            }
+            Expression::Import(import) => {
+                self.assign(&import.name, Type::Unknown);
+                Type::Unknown
+            }
+            Expression::Attribute(attribute) => {
+                self.check_expression(&attribute.object);
+                Type::Unknown
+            }
+            Expression::Assignment(assignment) => {
+                let value_type = self.check_expression(&assignment.rhs);
+                if let Expression::Variable(name) = &*assignment.lhs {
+                    self.assign(name, value_type);
+                }
+                value_type
+            }
+            Expression::Annotation(annotation) => self.check_annotated_variable(annotation),
+            Expression::Unary(unary) => {
+                let value_type = self.check_expression(&unary.expr);
+                match unary.op {
+                    Operator::Neg if matches!(value_type, Type::Str | Type::Bytes | Type::None) => {
+                        self.error(format!(
+                            "bad operand type for unary -: '{}'",
+                            value_type.name()
+                        ));
+                        Type::Unknown
+                    }
+                    Operator::Neg if value_type == Type::Bool => Type::Int,
+                    Operator::Neg => value_type,
+                    _ => Type::Bool,
+                }
+            }
+            Expression::Binary(binary) => self.check_binary(binary),
+            Expression::Index(index) => {
+                let object_type = self.check_expression(&index.object);
+                self.check_expression(&index.index);
+                match object_type {
+                    Type::Str => Type::Str,
+                    Type::Bytes => Type::Int,
+                    Type::Unknown => Type::Unknown,
+                    other => {
+                        self.error(format!("'{}' object is not subscriptable", other.name()));
+                        Type::Unknown
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_function(&mut self, function: &FunctionExpression) {
+        let mut scope = Scope::new();
+        for (index, arg) in function.args.iter().enumerate() {
+            let arg_type = function.arg_types.get(index).cloned().flatten();
+            scope.insert(arg.to_string(), Type::from_annotation(&arg_type));
+        }
+        self.scopes.push(scope);
+        self.return_types.push(function.return_type.clone());
+        function.body.exprs.iter().for_each(|expr| {
+            self.check_expression(expr);
+        });
+        self.return_types.pop();
+        self.scopes.pop();
+    }
+
+    fn check_call(&mut self, call: &CallExpression) -> Type {
+        let arg_types: Vec<Type> = call
+            .args
+            .iter()
+            .map(|arg| self.check_expression(arg))
+            .collect();
+        call.kwargs.iter().for_each(|(_, value)| {
+            self.check_expression(value);
+        });
+        let name = match &*call.callable {
+            // variables shadow the function of the same name
+            Expression::Variable(name) if self.lookup(name).is_none() => name,
+            _ => {
+                self.check_expression(&call.callable);
+                return Type::Unknown;
+            }
+        };
+        let signature = match self.signatures.get(name) {
+            Some(signature) => signature,
+            None => return Type::Unknown,
+        };
+        let return_type = Type::from_annotation(&signature.return_type);
+        if signature.arity != arg_types.len() {
+            let message = format!(
+                "{}() takes {} positional arguments but {} were given",
+                name,
+                signature.arity,
+                arg_types.len()
+            );
+            self.error(message);
+            return return_type;
+        }
+        let expected: Vec<Option<TypeAnnotation>> = signature.arg_types.clone();
+        for (index, arg_type) in arg_types.into_iter().enumerate() {
+            if let Some(Some(annotation)) = expected.get(index) {
+                self.check_annotation(
+                    annotation,
+                    arg_type,
+                    &format!("argument {} of {}()", index + 1, name),
+                );
+            }
+        }
+        return_type
+    }
+
+    fn check_if(&mut self, if_expr: &IfExpression) {
+        self.check_expression(&if_expr.condition);
+        if_expr.elif_branches.iter().for_each(|elif| {
+            self.check_expression(&elif.condition);
+        });
+        let mut branches = vec![&if_expr.then_branch];
+        branches.extend(if_expr.elif_branches.iter().map(|elif| &elif.then_branch));
+        branches.push(&if_expr.else_branch);
+        self.check_branches(&branches);
+    }
+
+    fn check_try(&mut self, try_expr: &TryExpression) {
+        try_expr.handlers.iter().for_each(|handler| {
+            self.check_expression(&handler.exception_type);
+        });
+        // the body may stop anywhere for an except clause to run instead, so
+        // the else branch is just as uncertain
+        let mut branches = vec![&try_expr.body, &try_expr.else_branch];
+        branches.extend(try_expr.handlers.iter().map(|handler| &handler.body));
+        self.check_branches(&branches);
+        try_expr
+            .handlers
+            .iter()
+            .filter_map(|handler| handler.name.as_ref())
+            .for_each(|name| self.assign(name, Type::Unknown));
+    }
+
+    // checks each branch from the same starting types, then keeps the types
+    // all of them agree on
+    fn check_branches(&mut self, branches: &[&Node]) {
+        let before = match self.scopes.last() {
+            Some(scope) => scope.clone(),
+            None => return,
+        };
+        let mut merged = before.clone();
+        for branch in branches {
+            if let Some(scope) = self.scopes.last_mut() {
+                *scope = before.clone();
+            }
+            self.check_expression(branch);
+            if let Some(scope) = self.scopes.last() {
+                for (name, branch_type) in scope {
+                    let merged_type = match merged.get(name) {
+                        Some(merged_type) => merged_type.join(*branch_type),
+                        None => Type::Unknown,
+                    };
+                    merged.insert(name.to_string(), merged_type);
+                }
+            }
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            *scope = merged;
+        }
+    }
+
+    fn check_annotated_variable(&mut self, annotation: &AnnotationExpression) -> Type {
+        if let Some(value) = &annotation.value {
+            let value_type = self.check_expression(value);
+            self.check_annotation(
+                &annotation.annotation,
+                value_type,
+                &format!("variable '{}'", annotation.name),
+            );
+        }
+        let declared = Type::from_annotation(&Some(annotation.annotation.clone()));
+        self.assign(&annotation.name, declared);
+        Type::None
+    }
+
+    fn check_annotation(&mut self, annotation: &TypeAnnotation, actual: Type, target: &str) {
+        let accepted = actual
+            .sample()
+            .and_then(|sample| annotation_accepts(annotation, &sample));
+        if accepted == Some(false) {
+            self.error(format!(
+                "{} expected {}, got {}",
+                target,
+                annotation,
+                actual.name()
+            ));
+        }
+    }
+
+    fn check_binary(&mut self, binary: &BinaryExpression) -> Type {
+        let lhs = self.check_expression(&binary.lhs);
+        let rhs = self.check_expression(&binary.rhs);
+        let symbol = match binary.op {
+            Operator::Add => "+",
+            Operator::Sub => "-",
+            Operator::Mul => "*",
+            Operator::Div => "/",
+            Operator::FloorDiv => "//",
+            Operator::Mod => "%",
+            Operator::Less | Operator::LessEqual | Operator::Greater | Operator::GreaterEqual => {
+                if (lhs.is_numeric() && rhs == Type::Str) || (lhs == Type::Str && rhs.is_numeric())
+                {
+                    self.error(format!(
+                        "'<' not supported between instances of '{}' and '{}'",
+                        lhs.name(),
+                        rhs.name()
+                    ));
+                }
+                return Type::Bool;
+            }
+            Operator::Equal | Operator::NotEqual => return Type::Bool,
+            _ => return lhs.join(rhs),
+        };
+        if lhs == Type::Unknown || rhs == Type::Unknown {
+            return Type::Unknown;
+        }
+        let result = match (binary.op.clone(), lhs, rhs) {
+            (Operator::Div, lhs, rhs) if lhs.is_numeric() && rhs.is_numeric() => Some(Type::Float),
+            (_, Type::Float, rhs) if rhs.is_numeric() => Some(Type::Float),
+            (_, lhs, Type::Float) if lhs.is_numeric() => Some(Type::Float),
+            (_, lhs, rhs) if lhs.is_numeric() && rhs.is_numeric() => Some(Type::Int),
+            (Operator::Add, Type::Str, Type::Str) => Some(Type::Str),
+            (Operator::Add, Type::Bytes, Type::Bytes) => Some(Type::Bytes),
+            (Operator::Mul, Type::Str, Type::Int | Type::Bool) => Some(Type::Str),
+            (Operator::Mul, Type::Int | Type::Bool, Type::Str) => Some(Type::Str),
+            (Operator::Mul, Type::Bytes, Type::Int | Type::Bool) => Some(Type::Bytes),
+            (Operator::Mul, Type::Int | Type::Bool, Type::Bytes) => Some(Type::Bytes),
+            // printf-style formatting accepts any value
+            (Operator::Mod, Type::Str, _) => Some(Type::Str),
+            _ => None,
+        };
+        match result {
+            Some(result) => result,
+            None => {
+                self.error(format!(
+                    "unsupported operand type(s) for {}: '{}' and '{}'",
+                    symbol,
+                    lhs.name(),
+                    rhs.name()
+                ));
+                Type::Unknown
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn check_source(source: &str) -> Vec<String> {
+        let mut parser = Parser::new(Lexer::new(source).collect());
+        let program = parser.parse().unwrap();
+        TypeChecker::new()
+            .check(&program)
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect()
+    }
+
+    #[test]
+    fn test_reported_errors() {
+        vec![
+            (
+                "1 + \"a\"",
+                "unsupported operand type(s) for +: 'int' and 'str'",
+            ),
+            (
+                "x = \"a\"\nx - 1",
+                "unsupported operand type(s) for -: 'str' and 'int'",
+            ),
+            ("-\"a\"", "bad operand type for unary -: 'str'"),
+            (
+                "def f(a, b):\n    return a\nf(1)",
+                "f() takes 2 positional arguments but 1 were given",
+            ),
+            (
+                "def f(n: int):\n    return n\nf(\"a\")",
+                "argument 1 of f() expected int, got str",
+            ),
+            (
+                "def f(n) -> str:\n    return 1",
+                "return value expected str, got int",
+            ),
+            ("x: int = \"a\"", "variable 'x' expected int, got str"),
+            (
+                "def f(n: str):\n    return n + 1",
+                "unsupported operand type(s) for +: 'str' and 'int'",
+            ),
+        ]
+        .into_iter()
+        .for_each(|(source, expected)| {
+            assert_eq!(
+                check_source(source),
+                vec![String::from(expected)],
+                "{}",
+                source
+            );
+        });
+    }
+
+    #[test]
+    fn test_accepted_programs() {
+        vec![
+            "1 + 2.5",
+            "\"a\" + \"b\"",
+            "\"%d\" % 1",
+            "3 * b\"a\"",
+            "x = 1\nx = \"a\"\nx + \"b\"",
+            // the branches disagree on x, so it's unknown afterwards
+            "x = 1\nif True:\n    x = \"a\"\nx + 1",
+            "def f(n: float):\n    return n\nf(1)",
+            "def f(a):\n    return a\nf = abs\nf(1, 2)",
+            "def f(a):\n    return a + 1\nf(\"a\")",
+            "y + 1",
+        ]
+        .into_iter()
+        .for_each(|source| {
+            assert_eq!(check_source(source), Vec::<String>::new(), "{}", source);
+        });
+    }
+
+    #[test]
+    fn test_diagnostic_spans() {
+        let (tokens, spans) = Lexer::new("x = 1\nprint(x + \"a\")").tokenize();
+        let program = Parser::new_with_spans(tokens, spans).parse().unwrap();
+        let diagnostics = TypeChecker::new().check(&program);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].span, Some(Span::new(12, 19)));
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::HashSet;
+
+use crate::bytecode::Bytecode;
+use crate::chunk::{Chunk, Decoded};
+use crate::function::Function;
+use crate::object::Value;
+use crate::program::{operands, CompiledProgram, Target};
+
+/// Checks that the bytecode of every function in `program` decodes, jumps
+/// to instructions of its own chunk and only refers to constants, globals
+/// and builtins the program holds, so bytecode from a file can't send the
+/// VM out of bounds.
+pub(crate) fn verify(program: &CompiledProgram) -> Result<(), String> {
+    let builtins: HashSet<u64> = program.builtins.iter().map(|(index, _)| *index).collect();
+    program.functions().try_for_each(|function| {
+        verify_function(function, program, &builtins)
+            .map_err(|err| format!("{}: {}", function.name, err))
+    })
+}
+
+fn verify_function(
+    function: &Function,
+    program: &CompiledProgram,
+    builtins: &HashSet<u64>,
+) -> Result<(), String> {
+    let chunk = &function.chunk;
+    if function.params.len() != function.arity {
+        return Err(format!(
+            "{} parameters for an arity of {}",
+            function.params.len(),
+            function.arity
+        ));
+    }
+
+    let mut starts = HashSet::new();
+    let mut jumps = Vec::new();
+    let mut ip = 0;
+    while ip < chunk.data.len() {
+        let instruction = chunk
+            .decode(ip)
+            .filter(|instruction| instruction.op != Bytecode::Unknown)
+            .ok_or_else(|| format!("invalid opcode at byte {}", ip))?;
+        if instruction.next_addr() > chunk.data.len() {
+            return Err(format!("truncated operand at byte {}", ip));
+        }
+        if let Some(target) = jump_target(chunk, ip, &instruction) {
+            jumps.push((ip, target));
+        }
+        starts.insert(ip);
+        ip = instruction.next_addr();
+    }
+    // jumping to the end returns like running off it does
+    starts.insert(chunk.data.len());
+    if let Some((ip, _)) = jumps
+        .iter()
+        .find(|(_, target)| !target.is_some_and(|target| starts.contains(&target)))
+    {
+        return Err(format!("jump at byte {} misses every instruction", ip));
+    }
+
+    for operand in operands(chunk) {
+        let Some(index) = chunk.read_operand(operand.addr, operand.size) else {
+            continue;
+        };
+        let valid = match operand.target {
+            Target::Global => program.globals.get(index).is_some(),
+            Target::Builtin => builtins.contains(&index),
+            Target::Constant => constant_fits(chunk, operand.addr, index),
+            Target::Other => true,
+        };
+        if !valid {
+            return Err(format!("invalid index {} at byte {}", index, operand.addr));
+        }
+    }
+    Ok(())
+}
+
+// the constant exists and has the type the instruction using it expects
+fn constant_fits(chunk: &Chunk, operand_addr: usize, index: u64) -> bool {
+    let Some(constant) = chunk.constants.get(index as usize) else {
+        return false;
+    };
+    // prefixes sit before the opcode, so it's the byte before the operand
+    let op = chunk
+        .data
+        .get(operand_addr.wrapping_sub(1))
+        .and_then(|byte| Bytecode::try_from(*byte).ok());
+    match op {
+        Some(Bytecode::MakeFunction) => matches!(constant, Value::Function(_)),
+        Some(Bytecode::GetAttr | Bytecode::Import) => matches!(constant, Value::String(_)),
+        _ => true,
+    }
+}
+
+// where the jump at `addr` lands, Some(None) when it lands outside memory
+fn jump_target(chunk: &Chunk, addr: usize, instruction: &Decoded) -> Option<Option<usize>> {
+    let offset = match instruction.op {
+        Bytecode::Jump
+        | Bytecode::Loop
+        | Bytecode::JumpIfFalse
+        | Bytecode::ConstLessJumpIfFalse
+        | Bytecode::SetupTry => chunk.get_jump_offset_safe(instruction.operand_addr),
+        Bytecode::JumpLong => chunk
+            .read_operand(instruction.operand_addr, instruction.operand_size)
+            .map(|offset| offset as i64),
+        _ => return None,
+    };
+    Some(offset.and_then(|offset| addr.checked_add_signed(offset as isize)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DEFAULT_OPT_LEVEL;
+    use crate::symbol_table::SymbolTable;
+    use std::rc::Rc;
+
+    type Emitter = fn(&mut Chunk);
+
+    fn program(emit: Emitter) -> CompiledProgram {
+        let mut globals = SymbolTable::new();
+        globals.insert("limit", Some(Value::Integer(3)));
+        let mut main = Function::new_global_scope();
+        emit(&mut main.chunk);
+        let mut program = CompiledProgram::new(main, globals, DEFAULT_OPT_LEVEL);
+        program.builtins = vec![(1, String::from("print"))];
+        program
+    }
+
+    #[test]
+    fn test_verify() {
+        vec![
+            (
+                (|chunk| {
+                    chunk.emit_with_index(Bytecode::GetGlobal, 1);
+                    chunk.emit_with_index(Bytecode::GetBuiltin, 1);
+                    let name = chunk.add_constant(Value::String(String::from("upper").into()));
+                    chunk.emit_with_index(Bytecode::GetAttr, name);
+                    let function = chunk
+                        .add_constant(Value::Function(Rc::new(Function::new(String::from("f")))));
+                    chunk.emit_with_index(Bytecode::MakeFunction, function);
+                    let operand = chunk.emit_jump(Bytecode::JumpIfFalse);
+                    chunk.emit(Bytecode::Pop);
+                    chunk.patch_jump(operand, chunk.size()).unwrap();
+                }) as Emitter,
+                None,
+            ),
+            (
+                |chunk| chunk.data.push(Bytecode::Unknown as u8),
+                Some("invalid opcode at byte 0"),
+            ),
+            (
+                |chunk| chunk.data.push(0xEE),
+                Some("invalid opcode at byte 0"),
+            ),
+            (
+                |chunk| chunk.emit(Bytecode::Const),
+                Some("truncated operand at byte 0"),
+            ),
+            (
+                |chunk| {
+                    let operand = chunk.emit_jump(Bytecode::Jump);
+                    chunk.emit(Bytecode::Pop);
+                    chunk.patch_jump(operand, 1).unwrap();
+                },
+                Some("jump at byte 0 misses every instruction"),
+            ),
+            (
+                |chunk| {
+                    chunk.emit(Bytecode::JumpLong);
+                    chunk.data.extend_from_slice(&u64::MAX.to_ne_bytes());
+                },
+                Some("jump at byte 0 misses every instruction"),
+            ),
+            (
+                |chunk| chunk.emit_with_index(Bytecode::Const, 0),
+                Some("invalid index 0 at byte 1"),
+            ),
+            (
+                |chunk| chunk.emit_with_index(Bytecode::GetGlobal, 2),
+                Some("invalid index 2 at byte 1"),
+            ),
+            (
+                |chunk| chunk.emit_with_index(Bytecode::GetBuiltin, 2),
+                Some("invalid index 2 at byte 1"),
+            ),
+            (
+                |chunk| {
+                    let constant = chunk.add_constant(Value::Integer(1));
+                    chunk.emit_with_index(Bytecode::MakeFunction, constant);
+                },
+                Some("invalid index 0 at byte 1"),
+            ),
+        ]
+        .into_iter()
+        .for_each(|(emit, expected)| {
+            let result = verify(&program(emit));
+            assert_eq!(
+                result,
+                expected.map_or(Ok(()), |err| Err(format!("<main>: {}", err)))
+            );
+        });
+    }
+
+    #[test]
+    fn test_verify_nested() {
+        let mut nested = Function::new(String::from("nested"));
+        nested.arity = 1;
+        nested.chunk.emit_with_index(Bytecode::GetLocal, 0);
+        let program = program(|_| ());
+        let mut main = Function::new_global_scope();
+        main.chunk.add_constant(Value::Function(Rc::new(nested)));
+        let program = CompiledProgram { main, ..program };
+        assert_eq!(
+            verify(&program),
+            Err(String::from("nested: 0 parameters for an arity of 1"))
+        );
+    }
+}
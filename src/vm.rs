@@ -1,10 +1,30 @@
 use log::trace;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::rc::Rc;
 
-use crate::bytecode::{Bytecode, SIZE_INDEX, SIZE_INSTRUCTION};
-use crate::chunk::Chunk;
+use crate::bytecode::{Bytecode, SIZE_JUMP, SIZE_NARROW_INDEX};
+use crate::chunk::{Chunk, Decoded};
 use crate::config::Config;
-use crate::function::Function;
+use crate::exception::{exception_matches, exception_of, Exception};
+use crate::format::percent_format;
+use crate::function::{annotation_accepts, Function};
+use crate::functools::CachedFunction;
+use crate::gc::Heap;
+use crate::hashlib::{hash_attribute, hash_method};
+#[cfg(feature = "jit")]
+use crate::jit::{self, Jit};
+use crate::methods::{bytes_method, file_method, string_method};
+use crate::module::{load_builtin_module, load_module, Module, DOC_GLOBAL};
+use crate::native::{compare, NativeContext, NativeFunction, NativeResult, OutputStats};
 use crate::object::{Object, Value};
+use crate::policy::{Action, Decision, Policy, PolicyRequest};
+use crate::profile::{CallSite, Profile};
+use crate::random::Random;
+use crate::re::{match_attribute, match_method, pattern_attribute, pattern_method};
+use crate::register::{Instruction, Operand, RegisterCode};
+use crate::replay::{next_result, ExecutionLog, Replay};
 use crate::symbol_table::SymbolTable;
 
 #[derive(Clone, Debug)]
@@ -13,46 +33,113 @@ pub enum VmError {
     InvalidOperand(String),
     UndefinedName(String),
     WrongArgumentCount(String),
+    ResourceLimitExceeded(String),
+    AttributeError(String),
+    ImportError(String),
+    ValueError(String),
+    IndexError(String),
+    ZeroDivisionError(String),
+    OsError(String),
+    ReplayDiverged(String),
+    PermissionError(String),
+    // `sys.exit()` ending the run with this status
+    SystemExit(i32),
+    // raised by a `raise` statement
+    Exception(Exception),
 }
 
 pub struct Frame {
-    function: Function,
+    // shared with the callable: constants are runtime values and global
+    // operands are symbol table slots since compilation, so pushing a frame
+    // copies no code
+    function: Rc<Function>,
     stack_size: usize,
     ip: usize,
+    // operand of the instruction at `ip`, set by `set_instruction`
+    operand_addr: usize,
+    operand_size: usize,
+    // set while executing the top level of an imported module
+    module: Option<Module>,
 }
 
 impl Frame {
-    fn get_chunk(&self) -> &Chunk {
-        &self.function.chunk
+    fn new(function: Rc<Function>, stack_size: usize) -> Frame {
+        Frame {
+            function,
+            stack_size,
+            ip: 0,
+            operand_addr: 0,
+            operand_size: 0,
+            module: None,
+        }
     }
 
-    fn get_opcode(&self) -> Result<Bytecode, VmError> {
-        let op = self.function.chunk.data[self.ip];
-        let op = match Bytecode::try_from(op) {
-            Ok(op) => op,
-            Err(_) => {
-                return Err(VmError::InvalidBytecode(format!(
-                    "Invalid bytecode: {}",
-                    op
-                )))
-            }
-        };
-        Ok(op)
+    fn new_module(function: Rc<Function>, stack_size: usize, module: Module) -> Frame {
+        let mut frame = Frame::new(function, stack_size);
+        frame.module = Some(module);
+        frame
+    }
+
+    // where `run_frame` left off, so calls and imports can resume after it
+    fn set_instruction(&mut self, ip: usize, instruction: &Decoded) {
+        self.ip = ip;
+        self.operand_addr = instruction.operand_addr;
+        self.operand_size = instruction.operand_size;
+    }
+
+    // the call at `addr` of register code, resuming at `next_addr`
+    fn set_call_site(&mut self, addr: usize, next_addr: usize) {
+        self.ip = addr;
+        self.operand_addr = next_addr;
+        self.operand_size = 0;
     }
 
     fn set_ip(&mut self, addr: usize) {
         self.ip = addr;
     }
 
-    fn incr_ip(&mut self, offset: usize) {
-        self.ip += offset;
+    // ips past the end of the chunk return from the frame
+    fn next_instruction(&mut self) {
+        self.ip = self.operand_addr.saturating_add(self.operand_size);
     }
 }
 
+// `try` block being executed: an exception unwinds the frames and the
+// stack back to where it was set up, then jumps to its except clauses
+struct Handler {
+    frames: usize,
+    stack_size: usize,
+    handling: usize,
+    addr: usize,
+}
+
 pub struct Vm {
     config: Config,
-    stack: Vec<Object>,
+    stack: Vec<Value>,
     frames: Vec<Frame>,
+    handlers: Vec<Handler>,
+    // exceptions the except clauses being executed handle, innermost last,
+    // with the number of frames at the time for returns to drop them
+    handling: Vec<(usize, Exception)>,
+    // value of the last top-level expression statement of the current run
+    result: Option<Value>,
+    // line of the main script the last run failed at
+    error_line: Option<usize>,
+    output: OutputStats,
+    stdout: Box<dyn Write>,
+    stderr: Box<dyn Write>,
+    replay: Option<Replay>,
+    profile: Profile,
+    policy: Option<Box<Policy>>,
+    // imported modules by name, kept across runs like `sys.modules`
+    modules: HashMap<String, Value>,
+    // generator behind the `random` module, kept across runs
+    random: Random,
+    // collector of the reference cycles scripts make
+    heap: Heap,
+    // native code of the hot functions, kept across runs
+    #[cfg(feature = "jit")]
+    jit: Jit,
 }
 
 impl Vm {
@@ -61,6 +148,21 @@ impl Vm {
             config: Config::new(),
             stack: Vec::new(),
             frames: Vec::new(),
+            handlers: Vec::new(),
+            handling: Vec::new(),
+            result: None,
+            error_line: None,
+            output: OutputStats::default(),
+            stdout: Box::new(io::stdout()),
+            stderr: Box::new(io::stderr()),
+            replay: None,
+            profile: Profile::new(),
+            policy: None,
+            modules: HashMap::new(),
+            random: Random::new(),
+            heap: Heap::new(),
+            #[cfg(feature = "jit")]
+            jit: Jit::new(),
         }
     }
 
@@ -83,274 +185,1099 @@ impl Vm {
         &mut self,
         config: Config,
         globals: &mut SymbolTable,
+        builtins: &SymbolTable,
         function: Function,
-    ) -> Result<Object, VmError> {
+    ) -> Result<Value, VmError> {
         self.init(config, function);
         trace!("Globals: {:?}", globals);
 
-        while !self.frames.is_empty() {
-            let result = self.interpret_function(globals);
-            match result {
-                Ok(ret_val) => self.stack.push(ret_val),
-                Err(err) => {
-                    self.tear_down();
-                    return Err(err);
-                }
-            };
-        }
-
-        let result = match self.stack.pop() {
-            Some(value) => value,
-            _ => Object::new_none(),
+        let outcome = self.run_frames(0, globals, builtins);
+        let result = self.result.take().unwrap_or(Value::None);
+        // the frames still point at the failing instruction
+        self.error_line = match outcome {
+            Ok(_) => None,
+            Err(_) => self.current_line(),
         };
         self.tear_down();
-        Ok(result)
+        match outcome {
+            Ok(_) => Ok(result),
+            // uncaught exceptions report like the errors they stand for
+            Err(VmError::Exception(exception)) => Err(exception.into_error()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Line of the main script the error of the last run was raised at,
+    /// None if it succeeded or the line is unknown.
+    pub fn error_line(&self) -> Option<usize> {
+        self.error_line
+    }
+
+    // lines of the innermost frame, unless its code comes from a module and
+    // so from another source
+    fn current_line(&self) -> Option<usize> {
+        let frame = self.frames.last()?;
+        if frame.function.module.is_some() {
+            return None;
+        }
+        frame.function.chunk.line_at(frame.ip as u64)
     }
 
     fn init(&mut self, config: Config, function: Function) {
         self.config = config;
         self.stack.clear();
         self.frames.clear();
-        self.frames.push(Frame {
-            function,
-            stack_size: 0,
-            ip: 0,
-        });
+        self.handlers.clear();
+        self.handling.clear();
+        self.result = None;
+        self.frames.push(Frame::new(Rc::new(function), 0));
     }
 
     fn tear_down(&mut self) {
         self.stack.clear();
         self.frames.clear();
+        self.handlers.clear();
+        self.handling.clear();
+    }
+
+    // parameters live in the frame slots starting at `stack_size`
+    fn check_arguments(&self, function: &Function, stack_size: usize) -> Result<(), VmError> {
+        for (index, param) in function.params.iter().enumerate() {
+            let (Some(Some(annotation)), Some(arg)) = (
+                function.arg_types.get(index),
+                self.stack.get(stack_size + index),
+            ) else {
+                continue;
+            };
+            if annotation_accepts(annotation, arg) == Some(false) {
+                return Err(VmError::InvalidOperand(format!(
+                    "TypeError: {}() argument '{}' must be {}, not {}",
+                    function.name,
+                    param,
+                    annotation,
+                    arg.type_name()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn return_from_frame(&mut self, ret_val: Value) -> Result<(), VmError> {
+        let frame = match self.frames.pop() {
+            Some(frame) => frame,
+            _ => unreachable!(),
+        };
+        if let (true, Some(annotation)) =
+            (self.config.check_annotations, &frame.function.return_type)
+        {
+            if annotation_accepts(annotation, &ret_val) == Some(false) {
+                return Err(VmError::InvalidOperand(format!(
+                    "TypeError: {}() must return {}, not {}",
+                    frame.function.name,
+                    annotation,
+                    ret_val.type_name()
+                )));
+            }
+        }
+        // pop frame locals
+        trace!(
+            "Return from {}, Stack Size: {} New Stack Size: {}",
+            frame.function.name,
+            self.stack.len(),
+            frame.stack_size
+        );
+        self.stack.resize(frame.stack_size, Value::None);
+        // a return may leave try blocks and except clauses
+        let frames = self.frames.len();
+        self.handlers.retain(|handler| handler.frames <= frames);
+        self.handling
+            .retain(|(handling_frames, _)| *handling_frames <= frames);
+        match frame.module {
+            // importing evaluates to the module object, not the last value
+            Some(module) => {
+                let name = module.name.to_string();
+                let module = Value::Module(module.into());
+                self.modules.insert(name, module.clone());
+                self.stack.push(module);
+            }
+            None => self.stack.push(ret_val),
+        }
+        Ok(())
+    }
+
+    /// Frees the values only reachable through reference cycles, returning
+    /// how many containers were cleared to break them.
+    pub fn collect_garbage(&mut self) -> usize {
+        self.heap.collect()
+    }
+
+    pub fn loaded_modules(&self) -> Vec<String> {
+        self.modules.keys().map(|name| name.to_string()).collect()
+    }
+
+    /// Calls `callable` with the `args_count` arguments on top of the stack.
+    /// Only natives accept keyword arguments so far.
+    fn call(
+        &mut self,
+        callable: Value,
+        args_count: usize,
+        kwargs: Vec<(String, Value)>,
+        globals: &mut SymbolTable,
+        builtins: &SymbolTable,
+    ) -> Result<(), VmError> {
+        trace!("Callable: {:?}", callable);
+        if self.config.profile_opcodes {
+            let callee = match &callable {
+                Value::Function(function) => function.name.to_string(),
+                Value::NativeFunction(native_function) => native_function.name.to_string(),
+                other => other.type_name().to_string(),
+            };
+            let frame = self.current_frame();
+            let call_site = CallSite {
+                caller: frame.function.name.to_string(),
+                ip: frame.ip,
+                callee,
+            };
+            self.profile.count_call(call_site);
+        }
+        match &callable {
+            Value::Function(function) => {
+                if let Some((name, _)) = kwargs.first() {
+                    return Err(VmError::InvalidOperand(format!(
+                        "TypeError: {}() got an unexpected keyword argument '{}'",
+                        function.name, name
+                    )));
+                }
+                if args_count != function.arity {
+                    return Err(VmError::WrongArgumentCount(format!(
+                        "Function: {} expect {} arguments, {} given.",
+                        function.name, function.arity, args_count
+                    )));
+                }
+                let stack_size = self
+                    .stack
+                    .len()
+                    .checked_sub(function.arity)
+                    .ok_or_else(|| {
+                        VmError::InvalidBytecode(String::from("Call without its arguments"))
+                    })?;
+                if self.config.check_annotations {
+                    self.check_arguments(function, stack_size)?;
+                }
+                #[cfg(feature = "jit")]
+                if let Some(result) = self.run_native(function, stack_size) {
+                    self.stack.truncate(stack_size);
+                    self.stack.push(result);
+                    self.current_frame().next_instruction();
+                    return Ok(());
+                }
+                self.current_frame().next_instruction();
+                self.frames.push(Frame::new(function.clone(), stack_size));
+            }
+            Value::NativeFunction(native_function) => {
+                if native_function.arity < usize::MAX && args_count != native_function.arity {
+                    return Err(VmError::WrongArgumentCount(format!(
+                        "Function: {} expect {} arguments, {} given.",
+                        native_function.name, native_function.arity, args_count
+                    )));
+                }
+                if let Some((name, _)) = kwargs.iter().find(|(name, _)| {
+                    !native_function.any_kwargs && !native_function.kwargs.contains(name)
+                }) {
+                    return Err(VmError::InvalidOperand(format!(
+                        "TypeError: {}() got an unexpected keyword argument '{}'",
+                        native_function.name, name
+                    )));
+                }
+
+                let args = self.pop_args(args_count)?;
+                let result = self.call_native(native_function, args, kwargs, globals, builtins)?;
+                self.stack.push(result);
+                self.current_frame().next_instruction();
+            }
+            Value::Partial(partial) => {
+                let args = self.pop_args(args_count)?;
+                let (args, kwargs) = partial.bind(args, kwargs);
+                let args_count = args.len();
+                self.stack.extend(args);
+                return self.call(
+                    *partial.function.clone(),
+                    args_count,
+                    kwargs,
+                    globals,
+                    builtins,
+                );
+            }
+            Value::CachedFunction(cached) => {
+                let args = self.pop_args(args_count)?;
+                let result = self.call_cached(cached, args, kwargs, globals, builtins)?;
+                self.stack.push(result);
+                self.current_frame().next_instruction();
+            }
+            other => {
+                return Err(VmError::InvalidOperand(format!(
+                    "TypeError: '{}' object is not callable",
+                    other.type_name()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    // the `count` arguments on top of the stack, the last one being on top
+    fn pop_args(&mut self, count: usize) -> Result<Vec<Value>, VmError> {
+        let start = self
+            .stack
+            .len()
+            .checked_sub(count)
+            .ok_or_else(|| VmError::InvalidBytecode(String::from("Pop from an empty stack")))?;
+        Ok(self.stack.split_off(start))
+    }
+
+    // Malformed bytecode could pop more than was pushed or refer to slots
+    // and constants that don't exist, which is reported rather than panicking.
+    fn pop(&mut self) -> Result<Value, VmError> {
+        self.stack
+            .pop()
+            .ok_or_else(|| VmError::InvalidBytecode(String::from("Pop from an empty stack")))
+    }
+
+    // slots are numbered from `base`, that of the current frame
+    fn local_slot(&mut self, base: usize, stack_offset: u64) -> Result<&mut Value, VmError> {
+        self.stack
+            .get_mut(base + stack_offset as usize)
+            .ok_or_else(|| {
+                VmError::InvalidBytecode(format!("Invalid local slot: {}", stack_offset))
+            })
+    }
+
+    // result of an `lru_cache` wrapped function, calling it on a cache miss
+    fn call_cached(
+        &mut self,
+        cached: &CachedFunction,
+        args: Vec<Value>,
+        kwargs: Vec<(String, Value)>,
+        globals: &mut SymbolTable,
+        builtins: &SymbolTable,
+    ) -> NativeResult {
+        if let Some(result) = cached.lookup(&args, &kwargs)? {
+            return Ok(result);
+        }
+        let result = self.call_value(
+            &cached.function,
+            args.clone(),
+            kwargs.clone(),
+            globals,
+            builtins,
+        )?;
+        cached.store(&args, &kwargs, &result)?;
+        if self.heap.track(cached.container()) {
+            self.heap.collect();
+        }
+        Ok(result)
+    }
+
+    // runs a native, answering it from the replay log instead when it is
+    // nondeterministic and a run is being replayed
+    fn call_native(
+        &mut self,
+        native_function: &NativeFunction,
+        args: Vec<Value>,
+        kwargs: Vec<(String, Value)>,
+        globals: &mut SymbolTable,
+        builtins: &SymbolTable,
+    ) -> NativeResult {
+        self.check_policy(Action::CallNative {
+            name: &native_function.name,
+            args: &args,
+            kwargs: &kwargs,
+        })?;
+        let result = match &mut self.replay {
+            Some(Replay::Replay(entries)) if native_function.nondeterministic => {
+                next_result(entries, &native_function.name)?
+            }
+            _ => {
+                let mut context = NativeContext::new(self, globals, builtins, kwargs);
+                (native_function.function.as_ref())(&mut context, args)?
+            }
+        };
+        if let Some(Replay::Record(log)) = &mut self.replay {
+            if native_function.nondeterministic {
+                log.record(&native_function.name, &result)?;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Calls `callable` on behalf of a native and returns its result. A
+    /// script function runs to completion on the frame stack first.
+    pub(crate) fn call_value(
+        &mut self,
+        callable: &Value,
+        args: Vec<Value>,
+        kwargs: Vec<(String, Value)>,
+        globals: &mut SymbolTable,
+        builtins: &SymbolTable,
+    ) -> NativeResult {
+        match callable {
+            Value::Function(function) => {
+                if let Some((name, _)) = kwargs.first() {
+                    return Err(VmError::InvalidOperand(format!(
+                        "TypeError: {}() got an unexpected keyword argument '{}'",
+                        function.name, name
+                    )));
+                }
+                if args.len() != function.arity {
+                    return Err(VmError::WrongArgumentCount(format!(
+                        "Function: {} expect {} arguments, {} given.",
+                        function.name,
+                        function.arity,
+                        args.len()
+                    )));
+                }
+                let stack_size = self.stack.len();
+                // same layout the Call instruction leaves, last argument on top
+                self.stack.extend(args);
+                if self.config.check_annotations {
+                    self.check_arguments(function, stack_size)?;
+                }
+                let depth = self.frames.len();
+                self.frames.push(Frame::new(function.clone(), stack_size));
+                self.run_frames(depth, globals, builtins)
+            }
+            Value::NativeFunction(native_function) => {
+                if native_function.arity < usize::MAX && args.len() != native_function.arity {
+                    return Err(VmError::WrongArgumentCount(format!(
+                        "Function: {} expect {} arguments, {} given.",
+                        native_function.name,
+                        native_function.arity,
+                        args.len()
+                    )));
+                }
+                if let Some((name, _)) = kwargs.iter().find(|(name, _)| {
+                    !native_function.any_kwargs && !native_function.kwargs.contains(name)
+                }) {
+                    return Err(VmError::InvalidOperand(format!(
+                        "TypeError: {}() got an unexpected keyword argument '{}'",
+                        native_function.name, name
+                    )));
+                }
+                self.call_native(native_function, args, kwargs, globals, builtins)
+            }
+            Value::Partial(partial) => {
+                let (args, kwargs) = partial.bind(args, kwargs);
+                self.call_value(&partial.function, args, kwargs, globals, builtins)
+            }
+            Value::CachedFunction(cached) => {
+                self.call_cached(cached, args, kwargs, globals, builtins)
+            }
+            other => Err(VmError::InvalidOperand(format!(
+                "TypeError: '{}' object is not callable",
+                other.type_name()
+            ))),
+        }
+    }
+
+    // exception of a Raise taking `count` values: none re-raises the one
+    // being handled, the second one is the cause
+    fn raised_exception(&mut self, count: u64) -> Result<Exception, VmError> {
+        if count == 0 {
+            return match self.handling.last() {
+                Some((_, exception)) => Ok(exception.clone()),
+                None => Err(VmError::Exception(Exception::new(
+                    "RuntimeError",
+                    vec![Value::String(
+                        String::from("No active exception to reraise").into(),
+                    )],
+                ))),
+            };
+        }
+        let cause = match count {
+            2 => Some(self.pop()?),
+            _ => None,
+        };
+        let mut exception = exception_of(&self.pop()?)?;
+        if let Some(cause) = cause {
+            exception.cause = match &cause {
+                Value::None => None,
+                value => Some(Box::new(exception_of(value)?)),
+            };
+        }
+        Ok(exception)
+    }
+
+    /// Function of the innermost frame, the script itself at the top level.
+    pub(crate) fn current_function(&self) -> Option<&Function> {
+        self.frames.last().map(|frame| frame.function.as_ref())
+    }
+
+    // the instruction of the current function being executed, a call while
+    // a native runs
+    pub(crate) fn current_ip(&self) -> usize {
+        self.frames.last().map_or(0, |frame| frame.ip)
+    }
+
+    pub(crate) fn random_mut(&mut self) -> &mut Random {
+        &mut self.random
+    }
+
+    pub(crate) fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub(crate) fn output_mut(&mut self) -> &mut OutputStats {
+        &mut self.output
+    }
+
+    pub(crate) fn stdout_mut(&mut self) -> &mut dyn Write {
+        self.stdout.as_mut()
+    }
+
+    pub(crate) fn stderr_mut(&mut self) -> &mut dyn Write {
+        self.stderr.as_mut()
+    }
+
+    /// Records the results of nondeterministic natives from now on.
+    pub fn start_recording(&mut self) {
+        self.replay = Some(Replay::Record(ExecutionLog::new()));
+    }
+
+    pub fn execution_log(&self) -> Option<&ExecutionLog> {
+        match &self.replay {
+            Some(Replay::Record(log)) => Some(log),
+            _ => None,
+        }
+    }
+
+    /// Answers nondeterministic natives from `log` instead of calling them.
+    pub fn start_replay(&mut self, log: ExecutionLog) {
+        self.replay = Some(Replay::Replay(log.entries.into()));
+    }
+
+    /// Redirects what scripts print, e.g. into a buffer.
+    pub fn profile(&self) -> &Profile {
+        &self.profile
     }
 
-    fn interpret_function(&mut self, globals: &mut SymbolTable) -> Result<Object, VmError> {
-        trace!("interpret_function({})", self.current_frame().function.name);
-        while self.current_frame().ip < self.current_frame().get_chunk().data.len() {
-            let op = self.current_frame().get_opcode()?;
-            trace!("IP: {:X} OpCode: {:?}", self.current_frame().ip, op);
+    pub fn set_policy(&mut self, policy: Option<Box<Policy>>) {
+        self.policy = policy;
+    }
+
+    // consults the embedder policy, if any, about an action of the current
+    // instruction
+    fn check_policy(&mut self, action: Action) -> Result<(), VmError> {
+        let Some(policy) = self.policy.as_mut() else {
+            return Ok(());
+        };
+        let frame = match self.frames.last() {
+            Some(frame) => frame,
+            None => unreachable!(),
+        };
+        let request = PolicyRequest {
+            action,
+            function: &frame.function.name,
+            ip: frame.ip,
+        };
+        match policy(&request) {
+            Decision::Allow => Ok(()),
+            Decision::Deny(reason) => Err(VmError::PermissionError(format!(
+                "PermissionError: {} denied: {}",
+                request.action.describe(),
+                reason
+            ))),
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn set_config(&mut self, config: Config) {
+        self.config = config;
+    }
+
+    pub fn set_stdout(&mut self, stdout: Box<dyn Write>) {
+        self.stdout = stdout;
+    }
+
+    pub fn set_stderr(&mut self, stderr: Box<dyn Write>) {
+        self.stderr = stderr;
+    }
+
+    fn import_module(
+        &mut self,
+        name: &str,
+        globals: &mut SymbolTable,
+        builtins: &SymbolTable,
+    ) -> Result<(), VmError> {
+        if let Some(module_obj) = self.modules.get(name) {
+            self.stack.push(module_obj.clone());
+            return Ok(());
+        }
+        if let Some(module) = load_builtin_module(name, &self.config, globals) {
+            let module = Value::Module(module.into());
+            self.modules.insert(name.to_string(), module.clone());
+            self.stack.push(module);
+            return Ok(());
+        }
+        let is_loading = self.frames.iter().any(|frame| match &frame.module {
+            Some(module) => module.name == name,
+            None => false,
+        });
+        if is_loading {
+            return Err(VmError::ImportError(format!(
+                "ImportError: circular import of module '{}'",
+                name
+            )));
+        }
+        let (module, function) = match load_module(name, &self.config, globals, builtins) {
+            Ok(loaded) => loaded,
+            Err(err) => return Err(VmError::ImportError(format!("ImportError: {}", err))),
+        };
+        trace!("Import module {} from {}", module.name, module.path);
+        self.frames.push(Frame::new_module(
+            Rc::new(function),
+            self.stack.len(),
+            module,
+        ));
+        Ok(())
+    }
+
+    // attribute `name` of `object` for the `GetAttr` at `ip`
+    fn get_attr(
+        &self,
+        chunk: &Chunk,
+        ip: usize,
+        object: &Value,
+        name: &str,
+        globals: &SymbolTable,
+    ) -> Result<Value, VmError> {
+        match object {
+            // the slot found last time, while it still holds this attribute
+            // of this module
+            Value::Module(module) => match chunk
+                .cache
+                .get(ip)
+                .and_then(|slot| globals.get(slot))
+                .filter(|attribute| module.is_attribute_name(&attribute.name, name))
+                .or_else(|| module_attribute(module, name, globals))
+            {
+                Some(attribute) => {
+                    chunk.cache.set(ip, attribute.id);
+                    Ok(attribute.value.clone())
+                }
+                None => self.get_attribute(object, name, globals),
+            },
+            _ => self.get_attribute(object, name, globals),
+        }
+    }
+
+    // result of `function` run as native code on the arguments from
+    // `stack_size` up, None when it isn't hot yet, can't be compiled or
+    // gave up, the interpreter running it then
+    #[cfg(feature = "jit")]
+    fn run_native(&mut self, function: &Rc<Function>, stack_size: usize) -> Option<Value> {
+        let threshold = self.config.jit_threshold?;
+        if self.config.trace || self.config.profile_opcodes {
+            return None;
+        }
+        let code = self.jit.hot_code(function, threshold)?;
+        let args = self.stack[stack_size..]
+            .iter()
+            .map(|arg| match arg {
+                Value::Integer(arg) => Some(*arg),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+        jit::run(code, &args).map(Value::Integer)
+    }
+
+    fn get_attribute(
+        &self,
+        object: &Value,
+        name: &str,
+        globals: &SymbolTable,
+    ) -> Result<Value, VmError> {
+        match object {
+            Value::Module(module) => match module_attribute(module, name, globals) {
+                Some(attribute) => Ok(attribute.value.clone()),
+                None => Err(VmError::AttributeError(format!(
+                    "AttributeError: module '{}' has no attribute '{}'",
+                    module.name, name
+                ))),
+            },
+            Value::String(text) => match string_method(text, name) {
+                Some(method) => Ok(Value::NativeFunction(method.into())),
+                None => Err(VmError::AttributeError(format!(
+                    "AttributeError: 'str' object has no attribute '{}'",
+                    name
+                ))),
+            },
+            Value::Bytes(bytes) => match bytes_method(bytes, name) {
+                Some(method) => Ok(Value::NativeFunction(method.into())),
+                None => Err(VmError::AttributeError(format!(
+                    "AttributeError: 'bytes' object has no attribute '{}'",
+                    name
+                ))),
+            },
+            Value::File(file) => match name {
+                "name" => Ok(Value::String(file.name.to_string().into())),
+                "mode" => Ok(Value::String(file.mode.to_string().into())),
+                "closed" => Ok(Value::new_from_bool(file.is_closed())),
+                _ => match file_method(file, name) {
+                    Some(method) => Ok(Value::NativeFunction(method.into())),
+                    None => Err(VmError::AttributeError(format!(
+                        "AttributeError: '_io.TextIOWrapper' object has no attribute '{}'",
+                        name
+                    ))),
+                },
+            },
+            Value::Pattern(pattern) => match pattern_attribute(pattern, name) {
+                Some(value) => Ok(value),
+                None => bound_method(object, name, pattern_method(pattern, name)),
+            },
+            Value::Match(found) => match match_attribute(found, name) {
+                Some(value) => Ok(value),
+                None => bound_method(object, name, match_method(found, name)),
+            },
+            Value::HashObject(hash) => match hash_attribute(hash, name) {
+                Some(value) => Ok(value),
+                None => bound_method(object, name, hash_method(hash, name)),
+            },
+            Value::Partial(partial) => match name {
+                "func" => Ok(partial.function.as_ref().clone()),
+                "args" => Ok(Value::Tuple(partial.args.clone().into())),
+                _ => Err(VmError::AttributeError(format!(
+                    "AttributeError: 'functools.partial' object has no attribute '{}'",
+                    name
+                ))),
+            },
+            Value::CachedFunction(cached) if name == "__wrapped__" => {
+                Ok(cached.function.as_ref().clone())
+            }
+            Value::Exception(exception) => match name {
+                "args" => Ok(Value::Tuple(exception.args.clone().into())),
+                "__cause__" => Ok(match &exception.cause {
+                    Some(cause) => Value::Exception(cause.as_ref().clone().into()),
+                    None => Value::None,
+                }),
+                _ => Err(VmError::AttributeError(format!(
+                    "AttributeError: '{}' object has no attribute '{}'",
+                    exception.class, name
+                ))),
+            },
+            Value::Function(function) if name == DOC_GLOBAL => Ok(match &function.doc {
+                Some(doc) => Value::String(doc.to_string().into()),
+                None => Value::None,
+            }),
+            other => Err(VmError::AttributeError(format!(
+                "AttributeError: '{}' object has no attribute '{}'",
+                other.type_name(),
+                name
+            ))),
+        }
+    }
+
+    // Runs the frames above `depth`, resuming at the except clauses of the
+    // innermost try block among them when an error can be caught.
+    fn run_frames(
+        &mut self,
+        depth: usize,
+        globals: &mut SymbolTable,
+        builtins: &SymbolTable,
+    ) -> Result<Value, VmError> {
+        loop {
+            match self.dispatch(depth, globals, builtins) {
+                Err(error) => self.unwind(depth, error)?,
+                result => return result,
+            }
+        }
+    }
+
+    // unwinds to the innermost try block set up above `depth`, pushing the
+    // exception for its except clauses
+    fn unwind(&mut self, depth: usize, error: VmError) -> Result<(), VmError> {
+        if self
+            .handlers
+            .last()
+            .is_none_or(|handler| handler.frames <= depth)
+        {
+            return Err(error);
+        }
+        let Some(exception) = Exception::from_error(&error) else {
+            return Err(error);
+        };
+        let handler = self.handlers.pop().unwrap();
+        trace!("Unwind to {:X}: {}", handler.addr, exception);
+        self.frames.truncate(handler.frames);
+        self.stack.truncate(handler.stack_size);
+        self.handling.truncate(handler.handling);
+        self.handling.push((handler.frames, exception.clone()));
+        self.stack.push(Value::Exception(exception.into()));
+        self.current_frame().set_ip(handler.addr);
+        Ok(())
+    }
+
+    // Single dispatch loop over the frame stack: calls push a frame and returns
+    // pop it, so no Rust recursion is involved in script function calls. Only
+    // natives calling back into scripts nest it, running until the frame
+    // stack is back to `depth`.
+    fn dispatch(
+        &mut self,
+        depth: usize,
+        globals: &mut SymbolTable,
+        builtins: &SymbolTable,
+    ) -> Result<Value, VmError> {
+        while self.frames.len() > depth {
+            let frame = self.current_frame();
+            let function = frame.function.clone();
+            let base = frame.stack_size;
+            let mut ip = frame.ip;
+            let frames = self.frames.len();
+            // opcode profiles and traces are of the stack bytecode
+            let result = match &function.registers {
+                Some(code) if !self.config.trace && !self.config.profile_opcodes => {
+                    self.run_registers(&function.chunk, code, &mut ip, base, globals, builtins)
+                }
+                _ => self.run_frame(&function.chunk, &mut ip, base, globals, builtins),
+            };
+            if let Err(error) = result {
+                // still in the same frame, which has to point at the failing
+                // instruction for unwinding and error lines
+                if self.frames.len() == frames {
+                    self.current_frame().set_ip(ip);
+                }
+                return Err(error);
+            }
+        }
+
+        let result = match self.stack.pop() {
+            Some(value) => value,
+            _ => Value::None,
+        };
+        Ok(result)
+    }
+
+    // Runs the instructions of the innermost frame until it calls, returns
+    // or imports. Its ip, chunk and stack base are kept in locals meanwhile
+    // and written back to the frame before leaving it.
+    fn run_frame(
+        &mut self,
+        chunk: &Chunk,
+        ip: &mut usize,
+        base: usize,
+        globals: &mut SymbolTable,
+        builtins: &SymbolTable,
+    ) -> Result<(), VmError> {
+        loop {
+            let Some(instruction) = chunk.decode(*ip) else {
+                if *ip < chunk.data.len() {
+                    return Err(VmError::InvalidBytecode(format!(
+                        "Invalid bytecode: {}",
+                        chunk.data[*ip]
+                    )));
+                }
+                // falling off the end of a chunk returns the top of the stack
+                let ret_val = match self.stack.pop() {
+                    Some(value) => value,
+                    _ => Value::None,
+                };
+                return self.return_from_frame(ret_val);
+            };
+            let op = instruction.op.clone();
+            trace!("IP: {:X} OpCode: {:?}", *ip, op);
             if self.config.trace {
                 self.dump_stack();
             }
+            if self.config.profile_opcodes {
+                self.profile.count_opcode(&op);
+            }
+            // no instruction pushes more than a few values, so checking once
+            // per instruction bounds the stack
+            if let Some(max_stack_size) = self.config.max_stack_size {
+                if self.stack.len() > max_stack_size {
+                    return Err(VmError::ResourceLimitExceeded(format!(
+                        "value stack limit of {} exceeded",
+                        max_stack_size
+                    )));
+                }
+            }
 
             match op {
                 Bytecode::Nop => {
-                    self.current_frame().incr_ip(SIZE_INSTRUCTION);
+                    *ip = instruction.next_addr();
                 }
 
                 // Literals
                 Bytecode::None => {
-                    self.stack.push(Object::new_none());
-                    self.current_frame().incr_ip(SIZE_INSTRUCTION);
+                    self.stack.push(Value::None);
+                    *ip = instruction.next_addr();
                 }
-                Bytecode::True => {
-                    self.stack.push(Object::new_true());
-                    self.current_frame().incr_ip(SIZE_INSTRUCTION);
+                Bytecode::PushTrue => {
+                    self.stack.push(Value::Bool(true));
+                    *ip = instruction.next_addr();
                 }
-                Bytecode::False => {
-                    self.stack.push(Object::new_false());
-                    self.current_frame().incr_ip(SIZE_INSTRUCTION);
+                Bytecode::PushFalse => {
+                    self.stack.push(Value::Bool(false));
+                    *ip = instruction.next_addr();
                 }
                 Bytecode::Const => {
-                    let offset_addr = self.current_frame().ip + SIZE_INSTRUCTION;
-                    let index = self.current_frame().get_chunk().get_data_u64(offset_addr);
-                    let constant = &self.current_frame().get_chunk().constants[index as usize];
-                    let object = Object::from_literal(&constant);
-                    self.stack.push(object);
-                    self.current_frame().incr_ip(SIZE_INSTRUCTION + SIZE_INDEX);
+                    let index = operand(chunk, &instruction)?;
+                    let value = get_constant(chunk, index)?.clone();
+                    self.stack.push(value);
+                    *ip = instruction.next_addr();
                 }
                 Bytecode::Pop => {
-                    self.stack.pop().unwrap();
-                    self.current_frame().incr_ip(SIZE_INSTRUCTION);
+                    self.pop()?;
+                    *ip = instruction.next_addr();
+                }
+                Bytecode::PopResult => {
+                    self.result = self.stack.pop();
+                    *ip = instruction.next_addr();
                 }
 
                 // Globals Manipulation
                 Bytecode::GetGlobal => {
-                    let index_addr = self.current_frame().ip + SIZE_INSTRUCTION;
-                    let index = self.current_frame().get_chunk().get_data_u64(index_addr);
+                    let index = operand(chunk, &instruction)?;
                     let global_obj = match globals.get(index) {
                         Some(obj) => obj,
-                        None => {
-                            return Err(VmError::UndefinedName(format!(
-                                "NameError: name '{}' not defined",
-                                index
-                            )))
-                        }
+                        None => return Err(undefined_global(globals, index)),
                     };
-                    self.stack.push(global_obj.clone());
-                    self.current_frame().incr_ip(SIZE_INSTRUCTION + SIZE_INDEX);
+                    self.stack.push(global_obj.value.clone());
+                    *ip = instruction.next_addr();
                 }
                 Bytecode::SetGlobal => {
-                    let index_addr = self.current_frame().ip + SIZE_INSTRUCTION;
-                    let index = self.current_frame().get_chunk().get_data_u64(index_addr);
-                    let rhs = self.stack.pop().unwrap();
-                    let global_obj = globals.get_mut(index);
-                    global_obj.value = rhs.value;
-                    self.current_frame().incr_ip(SIZE_INSTRUCTION + SIZE_INDEX);
+                    let index = operand(chunk, &instruction)?;
+                    let rhs = self.pop()?;
+                    if !globals.assign(index, rhs) {
+                        return Err(VmError::InvalidBytecode(format!(
+                            "Invalid global: {}",
+                            index
+                        )));
+                    }
+                    *ip = instruction.next_addr();
+                }
+
+                Bytecode::InplaceAddGlobal => {
+                    let index = operand(chunk, &instruction)?;
+                    let rhs = self.pop()?;
+                    let Some(global_obj) = globals.get_mut(index) else {
+                        return Err(undefined_global(globals, index));
+                    };
+                    inplace_add(&mut global_obj.value, &rhs)?;
+                    *ip = instruction.next_addr();
+                }
+                Bytecode::IncrementGlobal => {
+                    let index = byte_operand(chunk, &instruction, 0)?;
+                    let constant = byte_operand(chunk, &instruction, SIZE_NARROW_INDEX)?;
+                    let rhs = get_constant(chunk, constant)?.clone();
+                    let Some(global_obj) = globals.get_mut(index) else {
+                        return Err(undefined_global(globals, index));
+                    };
+                    inplace_add(&mut global_obj.value, &rhs)?;
+                    *ip = instruction.next_addr();
+                }
+
+                Bytecode::GetBuiltin => {
+                    let index = operand(chunk, &instruction)?;
+                    // the builtin may have been removed since compilation
+                    let builtin_obj = match builtins.get(index) {
+                        Some(obj) => obj,
+                        None => return Err(undefined_builtin(builtins, index)),
+                    };
+                    self.stack.push(builtin_obj.value.clone());
+                    *ip = instruction.next_addr();
                 }
 
                 // Locals Manipulation
                 Bytecode::GetLocal => {
-                    let index_addr = self.current_frame().ip + SIZE_INSTRUCTION;
-                    let stack_offset = self.current_frame().get_chunk().get_data_u64(index_addr);
-                    let local_obj = self.stack[stack_offset as usize].clone();
+                    let stack_offset = operand(chunk, &instruction)?;
+                    let local_obj = self.local_slot(base, stack_offset)?.clone();
                     self.stack.push(local_obj);
-                    self.current_frame().incr_ip(SIZE_INSTRUCTION + SIZE_INDEX);
+                    *ip = instruction.next_addr();
                 }
                 Bytecode::SetLocal => {
-                    let index_addr = self.current_frame().ip + SIZE_INSTRUCTION;
-                    let stack_offset = self.current_frame().get_chunk().get_data_u64(index_addr);
-                    match self.stack.last() {
-                        Some(local_obj) => {
-                            self.stack[stack_offset as usize] = local_obj.clone();
+                    let stack_offset = operand(chunk, &instruction)?;
+                    let local_obj = match self.stack.last() {
+                        Some(local_obj) => local_obj.clone(),
+                        None => {
+                            return Err(VmError::InvalidBytecode(String::from(
+                                "SetLocal on an empty stack",
+                            )))
                         }
-                        None => panic!("SetLocal on empty stack"),
                     };
-                    self.current_frame().incr_ip(SIZE_INSTRUCTION + SIZE_INDEX);
+                    *self.local_slot(base, stack_offset)? = local_obj;
+                    *ip = instruction.next_addr();
+                }
+                Bytecode::InplaceAddLocal => {
+                    let stack_offset = operand(chunk, &instruction)?;
+                    let rhs = self.pop()?;
+                    inplace_add(self.local_slot(base, stack_offset)?, &rhs)?;
+                    *ip = instruction.next_addr();
+                }
+                Bytecode::IncrementLocal => {
+                    let stack_offset = byte_operand(chunk, &instruction, 0)?;
+                    let constant = byte_operand(chunk, &instruction, SIZE_NARROW_INDEX)?;
+                    let rhs = get_constant(chunk, constant)?.clone();
+                    inplace_add(self.local_slot(base, stack_offset)?, &rhs)?;
+                    *ip = instruction.next_addr();
                 }
 
                 Bytecode::Call => {
-                    let args_addr = self.current_frame().ip + SIZE_INSTRUCTION;
-                    let args_count =
-                        self.current_frame().get_chunk().get_data_u64(args_addr) as usize;
-                    let callable = self.stack.pop().unwrap();
-                    trace!("Callable: {:?}", callable);
-                    let index = globals.get_index(&callable.name);
-                    trace!("GlobalIndex: {}", index);
-                    let function_obj = match globals.get(index) {
-                        Some(obj) => obj,
-                        None => {
-                            return Err(VmError::UndefinedName(format!(
-                                "NameError: name '{}' not defined",
-                                index
+                    let args_count = operand(chunk, &instruction)? as usize;
+                    let callable = self.pop()?;
+                    self.current_frame().set_instruction(*ip, &instruction);
+                    return self.call(callable, args_count, Vec::new(), globals, builtins);
+                }
+
+                Bytecode::CallKw => {
+                    let args_count = operand(chunk, &instruction)? as usize;
+                    let callable = self.pop()?;
+                    let names = match self.pop()? {
+                        Value::String(names) => names,
+                        other => {
+                            return Err(VmError::InvalidOperand(format!(
+                                "Expected keyword names, found {:?}",
+                                other
                             )))
                         }
                     };
-                    trace!("Function: {:?}", function_obj);
-                    match &function_obj.value {
-                        Value::Function(function) => {
-                            if args_count != function.arity {
-                                return Err(VmError::WrongArgumentCount(format!(
-                                    "Function: {} expect {} arguments, {} given.",
-                                    function.name, function.arity, args_count
-                                )));
-                            }
-                            self.current_frame().incr_ip(SIZE_INSTRUCTION + SIZE_INDEX);
-                            self.frames.push(Frame {
-                                function: function.clone(),
-                                stack_size: self.stack.len() - function.arity,
-                                ip: 0,
-                            });
-                        }
-                        Value::NativeFunction(native_function) => {
-                            if native_function.arity < usize::MAX {
-                                if args_count != native_function.arity {
-                                    return Err(VmError::WrongArgumentCount(format!(
-                                        "Function: {} expect {} arguments, {} given.",
-                                        native_function.name, native_function.arity, args_count
-                                    )));
-                                }
-                            }
-
-                            let func = native_function.function.as_ref();
-                            let mut args = Vec::new();
-                            for _ in 0..args_count {
-                                let arg = self.stack.pop().unwrap();
-                                args.push(arg);
-                            }
-                            let result = func(args);
-                            self.stack.push(result);
-                            self.current_frame().incr_ip(SIZE_INSTRUCTION + SIZE_INDEX);
-                        }
-                        _ => {
-                            return Err(VmError::InvalidOperand(format!(
-                                "Invalid callable: '{}'",
-                                &callable.name
-                            )));
-                        }
+                    let mut kwargs = Vec::new();
+                    for name in names.split(',') {
+                        kwargs.push((name.to_string(), self.pop()?));
                     }
+                    self.current_frame().set_instruction(*ip, &instruction);
+                    return self.call(callable, args_count, kwargs, globals, builtins);
+                }
+
+                Bytecode::GetAttr => {
+                    let index = operand(chunk, &instruction)?;
+                    let name = get_constant_string(chunk, index)?;
+                    let object = self.pop()?;
+                    let attribute = self.get_attr(chunk, *ip, &object, name, globals)?;
+                    self.stack.push(attribute);
+                    *ip = instruction.next_addr();
+                }
+
+                Bytecode::MakeFunction => {
+                    let index = operand(chunk, &instruction)?;
+                    let function = match get_constant(chunk, index)? {
+                        Value::Function(function) => function.clone(),
+                        other => {
+                            return Err(VmError::InvalidBytecode(format!(
+                                "MakeFunction of a non-function constant: {:?}",
+                                other
+                            )))
+                        }
+                    };
+                    self.stack.push(Value::Function(function));
+                    *ip = instruction.next_addr();
+                }
+
+                Bytecode::GetItem => {
+                    let index = self.pop()?;
+                    let object = self.pop()?;
+                    self.stack.push(get_item(&object, &index)?);
+                    *ip = instruction.next_addr();
+                }
+
+                Bytecode::Import => {
+                    let index = operand(chunk, &instruction)?;
+                    let name = get_constant_string(chunk, index)?;
+                    self.current_frame().set_instruction(*ip, &instruction);
+                    self.check_policy(Action::Import { module: name })?;
+                    // resume after the Import once the module frame returns
+                    self.current_frame().next_instruction();
+                    return self.import_module(name, globals, builtins);
                 }
 
                 Bytecode::Return => {
-                    let ret_val = self.stack.pop().unwrap();
-                    let stack_size = self.current_frame().stack_size;
-                    assert!(self.frames.pop().is_some());
-                    // pop frame locals
-                    trace!(
-                        "Stack Size: {} New Stack Size: {}",
-                        self.stack.len(),
-                        stack_size
-                    );
-                    self.stack.resize(stack_size, Object::new_none());
-                    return Ok(ret_val);
+                    let ret_val = self.pop()?;
+                    return self.return_from_frame(ret_val);
                 }
 
                 // Control Flow
-                Bytecode::Jump => {
-                    let offset_addr = self.current_frame().ip + SIZE_INSTRUCTION;
-                    let addr_offset = self.current_frame().get_chunk().get_data_u64(offset_addr);
-                    trace!(
-                        "{:?} IP: {:X}, AddrOffset: {:X}, Result: {:X}",
-                        op,
-                        self.current_frame().ip,
-                        addr_offset,
-                        offset_addr
-                    );
-                    self.current_frame().incr_ip(addr_offset as usize);
+                Bytecode::Jump | Bytecode::Loop => {
+                    let offset = jump_offset(chunk, &instruction)?;
+                    trace!("{:?} IP: {:X}, Offset: {}", op, *ip, offset);
+                    *ip = jump_target(*ip, offset)?;
+                }
+                Bytecode::JumpLong => {
+                    let offset = operand(chunk, &instruction)? as i64;
+                    trace!("{:?} IP: {:X}, Offset: {}", op, *ip, offset);
+                    *ip = jump_target(*ip, offset)?;
                 }
 
                 Bytecode::JumpIfFalse => {
                     // we remove the conditional value from the stack
-                    let conditional_value = self.stack.pop().unwrap();
-                    if conditional_value.is_falsey() {
-                        let offset_addr = self.current_frame().ip + SIZE_INSTRUCTION;
-                        let addr_offset =
-                            self.current_frame().get_chunk().get_data_u64(offset_addr);
-                        trace!(
-                            "{:?} IP: {:X}, AddrOffset: {:X}, Result: {:X}",
-                            op,
-                            self.current_frame().ip,
-                            addr_offset,
-                            offset_addr,
-                        );
-                        self.current_frame().incr_ip(addr_offset as usize);
+                    let conditional_value = self.pop()?;
+                    if !truthy(&conditional_value)? {
+                        let offset = jump_offset(chunk, &instruction)?;
+                        trace!("{:?} IP: {:X}, Offset: {}", op, *ip, offset);
+                        *ip = jump_target(*ip, offset)?;
                     } else {
-                        self.current_frame().incr_ip(SIZE_INSTRUCTION + SIZE_INDEX);
+                        *ip = instruction.next_addr();
                     }
                 }
 
-                Bytecode::Loop => {
-                    let addr_addr = self.current_frame().ip + SIZE_INSTRUCTION;
-                    let addr = self.current_frame().get_chunk().get_data_u64(addr_addr);
-                    trace!(
-                        "{:?} IP: {:X}, Addr: {:X}",
-                        op,
-                        self.current_frame().ip,
-                        addr,
-                    );
-                    self.current_frame().set_ip(addr as usize);
+                Bytecode::ConstLessJumpIfFalse => {
+                    let constant = byte_operand(chunk, &instruction, SIZE_JUMP)?;
+                    let rhs = get_constant(chunk, constant)?;
+                    let lhs = self.pop()?;
+                    let less = match int_op(&Bytecode::Less, &lhs, rhs) {
+                        Some(less) => less,
+                        None => logic_op(&Bytecode::Less, &lhs, rhs)?,
+                    };
+                    if !truthy(&less)? {
+                        let offset = jump_offset(chunk, &instruction)?;
+                        *ip = jump_target(*ip, offset)?;
+                    } else {
+                        *ip = instruction.next_addr();
+                    }
                 }
 
-                // Unary Ops
-                Bytecode::Not => {
-                    let rhs = self.stack.pop().unwrap();
-                    let result = Object::new(Value::new_from_bool(rhs.is_falsey()));
-                    self.stack.push(result);
-                    self.current_frame().incr_ip(SIZE_INSTRUCTION);
-                }
-                Bytecode::Neg => {
-                    let rhs = self.stack.pop().unwrap();
-                    let result = match rhs.value {
-                        Value::Integer(value) => Value::Integer(-value),
-                        Value::Float(value) => Value::Float(-value),
-                        _ => {
+                // Exceptions
+                Bytecode::SetupTry => {
+                    let offset = jump_offset(chunk, &instruction)?;
+                    let addr = jump_target(*ip, offset)?;
+                    self.handlers.push(Handler {
+                        frames: self.frames.len(),
+                        stack_size: self.stack.len(),
+                        handling: self.handling.len(),
+                        addr,
+                    });
+                    *ip = instruction.next_addr();
+                }
+                Bytecode::PopTry => {
+                    self.handlers.pop();
+                    *ip = instruction.next_addr();
+                }
+                Bytecode::PopExcept => {
+                    self.handling.pop();
+                    *ip = instruction.next_addr();
+                }
+                Bytecode::ExceptMatch => {
+                    let class = self.pop()?;
+                    let matches = match self.stack.last() {
+                        Some(Value::Exception(exception)) => exception_matches(exception, &class)?,
+                        other => {
                             return Err(VmError::InvalidOperand(format!(
-                                "TypeError: unsupported operand type for '-': {:?}",
-                                rhs.value
-                            )));
+                                "Expected exception, found {:?}",
+                                other
+                            )))
                         }
                     };
-                    self.stack.push(Object::new(result));
-                    self.current_frame().incr_ip(SIZE_INSTRUCTION);
+                    self.stack.push(Value::new_from_bool(matches));
+                    *ip = instruction.next_addr();
+                }
+                Bytecode::Raise => {
+                    let count = operand(chunk, &instruction)?;
+                    return Err(VmError::Exception(self.raised_exception(count)?));
+                }
+
+                // Unary Ops
+                Bytecode::Not | Bytecode::Neg => {
+                    let rhs = self.pop()?;
+                    let result = unary_op(&op, &rhs)?;
+                    self.stack.push(result);
+                    *ip = instruction.next_addr();
                 }
 
                 // Binary Ops
@@ -362,52 +1289,388 @@ impl Vm {
                 | Bytecode::LessEqual
                 | Bytecode::Greater
                 | Bytecode::GreaterEqual => {
-                    let rhs = self.stack.pop().unwrap();
-                    let lhs = self.stack.pop().unwrap();
-                    let result = logic_op(&op, &lhs, &rhs)?;
+                    let rhs = self.pop()?;
+                    let lhs = self.pop()?;
+                    let result = match int_op(&op, &lhs, &rhs) {
+                        Some(result) => result,
+                        None => logic_op(&op, &lhs, &rhs)?,
+                    };
                     self.stack.push(result);
-                    self.current_frame().incr_ip(SIZE_INSTRUCTION);
+                    *ip = instruction.next_addr();
                 }
 
                 // Binary Ops
-                Bytecode::Add | Bytecode::Sub | Bytecode::Mul | Bytecode::Div => {
-                    let rhs = self.stack.pop().unwrap();
-                    let lhs = self.stack.pop().unwrap();
-                    let result = binary_op(&op, &lhs, &rhs)?;
+                Bytecode::Add
+                | Bytecode::Sub
+                | Bytecode::Mul
+                | Bytecode::Div
+                | Bytecode::FloorDiv
+                | Bytecode::Mod => {
+                    let rhs = self.pop()?;
+                    let lhs = self.pop()?;
+                    let result = match int_op(&op, &lhs, &rhs) {
+                        Some(result) => result,
+                        None => binary_op(&op, &lhs, &rhs)?,
+                    };
                     self.stack.push(result);
-                    self.current_frame().incr_ip(SIZE_INSTRUCTION);
+                    *ip = instruction.next_addr();
+                }
+                _ => {
+                    return Err(VmError::InvalidBytecode(format!(
+                        "Unsupported bytecode: {:?}",
+                        op
+                    )))
                 }
-                _ => unimplemented!(),
             };
         }
+    }
 
-        let stack_size = self.current_frame().stack_size;
-        assert!(self.frames.pop().is_some());
-        let result = match self.stack.pop() {
-            Some(value) => value,
-            _ => Object::new_none(),
-        };
-        self.stack.resize(stack_size, Object::new_none());
-        Ok(result)
+    // Runs the register code of the innermost frame like `run_frame` runs
+    // its bytecode, `ip` holding the bytecode address of the instruction
+    // being executed so errors and calls find their line and resume point.
+    fn run_registers(
+        &mut self,
+        chunk: &Chunk,
+        code: &RegisterCode,
+        ip: &mut usize,
+        base: usize,
+        globals: &mut SymbolTable,
+        builtins: &SymbolTable,
+    ) -> Result<(), VmError> {
+        // calls leave their result on top of the arguments they consumed
+        self.stack.resize(base + code.registers, Value::None);
+        if let Some(max_stack_size) = self.config.max_stack_size {
+            if self.stack.len() > max_stack_size {
+                return Err(VmError::ResourceLimitExceeded(format!(
+                    "value stack limit of {} exceeded",
+                    max_stack_size
+                )));
+            }
+        }
+        let mut pc = code.resume_at(*ip);
+        loop {
+            *ip = code.addrs[pc];
+            match &code.instructions[pc] {
+                Instruction::Move { dst, src } => {
+                    self.stack[base + dst] = read(&self.stack, base, code, src).clone();
+                }
+                Instruction::GetGlobal { dst, index } => {
+                    let Some(global_obj) = globals.get(*index) else {
+                        return Err(undefined_global(globals, *index));
+                    };
+                    self.stack[base + dst] = global_obj.value.clone();
+                }
+                Instruction::SetGlobal { index, src } => {
+                    let value = read(&self.stack, base, code, src).clone();
+                    if !globals.assign(*index, value) {
+                        return Err(VmError::InvalidBytecode(format!(
+                            "Invalid global: {}",
+                            index
+                        )));
+                    }
+                }
+                Instruction::GetBuiltin { dst, index } => {
+                    let Some(builtin_obj) = builtins.get(*index) else {
+                        return Err(undefined_builtin(builtins, *index));
+                    };
+                    self.stack[base + dst] = builtin_obj.value.clone();
+                }
+                Instruction::GetAttr { dst, object, name } => {
+                    let name = get_constant_string(chunk, *name)?;
+                    let object = read(&self.stack, base, code, object);
+                    self.stack[base + dst] = self.get_attr(chunk, *ip, object, name, globals)?;
+                }
+                Instruction::GetItem { dst, object, index } => {
+                    let object = read(&self.stack, base, code, object);
+                    let index = read(&self.stack, base, code, index);
+                    self.stack[base + dst] = get_item(object, index)?;
+                }
+                Instruction::PopResult { src } => {
+                    self.result = Some(read(&self.stack, base, code, src).clone());
+                }
+                Instruction::InplaceAdd { dst, rhs } => {
+                    let rhs = read(&self.stack, base, code, rhs).clone();
+                    inplace_add(&mut self.stack[base + dst], &rhs)?;
+                }
+                Instruction::InplaceAddGlobal { index, rhs } => {
+                    let rhs = read(&self.stack, base, code, rhs);
+                    let Some(global_obj) = globals.get_mut(*index) else {
+                        return Err(undefined_global(globals, *index));
+                    };
+                    inplace_add(&mut global_obj.value, rhs)?;
+                }
+                Instruction::Unary { op, dst, src } => {
+                    self.stack[base + dst] = unary_op(op, read(&self.stack, base, code, src))?;
+                }
+                Instruction::Binary { op, dst, lhs, rhs } => {
+                    let lhs = read(&self.stack, base, code, lhs);
+                    let rhs = read(&self.stack, base, code, rhs);
+                    let result = match int_op(op, lhs, rhs) {
+                        Some(result) => result,
+                        None => register_binary_op(op, lhs, rhs)?,
+                    };
+                    self.stack[base + dst] = result;
+                }
+                Instruction::Call {
+                    callable,
+                    args,
+                    addr,
+                    next_addr,
+                } => {
+                    // the arguments end up on top of the stack, as calls expect
+                    let slot = base + callable;
+                    let callable = std::mem::replace(&mut self.stack[slot], Value::None);
+                    self.stack.truncate(slot);
+                    self.current_frame().set_call_site(*addr, *next_addr);
+                    return self.call(callable, *args, Vec::new(), globals, builtins);
+                }
+                Instruction::Jump { target } => {
+                    pc = *target;
+                    continue;
+                }
+                Instruction::JumpIfFalse { condition, target } => {
+                    if !truthy(read(&self.stack, base, code, condition))? {
+                        pc = *target;
+                        continue;
+                    }
+                }
+                Instruction::CompareJump {
+                    op,
+                    lhs,
+                    rhs,
+                    target,
+                } => {
+                    let lhs = read(&self.stack, base, code, lhs);
+                    let rhs = read(&self.stack, base, code, rhs);
+                    let result = match int_op(op, lhs, rhs) {
+                        Some(result) => result,
+                        None => logic_op(op, lhs, rhs)?,
+                    };
+                    if !truthy(&result)? {
+                        pc = *target;
+                        continue;
+                    }
+                }
+                Instruction::Return { src } => {
+                    let ret_val = read(&self.stack, base, code, src).clone();
+                    return self.return_from_frame(ret_val);
+                }
+            }
+            pc += 1;
+        }
+    }
+}
+
+impl Drop for Vm {
+    // the interpreter's globals are gone by now, so the cycles its scripts
+    // made are garbage
+    fn drop(&mut self) {
+        self.tear_down();
+        self.heap.collect();
+    }
+}
+
+// value of a register code operand, registers numbered from `base`
+#[inline]
+fn read<'a>(
+    stack: &'a [Value],
+    base: usize,
+    code: &'a RegisterCode,
+    operand: &Operand,
+) -> &'a Value {
+    match operand {
+        Operand::Register(register) => &stack[base + register],
+        Operand::Constant(index) => &code.constants[*index],
+    }
+}
+
+// the binary and logic opcodes share an instruction in register code
+fn register_binary_op(op: &Bytecode, lhs: &Value, rhs: &Value) -> Result<Value, VmError> {
+    match op {
+        Bytecode::Add
+        | Bytecode::Sub
+        | Bytecode::Mul
+        | Bytecode::Div
+        | Bytecode::FloorDiv
+        | Bytecode::Mod => binary_op(op, lhs, rhs),
+        _ => logic_op(op, lhs, rhs),
     }
 }
 
-fn logic_op(op: &Bytecode, lhs: &Object, rhs: &Object) -> Result<Object, VmError> {
+// index operand of `instruction`
+fn operand(chunk: &Chunk, instruction: &Decoded) -> Result<u64, VmError> {
+    chunk
+        .read_operand(instruction.operand_addr, instruction.operand_size)
+        .ok_or_else(|| truncated_operand(instruction))
+}
+
+// one byte part of a superinstruction's operand, `offset` bytes in
+fn byte_operand(chunk: &Chunk, instruction: &Decoded, offset: usize) -> Result<u64, VmError> {
+    chunk
+        .read_operand(instruction.operand_addr + offset, SIZE_NARROW_INDEX)
+        .ok_or_else(|| truncated_operand(instruction))
+}
+
+fn jump_offset(chunk: &Chunk, instruction: &Decoded) -> Result<i64, VmError> {
+    chunk
+        .get_jump_offset_safe(instruction.operand_addr)
+        .ok_or_else(|| truncated_operand(instruction))
+}
+
+fn truncated_operand(instruction: &Decoded) -> VmError {
+    VmError::InvalidBytecode(format!(
+        "Truncated operand at {:X}",
+        instruction.operand_addr
+    ))
+}
+
+// address `offset` bytes from the opcode at `ip`
+fn jump_target(ip: usize, offset: i64) -> Result<usize, VmError> {
+    ip.checked_add_signed(offset as isize)
+        .ok_or_else(|| VmError::InvalidBytecode(format!("Invalid jump at {:X}", ip)))
+}
+
+// a global id the code was compiled against, the object has been removed
+// since or the id is bogus
+fn undefined_global(globals: &SymbolTable, index: u64) -> VmError {
+    match globals.removed_name(index) {
+        Some(name) => VmError::UndefinedName(format!("NameError: name '{}' not defined", name)),
+        None => VmError::InvalidBytecode(format!("Invalid global: {}", index)),
+    }
+}
+
+fn undefined_builtin(builtins: &SymbolTable, index: u64) -> VmError {
+    match builtins.removed_name(index) {
+        Some(name) => VmError::UndefinedName(format!("NameError: builtin '{}' not defined", name)),
+        None => VmError::InvalidBytecode(format!("Invalid builtin: {}", index)),
+    }
+}
+
+fn get_constant(chunk: &Chunk, index: u64) -> Result<&Value, VmError> {
+    chunk
+        .constants
+        .get(index as usize)
+        .ok_or_else(|| VmError::InvalidBytecode(format!("Invalid constant: {}", index)))
+}
+
+fn get_constant_string(chunk: &Chunk, index: u64) -> Result<&str, VmError> {
+    match get_constant(chunk, index)? {
+        Value::String(value) => Ok(value),
+        other => Err(VmError::InvalidOperand(format!(
+            "Expected string constant, found {:?}",
+            other
+        ))),
+    }
+}
+
+/// Truth value of `object` as seen by conditionals and logic operators.
+/// Every truth test in the VM goes through here, so instance hooks such as
+/// `__bool__` and `__len__` only need to be dispatched from this one place;
+/// it is fallible because those hooks may raise.
+// `method` looked up on `object` as attribute `name`
+fn bound_method(
+    object: &Value,
+    name: &str,
+    method: Option<NativeFunction>,
+) -> Result<Value, VmError> {
+    match method {
+        Some(method) => Ok(Value::NativeFunction(method.into())),
+        None => Err(VmError::AttributeError(format!(
+            "AttributeError: '{}' object has no attribute '{}'",
+            object.type_name(),
+            name
+        ))),
+    }
+}
+
+// the global holding attribute `name` of `module`
+fn module_attribute<'a>(
+    module: &Module,
+    name: &str,
+    globals: &'a SymbolTable,
+) -> Option<&'a Object> {
+    let attribute_name = module.attribute_name(name);
+    if !globals.contains_name(&attribute_name) {
+        return None;
+    }
+    globals.get(globals.get_index(&attribute_name))
+}
+
+fn truthy(object: &Value) -> Result<bool, VmError> {
+    Ok(object.is_truthy())
+}
+
+pub(crate) fn unary_op(op: &Bytecode, rhs: &Value) -> Result<Value, VmError> {
+    let result = match (op, rhs) {
+        (Bytecode::Not, _) => Value::new_from_bool(!truthy(rhs)?),
+        (Bytecode::Neg, Value::Integer(value)) => {
+            Value::Integer(value.checked_neg().ok_or_else(integer_overflow)?)
+        }
+        (Bytecode::Neg, Value::Float(value)) => Value::Float(-value),
+        (Bytecode::Neg, value) => {
+            return Err(VmError::InvalidOperand(format!(
+                "TypeError: unsupported operand type for '-': {:?}",
+                value
+            )))
+        }
+        _ => unreachable!(),
+    };
+    Ok(result)
+}
+
+pub(crate) fn logic_op(op: &Bytecode, lhs: &Value, rhs: &Value) -> Result<Value, VmError> {
     let result = match op {
-        Bytecode::And => Value::new_from_bool(lhs.is_truthy() && rhs.is_truthy()),
-        Bytecode::Or => Value::new_from_bool(lhs.is_truthy() || rhs.is_truthy()),
-        Bytecode::Equal => Value::new_from_bool(lhs.value == rhs.value),
-        Bytecode::NotEqual => Value::new_from_bool(lhs.value != rhs.value),
-        Bytecode::Less => Value::new_from_bool(lhs.value < rhs.value),
-        Bytecode::LessEqual => Value::new_from_bool(lhs.value <= rhs.value),
-        Bytecode::Greater => Value::new_from_bool(lhs.value > rhs.value),
-        Bytecode::GreaterEqual => Value::new_from_bool(lhs.value >= rhs.value),
+        Bytecode::And => Value::new_from_bool(truthy(lhs)? && truthy(rhs)?),
+        Bytecode::Or => Value::new_from_bool(truthy(lhs)? || truthy(rhs)?),
+        Bytecode::Equal => Value::new_from_bool(lhs == rhs),
+        Bytecode::NotEqual => Value::new_from_bool(lhs != rhs),
+        Bytecode::Less | Bytecode::LessEqual | Bytecode::Greater | Bytecode::GreaterEqual => {
+            let (symbol, accepted): (&str, &[Ordering]) = match op {
+                Bytecode::Less => ("<", &[Ordering::Less]),
+                Bytecode::LessEqual => ("<=", &[Ordering::Less, Ordering::Equal]),
+                Bytecode::Greater => (">", &[Ordering::Greater]),
+                _ => (">=", &[Ordering::Greater, Ordering::Equal]),
+            };
+            let ordering = compare(symbol, lhs, rhs)?;
+            // NaN is ordered as equal to anything for sorting, but every
+            // comparison with it is false
+            Value::new_from_bool(accepted.contains(&ordering) && !is_nan(lhs) && !is_nan(rhs))
+        }
         _ => unreachable!(),
     };
-    Ok(Object::new(result))
+    Ok(result)
+}
+
+/// `lhs op rhs` for the hot case of two ints, skipping the matches of
+/// `binary_op` and `logic_op`. None for other operands, other operations
+/// and results that overflow, which those handle instead.
+#[inline]
+fn int_op(op: &Bytecode, lhs: &Value, rhs: &Value) -> Option<Value> {
+    let (Value::Integer(lhs), Value::Integer(rhs)) = (lhs, rhs) else {
+        return None;
+    };
+    let result = match op {
+        Bytecode::Add => Value::Integer(lhs.checked_add(*rhs)?),
+        Bytecode::Sub => Value::Integer(lhs.checked_sub(*rhs)?),
+        Bytecode::Mul => Value::Integer(lhs.checked_mul(*rhs)?),
+        // the floored modulo is the Euclidean one for positive divisors
+        Bytecode::Mod if *rhs > 0 => Value::Integer(lhs.rem_euclid(*rhs)),
+        Bytecode::Equal => Value::Bool(lhs == rhs),
+        Bytecode::NotEqual => Value::Bool(lhs != rhs),
+        Bytecode::Less => Value::Bool(lhs < rhs),
+        Bytecode::LessEqual => Value::Bool(lhs <= rhs),
+        Bytecode::Greater => Value::Bool(lhs > rhs),
+        Bytecode::GreaterEqual => Value::Bool(lhs >= rhs),
+        _ => return None,
+    };
+    Some(result)
+}
+
+fn is_nan(object: &Value) -> bool {
+    matches!(object, Value::Float(value) if value.is_nan())
 }
 
-fn binary_op(op: &Bytecode, lhs: &Object, rhs: &Object) -> Result<Object, VmError> {
+pub(crate) fn binary_op(op: &Bytecode, lhs: &Value, rhs: &Value) -> Result<Value, VmError> {
     let unsupported_operand_types = || -> VmError {
         VmError::InvalidOperand(format!(
             "TypeError: unsupported operand type(s) for {:?}: {:?} and {:?}",
@@ -415,13 +1678,24 @@ fn binary_op(op: &Bytecode, lhs: &Object, rhs: &Object) -> Result<Object, VmErro
         ))
     };
 
+    let zero_division = |message: &str| -> VmError {
+        VmError::ZeroDivisionError(format!("ZeroDivisionError: {}", message))
+    };
+
     let apply_i64_op = |lhs: i64, rhs: i64| -> Result<Value, VmError> {
         let result = match op {
-            Bytecode::Add => lhs + rhs,
-            Bytecode::Sub => lhs - rhs,
-            Bytecode::Mul => lhs * rhs,
-            Bytecode::Div => lhs / rhs,
-            __ => return Err(unsupported_operand_types()),
+            Bytecode::Add => lhs.checked_add(rhs).ok_or_else(integer_overflow)?,
+            Bytecode::Sub => lhs.checked_sub(rhs).ok_or_else(integer_overflow)?,
+            Bytecode::Mul => lhs.checked_mul(rhs).ok_or_else(integer_overflow)?,
+            Bytecode::Div if rhs == 0 => return Err(zero_division("division by zero")),
+            Bytecode::Div => lhs.checked_div(rhs).ok_or_else(division_overflow)?,
+            Bytecode::FloorDiv if rhs == 0 => {
+                return Err(zero_division("integer division or modulo by zero"))
+            }
+            Bytecode::FloorDiv => floor_div(lhs, rhs).ok_or_else(division_overflow)?,
+            Bytecode::Mod if rhs == 0 => return Err(zero_division("integer modulo by zero")),
+            Bytecode::Mod => floor_mod(lhs, rhs).ok_or_else(division_overflow)?,
+            _ => return Err(unsupported_operand_types()),
         };
         Ok(Value::Integer(result))
     };
@@ -431,7 +1705,23 @@ fn binary_op(op: &Bytecode, lhs: &Object, rhs: &Object) -> Result<Object, VmErro
             Bytecode::Add => lhs + rhs,
             Bytecode::Sub => lhs - rhs,
             Bytecode::Mul => lhs * rhs,
+            Bytecode::Div if rhs == 0.0 => return Err(zero_division("float division by zero")),
             Bytecode::Div => lhs / rhs,
+            Bytecode::FloorDiv if rhs == 0.0 => {
+                return Err(zero_division("float floor division by zero"))
+            }
+            Bytecode::FloorDiv => (lhs / rhs).floor(),
+            Bytecode::Mod => {
+                if rhs == 0.0 {
+                    return Err(zero_division("float modulo"));
+                }
+                let result = lhs % rhs;
+                if result != 0.0 && (result < 0.0) != (rhs < 0.0) {
+                    result + rhs
+                } else {
+                    result
+                }
+            }
             _ => return Err(unsupported_operand_types()),
         };
         Ok(Value::Float(result))
@@ -439,27 +1729,378 @@ fn binary_op(op: &Bytecode, lhs: &Object, rhs: &Object) -> Result<Object, VmErro
 
     let apply_string_op = |lhs: &str, rhs: &str| -> Result<Value, VmError> {
         match op {
-            Bytecode::Add => Ok(Value::String(lhs.to_string() + rhs)),
-            _ => return Err(unsupported_operand_types()),
+            Bytecode::Add => Ok(Value::String((lhs.to_string() + rhs).into())),
+            _ => Err(unsupported_operand_types()),
         }
     };
 
-    let result = match &lhs.value {
-        Value::Integer(lhs_val) => match &rhs.value {
-            Value::Integer(rhs_val) => Object::new(apply_i64_op(*lhs_val, *rhs_val)?),
-            Value::Float(rhs_val) => Object::new(apply_f64_op(*lhs_val as f64, *rhs_val)?),
+    if let Bytecode::Mul = op {
+        match (lhs, rhs) {
+            (
+                sequence @ (Value::String(_) | Value::Bytes(_) | Value::List(_) | Value::Tuple(_)),
+                Value::Integer(count),
+            )
+            | (
+                Value::Integer(count),
+                sequence @ (Value::String(_) | Value::Bytes(_) | Value::List(_) | Value::Tuple(_)),
+            ) => return repeat_sequence(sequence, *count),
+            _ => (),
+        }
+    }
+
+    let result = match lhs {
+        Value::Integer(lhs_val) => match rhs {
+            Value::Integer(rhs_val) => apply_i64_op(*lhs_val, *rhs_val)?,
+            Value::Float(rhs_val) => apply_f64_op(*lhs_val as f64, *rhs_val)?,
             _ => return Err(unsupported_operand_types()),
         },
-        Value::Float(lhs_val) => match &rhs.value {
-            Value::Integer(rhs_val) => Object::new(apply_f64_op(*lhs_val, *rhs_val as f64)?),
-            Value::Float(rhs_val) => Object::new(apply_f64_op(*lhs_val, *rhs_val)?),
+        Value::Float(lhs_val) => match rhs {
+            Value::Integer(rhs_val) => apply_f64_op(*lhs_val, *rhs_val as f64)?,
+            Value::Float(rhs_val) => apply_f64_op(*lhs_val, *rhs_val)?,
             _ => return Err(unsupported_operand_types()),
         },
-        Value::String(lhs_val) => match &rhs.value {
-            Value::String(rhs_val) => Object::new(apply_string_op(&lhs_val, &rhs_val)?),
+        Value::String(lhs_val) => match rhs {
+            // a tuple holds the arguments, anything else is the only one
+            Value::Tuple(args) if matches!(op, Bytecode::Mod) => {
+                Value::String(percent_format(lhs_val, args)?.into())
+            }
+            _ if matches!(op, Bytecode::Mod) => {
+                Value::String(percent_format(lhs_val, std::slice::from_ref(rhs))?.into())
+            }
+            Value::String(rhs_val) => apply_string_op(lhs_val, rhs_val)?,
+            _ => return Err(unsupported_operand_types()),
+        },
+        Value::Bytes(lhs_val) => match (rhs, op) {
+            (Value::Bytes(rhs_val), Bytecode::Add) => {
+                Value::Bytes([lhs_val.as_slice(), rhs_val].concat().into())
+            }
             _ => return Err(unsupported_operand_types()),
         },
         _ => return Err(unsupported_operand_types()),
     };
     Ok(result)
 }
+
+/// Bytes a repeated sequence may take.
+const MAX_REPEAT_SIZE: usize = 1 << 30;
+
+/// `sequence * count` in either order, empty for counts below one.
+fn repeat_sequence(sequence: &Value, count: i64) -> Result<Value, VmError> {
+    let count = count.max(0) as usize;
+    let size = match sequence {
+        Value::String(value) => value.len(),
+        Value::Bytes(value) => value.len(),
+        Value::List(items) | Value::Tuple(items) => items.len() * size_of::<Value>(),
+        _ => unreachable!(),
+    };
+    // failing to allocate would abort the process, so too much raises
+    if size
+        .checked_mul(count)
+        .is_none_or(|size| size > MAX_REPEAT_SIZE)
+    {
+        return Err(VmError::Exception(Exception::new(
+            "MemoryError",
+            vec![Value::String(
+                "repeated sequence is too long".to_string().into(),
+            )],
+        )));
+    }
+    Ok(match sequence {
+        Value::String(value) => Value::String(value.repeat(count).into()),
+        Value::Bytes(value) => Value::Bytes(value.repeat(count).into()),
+        Value::List(items) => Value::List(repeat_items(items, count).into()),
+        Value::Tuple(items) => Value::Tuple(repeat_items(items, count).into()),
+        _ => unreachable!(),
+    })
+}
+
+fn repeat_items(items: &[Value], count: usize) -> Vec<Value> {
+    (0..count).flat_map(|_| items.iter().cloned()).collect()
+}
+
+/// `target = target + rhs` without copying `target` when both are strings,
+/// so repeatedly appending to a string is amortized linear overall.
+fn inplace_add(target: &mut Value, rhs: &Value) -> Result<(), VmError> {
+    if let Some(result) = int_op(&Bytecode::Add, target, rhs) {
+        *target = result;
+        return Ok(());
+    }
+    if let (Value::String(target), Value::String(rhs)) = (&mut *target, rhs) {
+        Rc::make_mut(target).push_str(rhs);
+        return Ok(());
+    }
+    *target = binary_op(&Bytecode::Add, target, rhs)?;
+    Ok(())
+}
+
+/// `object[index]` for the sequence types, counting negative indices from
+/// the end. Strings index by character, bytes yield the byte as an int.
+fn get_item(object: &Value, index: &Value) -> Result<Value, VmError> {
+    let length = match object {
+        Value::String(value) => value.chars().count(),
+        Value::Bytes(value) => value.len(),
+        Value::List(items) | Value::Tuple(items) => items.len(),
+        other => {
+            return Err(VmError::InvalidOperand(format!(
+                "TypeError: '{}' object is not subscriptable",
+                other.type_name()
+            )))
+        }
+    };
+    let index = match index {
+        Value::Integer(index) => *index,
+        other => {
+            return Err(VmError::InvalidOperand(format!(
+                "TypeError: {} indices must be integers, not {}",
+                object.type_name(),
+                other.type_name()
+            )))
+        }
+    };
+    let position = if index < 0 {
+        index + length as i64
+    } else {
+        index
+    };
+    if position < 0 || position >= length as i64 {
+        let type_name = match object {
+            Value::String(_) => "string",
+            Value::Bytes(_) => "bytes",
+            Value::Tuple(_) => "tuple",
+            _ => "list",
+        };
+        return Err(VmError::IndexError(format!(
+            "IndexError: {} index out of range",
+            type_name
+        )));
+    }
+    let item = match &object {
+        Value::String(value) => Value::String(
+            value
+                .chars()
+                .nth(position as usize)
+                .unwrap()
+                .to_string()
+                .into(),
+        ),
+        Value::Bytes(value) => Value::Integer(value[position as usize] as i64),
+        Value::List(items) | Value::Tuple(items) => items[position as usize].clone(),
+        _ => unreachable!(),
+    };
+    Ok(item)
+}
+
+// Python's `%` takes the sign of the divisor, None for a zero divisor or
+// a remainder that overflows
+pub(crate) fn floor_mod(lhs: i64, rhs: i64) -> Option<i64> {
+    let result = lhs.checked_rem(rhs)?;
+    if result != 0 && (result < 0) != (rhs < 0) {
+        Some(result + rhs)
+    } else {
+        Some(result)
+    }
+}
+
+/// Quotient rounded towards negative infinity like Python's `//`, None
+/// for a zero divisor or a quotient that overflows.
+pub(crate) fn floor_div(lhs: i64, rhs: i64) -> Option<i64> {
+    let quotient = lhs.checked_div(rhs)?;
+    if lhs % rhs != 0 && (lhs < 0) != (rhs < 0) {
+        Some(quotient - 1)
+    } else {
+        Some(quotient)
+    }
+}
+
+/// Error of integer arithmetic whose result doesn't fit an int.
+pub(crate) fn integer_overflow() -> VmError {
+    VmError::InvalidOperand(String::from("OverflowError: integer overflow"))
+}
+
+/// Error of an integer division whose result doesn't fit an int.
+pub(crate) fn division_overflow() -> VmError {
+    VmError::ValueError(String::from(
+        "OverflowError: integer division result too large",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // runs the instructions `emit` adds to an empty script
+    fn run_chunk(emit: fn(&mut Chunk)) -> Result<Value, VmError> {
+        let mut function = Function::new_global_scope();
+        emit(&mut function.chunk);
+        Vm::new().interpret(
+            Config::new(),
+            &mut SymbolTable::new(),
+            &SymbolTable::new(),
+            function,
+        )
+    }
+
+    #[test]
+    fn test_call_shares_function() {
+        let mut function = Function::new(String::from("f"));
+        function.chunk.add_constant(Value::Integer(1));
+        let function = Rc::new(function);
+        let mut vm = Vm::new();
+        vm.init(Config::new(), Function::new_global_scope());
+        vm.call(
+            Value::Function(function.clone()),
+            0,
+            Vec::new(),
+            &mut SymbolTable::new(),
+            &SymbolTable::new(),
+        )
+        .unwrap();
+        assert_eq!(vm.frames.len(), 2);
+        assert!(Rc::ptr_eq(&vm.current_frame().function, &function));
+    }
+
+    #[test]
+    fn test_malformed_bytecode() {
+        let emitters: Vec<fn(&mut Chunk)> = vec![
+            |chunk| chunk.emit(Bytecode::Pop),
+            |chunk| chunk.emit(Bytecode::Add),
+            |chunk| {
+                chunk.emit_with_index(Bytecode::Const, 5);
+            },
+            |chunk| {
+                chunk.emit_with_index(Bytecode::GetLocal, 3);
+            },
+            |chunk| {
+                chunk.emit_with_index(Bytecode::SetLocal, 0);
+            },
+            |chunk| {
+                chunk.emit(Bytecode::None);
+                chunk.emit_with_index(Bytecode::SetLocal, 9);
+            },
+            |chunk| {
+                chunk.emit(Bytecode::None);
+                chunk.emit_with_index(Bytecode::SetGlobal, 7);
+            },
+            |chunk| {
+                chunk.emit_with_index(Bytecode::Call, 0);
+            },
+            |chunk| chunk.emit(Bytecode::Unknown),
+            |chunk| chunk.data.push(0xEE),
+            // operands cut short
+            |chunk| {
+                chunk.add_constant(Value::Integer(1));
+                chunk.emit(Bytecode::Wide);
+                chunk.emit(Bytecode::Const);
+                chunk.data.push(0);
+            },
+            // prefixes only go before instructions with an index
+            |chunk| {
+                chunk.emit(Bytecode::Wide);
+                chunk.emit(Bytecode::Pop);
+            },
+            |chunk| chunk.emit(Bytecode::Jump),
+            // jumping before the start of the chunk
+            |chunk| {
+                chunk.emit(Bytecode::Jump);
+                chunk.data.extend_from_slice(&(-8i16).to_ne_bytes());
+            },
+            |chunk| {
+                chunk.emit(Bytecode::PushFalse);
+                chunk.emit(Bytecode::JumpIfFalse);
+                chunk.data.push(9);
+            },
+        ];
+        emitters
+            .into_iter()
+            .enumerate()
+            .for_each(|(case, emit)| match run_chunk(emit) {
+                Err(VmError::InvalidBytecode(_)) => {}
+                other => panic!("case {}: {:?}", case, other),
+            });
+    }
+
+    #[test]
+    fn test_negation_overflow() {
+        let result = run_chunk(|chunk| {
+            let index = chunk.add_constant(Value::Integer(i64::MIN));
+            chunk.emit_with_index(Bytecode::Const, index);
+            chunk.emit(Bytecode::Neg);
+        });
+        match result {
+            Err(VmError::InvalidOperand(message)) => {
+                assert_eq!(message, "OverflowError: integer overflow")
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_int_op() {
+        let operands = [0, 1, -1, 7, -7, 3, i64::MAX, i64::MIN];
+        let ops = [
+            Bytecode::Add,
+            Bytecode::Sub,
+            Bytecode::Mul,
+            Bytecode::Mod,
+            Bytecode::Equal,
+            Bytecode::NotEqual,
+            Bytecode::Less,
+            Bytecode::LessEqual,
+            Bytecode::Greater,
+            Bytecode::GreaterEqual,
+        ];
+        ops.iter().for_each(|op| {
+            for lhs in operands {
+                for rhs in operands {
+                    let (lhs, rhs) = (Value::Integer(lhs), Value::Integer(rhs));
+                    let Some(result) = int_op(op, &lhs, &rhs) else {
+                        continue;
+                    };
+                    let expected = match op {
+                        Bytecode::Add | Bytecode::Sub | Bytecode::Mul | Bytecode::Mod => {
+                            binary_op(op, &lhs, &rhs)
+                        }
+                        _ => logic_op(op, &lhs, &rhs),
+                    };
+                    assert_eq!(Some(result), expected.ok(), "{:?} {:?} {:?}", lhs, op, rhs);
+                }
+            }
+        });
+        // left to `binary_op`
+        vec![
+            (Bytecode::Add, Value::Integer(i64::MAX), Value::Integer(1)),
+            (Bytecode::Mod, Value::Integer(7), Value::Integer(0)),
+            (Bytecode::Mod, Value::Integer(7), Value::Integer(-2)),
+            (Bytecode::Div, Value::Integer(7), Value::Integer(2)),
+            (Bytecode::Add, Value::Integer(1), Value::Float(1.0)),
+            (Bytecode::Less, Value::Bool(true), Value::Integer(2)),
+        ]
+        .into_iter()
+        .for_each(|(op, lhs, rhs)| assert_eq!(int_op(&op, &lhs, &rhs), None));
+
+        // which raises when the VM falls back to it on overflow
+        vec![
+            (|chunk| {
+                let lhs = chunk.add_constant(Value::Integer(i64::MAX));
+                let rhs = chunk.add_constant(Value::Integer(1));
+                chunk.emit_with_index(Bytecode::Const, lhs);
+                chunk.emit_with_index(Bytecode::Const, rhs);
+                chunk.emit(Bytecode::Add);
+            }) as fn(&mut Chunk),
+            |chunk| {
+                let lhs = chunk.add_constant(Value::Integer(i64::MIN));
+                let rhs = chunk.add_constant(Value::Integer(2));
+                chunk.emit_with_index(Bytecode::Const, lhs);
+                chunk.emit_with_index(Bytecode::Const, rhs);
+                chunk.emit(Bytecode::Mul);
+            },
+        ]
+        .into_iter()
+        .for_each(|emit| match run_chunk(emit) {
+            Err(VmError::InvalidOperand(message)) => {
+                assert_eq!(message, "OverflowError: integer overflow")
+            }
+            other => panic!("{:?}", other),
+        });
+    }
+}
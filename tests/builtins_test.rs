@@ -0,0 +1,618 @@
+use rustpy::config::Config;
+use rustpy::object::Value;
+use rustpy::{Interpreter, InterpreterError};
+
+mod common;
+
+fn assert_results(cases: Vec<(&str, Value)>) {
+    cases.into_iter().for_each(|(source, expected)| {
+        let mut interpreter = Interpreter::new(Config::new());
+        let result = interpreter.run(source);
+        assert!(result.is_ok(), "{}: {:?}", source, result);
+        common::assert_value_eq(&result.unwrap(), &expected);
+    });
+}
+
+fn assert_errors(cases: Vec<(&str, &str)>) {
+    cases.into_iter().for_each(|(source, expected)| {
+        let mut interpreter = Interpreter::new(Config::new());
+        match interpreter.run(source) {
+            Err(InterpreterError::VmError(err)) => {
+                let message = format!("{:?}", err);
+                assert!(message.contains(expected), "{}: {}", source, message)
+            }
+            other => panic!("{}: expected VmError, got {:?}", source, other),
+        }
+    });
+}
+
+#[test]
+fn conversions() {
+    let string = |text: &str| Value::String(String::from(text).into());
+    assert_results(vec![
+        ("int()", Value::Integer(0)),
+        (r#"int("42")"#, Value::Integer(42)),
+        (r#"int(" -1_000 ")"#, Value::Integer(-1000)),
+        (r#"int("ff", 16)"#, Value::Integer(255)),
+        (r#"int("0x_ff", 0)"#, Value::Integer(255)),
+        (r#"int("0b101", 2)"#, Value::Integer(5)),
+        ("int(3.9)", Value::Integer(3)),
+        ("int(-3.9)", Value::Integer(-3)),
+        ("int(True)", Value::Integer(1)),
+        ("float()", Value::Float(0.0)),
+        (r#"float(" 2.5 ")"#, Value::Float(2.5)),
+        (r#"float("1e3")"#, Value::Float(1000.0)),
+        ("float(2)", Value::Float(2.0)),
+        ("str()", string("")),
+        ("str(None)", string("None")),
+        ("str(1.0)", string("1.0")),
+        ("str(12) + str(True)", string("12True")),
+        ("bool()", Value::Bool(false)),
+        (r#"bool("")"#, Value::Bool(false)),
+        ("bool(0.5)", Value::Bool(true)),
+        (r#"bool("a b".split())"#, Value::Bool(true)),
+    ]);
+}
+
+#[test]
+fn conversion_errors() {
+    assert_errors(vec![
+        (
+            r#"int("3.5")"#,
+            "ValueError: invalid literal for int() with base 10: '3.5'",
+        ),
+        (r#"int("1__0")"#, "invalid literal for int()"),
+        (r#"int("")"#, "invalid literal for int()"),
+        (r#"int("z", 37)"#, "int() base must be >= 2 and <= 36, or 0"),
+        (
+            "int(1.5, 10)",
+            "can't convert non-string with explicit base",
+        ),
+        ("int(None)", "not 'NoneType'"),
+        (
+            r#"float("abc")"#,
+            "ValueError: could not convert string to float: 'abc'",
+        ),
+        ("float(None)", "not 'NoneType'"),
+        ("str(1, 2)", "str() takes at most 1 argument(s) (2 given)"),
+    ]);
+}
+
+#[test]
+fn aggregates() {
+    let string = |text: &str| Value::String(String::from(text).into());
+    assert_results(vec![
+        ("max(3, 1, 2)", Value::Integer(3)),
+        ("min(3, 1.5, 2)", Value::Float(1.5)),
+        (r#"max("hello")"#, string("o")),
+        (r#"min("b a c".split())"#, string("a")),
+        (r#"max("a ccc bb".split(), key=len)"#, string("ccc")),
+        (r#"max("bb aa".split(), key=len)"#, string("bb")),
+        (r#"min("", default=7)"#, Value::Integer(7)),
+        ("max(1, 2.5, True)", Value::Float(2.5)),
+        (
+            "def neg(x):\n    return -x\nmax(3, 1, 2, key=neg)",
+            Value::Integer(1),
+        ),
+        (r#"sum(b"ab")"#, Value::Integer(195)),
+        (r#"sum(b"ab", 1.5)"#, Value::Float(196.5)),
+        (r#"sum("", start=4)"#, Value::Integer(4)),
+    ]);
+}
+
+#[test]
+fn aggregate_errors() {
+    assert_errors(vec![
+        ("max()", "max expected at least 1 argument, got 0"),
+        (r#"max("")"#, "ValueError: max() arg is an empty sequence"),
+        ("min(1)", "TypeError: 'int' object is not iterable"),
+        (
+            r#"max(1, "a")"#,
+            "'>' not supported between instances of 'str' and 'int'",
+        ),
+        (
+            "min(1, 2, default=0)",
+            "Cannot specify a default for min() with multiple positional arguments",
+        ),
+        (r#"max("ab", key=1)"#, "'int' object is not callable"),
+        (
+            r#"sum("ab".split(), "")"#,
+            "sum() can't sum strings [use ''.join(seq) instead]",
+        ),
+        (r#"sum("ab".split())"#, "unsupported operand type(s)"),
+        (
+            "sum(1, 2, 3)",
+            "sum() takes at most 2 argument(s) (3 given)",
+        ),
+    ]);
+}
+
+#[test]
+fn ordering() {
+    let strings = |texts: &[&str]| {
+        Value::List(
+            texts
+                .iter()
+                .map(|text| Value::String(text.to_string().into()))
+                .collect::<Vec<_>>()
+                .into(),
+        )
+    };
+    assert_results(vec![
+        (r#"sorted("cab")"#, strings(&["a", "b", "c"])),
+        (r#"sorted("cab", reverse=True)"#, strings(&["c", "b", "a"])),
+        (
+            r#"sorted("bb a ccc dd".split(), key=len)"#,
+            strings(&["a", "bb", "dd", "ccc"]),
+        ),
+        (
+            r#"sorted("bb a ccc dd".split(), key=len, reverse=True)"#,
+            strings(&["ccc", "bb", "dd", "a"]),
+        ),
+        (r#"sorted("")"#, Value::List(vec![].into())),
+        (r#"list(reversed("abc"))"#, strings(&["c", "b", "a"])),
+        (
+            r#"list(reversed(b"ab"))"#,
+            Value::List(vec![Value::Integer(98), Value::Integer(97)].into()),
+        ),
+        (r#"sorted(reversed("bca"))"#, strings(&["a", "b", "c"])),
+        (
+            "r = reversed(\"ab\")\nlist(r)\nlist(r)",
+            Value::List(vec![].into()),
+        ),
+        ("list()", Value::List(vec![].into())),
+    ]);
+}
+
+#[test]
+fn ordering_errors() {
+    assert_errors(vec![
+        ("sorted(1)", "'int' object is not iterable"),
+        (
+            r#"sorted("a b".split(), key=len, reverse=True, cmp=1)"#,
+            "unexpected keyword argument 'cmp'",
+        ),
+        (r#"sorted("ab", key=1)"#, "'int' object is not callable"),
+        ("reversed(1)", "'int' object is not reversible"),
+        (
+            r#"reversed(reversed("a"))"#,
+            "'reversed' object is not reversible",
+        ),
+        ("sorted()", "sorted() takes exactly 1 argument(s) (0 given)"),
+    ]);
+}
+
+// script callbacks run as the first statement: locals are still addressed
+// from the bottom of the value stack
+const CALLBACKS: &str = r#"
+import functools
+def double(x):
+    return x * 2
+def add(a, b):
+    return a + b
+def odd(x):
+    return x % 2
+"#;
+
+#[test]
+fn higher_order() {
+    let integers = |values: &[i64]| {
+        Value::List(
+            values
+                .iter()
+                .map(|v| Value::Integer(*v))
+                .collect::<Vec<_>>()
+                .into(),
+        )
+    };
+    let sources: Vec<(String, Value)> = vec![
+        (
+            r#"list(map(double, b"\x01\x02\x03"))"#,
+            integers(&[2, 4, 6]),
+        ),
+        (
+            r#"list(map(add, b"\x01\x02", b"\x0a\x14\x1e"))"#,
+            integers(&[11, 22]),
+        ),
+        ("list(map(len, \"a bb\".split()))", integers(&[1, 2])),
+        (r#"list(filter(odd, b"\x01\x02\x03"))"#, integers(&[1, 3])),
+        (
+            r#"list(filter(None, "a  b".split(" ")))"#,
+            Value::List(
+                vec![
+                    Value::String(String::from("a").into()),
+                    Value::String(String::from("b").into()),
+                ]
+                .into(),
+            ),
+        ),
+        (
+            r#"sum(map(double, filter(odd, b"\x01\x02\x03")))"#,
+            Value::Integer(8),
+        ),
+        (
+            r#"functools.reduce(add, b"\x01\x02\x03")"#,
+            Value::Integer(6),
+        ),
+        (r#"functools.reduce(add, b"", 5)"#, Value::Integer(5)),
+        (
+            r#"functools.reduce(add, map(double, b"\x01\x02"), 1)"#,
+            Value::Integer(7),
+        ),
+        ("functools.partial(add, 1)(2)", Value::Integer(3)),
+        ("functools.partial(add, 1, 2)()", Value::Integer(3)),
+        (
+            r#"functools.partial(sorted, key=len)("ccc a bb".split())"#,
+            Value::List(
+                vec![
+                    Value::String(String::from("a").into()),
+                    Value::String(String::from("bb").into()),
+                    Value::String(String::from("ccc").into()),
+                ]
+                .into(),
+            ),
+        ),
+        (
+            r#"functools.partial(max, key=len)("a bb c".split(), key=None)"#,
+            Value::String(String::from("c").into()),
+        ),
+        (
+            "functools.partial(add, 1).args",
+            Value::Tuple(vec![Value::Integer(1)].into()),
+        ),
+        ("functools.lru_cache(double)(4)", Value::Integer(8)),
+        (
+            "functools.lru_cache(maxsize=None)(double)(4)",
+            Value::Integer(8),
+        ),
+        (
+            "functools.partial(functools.lru_cache(add), 1)(2)",
+            Value::Integer(3),
+        ),
+    ]
+    .into_iter()
+    .map(|(expr, expected)| (format!("{}{}", CALLBACKS, expr), expected))
+    .collect();
+    assert_results(
+        sources
+            .iter()
+            .map(|(source, expected)| (source.as_str(), expected.clone()))
+            .collect(),
+    );
+}
+
+#[test]
+fn higher_order_errors() {
+    let sources: Vec<(String, &str)> = vec![
+        ("map(double)", "map() must have at least two arguments."),
+        ("map(double, 1)", "'int' object is not iterable"),
+        (r#"list(map(1, "a"))"#, "'int' object is not callable"),
+        (r#"list(map(add, "a"))"#, "expect 2 arguments, 1 given"),
+        ("filter(odd, 1)", "'int' object is not iterable"),
+        (
+            r#"functools.reduce(add, "")"#,
+            "reduce() of empty iterable with no initial value",
+        ),
+        (
+            r#"functools.reduce(add)"#,
+            "reduce() takes at least 2 argument(s) (1 given)",
+        ),
+        (
+            "functools.partial(1)",
+            "the first argument must be callable",
+        ),
+        (
+            "functools.partial(add, 1)(2, 3)",
+            "expect 2 arguments, 3 given",
+        ),
+        (
+            "functools.partial(add, x=1)(2)",
+            "unexpected keyword argument 'x'",
+        ),
+        (
+            r#"functools.lru_cache(add)(list("a"), 1)"#,
+            "unhashable type: 'list'",
+        ),
+        (
+            r#"functools.lru_cache("a")"#,
+            "Expected first argument to be an integer, a callable, or None",
+        ),
+    ]
+    .into_iter()
+    .map(|(expr, expected)| (format!("{}{}", CALLBACKS, expr), expected))
+    .collect();
+    assert_errors(
+        sources
+            .iter()
+            .map(|(source, expected)| (source.as_str(), *expected))
+            .collect(),
+    );
+}
+
+#[test]
+fn numeric_equality() {
+    assert_results(vec![
+        ("1 == 1.0", Value::Bool(true)),
+        ("True == 1", Value::Bool(true)),
+        ("False == 0.0", Value::Bool(true)),
+        ("1 != 1.0", Value::Bool(false)),
+        ("0.5 == 0", Value::Bool(false)),
+        ("9007199254740993 == 9007199254740992.0", Value::Bool(false)),
+        (r#""1" == 1"#, Value::Bool(false)),
+        (r#"list("ab") == list("ab")"#, Value::Bool(true)),
+        ("divmod(7, 2) == divmod(7.0, 2.0)", Value::Bool(true)),
+        ("x = 0.0\nx == -x", Value::Bool(true)),
+    ]);
+}
+
+#[test]
+fn ordering_comparisons() {
+    assert_results(vec![
+        ("2 < 1.5", Value::Bool(false)),
+        ("1 <= 1.0", Value::Bool(true)),
+        ("True > 0", Value::Bool(true)),
+        (r#""ab" < "b""#, Value::Bool(true)),
+        (r#"b"b" >= b"ab""#, Value::Bool(true)),
+        (r#"list("ab") < list("b")"#, Value::Bool(true)),
+        ("divmod(7, 2) > divmod(7, 3)", Value::Bool(true)),
+        (r#"float("nan") <= float("nan")"#, Value::Bool(false)),
+        (r#"float("nan") > 1"#, Value::Bool(false)),
+    ]);
+    assert_errors(vec![
+        (
+            r#""a" < 1"#,
+            "TypeError: '<' not supported between instances of 'str' and 'int'",
+        ),
+        (
+            r#"1 >= "a""#,
+            "TypeError: '>=' not supported between instances of 'int' and 'str'",
+        ),
+        (
+            "None > None",
+            "TypeError: '>' not supported between instances of 'NoneType' and 'NoneType'",
+        ),
+        (
+            r#"list("a") <= "a""#,
+            "TypeError: '<=' not supported between instances of 'list' and 'str'",
+        ),
+    ]);
+}
+
+#[test]
+fn truth_tests() {
+    assert_results(vec![
+        (r#"any("0 1".split())"#, Value::Bool(true)),
+        (r#"any(b"\x00\x00")"#, Value::Bool(false)),
+        (r#"any("")"#, Value::Bool(false)),
+        (r#"all(b"\x01\x02")"#, Value::Bool(true)),
+        (r#"all(b"\x01\x00")"#, Value::Bool(false)),
+        (r#"all("")"#, Value::Bool(true)),
+        (r#"any(map(bool, b"\x00\x01"))"#, Value::Bool(true)),
+        ("False < True", Value::Bool(true)),
+        ("max(True, False)", Value::Bool(true)),
+        // short-circuits: the rest of the shared iterator is left over
+        (
+            "items = map(bool, b\"\\x00\\x01\\x00\\x01\")\nany(items)\nlist(items)",
+            Value::List(vec![Value::Bool(false), Value::Bool(true)].into()),
+        ),
+    ]);
+    assert_errors(vec![("any(1)", "'int' object is not iterable")]);
+}
+
+#[test]
+fn character_conversions() {
+    let string = |text: &str| Value::String(String::from(text).into());
+    assert_results(vec![
+        (r#"ord("a")"#, Value::Integer(97)),
+        (r#"ord("é")"#, Value::Integer(233)),
+        (r#"ord("😀")"#, Value::Integer(0x1F600)),
+        (r#"ord(b"A")"#, Value::Integer(65)),
+        ("chr(97)", string("a")),
+        ("chr(0x1F600)", string("😀")),
+        ("chr(ord(\"z\"))", string("z")),
+        ("hex(255)", string("0xff")),
+        ("hex(-255)", string("-0xff")),
+        ("hex(0)", string("0x0")),
+        ("bin(5)", string("0b101")),
+        ("bin(-5)", string("-0b101")),
+        ("oct(8)", string("0o10")),
+        ("oct(True)", string("0o1")),
+        (
+            "hex(-9223372036854775807 - 1)",
+            string("-0x8000000000000000"),
+        ),
+    ]);
+    assert_errors(vec![
+        (
+            r#"ord("ab")"#,
+            "ord() expected a character, but string of length 2 found",
+        ),
+        (
+            r#"ord("")"#,
+            "ord() expected a character, but string of length 0 found",
+        ),
+        ("ord(1)", "ord() expected string of length 1, but int found"),
+        ("chr(-1)", "chr() arg not in range(0x110000)"),
+        ("chr(1114112)", "chr() arg not in range(0x110000)"),
+        ("chr(55296)", "surrogate code point"),
+        (
+            r#"chr("a")"#,
+            "'str' object cannot be interpreted as an integer",
+        ),
+        (
+            "hex(1.5)",
+            "'float' object cannot be interpreted as an integer",
+        ),
+    ]);
+}
+
+#[test]
+fn numeric_builtins() {
+    let pair = |lhs: Value, rhs: Value| Value::Tuple(vec![lhs, rhs].into());
+    assert_results(vec![
+        ("round(2.5)", Value::Integer(2)),
+        ("round(3.5)", Value::Integer(4)),
+        ("round(-0.5)", Value::Integer(0)),
+        ("round(2.675, 2)", Value::Float(2.67)),
+        ("round(1.25, 1)", Value::Float(1.2)),
+        ("round(1234.5, -2)", Value::Float(1200.0)),
+        ("round(7)", Value::Integer(7)),
+        ("round(1250, -2)", Value::Integer(1200)),
+        ("round(1350, -2)", Value::Integer(1400)),
+        ("round(-1250, -2)", Value::Integer(-1200)),
+        ("round(5, -20)", Value::Integer(0)),
+        ("divmod(7, 2)", pair(Value::Integer(3), Value::Integer(1))),
+        ("divmod(-7, 2)", pair(Value::Integer(-4), Value::Integer(1))),
+        (
+            "divmod(-9223372036854775807 - 1, 3)",
+            pair(Value::Integer(-3074457345618258603), Value::Integer(1)),
+        ),
+        (
+            "divmod(7, -2)",
+            pair(Value::Integer(-4), Value::Integer(-1)),
+        ),
+        ("divmod(7.5, 2)", pair(Value::Float(3.0), Value::Float(1.5))),
+        (
+            "divmod(-7.5, 2)",
+            pair(Value::Float(-4.0), Value::Float(0.5)),
+        ),
+        ("divmod(7, 2)[0]", Value::Integer(3)),
+        ("len(divmod(7, 2))", Value::Integer(2)),
+        ("pow(2, 10)", Value::Integer(1024)),
+        ("pow(2, -1)", Value::Float(0.5)),
+        ("pow(2.0, 3)", Value::Float(8.0)),
+        ("pow(3, 4, 5)", Value::Integer(1)),
+        ("pow(3, 2, -5)", Value::Integer(-1)),
+        ("pow(3, -1, 7)", Value::Integer(5)),
+        ("pow(-2, 3, 5)", Value::Integer(2)),
+        ("pow(2, 100, 1)", Value::Integer(0)),
+        (
+            "pow(123456789, 1000000007, 1000000009)",
+            Value::Integer(734806636),
+        ),
+    ]);
+    assert_errors(vec![
+        (r#"round("a")"#, "type str doesn't define __round__ method"),
+        (
+            "round(1.5, 1.5)",
+            "'float' object cannot be interpreted as an integer",
+        ),
+        (
+            "divmod(1, 0)",
+            "ZeroDivisionError: integer division or modulo by zero",
+        ),
+        ("divmod(1.0, 0)", "ZeroDivisionError: float divmod()"),
+        (
+            "divmod(-9223372036854775807 - 1, -1)",
+            "OverflowError: integer division result too large",
+        ),
+        (
+            r#"divmod("a", 1)"#,
+            "unsupported operand type(s) for divmod(): 'str' and 'int'",
+        ),
+        (
+            "pow(2, 64)",
+            "OverflowError: integer pow() result too large",
+        ),
+        ("pow(2, 3, 0)", "pow() 3rd argument cannot be 0"),
+        (
+            "pow(2.0, 3, 5)",
+            "pow() 3rd argument not allowed unless all arguments are integers",
+        ),
+        (
+            "pow(2, -1, 4)",
+            "base is not invertible for the given modulus",
+        ),
+        ("pow(0, -1)", "0.0 cannot be raised to a negative power"),
+        (
+            "pow(-8, 0.5)",
+            "negative number cannot be raised to a fractional power",
+        ),
+    ]);
+}
+
+#[test]
+fn introspection() {
+    let names = |names: &[&str]| {
+        Value::List(
+            names
+                .iter()
+                .map(|name| Value::String(name.to_string().into()))
+                .collect::<Vec<_>>()
+                .into(),
+        )
+    };
+    assert_results(vec![
+        ("dir()", names(&["__doc__", "__name__"])),
+        ("x = 1\nglobals()", names(&["__doc__", "__name__", "x"])),
+        ("x = 1\nlocals() == globals()", Value::Bool(true)),
+        (
+            "def f(b, a):\n    c = a\n    return locals()\nf(1, 2)",
+            names(&["a", "b", "c"]),
+        ),
+        (
+            "x = 1\ndef f():\n    return globals()\nf()",
+            names(&["__doc__", "__name__", "f", "x"]),
+        ),
+        ("def f(a):\n    return dir()\nf(1)", names(&["a"])),
+        // names assigned after the call aren't bound yet
+        (
+            "names = dir()\nx = 1\ny = 2\nnames",
+            names(&["__doc__", "__name__"]),
+        ),
+        ("x = None\ndir()", names(&["__doc__", "__name__", "x"])),
+        (
+            "def f(a):\n    before = locals()\n    b = 1\n    if a:\n        c = 2\n    return str(before) + str(locals())\nf(1)",
+            Value::String(String::from("['a']['a', 'b', 'before']").into()),
+        ),
+        (r#"dir(b"")"#, names(&["decode"])),
+        (r#"len(dir(""))"#, Value::Integer(11)),
+        ("dir(1)", names(&[])),
+    ]);
+    assert_errors(vec![
+        ("dir(1, 2)", "dir() takes at most 1 argument(s) (2 given)"),
+        ("globals(1)", "globals"),
+    ]);
+}
+
+#[test]
+fn representations() {
+    let string = |text: &str| Value::String(String::from(text).into());
+    assert_results(vec![
+        (r#"repr("a")"#, string("'a'")),
+        (r#"repr("it's")"#, string(r#""it's""#)),
+        (r#"repr("a\tb\n")"#, string(r"'a\tb\n'")),
+        (r#"repr("\x07")"#, string(r"'\x07'")),
+        ("repr(1)", string("1")),
+        ("repr(1.0)", string("1.0")),
+        ("repr(None)", string("None")),
+        (r#"repr(b"a'")"#, string(r#"b"a'""#)),
+        (r#"repr("a b".split())"#, string("['a', 'b']")),
+        ("repr(divmod(7, 2))", string("(3, 1)")),
+        (r#"repr(repr("a"))"#, string(r#""'a'""#)),
+    ]);
+}
+
+#[test]
+fn singletons() {
+    let string = |text: &str| Value::String(String::from(text).into());
+    assert_results(vec![
+        ("...", Value::Ellipsis),
+        ("Ellipsis", Value::Ellipsis),
+        ("NotImplemented", Value::NotImplemented),
+        ("... == Ellipsis", Value::Bool(true)),
+        ("NotImplemented == None", Value::Bool(false)),
+        ("repr(...)", string("Ellipsis")),
+        ("str(NotImplemented)", string("NotImplemented")),
+        ("bool(...)", Value::Bool(true)),
+        ("x: int = ...\nx", Value::Ellipsis),
+        ("def stub(x: int) -> int: ...\nstub.__doc__", Value::None),
+    ]);
+    assert_errors(vec![
+        ("... + 1", "TypeError"),
+        (
+            "... < ...",
+            "'<' not supported between instances of 'ellipsis'",
+        ),
+    ]);
+}
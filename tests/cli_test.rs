@@ -0,0 +1,49 @@
+use std::fs;
+use std::process::Command;
+
+fn rustpy(args: &[&str]) -> (bool, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_rustpy"))
+        .args(args)
+        .output()
+        .expect("Unable to run rustpy");
+    (
+        output.status.success(),
+        String::from_utf8_lossy(&output.stdout).to_string(),
+    )
+}
+
+#[test]
+fn config_flags_and_exit_status() {
+    let dir = std::env::temp_dir().join(format!("rustpy_{}_cli", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("script.py");
+    fs::write(&script, "print(1)\nassert 1 == 2\nprint(2)\n").unwrap();
+    let script = script.to_str().unwrap();
+
+    // flags go before or after the subcommand, -O2 strips the assert
+    vec![
+        (vec!["--no-cache", "run", script], false, "1\n"),
+        (vec!["--no-cache", "-O2", "run", script], true, "1\n2\n"),
+        (vec!["run", "--no-cache", "-O2", script], true, "1\n2\n"),
+        (
+            vec!["--check-annotations", "--no-cache", "-O2", "run", script],
+            true,
+            "1\n2\n",
+        ),
+        (vec!["--no-cache", script], false, "1\n"),
+        (vec!["--no-cache", "-O2", script], true, "1\n2\n"),
+    ]
+    .into_iter()
+    .for_each(|(args, success, stdout)| {
+        assert_eq!(rustpy(&args), (success, String::from(stdout)), "{:?}", args);
+    });
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn unimplemented_commands_absent() {
+    let (success, help) = rustpy(&["--help"]);
+    assert!(success);
+    assert!(help.contains("typecheck"), "{}", help);
+    assert!(!help.contains("fmt") && !help.contains("lint"), "{}", help);
+}
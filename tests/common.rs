@@ -1,5 +1,14 @@
+// Each integration test crate only uses part of these helpers
+#![allow(dead_code)]
+
+use rustpy::config::Config;
+use rustpy::object::Value;
+use rustpy::Interpreter;
+use std::cell::RefCell;
 use std::fs;
-use std::path::PathBuf;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 pub fn load_source(filename: &str) -> String {
     let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -7,3 +16,82 @@ pub fn load_source(filename: &str) -> String {
 
     fs::read_to_string(path).expect("Unable to read file")
 }
+
+pub fn assert_value_eq(actual: &Value, expected: &Value) {
+    let diff = expected.diff(actual);
+    assert!(diff.is_equal(), "value mismatch {}", diff);
+}
+
+/// Cloneable in-memory sink for capturing what scripts print.
+#[derive(Clone, Default)]
+pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    pub fn contents(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).expect("Output is not UTF-8")
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// `.py` files of a fixture directory relative to the crate root, sorted.
+pub fn golden_scripts(dir: &str) -> Vec<PathBuf> {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push(dir);
+    let mut scripts: Vec<PathBuf> = fs::read_dir(&path)
+        .expect("Unable to read fixture directory")
+        .map(|entry| entry.expect("Unable to read fixture").path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "py"))
+        .collect();
+    scripts.sort();
+    scripts
+}
+
+/// Runs `script` and compares what it prints, followed by the rendered
+/// diagnostic of the error ending the run if any, with the adjacent
+/// `.expected` file. Set
+/// `UPDATE_GOLDEN=1` to rewrite the expected files instead.
+pub fn assert_golden(script: &Path) {
+    assert_golden_with(script, Config::new());
+}
+
+/// `assert_golden` running the script with `config`.
+pub fn assert_golden_with(script: &Path, mut config: Config) {
+    let source = fs::read_to_string(script).expect("Unable to read script");
+    if let Some(script_dir) = script.parent() {
+        config.search_path.push(script_dir.to_path_buf());
+    }
+    let stdout = SharedBuffer::default();
+    let mut interpreter = Interpreter::new(config);
+    interpreter.set_stdout(Box::new(stdout.clone()));
+    let result = interpreter.run(&source);
+    let mut actual = stdout.contents();
+    if let Err(err) = result {
+        let name = script.file_name().unwrap_or_default().to_string_lossy();
+        actual.push_str(&interpreter.diagnostic(&err).render(&name, &source));
+    }
+
+    let expected_path = script.with_extension("expected");
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&expected_path, &actual).expect("Unable to write expected output");
+        return;
+    }
+    let expected = fs::read_to_string(&expected_path)
+        .unwrap_or_else(|_| panic!("Missing {}", expected_path.display()));
+    let diff = Value::String(expected.into()).diff(&Value::String(actual.into()));
+    assert!(
+        diff.is_equal(),
+        "{}: output mismatch {}",
+        script.display(),
+        diff
+    );
+}
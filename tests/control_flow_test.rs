@@ -1,10 +1,10 @@
 use rustpy::config::Config;
 use rustpy::object::Value;
-use rustpy::Interpreter;
+use rustpy::{Interpreter, InterpreterError};
 
 #[test]
 fn if_statement() {
-    vec![
+    [
         (
             r###"
 if 1 < 0:
@@ -50,10 +50,36 @@ else:
 "###,
             1,
         ),
+        (
+            r###"
+if "":
+  1
+elif !None and "text":
+  2
+else:
+  3
+"###,
+            2,
+        ),
+        (
+            r###"
+x = 0
+if 1 > 0:
+  if 2 < 1:
+    if 3 > 2:
+      x = 1
+  x = x + 10
+x
+"###,
+            10,
+        ),
     ]
     .iter()
     .for_each(|(source, expected)| {
-        let mut interpreter = Interpreter::new(Config { trace: true });
+        let mut interpreter = Interpreter::new(Config {
+            trace: true,
+            ..Config::new()
+        });
         let result = interpreter.run(source);
         assert!(result.is_ok());
         let value = result.unwrap();
@@ -67,7 +93,7 @@ else:
 
 #[test]
 fn while_statement() {
-    vec![
+    [
         (
             r###"
 test = 1
@@ -119,10 +145,54 @@ test
 "###,
             20,
         ),
+        (
+            r###"
+test = 1
+while test < 3:
+  test = test + 1
+else:
+  test = test * 10
+test
+"###,
+            30,
+        ),
+        (
+            r###"
+test = 1
+while test < 10:
+  if test == 5:
+    break
+  test = test + 1
+else:
+  test = 0
+test
+"###,
+            5,
+        ),
+        (
+            r###"
+test = 0
+while test < 3:
+  inner = 0
+  while inner < 2:
+    inner = inner + 1
+  else:
+    if test == 1:
+      break
+  test = test + 1
+else:
+  test = 100
+test
+"###,
+            1,
+        ),
     ]
     .iter()
     .for_each(|(source, expected)| {
-        let mut interpreter = Interpreter::new(Config { trace: true });
+        let mut interpreter = Interpreter::new(Config {
+            trace: true,
+            ..Config::new()
+        });
         let result = interpreter.run(source);
         assert!(result.is_ok());
         let value = result.unwrap();
@@ -133,3 +203,256 @@ test
         assert_eq!(value, *expected);
     });
 }
+
+#[test]
+fn try_statement() {
+    [
+        (
+            r###"
+test = 1
+try:
+  test = 1 // 0
+except ZeroDivisionError:
+  test = 2
+else:
+  test = 3
+test
+"###,
+            2,
+        ),
+        (
+            r###"
+test = 1
+try:
+  test = 10 // 2
+except ZeroDivisionError:
+  test = 2
+else:
+  test = test * 10
+test
+"###,
+            50,
+        ),
+        (
+            r###"
+test = 0
+try:
+  test = int("x")
+except TypeError:
+  test = 1
+except ValueError:
+  test = 2
+except:
+  test = 3
+test
+"###,
+            2,
+        ),
+        (
+            r###"
+test = 0
+while test < 10:
+  test = test + 1
+  try:
+    if test == 3:
+      continue
+    if test == 5:
+      break
+    test = test + 0
+  except ValueError:
+    test = 100
+try:
+  test = test // 0
+except ZeroDivisionError:
+  test = test + 10
+test
+"###,
+            15,
+        ),
+        (
+            r###"
+test = 0
+while test < 10:
+  test = test + 1
+  try:
+    test = test // 0
+  except ZeroDivisionError:
+    if test == 4:
+      break
+    test = test + 0
+try:
+  raise
+except RuntimeError:
+  test = test * 10
+test
+"###,
+            40,
+        ),
+        (
+            r###"
+def first():
+  try:
+    return 1
+  except ValueError:
+    return 2
+test = first()
+try:
+  test = test // 0
+except ZeroDivisionError:
+  test = test + 5
+test
+"###,
+            6,
+        ),
+    ]
+    .iter()
+    .for_each(|(source, expected)| {
+        let mut interpreter = Interpreter::new(Config::new());
+        let value = match interpreter.run(source) {
+            Ok(Value::Integer(value)) => value,
+            other => panic!("Value not an integer: {:?}", other),
+        };
+        assert_eq!(value, *expected, "{}", source);
+    });
+}
+
+#[test]
+fn assert_statement() {
+    let source = r###"
+test = 0
+try:
+  assert test, "never"
+except AssertionError:
+  test = 1
+assert test == 1
+test
+"###;
+    // -O2 strips asserts
+    [(0, 1), (1, 1), (2, 0)]
+        .into_iter()
+        .for_each(|(opt_level, expected)| {
+            let mut interpreter = Interpreter::new(Config {
+                opt_level,
+                ..Config::new()
+            });
+            assert_eq!(
+                interpreter.run(source).ok(),
+                Some(Value::Integer(expected)),
+                "-O{}",
+                opt_level
+            );
+        });
+
+    let mut interpreter = Interpreter::new(Config::new());
+    let err = interpreter
+        .run("x = 2\nassert x < 1, \"too big\"\n")
+        .unwrap_err();
+    let diagnostic = interpreter.diagnostic(&err);
+    assert_eq!(diagnostic.code, "AssertionError");
+    assert_eq!(diagnostic.message, "too big");
+}
+
+#[test]
+fn misplaced_statements() {
+    [
+        ("test = 1\nreturn test\n", "'return' outside function"),
+        ("1\ncontinue\n", "'continue' not properly in loop"),
+        ("1\nbreak\n", "'break' outside loop"),
+        (
+            "if True:\n  test = 1\nelse:\n  return 2\n",
+            "'return' outside function",
+        ),
+        (
+            "while True:\n  def f():\n    break\n  break\n",
+            "'break' outside loop",
+        ),
+        (
+            "while True:\n  def f():\n    continue\n  break\n",
+            "'continue' not properly in loop",
+        ),
+        (
+            "while False:\n  test = 1\nelse:\n  try:\n    break\n  except ValueError:\n    test = 2\n",
+            "'break' outside loop",
+        ),
+    ]
+    .iter()
+    .for_each(|(source, expected)| {
+        let mut interpreter = Interpreter::new(Config::new());
+        match interpreter.run(source) {
+            Err(InterpreterError::CompilerError(err)) => {
+                let message = format!("{:?}", err);
+                assert!(message.contains(expected), "{}: {}", source, message)
+            }
+            other => panic!("{}: expected CompilerError, got {:?}", source, other),
+        }
+    });
+}
+
+#[test]
+fn expression_statements() {
+    [
+        // the result is the last expression statement evaluated
+        ("1 + 1\ntest = 5\n", Value::Integer(2)),
+        ("test = 5\n", Value::None),
+        (
+            "test = 0\nwhile test < 3:\n  test = test + 1\n  test * 10\ntest = 0\n",
+            Value::Integer(30),
+        ),
+        // values don't pile up on the stack, so locals stay in their slots
+        (
+            "def double(n):\n  return 2 * n\nprint(1)\nstr(2)\ndouble(3)\n",
+            Value::Integer(6),
+        ),
+        (
+            "def f():\n  test = 1\n  test = test + 1\n  test * 100\n  return test\nf()\n",
+            Value::Integer(2),
+        ),
+    ]
+    .iter()
+    .for_each(|(source, expected)| {
+        let mut interpreter = Interpreter::new(Config::new());
+        let result = interpreter.run(source);
+        assert_eq!(result.ok().as_ref(), Some(expected), "{}", source);
+    });
+
+    // a long loop of expression statements runs in a bounded stack
+    let mut interpreter = Interpreter::new(Config {
+        max_stack_size: Some(16),
+        ..Config::new()
+    });
+    let source = "test = 0\nwhile test < 1000:\n  test + 1\n  test = test + 1\ntest\n";
+    assert_eq!(interpreter.run(source).ok(), Some(Value::Integer(1000)));
+}
+
+#[test]
+fn long_jumps() {
+    // bodies too large for short jumps to span
+    let body = "  test = test + 1\n".repeat(3000);
+    [
+        format!("test = 0\nwhile test < 6000:\n{}test\n", body),
+        format!("test = 0\nif test == 0:\n{}else:\n  test = -1\ntest\n", body),
+        format!(
+            "test = 0\ndef f():\n  test = 0\n  try:\n  {}\n    raise ValueError\n  except ValueError:\n    return test\nf()\n",
+            body.replace("\n  ", "\n    ")
+        ),
+    ]
+    .iter()
+    .zip([6000, 3000, 3000])
+    .for_each(|(source, expected)| {
+        let mut interpreter = Interpreter::new(Config::new());
+        let result = interpreter.run(source);
+        assert_eq!(result.ok(), Some(Value::Integer(expected)));
+    });
+}
+
+#[test]
+fn wide_operands() {
+    // more constants and globals than a one byte index reaches
+    let source: String = (0..300)
+        .map(|index| format!("g{} = {}\n", index, index))
+        .chain((0..300).map(|index| format!("test = test + g{}\n", index)))
+        .collect();
+    let mut interpreter = Interpreter::new(Config::new());
+    let result = interpreter.run(&format!("test = 0\n{}test\n", source));
+    assert_eq!(result.ok(), Some(Value::Integer(44850)));
+}
@@ -0,0 +1,122 @@
+use std::fs;
+use std::path::PathBuf;
+
+use rustpy::config::Config;
+use rustpy::object::Value;
+use rustpy::{Interpreter, InterpreterError};
+
+mod common;
+
+fn files_config() -> Config {
+    Config {
+        allow_file_io: true,
+        ..Config::new()
+    }
+}
+
+// path unique to this test process, so tests may run in parallel
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("rustpy_{}_{}", std::process::id(), name))
+}
+
+fn run(source: &str) -> Result<Value, InterpreterError> {
+    Interpreter::new(files_config()).run(source)
+}
+
+#[test]
+fn read_and_write() {
+    let path = temp_path("read_and_write.txt");
+    let path = path.to_str().unwrap();
+    let string = |text: &str| Value::String(String::from(text).into());
+    let lines = |lines: &[&str]| {
+        Value::List(
+            lines
+                .iter()
+                .map(|line| string(line))
+                .collect::<Vec<_>>()
+                .into(),
+        )
+    };
+    vec![
+        (
+            format!("f = open(\"{}\", \"w\")\nf.write(\"a\\nb\\n\")", path),
+            Value::Integer(4),
+        ),
+        (format!("open(\"{}\").read()", path), string("a\nb\n")),
+        (format!("open(\"{}\").read(3)", path), string("a\nb")),
+        (
+            format!("f = open(\"{}\")\nf.readline() + f.readline() + f.readline()", path),
+            string("a\nb\n"),
+        ),
+        (format!("list(open(\"{}\"))", path), lines(&["a\n", "b\n"])),
+        (
+            format!("f = open(\"{}\", \"a\")\nprint(\"c\", \"d\", file=f)\nf.close()\nlist(open(\"{}\"))", path, path),
+            lines(&["a\n", "b\n", "c d\n"]),
+        ),
+        (
+            format!("f = open(\"{}\")\nf.close()\nf.closed", path),
+            Value::Bool(true),
+        ),
+        (format!("open(\"{}\").mode", path), string("r")),
+    ]
+    .into_iter()
+    .for_each(|(source, expected)| {
+        let result = run(&source);
+        assert!(result.is_ok(), "{}: {:?}", source, result);
+        common::assert_value_eq(&result.unwrap(), &expected);
+    });
+
+    fs::write(path, "é ü\n").unwrap();
+    common::assert_value_eq(
+        &run(&format!("open(\"{}\").read(2)", path)).unwrap(),
+        &string("é "),
+    );
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn file_errors() {
+    let path = temp_path("file_errors.txt");
+    let path = path.to_str().unwrap();
+    fs::write(path, "text").unwrap();
+    vec![
+        (format!("open(\"{}.missing\")", path), "FileNotFoundError"),
+        (format!("open(\"{}\", \"x\")", path), "invalid mode: 'x'"),
+        (format!("open(\"{}\").write(\"a\")", path), "not writable"),
+        (format!("open(\"{}\", \"a\").read()", path), "not readable"),
+        (
+            format!("f = open(\"{}\")\nf.close()\nf.read()", path),
+            "I/O operation on closed file.",
+        ),
+        (
+            format!("open(\"{}\", \"a\").write(1)", path),
+            "must be str, not int",
+        ),
+        (
+            "open(1)".to_string(),
+            "expected str, bytes or os.PathLike object",
+        ),
+    ]
+    .into_iter()
+    .for_each(|(source, expected)| match run(&source) {
+        Err(InterpreterError::VmError(err)) => {
+            let message = format!("{:?}", err);
+            assert!(message.contains(expected), "{}: {}", source, message)
+        }
+        other => panic!("{}: expected VmError, got {:?}", source, other),
+    });
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn file_access_disabled() {
+    let path = temp_path("file_access_disabled.txt");
+    let source = format!("open(\"{}\", \"w\")", path.to_str().unwrap());
+    match Interpreter::new(Config::new()).run(&source) {
+        Err(InterpreterError::VmError(err)) => {
+            assert!(format!("{:?}", err).contains("PermissionError"))
+        }
+        other => panic!("expected PermissionError, got {:?}", other),
+    }
+    assert!(!path.exists());
+}
@@ -13,15 +13,18 @@ def test(arg1, arg2):
 
 True
 "###,
-        Value::True,
+        Value::Bool(true),
     )]
     .into_iter()
     .for_each(|(source, expected)| {
-        let mut interpreter = Interpreter::new(Config { trace: true });
+        let mut interpreter = Interpreter::new(Config {
+            trace: true,
+            ..Config::new()
+        });
         let result = interpreter.run(source);
         assert!(result.is_ok());
         let value = result.unwrap();
-        assert_eq!(value, expected);
+        common::assert_value_eq(&value, &expected);
     });
 }
 
@@ -33,9 +36,9 @@ fn function_call() {
 def test():
   True
 
- test()
- "###,
-            Value::True,
+test()
+"###,
+            Value::None,
         ),
         (
             r###"
@@ -44,7 +47,7 @@ def test():
 
  test()
  "###,
-            Value::Integer(2),
+            Value::None,
         ),
         (
             r###"
@@ -74,13 +77,217 @@ double(10)
             &common::load_source("tests/test0_functions.py"),
             Value::Integer(20),
         ),
+        (
+            r###"
+"Script docs."
+def double(n):
+  "Doubles n."
+  return 2 * n
+
+__doc__ + " " + double.__doc__
+"###,
+            Value::String(String::from("Script docs. Doubles n.").into()),
+        ),
+        (
+            r###"
+def double(n):
+  return 2 * n
+
+double.__doc__
+"###,
+            Value::None,
+        ),
+        (
+            r###"
+def scale(n: float, factor: float) -> float:
+  result: float = n * factor
+  return result
+
+base: float = 4.0
+scale(base, 2.5)
+"###,
+            Value::Float(10.0),
+        ),
+        // definitions bind their name when they run
+        (
+            r###"
+if False:
+  def pick():
+    return 1
+else:
+  def pick():
+    return 2
+
+pick()
+"###,
+            Value::Integer(2),
+        ),
+        (
+            r###"
+def outer(n):
+  def inner(m):
+    return m * 2
+  return inner(n) + 1
+
+outer(3)
+"###,
+            Value::Integer(7),
+        ),
+        // the call uses the function object, not the name it was defined as
+        (
+            r###"
+def seven():
+  return 7
+
+alias = seven
+seven = 0
+alias()
+"###,
+            Value::Integer(7),
+        ),
     ]
     .into_iter()
     .for_each(|(source, expected)| {
-        let mut interpreter = Interpreter::new(Config { trace: true });
+        let mut interpreter = Interpreter::new(Config {
+            trace: true,
+            ..Config::new()
+        });
         let result = interpreter.run(source);
         assert!(result.is_ok());
         let value = result.unwrap();
-        assert_eq!(value, expected);
+        common::assert_value_eq(&value, &expected);
+    });
+}
+
+#[test]
+fn reload_functions() {
+    let mut interpreter = Interpreter::new(Config::new());
+    let result = interpreter.run(
+        r###"
+count = 0
+
+def step():
+    return count + 1
+
+def unchanged():
+    return 0
+
+count = step()
+"###,
+    );
+    assert!(result.is_ok(), "{:?}", result);
+
+    let reloaded = interpreter.reload(
+        r###"
+count = 0
+
+def step():
+    return count + 10
+
+def unchanged():
+    return 0
+
+def added():
+    return 2
+"###,
+    );
+    assert_eq!(reloaded.unwrap(), vec!["added", "step"]);
+
+    // `count` kept its value, `step` runs the new body
+    let result = interpreter.run("step()");
+    common::assert_value_eq(&result.unwrap(), &Value::Integer(11));
+    let result = interpreter.run("added()");
+    common::assert_value_eq(&result.unwrap(), &Value::Integer(2));
+
+    // broken sources leave the old definitions in place
+    assert!(interpreter
+        .reload("def step():\n    return missing\n")
+        .is_err());
+    let result = interpreter.run("step()");
+    common::assert_value_eq(&result.unwrap(), &Value::Integer(11));
+}
+
+#[test]
+fn checked_annotations() {
+    vec![
+        (
+            "def twice(n: int) -> int:\n    return n * 2\ntwice(4)\n",
+            true,
+        ),
+        // ints are accepted where floats are expected
+        (
+            "def half(n: float) -> float:\n    return n / 2\nhalf(4)\n",
+            true,
+        ),
+        (
+            "def same(n: int) -> int:\n    return n\nsame(\"a\")\n",
+            false,
+        ),
+        ("def name(n: int) -> str:\n    return n\nname(1)\n", false),
+        (
+            "def maybe(n: Optional[int]):\n    return n\nmaybe(None)\n",
+            true,
+        ),
+        (
+            "def unknown(n: Point) -> Point:\n    return n\nunknown(1)\n",
+            true,
+        ),
+        ("def untyped(n):\n    return n\nuntyped(1)\n", true),
+        // each argument is checked against its own parameter
+        (
+            "def label(n: int, text: str) -> str:\n    return text\nlabel(1, \"a\")\n",
+            true,
+        ),
+    ]
+    .into_iter()
+    .for_each(|(source, is_ok)| {
+        // annotations are only enforced when asked to
+        let mut interpreter = Interpreter::new(Config::new());
+        assert!(interpreter.run(source).is_ok(), "{}", source);
+
+        let mut interpreter = Interpreter::new(Config {
+            check_annotations: true,
+            ..Config::new()
+        });
+        let result = interpreter.run(source);
+        assert_eq!(result.is_ok(), is_ok, "{}: {:?}", source, result);
     });
 }
+
+#[test]
+fn help_signature() {
+    let stdout = common::SharedBuffer::default();
+    let mut interpreter = Interpreter::new(Config::new());
+    interpreter.set_stdout(Box::new(stdout.clone()));
+    let result = interpreter.run(
+        r###"
+def scale(n: int, factor: float) -> float:
+    "Scales n by factor."
+    return n * factor
+
+help(scale)
+"###,
+    );
+    assert!(result.is_ok(), "{:?}", result);
+    assert_eq!(
+        stdout.contents(),
+        "Help on function scale:\n\nscale(n: int, factor: float) -> float\n    Scales n by factor.\n"
+    );
+}
+
+#[test]
+fn nested_functions_are_local() {
+    let mut interpreter = Interpreter::new(Config::new());
+    let result = interpreter.run(
+        r###"
+def outer():
+  def inner():
+    return 1
+  return inner()
+
+outer()
+"###,
+    );
+    common::assert_value_eq(&result.unwrap(), &Value::Integer(1));
+    assert!(interpreter.run("inner()").is_err());
+}
@@ -0,0 +1,22 @@
+def count(r):
+    return r + 1 + (!17 == 0)
+
+def shift(r):
+    return r * 0 + (0 == 0) - 1
+
+def pick(r):
+    if r == 1:
+        return 1
+    return True
+
+counted = 0
+try:
+    counted = count(2)
+except TypeError:
+    print("count: TypeError")
+print(counted)
+try:
+    print(shift(3))
+except TypeError:
+    print("shift: TypeError")
+print(pick(1), pick(2), pick(True))
@@ -0,0 +1,18 @@
+# Comments and blank lines don't affect indentation.
+
+def total(limit):
+    # a comment at the block's indentation
+    result = 0
+
+        # a deeper one
+    i = 0
+    while i < limit:  # trailing comment
+
+  # a shallower one
+        result = result + i
+        i = i + 1
+    
+    return result
+
+print(total(5))  # 10
+# the last line is a comment
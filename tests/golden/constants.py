@@ -0,0 +1,24 @@
+def literals():
+    print(True, 1, 1.0, False, 0, 0.0)
+    print(1.0, 1, True)
+    print(0, False, 0.0)
+
+def compare(x):
+    print(x == True, x == 1, x == 1.0)
+
+def add(x):
+    y = x + 1
+    try:
+        return y + True
+    except TypeError:
+        return "TypeError"
+
+def scale(x):
+    return x * 1.0 + x * 1
+
+literals()
+compare(1)
+compare(True)
+compare(1.0)
+print(add(1), add(1.0))
+print(scale(2), scale(0))
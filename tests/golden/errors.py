@@ -0,0 +1,3 @@
+print("before")
+print("abc"[5])
+print("after")
@@ -0,0 +1,53 @@
+def check(value):
+    if value < 0:
+        raise ValueError("negative", value)
+    return value
+
+try:
+    check(-1)
+except ValueError as err:
+    print("caught", err, repr(err))
+else:
+    print("not reached")
+
+try:
+    checked = 1 // 1
+except ZeroDivisionError:
+    print("not reached")
+else:
+    print("else ran")
+
+try:
+    int("abc")
+except TypeError:
+    print("not reached")
+except Exception as err:
+    print(repr(err))
+
+try:
+    try:
+        "abc"[5]
+    except IndexError as err:
+        raise KeyError("missing") from err
+except LookupError as err:
+    print(repr(err), repr(err.__cause__))
+
+try:
+    raise RuntimeError from None
+except RuntimeError as err:
+    print(err.__cause__)
+
+try:
+    try:
+        1 % 0
+    except ValueError:
+        print("not reached")
+except ArithmeticError as err:
+    print("propagated", repr(err))
+
+try:
+    raise
+except RuntimeError as err:
+    print(err)
+
+raise KeyError("uncaught")
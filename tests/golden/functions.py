@@ -0,0 +1,23 @@
+def greet(name: str) -> str:
+    "Builds a greeting."
+    return "Hello, " + name
+
+print(greet("World"))
+print(greet.__doc__)
+help(greet)
+
+n = 3
+while n > 0:
+    print(n, end=" ")
+    n = n - 1
+print("liftoff")
+
+def sub(a, b):
+    return a - b
+
+def total(a, b, c):
+    partial = a + b
+    return partial + c
+
+print(sub(10, 3), sub(sub(10, 3), 1))
+print(total(1, 2, 3) + sub(5, total(0, 0, 1)))
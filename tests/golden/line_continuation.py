@@ -0,0 +1,12 @@
+total = 1 + \
+    2
+print(total)
+print("a",
+      "b",
+      sep="-")
+if total > 2 and \
+   total < 4:
+    value = (total *
+        10)
+    print(value)
+print("done")
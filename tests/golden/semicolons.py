@@ -0,0 +1,5 @@
+a = 1; b = 2
+print(a, b);
+if a < b:
+    a = 3; print(a)
+print(a); print(b)
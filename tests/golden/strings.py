@@ -0,0 +1,14 @@
+words = "  the quick  brown fox ".split()
+print(len(words), words)
+print("-".join(words).upper())
+print("banana".replace("a", "o", 2), "banana".find("na"))
+print("{:>5}|{:<5}|".format("r", "l"), "%d%%" % 50)
+s = ""
+i = 0
+while i < 5:
+    s = s + "ab"
+    i = i + 1
+print(s, end="!\n")
+print("a", "b", "c", sep=", ")
+print(b"caf\xc3\xa9".decode(), "é".encode())
+print("é"[0], b"ab"[1], 1_000 + 0x_ff)
@@ -0,0 +1,63 @@
+mod common;
+
+use rustpy::config::Config;
+
+#[test]
+fn golden_outputs() {
+    let scripts = common::golden_scripts("tests/golden");
+    assert!(!scripts.is_empty());
+    scripts
+        .iter()
+        .for_each(|script| common::assert_golden(script));
+}
+
+// optimized bytecode behaves the same
+#[test]
+fn golden_outputs_peephole() {
+    let config = Config {
+        peephole: true,
+        ..Config::new()
+    };
+    common::golden_scripts("tests/golden")
+        .iter()
+        .for_each(|script| common::assert_golden_with(script, config.clone()));
+}
+
+// whatever passes the optimization level runs
+#[test]
+fn golden_outputs_opt_levels() {
+    [0, 2].into_iter().for_each(|opt_level| {
+        let config = Config {
+            opt_level,
+            ..Config::new()
+        };
+        common::golden_scripts("tests/golden")
+            .iter()
+            .for_each(|script| common::assert_golden_with(script, config.clone()));
+    });
+}
+
+// as do functions run on the register VM
+#[test]
+fn golden_outputs_registers() {
+    let config = Config {
+        register_backend: true,
+        ..Config::new()
+    };
+    common::golden_scripts("tests/golden")
+        .iter()
+        .for_each(|script| common::assert_golden_with(script, config.clone()));
+}
+
+// and functions compiled to native code
+#[cfg(feature = "jit")]
+#[test]
+fn golden_outputs_jit() {
+    let config = Config {
+        jit_threshold: Some(1),
+        ..Config::new()
+    };
+    common::golden_scripts("tests/golden")
+        .iter()
+        .for_each(|script| common::assert_golden_with(script, config.clone()));
+}
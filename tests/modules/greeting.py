@@ -0,0 +1,10 @@
+"Greeting helpers."
+tick()
+prefix = "Hello, "
+
+def greet(name):
+    "Greets name with the module prefix."
+    return prefix + name
+
+if __name__ == "__main__":
+    tick()
@@ -0,0 +1,5 @@
+"Scope introspection helpers."
+limit = 3
+
+def module_names():
+    return globals()
@@ -0,0 +1,696 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use rustpy::config::Config;
+use rustpy::object::Value;
+use rustpy::{Interpreter, InterpreterError, NativeFunction, VmError};
+
+mod common;
+
+fn modules_config() -> Config {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("tests/modules");
+    Config {
+        search_path: vec![path],
+        ..Config::new()
+    }
+}
+
+#[test]
+fn import_module() {
+    vec![
+        (
+            r###"
+import greeting
+greeting.prefix
+"###,
+            Value::String(String::from("Hello, ").into()),
+        ),
+        (
+            r###"
+import greeting
+greeting.greet("World")
+"###,
+            Value::String(String::from("Hello, World").into()),
+        ),
+        (
+            r###"
+import greeting
+greeting.__name__
+"###,
+            Value::String(String::from("greeting").into()),
+        ),
+        (
+            r###"
+import greeting
+__name__
+"###,
+            Value::String(String::from("__main__").into()),
+        ),
+        (
+            r###"
+import greeting
+greeting.__doc__
+"###,
+            Value::String(String::from("Greeting helpers.").into()),
+        ),
+        (
+            r###"
+import greeting
+greeting.greet.__doc__
+"###,
+            Value::String(String::from("Greets name with the module prefix.").into()),
+        ),
+        // one `GetAttr` seeing different modules
+        (
+            r###"
+import greeting
+import scopes
+def name_of(module):
+    return module.__name__
+name_of(greeting) + name_of(scopes) + name_of(greeting)
+"###,
+            Value::String(String::from("greetingscopesgreeting").into()),
+        ),
+    ]
+    .into_iter()
+    .for_each(|(source, expected)| {
+        let mut interpreter = Interpreter::new(modules_config());
+        interpreter.register_native(NativeFunction::new("tick", 0, |_, _| Ok(Value::None)));
+        let result = interpreter.run(source);
+        assert!(result.is_ok(), "{:?}", result);
+        common::assert_value_eq(&result.unwrap(), &expected);
+    });
+}
+
+#[test]
+fn module_cache() {
+    let ticks = Arc::new(AtomicUsize::new(0));
+    let mut interpreter = Interpreter::new(modules_config());
+    let counter = ticks.clone();
+    interpreter.register_native(NativeFunction::new("tick", 0, move |_, _| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Ok(Value::None)
+    }));
+    assert!(interpreter.run("import greeting").is_ok());
+    assert!(interpreter.run("import greeting").is_ok());
+    assert_eq!(ticks.load(Ordering::SeqCst), 1);
+    assert_eq!(interpreter.loaded_modules(), vec![String::from("greeting")]);
+}
+
+#[test]
+fn missing_module() {
+    let mut interpreter = Interpreter::new(modules_config());
+    match interpreter.run("import does_not_exist") {
+        Err(InterpreterError::VmError(_)) => (),
+        other => panic!("Expected ImportError, got {:?}", other),
+    }
+}
+
+#[test]
+fn module_scope() {
+    let names = Value::List(
+        ["__doc__", "__name__", "limit", "module_names"]
+            .iter()
+            .map(|name| Value::String(name.to_string().into()))
+            .collect::<Vec<_>>()
+            .into(),
+    );
+    vec![
+        "import scopes\ndir(scopes)",
+        "import scopes\nscopes.module_names()",
+    ]
+    .into_iter()
+    .for_each(|source| {
+        let mut interpreter = Interpreter::new(modules_config());
+        let result = interpreter.run(source);
+        assert!(result.is_ok(), "{:?}", result);
+        common::assert_value_eq(&result.unwrap(), &names);
+    });
+}
+
+#[test]
+fn random_module() {
+    let string = |text: &str| Value::String(String::from(text).into());
+    vec![
+        (
+            "import random\nrandom.seed(42)\nrandom.random()",
+            Value::Float(0.6394267984578837),
+        ),
+        (
+            "import random\nrandom.seed(42)\nrandom.random()\nrandom.randint(1, 100)",
+            Value::Integer(4),
+        ),
+        (
+            "import random\nrandom.seed(-42)\nrandom.random()",
+            Value::Float(0.6394267984578837),
+        ),
+        (
+            "import random\nrandom.seed(0)\nrandom.shuffle(\"a b c d e\".split())",
+            Value::List(
+                ["c", "b", "a", "e", "d"]
+                    .iter()
+                    .map(|item| string(item))
+                    .collect::<Vec<_>>()
+                    .into(),
+            ),
+        ),
+        (
+            "import random\nrandom.seed(1)\nrandom.randint(3, 3)",
+            Value::Integer(3),
+        ),
+        ("import random\nrandom.choice(\"x\")", string("x")),
+        (
+            "import random\nx = random.random()\n0.0 <= x and x < 1.0",
+            Value::Bool(true),
+        ),
+    ]
+    .into_iter()
+    .for_each(|(source, expected)| {
+        let mut interpreter = Interpreter::new(Config::new());
+        let result = interpreter.run(source);
+        assert!(result.is_ok(), "{}: {:?}", source, result);
+        common::assert_value_eq(&result.unwrap(), &expected);
+    });
+
+    vec![
+        (
+            "import random\nrandom.randint(2, 1)",
+            "empty range in randrange(2, 2)",
+        ),
+        (
+            "import random\nrandom.choice(\"\")",
+            "Cannot choose from an empty sequence",
+        ),
+        (
+            "import random\nrandom.shuffle(\"ab\")",
+            "'str' object does not support item assignment",
+        ),
+        ("import random\nrandom.seed(1.5)", "supported seed types"),
+    ]
+    .into_iter()
+    .for_each(
+        |(source, expected)| match Interpreter::new(Config::new()).run(source) {
+            Err(InterpreterError::VmError(err)) => {
+                let message = format!("{:?}", err);
+                assert!(message.contains(expected), "{}: {}", source, message)
+            }
+            other => panic!("{}: expected VmError, got {:?}", source, other),
+        },
+    );
+
+    // the generator state carries over between runs of one interpreter
+    let mut interpreter = Interpreter::new(Config::new());
+    assert!(interpreter.run("import random\nrandom.seed(42)").is_ok());
+    common::assert_value_eq(
+        &interpreter.run("random.random()").unwrap(),
+        &Value::Float(0.6394267984578837),
+    );
+}
+
+#[test]
+fn re_module() {
+    let string = |text: &str| Value::String(String::from(text).into());
+    let strings = |items: &[&str]| {
+        Value::List(
+            items
+                .iter()
+                .map(|item| string(item))
+                .collect::<Vec<_>>()
+                .into(),
+        )
+    };
+    vec![
+        (r#"re.match("\\d+", "42abc").group()"#, string("42")),
+        (r#"re.match("\\d+", "abc42")"#, Value::None),
+        (r#"re.search("\\d+", "abc42").group()"#, string("42")),
+        (
+            r#"re.search("(\\w+)@(\\w+)", "mail bob@home now").groups()"#,
+            Value::Tuple(vec![string("bob"), string("home")].into()),
+        ),
+        (
+            r#"re.search("(?P<user>\\w+)@", "x bob@").group("user")"#,
+            string("bob"),
+        ),
+        (
+            r#"re.search("(a)(b)?", "a").group(1, 2)"#,
+            Value::Tuple(vec![string("a"), Value::None].into()),
+        ),
+        (
+            r#"re.search("é+", "aééb").span()"#,
+            Value::Tuple(vec![Value::Integer(1), Value::Integer(3)].into()),
+        ),
+        (r#"re.findall("\\d+", "a1b22c333")"#, strings(&["1", "22", "333"])),
+        (
+            r#"re.findall("(\\w)=(\\d)", "a=1 b=2")"#,
+            Value::List(vec![
+                Value::Tuple(vec![string("a"), string("1")].into()),
+                Value::Tuple(vec![string("b"), string("2")].into()),
+            ].into()),
+        ),
+        (
+            r#"re.sub("(\\w+) (\\w+)", "\\2 \\1", "hello world")"#,
+            string("world hello"),
+        ),
+        (r#"re.sub("a", "b", "aaa", count=2)"#, string("bba")),
+        (r#"re.sub("x", "\\g<0>\\g<0>", "axb")"#, string("axxb")),
+        (
+            r#"re.compile("ab", re.I).match("ABc").group()"#,
+            string("AB"),
+        ),
+        (
+            r#"repr(re.compile("ab", re.IGNORECASE))"#,
+            string("re.compile('ab', re.IGNORECASE)"),
+        ),
+        (
+            r#"str(re.match("ab", "abc"))"#,
+            string("<re.Match object; span=(0, 2), match='ab'>"),
+        ),
+        (
+            r#"re.compile("a.b", re.DOTALL).findall("a\nb")"#,
+            strings(&["a\nb"]),
+        ),
+        (r#"re.compile("o").sub("0", "foo")"#, string("f00")),
+        (r#"re.compile("\\w+").pattern"#, string("\\w+")),
+        (
+            "def double(m):\n    return str(int(m.group()) * 2)\nx = re.sub(\"\\\\d\", double, \"a1b2\")\nx",
+            string("a2b4"),
+        ),
+    ]
+    .into_iter()
+    .for_each(|(source, expected)| {
+        let source = format!("import re\n{}", source);
+        let result = Interpreter::new(Config::new()).run(&source);
+        assert!(result.is_ok(), "{}: {:?}", source, result);
+        common::assert_value_eq(&result.unwrap(), &expected);
+    });
+
+    vec![
+        (r#"re.compile("(")"#, "re.error"),
+        (r#"re.match("a", 1)"#, "expected string, got 'int'"),
+        (r#"re.match("a", "a").group(2)"#, "no such group"),
+        (r#"re.sub("a", "\\q", "a")"#, "bad escape"),
+        (r#"re.sub("a", "\\2", "a")"#, "invalid group reference 2"),
+        (
+            r#"re.match(re.compile("a"), "a", re.I)"#,
+            "cannot process flags argument with a compiled pattern",
+        ),
+    ]
+    .into_iter()
+    .for_each(|(source, expected)| {
+        let source = format!("import re\n{}", source);
+        match Interpreter::new(Config::new()).run(&source) {
+            Err(InterpreterError::VmError(err)) => {
+                let message = format!("{:?}", err);
+                assert!(message.contains(expected), "{}: {}", source, message)
+            }
+            other => panic!("{}: expected VmError, got {:?}", source, other),
+        }
+    });
+}
+
+#[test]
+fn hashlib_and_base64_modules() {
+    let string = |text: &str| Value::String(String::from(text).into());
+    let bytes = |text: &str| Value::Bytes(text.as_bytes().to_vec().into());
+    vec![
+        (
+            r#"hashlib.sha256(b"abc").hexdigest()"#,
+            string("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+        ),
+        (
+            "hashlib.md5().hexdigest()",
+            string("d41d8cd98f00b204e9800998ecf8427e"),
+        ),
+        (
+            r#"h = hashlib.sha256(b"abc")
+h.update(b"def")
+h.hexdigest()"#,
+            string("bef57ec7f53a6d40beb640a780a639c83bc29ac8a9816f1fc6c5c6dcd93c4721"),
+        ),
+        (
+            r#"h = hashlib.md5(b"a")
+c = h.copy()
+h.update(b"b")
+c.hexdigest()"#,
+            string("0cc175b9c0f1b6a831c399e269772661"),
+        ),
+        (
+            r#"hashlib.new("MD5", data=b"a").digest()[0]"#,
+            Value::Integer(0x0c),
+        ),
+        (r#"hashlib.new("sha256").digest_size"#, Value::Integer(32)),
+        (
+            r#"base64.b64encode(b"hello world")"#,
+            bytes("aGVsbG8gd29ybGQ="),
+        ),
+        (
+            r#"base64.b64decode("aGVsbG8gd29y\nbGQ=")"#,
+            bytes("hello world"),
+        ),
+        (
+            r#"base64.b64decode(base64.b64encode(b"\x00\xff"))"#,
+            Value::Bytes(vec![0, 255].into()),
+        ),
+    ]
+    .into_iter()
+    .for_each(|(expr, expected)| {
+        let source = format!("import hashlib\nimport base64\n{}", expr);
+        let mut interpreter = Interpreter::new(Config::new());
+        let result = interpreter.run(&source);
+        assert!(result.is_ok(), "{}: {:?}", source, result);
+        common::assert_value_eq(&result.unwrap(), &expected);
+    });
+
+    vec![
+        (
+            r#"hashlib.sha256("abc")"#,
+            "Strings must be encoded before hashing",
+        ),
+        (r#"hashlib.new("sha1")"#, "unsupported hash type sha1"),
+        (
+            r#"base64.b64encode("abc")"#,
+            "a bytes-like object is required",
+        ),
+        (r#"base64.b64decode(b"YQ")"#, "Incorrect padding"),
+    ]
+    .into_iter()
+    .for_each(|(expr, expected)| {
+        let source = format!("import hashlib\nimport base64\n{}", expr);
+        match Interpreter::new(Config::new()).run(&source) {
+            Err(InterpreterError::VmError(err)) => {
+                let message = format!("{:?}", err);
+                assert!(message.contains(expected), "{}: {}", source, message)
+            }
+            other => panic!("{}: expected VmError, got {:?}", source, other),
+        }
+    });
+}
+
+#[test]
+fn testing_module() {
+    let string = |text: &str| Value::String(String::from(text).into());
+    vec![
+        ("testing.diff(1, 1)", Value::None),
+        ("testing.diff(1, 2)", string("at $: expected 1, got 2")),
+        (
+            r#"testing.diff("1 ab".split(), "1 ac".split())"#,
+            string("at $[1]: strings differ at index 1\n  expected: \"ab\"\n  actual:   \"ac\""),
+        ),
+        (
+            r#"testing.assert_equal("a b".split(), "a b".split())"#,
+            Value::None,
+        ),
+        (
+            r#"message = None
+try:
+    testing.assert_equal("x", 1)
+except AssertionError as e:
+    message = str(e)
+message"#,
+            string("at $: expected str x, got int 1"),
+        ),
+    ]
+    .into_iter()
+    .for_each(|(expr, expected)| {
+        let source = format!("import testing\n{}", expr);
+        let mut interpreter = Interpreter::new(Config::new());
+        let result = interpreter.run(&source);
+        assert!(result.is_ok(), "{}: {:?}", source, result);
+        common::assert_value_eq(&result.unwrap(), &expected);
+    });
+}
+
+#[test]
+fn functools_lru_cache() {
+    // the least recently used 2 is evicted for 4, so computing it again
+    let source = r#"
+import functools
+def square(x):
+    tick()
+    return x * x
+cached = functools.lru_cache(maxsize=2)(square)
+a = cached(2)
+b = cached(2)
+c = cached(3)
+d = cached(4)
+e = cached(3)
+f = cached(2)
+a + b + c + d + e + f
+"#;
+    let ticks = Arc::new(AtomicUsize::new(0));
+    let mut interpreter = Interpreter::new(Config::new());
+    let counter = ticks.clone();
+    interpreter.register_native(NativeFunction::new("tick", 0, move |_, _| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Ok(Value::None)
+    }));
+    let result = interpreter.run(source);
+    assert!(result.is_ok(), "{:?}", result);
+    common::assert_value_eq(&result.unwrap(), &Value::Integer(46));
+    assert_eq!(ticks.load(Ordering::SeqCst), 4);
+}
+
+#[test]
+fn functools_lru_cache_cycle() {
+    vec![
+        // the cache holds the function holding the cache
+        r#"
+import functools
+cached = None
+def current(n):
+    return cached
+cached = functools.lru_cache(current)
+cached(1) == cached
+"#,
+        // or a lazy iterator holding it
+        r#"
+import functools
+cached = None
+def lazy(n):
+    return map(cached, "a b".split())
+cached = functools.lru_cache(lazy)
+cached(1) == cached(1)
+"#,
+    ]
+    .into_iter()
+    .for_each(|source| {
+        let mut interpreter = Interpreter::new(Config::new());
+        let result = interpreter.run(source);
+        assert!(result.is_ok(), "{:?}", result);
+        common::assert_value_eq(&result.unwrap(), &Value::Bool(true));
+        assert_eq!(interpreter.collect_garbage(), 0);
+        assert!(interpreter.run("cached = None\n").is_ok());
+        assert_eq!(interpreter.collect_garbage(), 1, "{}", source);
+        assert_eq!(interpreter.collect_garbage(), 0);
+    });
+}
+
+#[test]
+fn itertools_module() {
+    let string = |text: &str| Value::String(String::from(text).into());
+    let integers = |items: &[i64]| {
+        Value::List(
+            items
+                .iter()
+                .map(|item| Value::Integer(*item))
+                .collect::<Vec<_>>()
+                .into(),
+        )
+    };
+    let pairs = |items: &[(Value, Value)]| {
+        Value::List(
+            items
+                .iter()
+                .map(|(first, second)| Value::Tuple(vec![first.clone(), second.clone()].into()))
+                .collect::<Vec<_>>()
+                .into(),
+        )
+    };
+    vec![
+        ("list(islice(count(), 5))", integers(&[0, 1, 2, 3, 4])),
+        (
+            "list(islice(count(10, 2.5), 3))",
+            Value::List(vec![
+                Value::Integer(10),
+                Value::Float(12.5),
+                Value::Float(15.0),
+            ].into()),
+        ),
+        (
+            r#"list(islice("abcdefg", 1, 6, 2))"#,
+            Value::List(vec![string("b"), string("d"), string("f")].into()),
+        ),
+        (
+            r#"list(islice("abc", None))"#,
+            Value::List(vec![string("a"), string("b"), string("c")].into()),
+        ),
+        (
+            "c = count(1)\nlist(islice(c, 2))\nlist(islice(c, 2))",
+            integers(&[3, 4]),
+        ),
+        (
+            r#"list(chain("ab", divmod(7, 2), ""))"#,
+            Value::List(vec![
+                string("a"),
+                string("b"),
+                Value::Integer(3),
+                Value::Integer(1),
+            ].into()),
+        ),
+        (
+            r#"list(repeat("x", 3))"#,
+            Value::List(vec![string("x"), string("x"), string("x")].into()),
+        ),
+        ("list(repeat(1, -1))", integers(&[])),
+        ("list(islice(repeat(7), 2))", integers(&[7, 7])),
+        (
+            r#"list(product("ab", repeat=2))"#,
+            pairs(&[
+                (string("a"), string("a")),
+                (string("a"), string("b")),
+                (string("b"), string("a")),
+                (string("b"), string("b")),
+            ]),
+        ),
+        (
+            r#"list(product("ab", divmod(7, 2)))"#,
+            pairs(&[
+                (string("a"), Value::Integer(3)),
+                (string("a"), Value::Integer(1)),
+                (string("b"), Value::Integer(3)),
+                (string("b"), Value::Integer(1)),
+            ]),
+        ),
+        (r#"list(product("a", ""))"#, Value::List(vec![].into())),
+        ("list(product())", Value::List(vec![Value::Tuple(vec![].into())].into())),
+        ("sum(islice(count(1), 100))", Value::Integer(5050)),
+    ]
+    .into_iter()
+    .for_each(|(source, expected)| {
+        let source = format!(
+            "import itertools\ncount = itertools.count\nislice = itertools.islice\nchain = itertools.chain\nrepeat = itertools.repeat\nproduct = itertools.product\n{}",
+            source
+        );
+        let result = Interpreter::new(Config::new()).run(&source);
+        assert!(result.is_ok(), "{}: {:?}", source, result);
+        common::assert_value_eq(&result.unwrap(), &expected);
+    });
+
+    vec![
+        (r#"itertools.count("a")"#, "a number is required"),
+        ("itertools.islice(1, 2)", "'int' object is not iterable"),
+        (
+            r#"itertools.islice("a", -1)"#,
+            "Stop argument for islice() must be None or an integer",
+        ),
+        (
+            r#"itertools.islice("a", 0, 1, 0)"#,
+            "Step for islice() must be a positive integer or None.",
+        ),
+        (
+            r#"itertools.product("a", repeat=-1)"#,
+            "repeat argument cannot be negative",
+        ),
+    ]
+    .into_iter()
+    .for_each(|(source, expected)| {
+        let source = format!("import itertools\n{}", source);
+        match Interpreter::new(Config::new()).run(&source) {
+            Err(InterpreterError::VmError(err)) => {
+                let message = format!("{:?}", err);
+                assert!(message.contains(expected), "{}: {}", source, message)
+            }
+            other => panic!("{}: expected VmError, got {:?}", source, other),
+        }
+    });
+}
+
+#[test]
+fn sys_module() {
+    vec![
+        (
+            "import sys\nsys.version",
+            Value::String(format!("{} (rustpy)", rustpy::VERSION).into()),
+        ),
+        (
+            "import sys\nsys.implementation.name",
+            Value::String(String::from("rustpy").into()),
+        ),
+        (
+            "import sys\nsys.implementation.version[0] >= 0",
+            Value::Bool(true),
+        ),
+    ]
+    .into_iter()
+    .for_each(|(source, expected)| {
+        let mut interpreter = Interpreter::new(Config::new());
+        let result = interpreter.run(source);
+        assert!(result.is_ok(), "{:?}", result);
+        common::assert_value_eq(&result.unwrap(), &expected);
+    });
+
+    assert!(Interpreter::features().contains(&"sys"));
+}
+
+#[test]
+fn sys_argv_and_streams() {
+    let mut interpreter = Interpreter::new(Config {
+        argv: vec![String::from("script.py"), String::from("-v")],
+        ..Config::new()
+    });
+    let stdout = common::SharedBuffer::default();
+    let stderr = common::SharedBuffer::default();
+    interpreter.set_stdout(Box::new(stdout.clone()));
+    interpreter.set_stderr(Box::new(stderr.clone()));
+    let result = interpreter.run(
+        r#"
+import sys
+sys.stdout.write("out ")
+print("print", file=sys.stdout)
+sys.stderr.write("err ")
+print("print", file=sys.stderr)
+sys.argv
+"#,
+    );
+    assert!(result.is_ok(), "{:?}", result);
+    common::assert_value_eq(
+        &result.unwrap(),
+        &Value::List(
+            vec![
+                Value::String(String::from("script.py").into()),
+                Value::String(String::from("-v").into()),
+            ]
+            .into(),
+        ),
+    );
+    assert_eq!(stdout.contents(), "out print\n");
+    assert_eq!(stderr.contents(), "err print\n");
+}
+
+#[test]
+fn sys_exit() {
+    vec![
+        ("import sys\nsys.exit()", 0, ""),
+        ("import sys\nsys.exit(None)", 0, ""),
+        ("import sys\nsys.exit(3)\nprint(1)", 3, ""),
+        ("import sys\nsys.exit(\"failed\")", 1, "failed\n"),
+    ]
+    .into_iter()
+    .for_each(|(source, expected_status, expected_stderr)| {
+        let mut interpreter = Interpreter::new(Config::new());
+        let stdout = common::SharedBuffer::default();
+        let stderr = common::SharedBuffer::default();
+        interpreter.set_stdout(Box::new(stdout.clone()));
+        interpreter.set_stderr(Box::new(stderr.clone()));
+        match interpreter.run(source) {
+            Err(InterpreterError::VmError(VmError::SystemExit(status))) => {
+                assert_eq!(status, expected_status, "{}", source)
+            }
+            other => panic!("{}: expected SystemExit, got {:?}", source, other),
+        }
+        assert_eq!(stdout.contents(), "", "{}", source);
+        assert_eq!(stderr.contents(), expected_stderr, "{}", source);
+    });
+}
@@ -0,0 +1,141 @@
+use rustpy::config::Config;
+use rustpy::object::Value;
+use rustpy::{register_global_native, Interpreter, InterpreterError, NativeFunction, VmError};
+
+mod common;
+
+#[test]
+fn per_instance_native() {
+    let mut interpreter = Interpreter::new(Config::new());
+    interpreter.register_native(NativeFunction::new("double", 1, |_, args| match args[0] {
+        Value::Integer(value) => Ok(Value::Integer(value * 2)),
+        _ => Ok(Value::None),
+    }));
+    let result = interpreter.run("double(21)");
+    assert!(result.is_ok());
+    common::assert_value_eq(&result.unwrap(), &Value::Integer(42));
+
+    // other interpreters don't see per-instance natives
+    let mut other = Interpreter::new(Config::new());
+    assert!(other.run("double(21)").is_err());
+}
+
+#[test]
+fn global_native() {
+    register_global_native(NativeFunction::new("answer", 0, |_, _| {
+        Ok(Value::Integer(42))
+    }));
+    let mut interpreter = Interpreter::new(Config::new());
+    let result = interpreter.run("answer()");
+    assert!(result.is_ok());
+    common::assert_value_eq(&result.unwrap(), &Value::Integer(42));
+}
+
+#[test]
+fn shadow_builtin() {
+    vec![
+        (
+            r###"
+def abs(value):
+    return 42
+abs(-1)
+"###,
+            Value::Integer(42),
+        ),
+        (
+            r###"
+def magnitude(value):
+    return abs(value)
+magnitude(-7)
+"###,
+            Value::Integer(7),
+        ),
+    ]
+    .into_iter()
+    .for_each(|(source, expected)| {
+        let mut interpreter = Interpreter::new(Config::new());
+        let result = interpreter.run(source);
+        assert!(result.is_ok(), "{:?}", result);
+        common::assert_value_eq(&result.unwrap(), &expected);
+    });
+}
+
+#[test]
+fn print_keywords() {
+    vec![
+        ("print(1, 2)", "1 2\n"),
+        ("print(1, 2, sep=\"-\", end=\"\")", "1-2"),
+        ("print(\"a\", end=\"\")\nprint(\"b\")", "ab\n"),
+        ("print(\"a\", \"b\", sep=None, end=\"!\")", "a b!"),
+        ("print(end=\"\")", ""),
+        ("print(1, file=None)", "1\n"),
+    ]
+    .into_iter()
+    .for_each(|(source, expected)| {
+        let stdout = common::SharedBuffer::default();
+        let mut interpreter = Interpreter::new(Config::new());
+        interpreter.set_stdout(Box::new(stdout.clone()));
+        let result = interpreter.run(source);
+        assert!(result.is_ok(), "{}: {:?}", source, result);
+        assert_eq!(stdout.contents(), expected, "{}", source);
+    });
+}
+
+#[test]
+fn unexpected_keywords() {
+    vec![
+        "print(1, flush=True)",
+        "print(1, sep=2)",
+        "print(1, file=2)",
+        "abs(-1, value=2)",
+        "def f(a):\n    return a\nf(a=1)",
+    ]
+    .into_iter()
+    .for_each(|source| {
+        let mut interpreter = Interpreter::new(Config::new());
+        interpreter.set_stdout(Box::new(std::io::sink()));
+        match interpreter.run(source) {
+            Err(InterpreterError::VmError(_)) => (),
+            other => panic!("{}: expected VmError, got {:?}", source, other),
+        }
+    });
+}
+
+#[test]
+fn record_and_replay() {
+    use rustpy::replay::ExecutionLog;
+    use std::sync::atomic::{AtomicI64, Ordering};
+    use std::sync::Arc;
+
+    let ticks = Arc::new(AtomicI64::new(0));
+    let tick = {
+        let ticks = ticks.clone();
+        NativeFunction::new("tick", 0, move |_, _| {
+            Ok(Value::Integer(ticks.fetch_add(1, Ordering::SeqCst)))
+        })
+        .nondeterministic()
+    };
+    let source = "tick() * 10 + tick()";
+
+    let mut recorder = Interpreter::new(Config {
+        deterministic: true,
+        ..Config::new()
+    });
+    recorder.register_native(tick.clone());
+    common::assert_value_eq(&recorder.run(source).unwrap(), &Value::Integer(1));
+    let log = recorder.execution_log().unwrap().to_string();
+
+    // the host state moved on, the replay still sees the recorded results
+    ticks.store(100, Ordering::SeqCst);
+    let mut replayer = Interpreter::new(Config::new());
+    replayer.register_native(tick);
+    replayer.replay(ExecutionLog::parse(&log).unwrap());
+    common::assert_value_eq(&replayer.run(source).unwrap(), &Value::Integer(1));
+    assert_eq!(ticks.load(Ordering::SeqCst), 100);
+
+    // running past the end of the log diverges from the recording
+    match replayer.run("tick()") {
+        Err(InterpreterError::VmError(VmError::ReplayDiverged(_))) => (),
+        other => panic!("Expected ReplayDiverged, got {:?}", other),
+    }
+}
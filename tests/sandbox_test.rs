@@ -0,0 +1,225 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rustpy::config::Config;
+use rustpy::object::Value;
+use rustpy::policy::{Action, Decision, PolicyRequest};
+use rustpy::{Interpreter, InterpreterError, VmError};
+
+#[test]
+fn output_limits() {
+    vec![
+        (
+            r###"
+print("a")
+print("b")
+print("c")
+"###,
+            Config {
+                max_print_calls: Some(3),
+                ..Config::new()
+            },
+            true,
+        ),
+        (
+            r###"
+print("a")
+print("b")
+print("c")
+"###,
+            Config {
+                max_print_calls: Some(2),
+                ..Config::new()
+            },
+            false,
+        ),
+        (
+            r###"
+print("0123456789")
+"###,
+            Config {
+                max_output_bytes: Some(10),
+                ..Config::new()
+            },
+            false,
+        ),
+    ]
+    .into_iter()
+    .for_each(|(source, config, is_ok)| {
+        let mut interpreter = Interpreter::new(config);
+        let result = interpreter.run(source);
+        match result {
+            Ok(_) => assert!(is_ok),
+            Err(InterpreterError::VmError(_)) => assert!(!is_ok),
+            Err(err) => panic!("Unexpected error: {:?}", err),
+        }
+    });
+}
+
+#[test]
+fn remove_builtin() {
+    vec![
+        Config::new(),
+        Config {
+            register_backend: true,
+            ..Config::new()
+        },
+    ]
+    .into_iter()
+    .for_each(|config| {
+        let mut interpreter = Interpreter::new(config);
+        assert!(interpreter
+            .run("def show(value):\n    print(value)\n")
+            .is_ok());
+        assert!(interpreter.remove_builtin("print"));
+        assert!(!interpreter.remove_builtin("print"));
+
+        match interpreter.run("print(1)") {
+            Err(InterpreterError::CompilerError(_)) => (),
+            other => panic!("Expected NameError, got {:?}", other),
+        }
+        // code compiled before the removal fails at runtime instead
+        match interpreter.run("show(1)") {
+            Err(InterpreterError::VmError(VmError::UndefinedName(message))) => {
+                assert_eq!(message, "NameError: builtin 'print' not defined")
+            }
+            other => panic!("Expected NameError, got {:?}", other),
+        }
+    });
+}
+
+#[test]
+fn parser_limits() {
+    let nested = |depth: usize| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+    vec![
+        (nested(199), Config::new(), true),
+        (nested(100_000), Config::new(), false),
+        (format!("{}1", "-".repeat(100_000)), Config::new(), false),
+        (
+            nested(50),
+            Config {
+                max_parse_depth: Some(20),
+                ..Config::new()
+            },
+            false,
+        ),
+        (
+            String::from("1 + 2 + 3 + 4"),
+            Config {
+                max_tokens: Some(5),
+                ..Config::new()
+            },
+            false,
+        ),
+    ]
+    .into_iter()
+    .for_each(|(source, config, is_ok)| {
+        let mut interpreter = Interpreter::new(config);
+        match interpreter.run(&source) {
+            Ok(_) => assert!(is_ok),
+            Err(InterpreterError::ParserError(_)) => assert!(!is_ok),
+            Err(err) => panic!("Unexpected error: {:?}", err),
+        }
+    });
+}
+
+#[test]
+fn stack_limit() {
+    let recurse = r###"
+def down(n):
+    return down(n + 1)
+down(0)
+"###;
+    let mut interpreter = Interpreter::new(Config {
+        max_stack_size: Some(1000),
+        ..Config::new()
+    });
+    match interpreter.run(recurse) {
+        Err(InterpreterError::VmError(VmError::ResourceLimitExceeded(_))) => (),
+        other => panic!("Expected ResourceLimitExceeded, got {:?}", other),
+    }
+    // the stack is reset, so the interpreter stays usable
+    assert!(interpreter.run("1 + 2").is_ok());
+}
+
+#[test]
+fn policy_hooks() {
+    let requests = Rc::new(RefCell::new(Vec::new()));
+    let mut interpreter = Interpreter::new(Config::new());
+    interpreter.set_stdout(Box::new(std::io::sink()));
+    let seen = requests.clone();
+    interpreter.set_policy(move |request: &PolicyRequest| {
+        seen.borrow_mut().push(format!(
+            "{} in {}",
+            request.action.describe(),
+            request.function
+        ));
+        match request.action {
+            Action::CallNative {
+                name: "print",
+                args,
+                ..
+            } => match args.first() {
+                Some(Value::String(text)) if text.as_str() == "secret" => {
+                    Decision::Deny(String::from("no secrets"))
+                }
+                _ => Decision::Allow,
+            },
+            Action::Import { .. } => Decision::Deny(String::from("imports are disabled")),
+            _ => Decision::Allow,
+        }
+    });
+
+    assert!(interpreter.run("print(\"public\")\nabs(-1)\n").is_ok());
+    vec![
+        (
+            "def leak():\n    print(\"secret\")\nleak()\n",
+            "call to print() denied: no secrets",
+        ),
+        (
+            "import os\n",
+            "import of module 'os' denied: imports are disabled",
+        ),
+    ]
+    .into_iter()
+    .for_each(|(source, expected)| match interpreter.run(source) {
+        Err(InterpreterError::VmError(VmError::PermissionError(message))) => {
+            assert!(message.ends_with(expected), "{}", message)
+        }
+        other => panic!("Expected PermissionError, got {:?}", other),
+    });
+    assert_eq!(
+        requests.borrow()[..3],
+        [
+            "call to print() in <main>",
+            "call to abs() in <main>",
+            "call to print() in leak",
+        ]
+    );
+
+    interpreter.clear_policy();
+    assert!(interpreter.run("print(\"secret\")").is_ok());
+}
+
+#[test]
+fn compiler_limits() {
+    let sum = format!("1{}", " + 1".repeat(20_000));
+    let mut interpreter = Interpreter::new(Config::new());
+    let result = interpreter.run(&sum);
+    assert!(result.is_ok(), "{:?}", result);
+    assert_eq!(result.unwrap(), Value::Integer(20_001));
+
+    let elif_chain: String = (1..2000)
+        .map(|value| format!("elif x == {}:\n    y = {}\n", value, value))
+        .collect();
+    let source = format!("x = 1500\ny = 0\nif x == 0:\n    y = 0\n{}y\n", elif_chain);
+    let result = interpreter.run(&source);
+    assert!(result.is_ok(), "{:?}", result);
+    assert_eq!(result.unwrap(), Value::Integer(1500));
+
+    let subscripts = format!("\"a\"{}", "[0]".repeat(5_000));
+    match interpreter.run(&subscripts) {
+        Err(InterpreterError::CompilerError(_)) => (),
+        other => panic!("Expected CompilerError, got {:?}", other),
+    }
+}
@@ -0,0 +1,418 @@
+use rustpy::config::Config;
+use rustpy::object::Value;
+use rustpy::{Interpreter, InterpreterError, VmError};
+
+mod common;
+
+#[test]
+fn string_formatting() {
+    vec![
+        (r#""{} {}".format("a", 1)"#, "a 1"),
+        (r#""{1}-{0}".format("a", "b")"#, "b-a"),
+        (r#""{:>6.2f}".format(1.23456)"#, "  1.23"),
+        (r#""{:,}".format(1234567)"#, "1,234,567"),
+        (r#""%d items" % 3"#, "3 items"),
+        (r#""%5.1f%%" % 99.25"#, " 99.2%"),
+        (r#""%s" % None"#, "None"),
+        (r#""%d-%s" % divmod(7, 2)"#, "3-1"),
+        (r#""%s" % "abc".split()"#, "['abc']"),
+        (
+            r###"
+template = "{} + {} = {}"
+template.format(1, 2, 1 + 2)
+"###,
+            "1 + 2 = 3",
+        ),
+    ]
+    .into_iter()
+    .for_each(|(source, expected)| {
+        let mut interpreter = Interpreter::new(Config::new());
+        let result = interpreter.run(source);
+        assert!(result.is_ok(), "{}: {:?}", source, result);
+        common::assert_value_eq(
+            &result.unwrap(),
+            &Value::String(String::from(expected).into()),
+        );
+    });
+}
+
+#[test]
+fn modulo() {
+    vec![
+        ("7 % 3", Value::Integer(1)),
+        ("-7 % 3", Value::Integer(2)),
+        ("7 % -3", Value::Integer(-2)),
+        ("7.5 % 2", Value::Float(1.5)),
+        ("7 // 2", Value::Integer(3)),
+        ("-7 // 2", Value::Integer(-4)),
+        ("7 // -2", Value::Integer(-4)),
+        ("7.5 // 2", Value::Float(3.0)),
+        ("-7.5 // 2", Value::Float(-4.0)),
+        (
+            "(-9223372036854775807 - 1) // 3",
+            Value::Integer(-3074457345618258603),
+        ),
+    ]
+    .into_iter()
+    .for_each(|(source, expected)| {
+        let mut interpreter = Interpreter::new(Config::new());
+        let result = interpreter.run(source);
+        assert!(result.is_ok(), "{}: {:?}", source, result);
+        common::assert_value_eq(&result.unwrap(), &expected);
+    });
+}
+
+#[test]
+fn division_by_zero() {
+    vec![
+        ("1 / 0", "division by zero"),
+        ("1.5 / 0", "float division by zero"),
+        ("1 // 0", "integer division or modulo by zero"),
+        ("1 // 0.0", "float floor division by zero"),
+        ("1 % 0", "integer modulo by zero"),
+        ("1.5 % 0.0", "float modulo"),
+        ("x = 0\n10 / x", "division by zero"),
+    ]
+    .into_iter()
+    .for_each(|(source, expected)| {
+        let mut interpreter = Interpreter::new(Config::new());
+        match interpreter.run(source) {
+            Err(InterpreterError::VmError(VmError::ZeroDivisionError(message))) => {
+                assert_eq!(
+                    message,
+                    format!("ZeroDivisionError: {}", expected),
+                    "{}",
+                    source
+                )
+            }
+            other => panic!("{}: expected ZeroDivisionError, got {:?}", source, other),
+        }
+    });
+}
+
+#[test]
+fn integer_overflow() {
+    vec![
+        "(-9223372036854775807 - 1) / -1",
+        "(-9223372036854775807 - 1) // -1",
+        "(-9223372036854775807 - 1) % -1",
+        "9223372036854775807 + 1",
+        "-9223372036854775807 - 2",
+        "2 * 4611686018427387904",
+        "x = 9223372036854775807\nx * x",
+        "abs(-9223372036854775807 - 1)",
+    ]
+    .into_iter()
+    .for_each(|source| {
+        let mut interpreter = Interpreter::new(Config::new());
+        match interpreter.run(source) {
+            Err(InterpreterError::VmError(err)) => {
+                let message = format!("{:?}", err);
+                assert!(message.contains("OverflowError"), "{}: {}", source, message)
+            }
+            other => panic!("{}: expected OverflowError, got {:?}", source, other),
+        }
+    });
+}
+
+#[test]
+fn formatting_errors() {
+    vec![
+        r#""{} {}".format(1)"#,
+        r#""%d %d" % 1"#,
+        r#""%d" % "a""#,
+        r#""%d" % divmod(7, 2)"#,
+        r#""%d %d %d" % divmod(7, 2)"#,
+        r#""{:q}".format(1)"#,
+        "1 % 0",
+    ]
+    .into_iter()
+    .for_each(|source| {
+        let mut interpreter = Interpreter::new(Config::new());
+        match interpreter.run(source) {
+            Err(InterpreterError::VmError(_)) => (),
+            other => panic!("{}: expected VmError, got {:?}", source, other),
+        }
+    });
+}
+
+#[test]
+fn string_literals() {
+    vec![
+        (r#""tab\there""#, "tab\there"),
+        (r#""say \"hi\"""#, "say \"hi\""),
+        (r#"r"\d+\.\d*""#, r"\d+\.\d*"),
+        (r#"r"C:\new" + "\\""#, "C:\\new\\"),
+        (r#""\q""#, "\\q"),
+        (r#""caf\u00e9 \N{SNOWMAN}""#, "café ☃"),
+    ]
+    .into_iter()
+    .for_each(|(source, expected)| {
+        let mut interpreter = Interpreter::new(Config::new());
+        let result = interpreter.run(source);
+        assert!(result.is_ok(), "{}: {:?}", source, result);
+        common::assert_value_eq(
+            &result.unwrap(),
+            &Value::String(String::from(expected).into()),
+        );
+    });
+}
+
+#[test]
+fn bytes_and_indexing() {
+    vec![
+        (r#"b"ab"[0]"#, Value::Integer(97)),
+        (r#"b"ab"[-1]"#, Value::Integer(98)),
+        (r#""héllo"[1]"#, Value::String(String::from("é").into())),
+        (r#"len(b"a\x00c")"#, Value::Integer(3)),
+        (r#"len("héllo")"#, Value::Integer(5)),
+        (
+            r#""a\U0001F600b"[-2]"#,
+            Value::String(String::from("😀").into()),
+        ),
+        (r#"len("\N{EM DASH}\u00e9")"#, Value::Integer(2)),
+        (r#"b"a" + b"b""#, Value::Bytes(b"ab".to_vec().into())),
+        (r#""é".encode()"#, Value::Bytes(vec![0xc3, 0xa9].into())),
+        (
+            r#"b"\xc3\xa9".decode("utf-8")"#,
+            Value::String(String::from("é").into()),
+        ),
+        (
+            r#""abc".encode("ascii").decode()"#,
+            Value::String(String::from("abc").into()),
+        ),
+    ]
+    .into_iter()
+    .for_each(|(source, expected)| {
+        let mut interpreter = Interpreter::new(Config::new());
+        let result = interpreter.run(source);
+        assert!(result.is_ok(), "{}: {:?}", source, result);
+        common::assert_value_eq(&result.unwrap(), &expected);
+    });
+}
+
+#[test]
+fn bytes_errors() {
+    vec![
+        (r#"b"ab"[2]"#, "IndexError: bytes index out of range"),
+        (r#""ab"[-3]"#, "IndexError: string index out of range"),
+        ("1[0]", "TypeError: 'int' object is not subscriptable"),
+        (
+            r#""ab"["a"]"#,
+            "TypeError: str indices must be integers, not str",
+        ),
+        ("len(1)", "TypeError: object of type 'int' has no len()"),
+        (r#"b"a" + "b""#, "TypeError: unsupported operand type(s)"),
+        (
+            r#"b"\xff".decode()"#,
+            "UnicodeDecodeError: 'utf-8' codec can't decode byte 0xff in position 0",
+        ),
+        (
+            r#""é".encode("ascii")"#,
+            "UnicodeEncodeError: 'ascii' codec can't encode character 'é' in position 0",
+        ),
+    ]
+    .into_iter()
+    .for_each(|(source, expected)| {
+        let mut interpreter = Interpreter::new(Config::new());
+        match interpreter.run(source) {
+            Err(InterpreterError::VmError(err)) => {
+                let message = format!("{:?}", err);
+                assert!(message.contains(expected), "{}: {}", source, message)
+            }
+            other => panic!("{}: expected VmError, got {:?}", source, other),
+        }
+    });
+}
+
+#[test]
+fn string_building() {
+    vec![
+        (
+            r###"
+größe = "grö"
+größe = größe + "ße"
+größe
+"###,
+            Value::String(String::from("größe").into()),
+        ),
+        (
+            r###"
+s = ""
+i = 0
+while i < 20000:
+    s = s + "ab"
+    i = i + 1
+len(s)
+"###,
+            Value::Integer(40000),
+        ),
+        (
+            r###"
+def build(n):
+    s = "<"
+    i = 0
+    while i < n:
+        s = s + "x"
+        i = i + 1
+    return s + ">"
+build(3)
+"###,
+            Value::String(String::from("<xxx>").into()),
+        ),
+        (
+            r###"
+total = 1
+total = total + 1.5
+total
+"###,
+            Value::Float(2.5),
+        ),
+    ]
+    .into_iter()
+    .for_each(|(source, expected)| {
+        let mut interpreter = Interpreter::new(Config::new());
+        let result = interpreter.run(source);
+        assert!(result.is_ok(), "{}: {:?}", source, result);
+        common::assert_value_eq(&result.unwrap(), &expected);
+    });
+
+    let mut interpreter = Interpreter::new(Config::new());
+    match interpreter.run("s = \"a\"\ns = s + 1\n") {
+        Err(InterpreterError::VmError(_)) => (),
+        other => panic!("expected VmError, got {:?}", other),
+    }
+}
+
+#[test]
+fn sequence_repetition() {
+    let string = |text: &str| Value::String(String::from(text).into());
+    vec![
+        (r#""ab" * 3"#, string("ababab")),
+        (r#"3 * "ab""#, string("ababab")),
+        (r#""ab" * 0"#, string("")),
+        (r#""ab" * -2"#, string("")),
+        (r#""é" * 2"#, string("éé")),
+        (r#"b"\x00" * 3"#, Value::Bytes(vec![0, 0, 0].into())),
+        (
+            r#"list("ab") * 2"#,
+            Value::List(vec![string("a"), string("b"), string("a"), string("b")].into()),
+        ),
+        (
+            r#"2 * list("0")"#,
+            Value::List(vec![string("0"), string("0")].into()),
+        ),
+        ("n = 3\nlen(list(\"x\") * n)", Value::Integer(3)),
+        (
+            "try:\n  \"a\" * 9223372036854775807\nexcept MemoryError:\n  1\n",
+            Value::Integer(1),
+        ),
+    ]
+    .into_iter()
+    .for_each(|(source, expected)| {
+        let mut interpreter = Interpreter::new(Config::new());
+        let result = interpreter.run(source);
+        assert!(result.is_ok(), "{}: {:?}", source, result);
+        common::assert_value_eq(&result.unwrap(), &expected);
+    });
+
+    vec![
+        (r#""ab" * 1.5"#, "TypeError: unsupported operand type(s)"),
+        (r#""ab" * "c""#, "TypeError: unsupported operand type(s)"),
+    ]
+    .into_iter()
+    .for_each(|(source, expected)| {
+        let mut interpreter = Interpreter::new(Config::new());
+        match interpreter.run(source) {
+            Err(InterpreterError::VmError(err)) => {
+                let message = format!("{:?}", err);
+                assert!(message.contains(expected), "{}: {}", source, message)
+            }
+            other => panic!("{}: expected VmError, got {:?}", source, other),
+        }
+    });
+
+    // rather than aborting on the allocation
+    vec![
+        r#"list("ab") * 9223372036854775807"#,
+        r#""a" * 9223372036854775807"#,
+        r#"b"a" * 4611686018427387904"#,
+    ]
+    .into_iter()
+    .for_each(|source| {
+        let mut interpreter = Interpreter::new(Config::new());
+        let err = interpreter.run(source).unwrap_err();
+        let diagnostic = interpreter.diagnostic(&err);
+        assert_eq!(diagnostic.code, "MemoryError", "{}", source);
+        assert_eq!(diagnostic.message, "repeated sequence is too long");
+    });
+}
+
+#[test]
+fn string_methods() {
+    let string = |text: &str| Value::String(String::from(text).into());
+    let list = |items: &[&str]| {
+        Value::List(
+            items
+                .iter()
+                .map(|item| string(item))
+                .collect::<Vec<_>>()
+                .into(),
+        )
+    };
+    vec![
+        (r#""MiXed".upper()"#, string("MIXED")),
+        (r#""MiXed".lower()"#, string("mixed")),
+        (r#""  pad \n".strip()"#, string("pad")),
+        (r#""xxhixx".strip("x")"#, string("hi")),
+        (r#"" a  b c ".split()"#, list(&["a", "b", "c"])),
+        (r#""a  b c".split(None, 1)"#, list(&["a", "b c"])),
+        (r#""a,b,,c".split(",")"#, list(&["a", "b", "", "c"])),
+        (r#""a,b,c".split(",", 1)"#, list(&["a", "b,c"])),
+        (r#""-".join("a b c".split())"#, string("a-b-c")),
+        (r#"".".join("abc")"#, string("a.b.c")),
+        (r#""aaa".replace("a", "b")"#, string("bbb")),
+        (r#""aaa".replace("a", "b", 2)"#, string("bba")),
+        (r#""héllo".find("l")"#, Value::Integer(2)),
+        (r#""héllo".find("l", 3)"#, Value::Integer(3)),
+        (r#""hello".find("z")"#, Value::Integer(-1)),
+        (r#""hello".startswith("he")"#, Value::Bool(true)),
+        (r#""hello".endswith("he")"#, Value::Bool(false)),
+        (r#""{}!".format("hi")"#, string("hi!")),
+        (r#"len("a b".split())"#, Value::Integer(2)),
+        (r#""a b".split()[-1]"#, string("b")),
+    ]
+    .into_iter()
+    .for_each(|(source, expected)| {
+        let mut interpreter = Interpreter::new(Config::new());
+        let result = interpreter.run(source);
+        assert!(result.is_ok(), "{}: {:?}", source, result);
+        common::assert_value_eq(&result.unwrap(), &expected);
+    });
+}
+
+#[test]
+fn string_method_errors() {
+    vec![
+        (r#""a".upper(1)"#, "upper() takes no arguments (1 given)"),
+        (r#""a".split("")"#, "ValueError: empty separator"),
+        (r#""a".find(1)"#, "find() argument 1 must be str, not int"),
+        (
+            r#""-".join("a 1".split() + 1)"#,
+            "TypeError: unsupported operand type(s)",
+        ),
+        (r#""-".join(1)"#, "TypeError: can only join an iterable"),
+        (r#""a".shout()"#, "'str' object has no attribute 'shout'"),
+    ]
+    .into_iter()
+    .for_each(|(source, expected)| {
+        let mut interpreter = Interpreter::new(Config::new());
+        match interpreter.run(source) {
+            Err(InterpreterError::VmError(err)) => {
+                let message = format!("{:?}", err);
+                assert!(message.contains(expected), "{}: {}", source, message)
+            }
+            other => panic!("{}: expected VmError, got {:?}", source, other),
+        }
+    });
+}
@@ -0,0 +1,401 @@
+use rustpy::config::{Config, DEFAULT_OPT_LEVEL};
+use rustpy::object::Value;
+use rustpy::program::CompiledProgram;
+use rustpy::{Interpreter, InterpreterError, VmError, CACHE_DIR};
+use std::fs;
+
+#[test]
+fn check_source() {
+    vec![
+        ("def double(n):\n    return n * 2\ndouble(2)\n", true),
+        ("double(2)\n", false),
+    ]
+    .into_iter()
+    .for_each(|(source, is_ok)| {
+        let interpreter = Interpreter::new(Config::new());
+        match interpreter.check(source) {
+            Ok(_) => assert!(is_ok),
+            Err(InterpreterError::CompilerError(_)) => assert!(!is_ok),
+            Err(err) => panic!("Unexpected error: {:?}", err),
+        }
+    });
+}
+
+#[test]
+fn error_spans() {
+    vec![
+        ("x = 1\ny = $\n", (2, 5)),
+        ("print(1))\n", (1, 9)),
+        ("x = 1\nprint(y)\n", (2, 7)),
+        ("x = 1\nbreak\n", (2, 1)),
+    ]
+    .into_iter()
+    .for_each(|(source, (line, column))| {
+        let interpreter = Interpreter::new(Config::new());
+        let diagnostic = interpreter.check(source).unwrap_err().diagnostic();
+        let (start, _) = diagnostic.span.unwrap().line_col(source);
+        assert_eq!((start.line, start.column), (line, column), "{}", source);
+    });
+}
+
+#[test]
+fn runtime_error_lines() {
+    vec![
+        ("x = 1\ny = x / 0\n", Some(2)),
+        ("def first(n):\n    return n[0]\n\nfirst(1)\n", Some(2)),
+        ("x = 1\nprint(x)\nx.missing\n", Some(3)),
+        (
+            "i = 0\nwhile i < 3:\n    i = i + 1\nprint(10 // (i - 3))\n",
+            Some(4),
+        ),
+        ("x = 1\nprint(y)\n", None),
+        ("x = 1\n", None),
+    ]
+    .into_iter()
+    .for_each(|(source, line)| {
+        let mut interpreter = Interpreter::new(Config::new());
+        let _ = interpreter.run("1 / 0");
+        let result = interpreter.run(source);
+        assert_eq!(interpreter.error_line(), line, "{}", source);
+        // diagnostics of runtime errors point at that line
+        if let (Err(err), Some(line)) = (result, line) {
+            let span = interpreter.diagnostic(&err).span.unwrap();
+            assert_eq!(span.line_col(source).0.line, line, "{}", source);
+        }
+    });
+}
+
+#[test]
+fn disassemble_source() {
+    let interpreter = Interpreter::new(Config::new());
+    let listing = interpreter
+        .disassemble("def double(n):\n    return n * 2\ndouble(2)\n")
+        .unwrap();
+    assert_eq!(listing[0], "<main>:");
+    assert!(listing.iter().any(|line| line == "double:"));
+    assert!(listing.iter().any(|line| line.contains("GetLocal")));
+    // the first instruction of each source line is numbered
+    assert!(listing
+        .iter()
+        .any(|line| line.trim_start().starts_with("2 ") && line.contains("GetLocal")));
+
+    // checking and disassembling never define names
+    let mut interpreter = interpreter;
+    assert!(interpreter.run("double(2)").is_err());
+}
+
+#[test]
+fn disassemble_inplace_add() {
+    let interpreter = Interpreter::new(Config::new());
+    let listing = interpreter
+        .disassemble("s = \"\"\ns = s + \"a\"\nt = \"b\" + s\n")
+        .unwrap();
+    assert_eq!(
+        listing
+            .iter()
+            .filter(|line| line.contains("InplaceAddGlobal"))
+            .count(),
+        1
+    );
+    assert!(listing.iter().any(|line| line.ends_with("Add")));
+}
+
+#[test]
+fn disassemble_constant_folding() {
+    let interpreter = Interpreter::new(Config::new());
+    [
+        ("x = 2 * 3 + 1\n", vec!["Const"]),
+        ("x = \"a\" + \"b\"\n", vec!["Const"]),
+        ("x = !True\n", vec!["PushFalse"]),
+        ("x = -(1 + 1) < 0\n", vec!["PushTrue"]),
+        // only literal subtrees fold
+        (
+            "x = 1\ny = x + 2 * 3\n",
+            vec!["Const", "GetGlobal", "Const", "Add"],
+        ),
+        // failures are left to the runtime
+        ("x = 1 / 0\n", vec!["Const", "Const", "Div"]),
+        ("x = \"ab\" * 100000\n", vec!["Const", "Const", "Mul"]),
+    ]
+    .into_iter()
+    .for_each(|(source, expected)| {
+        let listing = interpreter.disassemble(source).unwrap();
+        let ops: Vec<&str> = listing
+            .iter()
+            .filter_map(|line| line.split(": ").nth(1))
+            .map(|op| op.split(',').next().unwrap_or(op))
+            .filter(|op| !matches!(*op, "SetGlobal" | "Nop"))
+            .collect();
+        assert_eq!(ops, expected, "{}", source);
+    });
+    let mut interpreter = interpreter;
+    assert_eq!(
+        interpreter.run("2 * 3 + 1 - 10 // 4\n").ok(),
+        Some(Value::Integer(5))
+    );
+}
+
+#[test]
+fn disassemble_dead_code() {
+    let mut interpreter = Interpreter::new(Config::new());
+    let source = r###"
+def f(n):
+    while n < 10:
+        n = n + 1
+        if n == 5:
+            break
+            print("after break")
+        continue
+        print("after continue")
+    return n
+    print("after return")
+f(0)
+"###;
+    let listing = interpreter.disassemble(source).unwrap();
+    assert!(!listing.iter().any(|line| line.contains("GetBuiltin")));
+    // nothing falls off the end of f after its return
+    let function_end = listing.iter().skip_while(|line| *line != "f:").last();
+    assert!(function_end.is_some_and(|line| line.ends_with("Return")));
+    assert_eq!(interpreter.run(source).ok(), Some(Value::Integer(5)));
+}
+
+#[test]
+fn disassemble_opt_levels() {
+    [
+        // constant folding from -O1
+        ("x = 2 + 3\n", "Add", [true, false, false]),
+        // dead code elimination from -O1
+        (
+            "def f():\n    return 1\n    print(1)\nf()\n",
+            "GetBuiltin",
+            [true, false, false],
+        ),
+        // assert stripping at -O2
+        ("x = 1\nassert x\n", "Raise", [true, true, false]),
+        // the peephole optimizer at -O2
+        (
+            "i = 0\nwhile i < 3:\n    i = i + 1\n",
+            "ConstLessJumpIfFalse",
+            [false, false, true],
+        ),
+    ]
+    .into_iter()
+    .for_each(|(source, op, present)| {
+        for (opt_level, present) in present.into_iter().enumerate() {
+            let interpreter = Interpreter::new(Config {
+                opt_level: opt_level as u8,
+                ..Config::new()
+            });
+            let listing = interpreter.disassemble(source).unwrap();
+            let found = listing
+                .iter()
+                .filter_map(|line| line.split(": ").nth(1))
+                .any(|line| line.split(',').next() == Some(op));
+            assert_eq!(found, present, "{} at -O{}: {}", op, opt_level, source);
+        }
+    });
+}
+
+#[test]
+fn compile_program_prunes_globals() {
+    let interpreter = Interpreter::new(Config::new());
+    let program = interpreter
+        .compile_program(
+            r###"
+def unused(n):
+    return n + 1
+
+def helper(n):
+    return n * 2
+
+def double(n):
+    return helper(n)
+
+total = double(2)
+"###,
+        )
+        .unwrap();
+    let mut names = program.global_names();
+    names.sort();
+    assert_eq!(names, vec!["double", "helper", "total"]);
+}
+
+#[test]
+fn compiled_program_bytes() {
+    let interpreter = Interpreter::new(Config::new());
+    let program = interpreter
+        .compile_program(
+            "limit = 3\ndef double(n: int) -> int:\n    return n * 2\nprint(double(limit))\n",
+        )
+        .unwrap();
+    let bytes = program.to_bytes().unwrap();
+    let read = CompiledProgram::from_bytes(&bytes).unwrap();
+    assert_eq!(read.global_names(), program.global_names());
+    assert_eq!(read.opt_level(), DEFAULT_OPT_LEVEL);
+    assert_eq!(read.to_bytes().unwrap(), bytes);
+
+    assert!(CompiledProgram::from_bytes(b"limit = 3\n").is_err());
+}
+
+#[test]
+fn run_bytecode() {
+    let source =
+        "limit = 3\ndef double(n):\n    return n * 2\ntotal = double(limit)\ntotal + len(\"ab\")\n";
+    let bytes = Interpreter::new(Config::new())
+        .compile_program(source)
+        .unwrap()
+        .to_bytes()
+        .unwrap();
+    [
+        Config::new(),
+        Config {
+            register_backend: true,
+            ..Config::new()
+        },
+    ]
+    .into_iter()
+    .for_each(|config| {
+        let mut interpreter = Interpreter::new(config);
+        // globals of earlier runs shift the slots the program links to
+        interpreter.run("other = 1\ntotal = 0\n").unwrap();
+        assert_eq!(
+            interpreter.run_bytecode(&bytes).ok(),
+            Some(Value::Integer(8))
+        );
+        assert_eq!(
+            interpreter.run("double(total)").ok(),
+            Some(Value::Integer(12))
+        );
+    });
+
+    let mut interpreter = Interpreter::new(Config::new());
+    interpreter.remove_builtin("len");
+    let result = interpreter.run_bytecode(&bytes);
+    assert!(
+        matches!(result, Err(InterpreterError::VmError(VmError::InvalidBytecode(ref message))) if message == "builtin 'len' is not available"),
+        "{:?}",
+        result
+    );
+    assert!(interpreter.global_names().is_empty());
+
+    let mut truncated = bytes.clone();
+    truncated.pop();
+    assert!(interpreter.run_bytecode(&truncated).is_err());
+    assert!(interpreter.run_bytecode(source.as_bytes()).is_err());
+}
+
+#[test]
+fn run_script_cache() {
+    let dir = std::env::temp_dir().join(format!("rustpy_{}_cache", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("script.py");
+    let cache = dir.join(CACHE_DIR);
+    let entries = || fs::read_dir(&cache).map_or(0, |entries| entries.count());
+    let run = |source: &str, cache_bytecode| {
+        let mut interpreter = Interpreter::new(Config {
+            cache_bytecode,
+            ..Config::new()
+        });
+        interpreter.run_script(&script, source).ok()
+    };
+    // unused definitions are cached too, `globals()` shows them
+    let source = "def unused():\n    return 0\nlen(globals())\n";
+
+    assert_eq!(run(source, false), Some(Value::Integer(3)));
+    assert_eq!(entries(), 0);
+    for _ in 0..2 {
+        assert_eq!(run(source, true), Some(Value::Integer(3)));
+        assert_eq!(entries(), 1);
+    }
+    // corrupt entries are replaced, as are those of earlier sources
+    let entry = fs::read_dir(&cache)
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .path();
+    fs::write(&entry, b"RPYC").unwrap();
+    assert_eq!(run(source, true), Some(Value::Integer(3)));
+    assert_ne!(fs::read(&entry).unwrap(), b"RPYC");
+    assert_eq!(run("1 + 1\n", true), Some(Value::Integer(2)));
+    assert_eq!(entries(), 1);
+    assert!(!entry.exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn typecheck_source() {
+    vec![
+        (
+            "def double(n: int) -> int:\n    return n * 2\ndouble(2)\n",
+            0,
+        ),
+        (
+            "def double(n: int) -> int:\n    return n * 2\ndouble(\"a\", 1)\n",
+            1,
+        ),
+        ("total = 1 + \"a\"\nlabel = -\"b\"\n", 2),
+    ]
+    .into_iter()
+    .for_each(|(source, errors)| {
+        let interpreter = Interpreter::new(Config::new());
+        let diagnostics = interpreter.typecheck(source).unwrap();
+        assert_eq!(diagnostics.len(), errors, "{}: {:?}", source, diagnostics);
+        assert!(diagnostics
+            .iter()
+            .all(|diagnostic| diagnostic.code == "TypeError"));
+    });
+
+    let interpreter = Interpreter::new(Config::new());
+    assert!(interpreter.typecheck("def (").is_err());
+}
+
+#[test]
+fn profile_opcodes() {
+    let source = r###"
+def double(n):
+    return n * 2
+
+i = 0
+while i < 3:
+    double(i)
+    i = i + 1
+"###;
+    let mut interpreter = Interpreter::new(Config::new());
+    interpreter.run(source).unwrap();
+    assert_eq!(interpreter.profile().total_opcodes(), 0);
+
+    let mut interpreter = Interpreter::new(Config {
+        profile_opcodes: true,
+        ..Config::new()
+    });
+    interpreter.run(source).unwrap();
+    let profile = interpreter.profile();
+    let count = |name: &str| {
+        profile
+            .opcode_counts()
+            .into_iter()
+            .find(|(opcode, _)| opcode == name)
+            .map_or(0, |(_, count)| count)
+    };
+    assert_eq!(count("Mul"), 3);
+    assert_eq!(count("Return"), 3);
+    let call_sites = profile.call_site_counts();
+    assert_eq!(call_sites.len(), 1);
+    assert_eq!(call_sites[0].0.caller, "<main>");
+    assert_eq!(call_sites[0].0.callee, "double");
+    assert_eq!(call_sites[0].1, 3);
+}
+
+#[test]
+fn global_names() {
+    let mut interpreter = Interpreter::new(Config::new());
+    let result = interpreter.run("import sys\nlimit = 3\ndef double(n):\n    return n * 2\n");
+    assert!(result.is_ok(), "{:?}", result);
+    assert!(interpreter.run("count = double(limit)").is_ok());
+    assert_eq!(
+        interpreter.global_names(),
+        vec!["__name__", "__doc__", "sys", "limit", "double", "count"]
+    );
+}